@@ -0,0 +1,192 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+use regex::Regex;
+
+/// Values that usually change between runs and the way to match them
+const VOLATILE_VALUES: &[(&str, &str, &str)] = &[
+	(r"\b[0-9]{4}-[0-9]{2}-[0-9]{2}[T ][0-9]{2}:[0-9]{2}:[0-9]{2}", "a date and time", "%{DATETIME} or %{ISODATETIME}"),
+	(r"\b[0-9]{4}-[0-9]{2}-[0-9]{2}\b", "a date", "%{DATE}"),
+	(r"\b[0-9]{2}:[0-9]{2}:[0-9]{2}\b", "a time", "%{TIME}"),
+	(r"\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b", "a UUID", "#!/[0-9a-f-]{36}/!#"),
+	(r"\b[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\b", "an IP address", "%{IPADDR}"),
+	(r"/tmp/[A-Za-z0-9._-]*[A-Za-z0-9]{6}", "a temporary path", "%{PATH}"),
+	(r"\b[0-9]+(?:\.[0-9]+)?\s?(?:ms|sec|seconds)\b", "a duration", "%{NUMBER}"),
+	(r"\b(?:[0-9]+[a-f]|[a-f]+[0-9])[0-9a-f]{6,}\b", "a hash or an id", "#!/[0-9a-f]+/!#"),
+];
+
+/// Commands whose output usually changes between runs
+const VOLATILE_COMMAND_REGEX: &str = r"\b(?:date|uptime|ps|top|free|df|du|shuf|mktemp|hostname)\b|\$RANDOM|\$\$";
+
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+pub struct Issue {
+	pub severity: Severity,
+	pub message: String,
+}
+
+/// Audit the test before it enters the suite and return the issues with the fixes
+/// The test is linted by compiling it, commands are checked against the policy,
+/// and expected outputs are checked for unknown patterns and values likely to change between runs
+pub fn audit(rec_file: &str, known_patterns: &[String]) -> Vec<Issue> {
+	let mut issues = Vec::new();
+	let error = |message: String| Issue { severity: Severity::Error, message };
+	let warning = |message: String| Issue { severity: Severity::Warning, message };
+
+	let compiled = match parser::compile_checked(rec_file, false) {
+		Ok(compiled) => compiled,
+		Err(e) => {
+			issues.push(error(format!("failed to compile: {}", e)));
+			return issues;
+		}
+	};
+
+	for deprecation in &compiled.deprecations {
+		issues.push(warning(deprecation.to_string()));
+	}
+
+	if Path::new(parser::POLICY_FILE).exists() {
+		if let Err(e) = parser::check_policy(&compiled.content, parser::POLICY_FILE) {
+			issues.push(error(e.to_string()));
+		}
+	}
+
+	let steps = get_steps(&compiled.content);
+	if steps.is_empty() {
+		issues.push(error("the test has no commands, record at least one".to_string()));
+	}
+
+	let var_regex = Regex::new(clt_core::pattern::VAR_REGEX).unwrap();
+	let raw_regex = Regex::new(r"#!/.*?/!#").unwrap();
+	let volatile_command_regex = Regex::new(VOLATILE_COMMAND_REGEX).unwrap();
+	let volatile_values: Vec<(Regex, &str, &str)> = VOLATILE_VALUES.iter()
+		.map(|(regex, name, fix)| (Regex::new(regex).unwrap(), *name, *fix))
+		.collect();
+
+	// Steps of blocks are named by the file they are written in, but groups expand into a different number of steps,
+	// and then only the number of the step in the compiled test is known
+	let flat_steps: Vec<parser::FlatStep> = parser::RecFile::read_with_blocks(rec_file)
+		.map(|test| parser::flatten(rec_file, &test))
+		.ok()
+		.filter(|flat_steps| flat_steps.len() == steps.len())
+		.unwrap_or_default();
+
+	for (n, (command, output)) in steps.iter().enumerate() {
+		let step = match flat_steps.get(n) {
+			Some(flat_step) if !flat_step.blocks.is_empty() => format!("{} `{}`", flat_step, command),
+			_ => format!("step {} `{}`", n + 1, command),
+		};
+		let mut has_patterns = false;
+		let mut ignored = false;
+		let sparse = output.first().is_some_and(|line| line.trim() == parser::ANCHORS);
+		for line in output {
+			// Only anchors of the sparse output are compared
+			let line = match line.strip_prefix(parser::ANCHOR_PREFIX) {
+				Some(anchor) if sparse => anchor,
+				_ if sparse => {
+					has_patterns = true;
+					continue;
+				},
+				_ => line,
+			};
+
+			// Lines between ignore markers are never compared
+			match line.trim() {
+				parser::SKIP_LINES => {
+					has_patterns = true;
+					continue;
+				},
+				parser::IGNORE_START => {
+					ignored = true;
+					has_patterns = true;
+					continue;
+				},
+				parser::IGNORE_END => {
+					ignored = false;
+					continue;
+				},
+				_ if ignored => continue,
+				_ => {},
+			}
+
+			for caps in var_regex.captures_iter(line) {
+				has_patterns = true;
+				if !known_patterns.iter().any(|p| p == &caps[1]) {
+					issues.push(error(format!("{}: unknown pattern {}, it is compared literally, define it in .patterns", step, &caps[0])));
+				}
+			}
+
+			// Parts already matched with patterns can not cause spurious diffs
+			let masked = raw_regex.replace_all(line, "");
+			let masked = var_regex.replace_all(&masked, "");
+			has_patterns = has_patterns || masked.len() != line.len();
+			if let Some((regex, name, fix)) = volatile_values.iter().find(|(regex, _, _)| regex.is_match(&masked)) {
+				let value = regex.find(&masked).unwrap().as_str();
+				issues.push(warning(format!("{}: output \"{}\" looks like {}, use {}", step, value, name, fix)));
+			}
+		}
+
+		if !has_patterns && !output.is_empty() && volatile_command_regex.is_match(command) {
+			issues.push(warning(format!("{}: output of the command is likely to change between runs, use patterns", step)));
+		}
+	}
+
+	issues
+}
+
+/// Score the readiness of the test from 0 to 100
+pub fn get_score(issues: &[Issue]) -> u32 {
+	let penalty: u32 = issues.iter().map(|issue| match issue.severity {
+		Severity::Error => 20,
+		Severity::Warning => 5,
+	}).sum();
+	100u32.saturating_sub(penalty)
+}
+
+/// Split the compiled test into commands with their expected output lines
+fn get_steps(content: &str) -> Vec<(String, Vec<String>)> {
+	let mut steps: Vec<(String, Vec<String>)> = Vec::new();
+	let mut last_line = "";
+	let mut in_input = false;
+	// Outputs with checks like lines>=N are not compared line by line
+	let mut compared = true;
+	for line in content.lines() {
+		if line == parser::COMMAND_PREFIX {
+			in_input = true;
+		} else if parser::is_output_line(line) {
+			in_input = false;
+			compared = line == parser::COMMAND_SEPARATOR;
+			steps.push((last_line.trim().to_string(), Vec::new()));
+		} else if !in_input && compared && !parser::is_duration_line(line) && parser::parse_exit_line(line).is_none() {
+			if let Some((_, output)) = steps.last_mut() {
+				output.push(line.to_string());
+			}
+		}
+		last_line = line;
+	}
+
+	for (_, output) in steps.iter_mut() {
+		while output.last().is_some_and(|line| line.trim().is_empty()) {
+			output.pop();
+		}
+	}
+
+	steps
+}
@@ -14,19 +14,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Cursor, BufReader, BufRead, SeekFrom, Seek, self};
+use std::fs;
+use std::io::{self, Read};
 use std::env;
-use std::path::Path;
-use regex::Regex;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use std::io::Write;
+use clt_core::compare::{Comparator, IGNORE_LINES_FILE};
+use termcolor::{ColorChoice, StandardStream};
 
-enum Diff {
-	Plus,
-	Minus
-}
+mod audit;
 
 fn main() {
 	// Set up the SIGINT signal handler
@@ -38,277 +32,247 @@ fn main() {
 	let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
 	let args: Vec<String> = env::args().collect();
-	if args.len() != 3 {
-		eprintln!("Usage: {} rec-file rep-file", args[0]);
-		std::process::exit(1);
-	}
-
-	let file_name: String = String::from(".patterns");
-	let file_path = Path::new(&file_name);
-
-	let pattern_matcher = PatternMatcher::new(match file_path.exists() {
-		true => Some(file_name),
-		false => None,
-	}).unwrap();
-
-	let input_content = parser::compile(&args[1]).unwrap();
-	let file1_cursor = Cursor::new(input_content);
-	let mut file1_reader = BufReader::new(file1_cursor);
-	move_cursor_to_line(&mut file1_reader, parser::COMMAND_PREFIX).unwrap();
-
-	let file2 = File::open(&args[2]).unwrap();
-	let mut file2_reader = BufReader::new(file2);
-	move_cursor_to_line(&mut file2_reader, parser::COMMAND_PREFIX).unwrap();
-
-	let mut line1 = String::new();
-	let mut line2 = String::new();
-
-	let mut lines1 = vec![];
-	let mut lines2 = vec![];
-
-	let mut files_have_diff = false;
-	loop {
-		let [read1, read2] = [
-			file1_reader.read_line(&mut line1).unwrap(),
-			file2_reader.read_line(&mut line2).unwrap(),
-		];
-
-		if read1 == 0 && read2 == 0 {
-			break;
+	if args.len() == 2 && args[1] == "--encrypt" {
+		let mut value = String::new();
+		io::stdin().read_line(&mut value).unwrap();
+		match parser::encrypt_secret(value.trim_end_matches(['\r', '\n'])) {
+			Ok(token) => println!("{}", token),
+			Err(e) => {
+				eprintln!("Failed to encrypt the secret: {}", e);
+				std::process::exit(1);
+			}
 		}
+		return;
+	}
 
-		if read1 == 0 {
-			print_diff(&mut stdout, line2.trim(), Diff::Plus);
-		} else if read2 == 0 {
-			print_diff(&mut stdout, line1.trim(), Diff::Minus);
-		} else {
-			println!("{}", line2.trim());
+	// Print the compiled test with secrets kept encrypted to put it into the bundle to reproduce the failure
+	if args.len() == 3 && args[1] == "--compile" {
+		match parser::compile_keeping_secrets(&args[2], false) {
+			Ok(compiled) => print!("{}", compiled.content),
+			Err(e) => {
+				print_compile_error(&args[2], &e);
+				std::process::exit(1);
+			}
 		}
+		return;
+	}
 
-		// Change the current mode if we are in output section or not
-		let mut r1 = read1;
-		while r1 > 0 && line1.trim() != parser::COMMAND_SEPARATOR {
-			line1.clear();
-			r1 = file1_reader.read_line(&mut line1).unwrap();
-			if read2 == 0 {
-				print_diff(&mut stdout, line1.trim(), Diff::Minus);
+	// Print the files the test reads besides itself to let clt test pick the tests affected by the changes
+	if args.len() == 3 && args[1] == "--depends" {
+		match parser::get_dependencies(&args[2]) {
+			Ok(files) => files.iter().for_each(|file| println!("{}", file.display())),
+			Err(e) => {
+				eprintln!("Failed to find the files {} depends on: {:#}", args[2], e);
+				std::process::exit(1);
 			}
 		}
+		return;
+	}
 
-		lines1.clear();
-		while r1 > 0 {
-			line1.clear();
-			r1 = file1_reader.read_line(&mut line1).unwrap();
-			if line1.trim() == parser::COMMAND_PREFIX {
-				break;
+	// Refuse to refine the test with groups before replaying it, as the refined file would have them expanded
+	if args.len() == 3 && args[1] == "--check-refine" {
+		match parser::find_group(&args[2]) {
+			Ok(None) => {},
+			Ok(Some((file, line))) => {
+				eprintln!("Refine can not write the group at {}:{} back, as it is replayed expanded into other steps, update its expected outputs by hand", file, line);
+				std::process::exit(1);
 			}
-			if parser::is_duration_line(&line1) {
-				continue;
+			Err(e) => {
+				eprintln!("Failed to read the test {}: {:#}", args[2], e);
+				std::process::exit(1);
 			}
-			lines1.push(line1.trim().to_string());
 		}
+		return;
+	}
 
-		let mut r2 = read2;
-		while r2 > 0 && line2.trim() != parser::COMMAND_SEPARATOR {
-			line2.clear();
-			r2 = file2_reader.read_line(&mut line2).unwrap();
-			if read1 == 0 {
-				print_diff(&mut stdout, line2.trim(), Diff::Plus);
-			} else {
-				println!("{}", line2.trim());
+	// Print the tests in the order to run them with the tests they require before them
+	if args.len() >= 3 && args[1] == "--plan" {
+		match parser::get_execution_plan(&args[2..]) {
+			Ok(plan) => plan.iter().for_each(|file| println!("{}", file)),
+			Err(e) => {
+				eprintln!("Failed to plan the tests: {:#}", e);
+				std::process::exit(1);
 			}
-
 		}
+		return;
+	}
 
-		lines2.clear();
-		while r2 > 0 {
-			line2.clear();
-			r2 = file2_reader.read_line(&mut line2).unwrap();
-			if line2.trim() == parser::COMMAND_PREFIX {
-				break;
-			}
-			if parser::is_duration_line(&line2) {
-				continue;
+	// Print the test upgraded to the current format to let clt migrate show the diff or replace the file
+	if args.len() == 3 && args[1] == "--migrate" {
+		let migrated = fs::read_to_string(&args[2])
+			.map_err(anyhow::Error::from)
+			.and_then(|content| parser::migrate_test(&content, &args[2]));
+		match migrated {
+			Ok((content, deprecations)) => {
+				for deprecation in deprecations {
+					eprintln!("{}", deprecation);
+				}
+				print!("{}", content);
+			},
+			Err(e) => {
+				print_compile_error(&args[2], &e);
+				std::process::exit(1);
 			}
-			lines2.push(line2.trim().to_string());
 		}
+		return;
+	}
 
-		let max_len = std::cmp::max(lines1.len(), lines2.len());
-
-		for i in 0..max_len {
-			match (lines1.get(i), lines2.get(i)) {
-				(None, Some(line)) => {
-					print_diff(&mut stdout, line.trim(), Diff::Plus);
-					files_have_diff = true;
-				},
-				(Some(line), None) => {
-					print_diff(&mut stdout, line.trim(), Diff::Minus);
-					files_have_diff = true;
-				},
-				(Some(line1), Some(line2)) => {
-					let has_diff: bool = pattern_matcher.has_diff(line1.to_string(), line2.to_string());
-					if has_diff {
-						print_diff(&mut stdout, line1.trim(), Diff::Minus);
-						print_diff(&mut stdout, line2.trim(), Diff::Plus);
-						files_have_diff = true;
-					} else {
-						println!("{}", line1.trim());
-					}
-				},
-				_ => {}
+	// Print the steps changed in the new version of the test to summarize them in the commit of clt refine --commit
+	if args.len() == 4 && args[1] == "--diff-structure" {
+		match parser::RecFile::read(&args[2]).and_then(|old| Ok((old, parser::RecFile::read(&args[3])?))) {
+			Ok((old, new)) => print!("{}", parser::diff_structures(&old, &new)),
+			Err(e) => {
+				eprintln!("Failed to read the tests to compare: {:#}", e);
+				std::process::exit(1);
 			}
 		}
+		return;
 	}
 
-	if files_have_diff {
-		std::process::exit(1);
-	}
-}
-
-enum MatchingPart {
-	Static(String),
-	Pattern(String),
-}
-
-struct PatternMatcher {
-	config: HashMap<String, String>,
-	var_regex: Regex,
-}
-
-impl PatternMatcher {
-	/// Initialize struct by using file name of the variables description for patterns
-	/// If the option is none, we just will have empty map of keys for pattersn
-	/// And in that case we will use only raw regexes to validate
-	fn new(file_name: Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
-		let config = match file_name {
-			Some(file_name) => Self::parse_config(file_name)?,
-			None =>  HashMap::new(),
+	// Print the test merged step by step from the versions of both branches for clt merge-tool,
+	// conflicts are marked with comment steps and make it fail, so git leaves the test for manual resolution
+	if args.len() == 5 && args[1] == "--merge" {
+		let read = |path: &str| parser::RecFile::read(path);
+		let (base, ours, theirs) = match read(&args[2]).and_then(|base| Ok((base, read(&args[3])?, read(&args[4])?))) {
+			Ok(versions) => versions,
+			Err(e) => {
+				eprintln!("Failed to read the versions of the test to merge: {:#}", e);
+				std::process::exit(2);
+			}
 		};
-
-		let var_regex = Regex::new(r"%\{[A-Z]{1}[A-Z_0-9]*\}")?;
-		Ok(Self { config, var_regex })
+		let merged = parser::merge_structures(&base, &ours, &theirs);
+		print!("{}", merged.file);
+		if merged.preamble_conflict {
+			eprintln!("The preamble was changed differently in both versions, the one of ours is kept");
+		}
+		if merged.conflicts > 0 {
+			eprintln!("{} conflicts are marked with the # <<<<<<< ours, # ======= and # >>>>>>> theirs steps", merged.conflicts);
+		}
+		if merged.conflicts > 0 || merged.preamble_conflict {
+			std::process::exit(1);
+		}
+		return;
 	}
 
-	/// Validate line from .rec file and line from .rep file
-	/// by using open regex patterns and matched variables
-	/// and return true or false in case if we have diff or not
-	fn has_diff(&self, rec_line: String, rep_line: String) -> bool {
-		let rec_line = self.replace_vars_to_patterns(rec_line);
-		let parts = self.split_into_parts(&rec_line);
-		let mut last_index = 0;
-
-		for part in parts {
-			match part {
-				MatchingPart::Static(static_part) => {
-					if rep_line[last_index..].starts_with(&static_part) {
-						last_index += static_part.len();
-					} else {
-						return true;
-					}
-				}
-				MatchingPart::Pattern(pattern) => {
-					let pattern_regex = Regex::new(&pattern).unwrap();
-					if let Some(mat) = pattern_regex.find(&rep_line[last_index..]) {
-						last_index += mat.end();
-					} else {
-						return true;
-					}
-				}
-			}
+	// Save the test to its history before clt migrate replaces it, so clt undo can bring it back
+	if args.len() == 3 && args[1] == "--save-history" {
+		if let Err(e) = parser::save_history(&args[2]) {
+			eprintln!("Failed to save the history of {}: {:#}", args[2], e);
+			std::process::exit(1);
 		}
-
-		last_index != rep_line.len()
+		return;
 	}
 
-	/// Helper method to split line into parts
-	/// To make it possible to validate pattern matched vars and static parts
-	///
-	fn split_into_parts(&self, rec_line: &str) -> Vec<MatchingPart> {
-		let mut parts = Vec::new();
+	// Restore the test as it was before the last edit for clt undo
+	if args.len() == 3 && args[1] == "--revert" {
+		if let Err(e) = parser::revert_last_edit(&args[2]) {
+			eprintln!("Failed to revert the last edit of {}: {:#}", args[2], e);
+			std::process::exit(1);
+		}
+		return;
+	}
 
-		let first_splits: Vec<&str> = rec_line.split("#!/").collect();
-		for first_split in first_splits {
-			let second_splits: Vec<&str> = first_split.split("/!#").collect();
-			if second_splits.len() == 1 {
-				parts.push(MatchingPart::Static(second_splits.first().unwrap().to_string()));
-			} else {
-				for (i, second_split) in second_splits.iter().enumerate() {
-					if i % 2 == 1 {
-						parts.push(MatchingPart::Static(second_split.to_string()));
-					} else {
-						parts.push(MatchingPart::Pattern(second_split.to_string()));
-					}
-				}
+	// Print the text from stdin with the values of the secrets of the test written as their tokens,
+	// so clt test --bundle packs the files of the run without the values
+	if args.len() == 3 && args[1] == "--hide-secrets" {
+		let mut text = String::new();
+		let hidden = io::stdin().read_to_string(&mut text)
+			.map_err(anyhow::Error::from)
+			.and_then(|_| parser::get_secrets(&parser::compile_keeping_secrets(&args[2], false)?.content))
+			.and_then(|secrets| parser::hide_secrets(&text, &secrets));
+		match hidden {
+			Ok(hidden) => print!("{}", hidden),
+			Err(e) => {
+				eprintln!("Failed to hide the secrets of {}: {:#}", args[2], e);
+				std::process::exit(1);
 			}
-
 		}
-		parts
+		return;
 	}
 
-	/// Helper function that go through matched variable patterns in line
-	/// And replace it all with values from our parsed config
-	/// So we have raw regex to validate as an output
-	fn replace_vars_to_patterns(&self, line: String) -> String {
-		let result = self.var_regex.replace_all(&line, |caps: &regex::Captures| {
-			let matched = &caps[0];
-			let key = matched[2..matched.len() - 1].to_string();
-			self.config.get(&key).unwrap_or(&matched.to_string()).clone()
-		});
-
-		result.into_owned()
+	if args.len() != 3 {
+		eprintln!("Usage: {} rec-file rep-file | --audit rec-file | --compile rec-file | --migrate rec-file | --depends rec-file | --plan rec-file... | --diff-structure old-rec-file rec-file | --merge base-rec-file ours-rec-file theirs-rec-file | --save-history rec-file | --revert rec-file | --hide-secrets rec-file | --check-refine rec-file | --encrypt", args[0]);
+		std::process::exit(1);
 	}
 
-	/// Helper to parse the variables into config map when we pass path to the file
-	fn parse_config(file_name: String) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-		let mut config: HashMap<String, String> = HashMap::new();
-
-		let file_path = Path::new(&file_name);
-		let file = File::open(&file_path)?;
-		let reader = BufReader::new(file);
+	let file_name: String = env::var("CLT_PATTERNS").unwrap_or(String::from(".patterns"));
+	let normalize = env::var("CLT_NORMALIZE").unwrap_or_default();
+	let mut comparator = match Comparator::load(&file_name, &normalize, IGNORE_LINES_FILE) {
+		Ok(comparator) => comparator,
+		Err(e) => {
+			eprintln!("{:#}", e);
+			std::process::exit(1);
+		}
+	};
 
-		for line in reader.lines() {
-			let line = line?.trim().to_string();
-			let parts: Vec<&str> = line.split_whitespace().collect(); // adjust this based on how your file is structured
-			if parts.len() == 2 {
-				config.insert(
-					parts[0].trim().to_string(),
-					format!("#!/{}/!#", parts[1].trim())
-				);
+	if args[1] == "--audit" {
+		let mut known_patterns: Vec<String> = comparator.matcher.config.keys().cloned().collect();
+		known_patterns.extend(parser::RUNTIME_VARIABLES.iter().map(|(name, _)| name.to_string()));
+		let issues = audit::audit(&args[2], &known_patterns);
+		for issue in &issues {
+			match issue.severity {
+				audit::Severity::Error => println!("[ERROR] {}", issue.message),
+				audit::Severity::Warning => println!("[WARN]  {}", issue.message),
 			}
 		}
 
-		Ok(config)
+		let score = audit::get_score(&issues);
+		let ready = score >= 80 && !issues.iter().any(|issue| matches!(issue.severity, audit::Severity::Error));
+		println!("Readiness score: {}/100, {}", score, if ready { "ready" } else { "not ready" });
+		if !ready {
+			std::process::exit(1);
+		}
+		return;
 	}
-}
 
-fn move_cursor_to_line<R: BufRead + Seek>(reader: &mut R, command_prefix: &str) -> io::Result<()> {
-	let mut line = String::new();
-
-	loop {
-		let pos = reader.seek(SeekFrom::Current(0))?;
-		let len = reader.read_line(&mut line)?;
+	let strict = env::var("CLT_STRICT").is_ok_and(|v| !v.is_empty() && v != "0");
+	comparator.show_matches = env::var("CLT_SHOW_MATCHES").is_ok_and(|v| !v.is_empty() && v != "0");
+	let compiled = match parser::compile_checked(&args[1], strict) {
+		Ok(compiled) => {
+			for deprecation in &compiled.deprecations {
+				eprintln!("Warning: {}", deprecation);
+			}
+			if let Some(warning) = parser::check_replay_hash(&args[1], &args[2], &compiled) {
+				eprintln!("Warning: {}", warning);
+			}
+			compiled
+		},
+		Err(e) => {
+			print_compile_error(&args[1], &e);
+			std::process::exit(1);
+		}
+	};
 
-		if len == 0 {
-			break;
+	let output_content = match fs::read_to_string(&args[2]) {
+		Ok(output_content) => output_content,
+		Err(e) => {
+			eprintln!("Failed to read {}: {}", args[2], e);
+			std::process::exit(1);
 		}
+	};
 
-		if line.trim() == command_prefix {
-			reader.seek(SeekFrom::Start(pos))?;
-			break;
+	let comparison = match comparator.compare_with_source(&compiled.content, &compiled.source, &output_content, &mut stdout) {
+		Ok(comparison) => comparison,
+		Err(e) => {
+			eprintln!("Failed to compare {} with {}: {}", args[1], args[2], e);
+			std::process::exit(1);
 		}
+	};
 
-		line.clear();
+	if comparison.ignored_count > 0 {
+		eprintln!("Filtered {} lines matching {}", comparison.ignored_count, IGNORE_LINES_FILE);
 	}
 
-	Ok(())
+	if comparison.has_diff {
+		std::process::exit(1);
+	}
 }
 
-fn print_diff(stdout:&mut StandardStream, line: &str, diff: Diff) {
-	let (line, color) = match diff {
-		Diff::Plus => (format!("+ {}", line.trim()), Color::Green),
-		Diff::Minus => (format!("- {}", line.trim()), Color::Red),
-	};
-	stdout.set_color(ColorSpec::new().set_fg(Some(color))).unwrap();
-	writeln!(stdout, "{}", line.trim()).unwrap();
-	stdout.reset().unwrap();
-}
\ No newline at end of file
+/// Print the error with the line of the test where it happened and the position marked
+fn print_compile_error(file: &str, e: &anyhow::Error) {
+	eprintln!("Failed to compile {}: {}", file, e);
+	if let Some(e) = e.downcast_ref::<parser::ParseError>() {
+		eprintln!("  {}", e.snippet);
+		eprintln!("  {}^", " ".repeat(e.column - 1));
+	}
+}
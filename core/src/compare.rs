@@ -0,0 +1,603 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek, SeekFrom};
+use std::path::Path;
+use anyhow::{Context, Result};
+use regex::Regex;
+use termcolor::{Color, ColorSpec, WriteColor};
+use crate::locale::LocaleNormalizer;
+use crate::pattern::PatternMatcher;
+
+pub const IGNORE_LINES_FILE: &str = ".ignore-lines";
+/// How many lines ahead the expected and actual outputs are searched for the next matching pair after a mismatch
+const ALIGN_WINDOW: usize = 16;
+/// How many lines are matched with patterns to align the outputs of one step, after that only equal lines are aligned,
+/// so a huge output that does not match at all is compared in about the time of the matching one
+const ALIGN_BUDGET: usize = 10000;
+
+enum Diff {
+	Plus,
+	Minus
+}
+
+/// Result of the comparison, the expected output with the diff is written to the output passed to compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comparison {
+	pub has_diff: bool,
+	/// Number of lines filtered out from both outputs by the ignore lines
+	pub ignored_count: usize,
+}
+
+/// Compares the replayed output with the expected one the same way cmp does
+pub struct Comparator {
+	pub matcher: PatternMatcher,
+	ignore_lines: Vec<Regex>,
+	/// Print the text consumed by each pattern after the matched lines, it is off to keep the output usable for refine
+	pub show_matches: bool,
+}
+
+impl Comparator {
+	pub fn new(matcher: PatternMatcher, ignore_lines: Vec<Regex>) -> Self {
+		Self { matcher, ignore_lines, show_matches: false }
+	}
+
+	/// Load the patterns and the lines to ignore from the files if they exist,
+	/// normalize takes the same options as CLT_NORMALIZE
+	pub fn load(patterns_file: &str, normalize: &str, ignore_lines_file: &str) -> Result<Self> {
+		let normalizer = LocaleNormalizer::new(normalize).context("Failed to parse normalization options")?;
+		let patterns_file = Path::new(patterns_file).exists().then(|| patterns_file.to_string());
+		let matcher = PatternMatcher::new(patterns_file, normalizer).context("Failed to load patterns")?;
+		let ignore_lines = load_ignore_lines(ignore_lines_file).with_context(|| format!("Failed to load {}", ignore_lines_file))?;
+		Ok(Self::new(matcher, ignore_lines))
+	}
+
+	/// Compare the compiled test with the output of its replay and write the expected output
+	/// with the differences marked by - and + to the output, so it can be used to refine the test
+	pub fn compare(&self, rec_content: &str, rep_content: &str, out: &mut impl WriteColor) -> Result<Comparison> {
+		self.compare_with_source(rec_content, rec_content, rep_content, out)
+	}
+
+	/// Same as compare, but the inputs replayed as they are in the compiled test are written as they are in the source,
+	/// the compiled test with %{NAME} of variables and secrets kept, so refine does not put their values into the test
+	/// Values of the secrets of the source are written as their tokens in outputs too
+	pub fn compare_with_source(&self, rec_content: &str, source: &str, rep_content: &str, out: &mut impl WriteColor) -> Result<Comparison> {
+		let secrets = parser::get_secrets(source)?;
+		if secrets.is_empty() {
+			return self.compare_steps(rec_content, source, rep_content, out);
+		}
+
+		// rec writes the values of the secrets to the .rep file as their tokens
+		let rep_content = parser::decrypt_secrets(rep_content)?;
+		let mut buffer = match out.supports_color() {
+			true => termcolor::Buffer::ansi(),
+			false => termcolor::Buffer::no_color(),
+		};
+		let comparison = self.compare_steps(rec_content, source, &rep_content, &mut buffer)?;
+		out.write_all(parser::hide_secrets(&String::from_utf8_lossy(buffer.as_slice()), &secrets)?.as_bytes())?;
+		Ok(comparison)
+	}
+
+	fn compare_steps(&self, rec_content: &str, source: &str, rep_content: &str, out: &mut impl WriteColor) -> Result<Comparison> {
+		let mut ignored_count = 0;
+		let input_sources = get_input_sources(rec_content, source);
+		let mut step = 0;
+		let skip_if_regex = Regex::new(parser::SKIP_IF_REGEX)?;
+		let timeout_regex = Regex::new(parser::TIMEOUT_REGEX)?;
+		let mut vars: HashMap<String, String> = HashMap::new();
+
+		// The env statement and the condition and timeout of the first step come before the first input, so print them here to keep them on refine
+		let env = parser::parse_env(rec_content).unwrap_or_default();
+		if !env.is_empty() {
+			writeln!(out, "{}", parser::ENV_STATEMENT)?;
+			for (name, value) in env {
+				writeln!(out, "{}={}", name, value)?;
+			}
+		}
+		let preamble = rec_content.lines().take_while(|line| *line != parser::COMMAND_PREFIX);
+		for line in preamble.filter(|line| skip_if_regex.is_match(line) || timeout_regex.is_match(line) || is_section_statement(line)) {
+			writeln!(out, "{}", line)?;
+		}
+
+		let mut file1_reader = BufReader::new(Cursor::new(rec_content));
+		move_cursor_to_line(&mut file1_reader, parser::COMMAND_PREFIX)?;
+
+		let mut file2_reader = BufReader::new(Cursor::new(rep_content));
+		move_cursor_to_line(&mut file2_reader, parser::COMMAND_PREFIX)?;
+
+		let mut line1 = String::new();
+		let mut line2 = String::new();
+
+		let mut lines1 = vec![];
+		let mut lines2 = vec![];
+
+		let mut files_have_diff = false;
+		loop {
+			let [read1, read2] = [
+				file1_reader.read_line(&mut line1)?,
+				file2_reader.read_line(&mut line2)?,
+			];
+
+			if read1 == 0 && read2 == 0 {
+				break;
+			}
+
+			if read1 == 0 {
+				print_diff(out, line2.trim(), Diff::Plus)?;
+			} else if read2 == 0 {
+				print_diff(out, line1.trim(), Diff::Minus)?;
+			} else {
+				writeln!(out, "{}", line2.trim())?;
+			}
+
+			// Change the current mode if we are in output section or not
+			let mut r1 = read1;
+			while r1 > 0 && !parser::is_output_line(line1.trim()) {
+				line1.clear();
+				r1 = file1_reader.read_line(&mut line1)?;
+				if read2 == 0 {
+					print_diff(out, line1.trim(), Diff::Minus)?;
+				}
+			}
+
+			// Keep the output statement with the check to print it instead of the plain one from the replay
+			let output_statement = line1.trim().to_string();
+			let output_check = parser::parse_output_check(&output_statement).unwrap_or(None);
+			let output_file = output_statement.starts_with("––– output: file=");
+
+			lines1.clear();
+			while r1 > 0 {
+				line1.clear();
+				r1 = file1_reader.read_line(&mut line1)?;
+				if r1 == 0 || line1.trim() == parser::COMMAND_PREFIX {
+					break;
+				}
+				if parser::is_duration_line(&line1) {
+					continue;
+				}
+				lines1.push(line1.trim().to_string());
+			}
+
+			let inputs = input_sources.get(step).map_or(&[][..], Vec::as_slice);
+			step += 1;
+			let mut r2 = read2;
+			let mut input_index = 0;
+			while r2 > 0 && line2.trim() != parser::COMMAND_SEPARATOR {
+				line2.clear();
+				r2 = file2_reader.read_line(&mut line2)?;
+				if read1 == 0 {
+					print_diff(out, line2.trim(), Diff::Plus)?;
+				} else if (output_check.is_some() || output_file) && line2.trim() == parser::COMMAND_SEPARATOR {
+					writeln!(out, "{}", output_statement)?;
+				} else {
+					match inputs.get(input_index) {
+						Some((compiled, source)) if *compiled == line2.trim() => writeln!(out, "{}", source)?,
+						_ => writeln!(out, "{}", line2.trim())?,
+					}
+				}
+				input_index += 1;
+			}
+
+			lines2.clear();
+			while r2 > 0 {
+				line2.clear();
+				r2 = file2_reader.read_line(&mut line2)?;
+				if r2 == 0 || line2.trim() == parser::COMMAND_PREFIX {
+					break;
+				}
+				if parser::is_duration_line(&line2) {
+					continue;
+				}
+				lines2.push(line2.trim().to_string());
+			}
+
+			// The step skipped by its condition has nothing to compare, so the expected output is kept as is
+			if lines2.iter().any(|line| line == parser::SKIPPED_LINE) {
+				for line in &lines1 {
+					writeln!(out, "{}", line)?;
+				}
+				continue;
+			}
+
+			// rec saves the exit code of each command, but it is compared only when the test expects it
+			let expected_exit = lines1.iter().position(|line| parser::parse_exit_line(line).is_some()).map(|pos| lines1.remove(pos));
+			let actual_exit = lines2.iter().position(|line| parser::parse_exit_line(line).is_some()).map(|pos| lines2.remove(pos));
+
+			// Noisy lines are filtered out from both outputs before comparing
+			if !self.ignore_lines.is_empty() {
+				let count = lines1.len() + lines2.len();
+				lines1.retain(|line| !self.ignore_lines.iter().any(|re| re.is_match(line)));
+				lines2.retain(|line| !self.ignore_lines.iter().any(|re| re.is_match(line)));
+				ignored_count += count - lines1.len() - lines2.len();
+			}
+
+			// Stderr recorded apart is compared on its own and only when the test expects it
+			let expected_error = split_error_section(&mut lines1);
+			let actual_error = split_error_section(&mut lines2);
+
+			// The expected output loaded from the file stays in it, so only the diff is printed under the statement
+			if output_file {
+				let mut buffer = match out.supports_color() {
+					true => termcolor::Buffer::ansi(),
+					false => termcolor::Buffer::no_color(),
+				};
+				let has_diff = self.compare_output(&mut buffer, &lines1, std::mem::take(&mut lines2), None, &mut vars)?;
+				if has_diff {
+					out.write_all(buffer.as_slice())?;
+				}
+				files_have_diff |= has_diff;
+			} else {
+				files_have_diff |= self.compare_output(out, &lines1, std::mem::take(&mut lines2), output_check.as_ref(), &mut vars)?;
+			}
+			if let Some(expected_error) = expected_error {
+				writeln!(out, "{}", parser::ERROR_STATEMENT)?;
+				files_have_diff |= self.compare_output(out, &expected_error, actual_error.unwrap_or_default(), None, &mut vars)?;
+			}
+			files_have_diff |= has_exit_diff(out, &expected_exit, &actual_exit)?;
+		}
+
+		Ok(Comparison { has_diff: files_have_diff, ignored_count })
+	}
+
+	/// Compare the expected lines of the output or the error section of the step with the actual ones
+	/// and write them with the differences marked, variables captured by patterns are added to vars
+	fn compare_output(
+		&self,
+		out: &mut impl WriteColor,
+		lines1: &[String],
+		mut lines2: Vec<String>,
+		output_check: Option<&parser::OutputCheck>,
+		vars: &mut HashMap<String, String>,
+	) -> Result<bool> {
+		let assert_regex = Regex::new(parser::ASSERT_REGEX)?;
+		let resize_regex = Regex::new(parser::RESIZE_REGEX)?;
+		let skip_if_regex = Regex::new(parser::SKIP_IF_REGEX)?;
+		let timeout_regex = Regex::new(parser::TIMEOUT_REGEX)?;
+		let mut has_diff = false;
+		let mut align_budget = ALIGN_BUDGET;
+
+		// All lines of the output checked for containing them are anchors
+		if output_check == Some(&parser::OutputCheck::Contains) {
+			return self.compare_anchors(out, lines1, &lines2, |line| (!line.is_empty()).then_some(line));
+		}
+
+		if let Some(output_check) = output_check {
+			while lines2.last().is_some_and(|line| line.is_empty()) {
+				lines2.pop();
+			}
+
+			let diff = output_check.check(lines1, &lines2);
+			if let Some((expected, actual)) = &diff {
+				print_diff(out, expected, Diff::Minus)?;
+				print_diff(out, actual, Diff::Plus)?;
+			}
+
+			// The expected hash or encoded output is printed in the diff already
+			let skip_hash = diff.is_some() && matches!(output_check, parser::OutputCheck::Sha256 | parser::OutputCheck::Base64);
+			for line in lines1 {
+				if !skip_hash || line.is_empty() {
+					writeln!(out, "{}", line)?;
+				}
+			}
+			return Ok(diff.is_some());
+		}
+
+		if self.matcher.normalizer.collation && !lines1.iter().any(|line| line == parser::IGNORE_START || line == parser::SKIP_LINES) {
+			lines2 = self.matcher.reorder_lines(lines1, lines2)?;
+		}
+
+		// Only anchor lines of the sparse output should appear in the actual output in the same order
+		if lines1.first().is_some_and(|line| line == parser::ANCHORS) {
+			return self.compare_anchors(out, lines1, &lines2, |line| line.strip_prefix(parser::ANCHOR_PREFIX));
+		}
+
+		let (mut i, mut j) = (0, 0);
+		while i < lines1.len() || j < lines2.len() {
+			// Skip the region of the output between ignore markers or matched by the skip lines pattern
+			// up to the line that matches the first expected line after it
+			let skipped_end = match lines1.get(i).map(String::as_str) {
+				Some(parser::IGNORE_START) => Some(lines1[i..].iter().position(|line| line == parser::IGNORE_END)
+					.map_or(lines1.len(), |pos| i + pos + 1)),
+				Some(parser::SKIP_LINES) => Some(i + 1),
+				_ => None,
+			};
+			if let Some(end) = skipped_end {
+				for line in &lines1[i..end] {
+					writeln!(out, "{}", line)?;
+				}
+				i = end;
+
+				match lines1.get(i) {
+					Some(next) if next != parser::IGNORE_START && next != parser::SKIP_LINES => {
+						while j < lines2.len() && self.matcher.has_diff(next.to_string(), lines2[j].to_string())? {
+							j += 1;
+						}
+					},
+					Some(_) => {},
+					None => j = lines2.len(),
+				}
+				continue;
+			}
+
+			// Assertions are evaluated with the variables captured so far and do not consume output lines
+			if let Some(caps) = lines1.get(i).and_then(|line| assert_regex.captures(line)) {
+				match parser::evaluate_assertion(&caps[1], vars) {
+					Ok(true) => writeln!(out, "{}", lines1[i])?,
+					Ok(false) => {
+						print_diff(out, &lines1[i], Diff::Minus)?;
+						print_diff(out, &format!("assertion failed: {}", &caps[1]), Diff::Plus)?;
+						has_diff = true;
+					},
+					Err(e) => {
+						print_diff(out, &lines1[i], Diff::Minus)?;
+						print_diff(out, &format!("assertion error: {}", e), Diff::Plus)?;
+						has_diff = true;
+					},
+				}
+				i += 1;
+				continue;
+			}
+
+			// The terminal is resized and the condition and timeout of the next step are handled by rec between steps,
+			// and the setup and teardown sections are already expanded, so there is nothing in the output to compare
+			let is_step_statement = |line: &String| {
+				resize_regex.is_match(line) || skip_if_regex.is_match(line) || timeout_regex.is_match(line) || is_section_statement(line)
+			};
+			if lines1.get(i).is_some_and(is_step_statement) {
+				writeln!(out, "{}", lines1[i])?;
+				i += 1;
+				continue;
+			}
+
+			// Lines inserted or removed in the middle do not shift the rest of the output into the diff,
+			// so refine keeps the patterns of the lines that still match and marks only the changed ones
+			if i < lines1.len() && j < lines2.len() && self.matcher.has_diff(lines1[i].clone(), lines2[j].clone())? {
+				if let Some((skip1, skip2)) = self.find_alignment(&lines1[i..], &lines2[j..], &mut align_budget, |line| is_step_statement(line) || assert_regex.is_match(line))? {
+					for line in &lines1[i..i + skip1] {
+						print_diff(out, line.trim(), Diff::Minus)?;
+					}
+					for line in &lines2[j..j + skip2] {
+						print_diff(out, line.trim(), Diff::Plus)?;
+					}
+					i += skip1;
+					j += skip2;
+					has_diff = true;
+					continue;
+				}
+			}
+
+			let (line1, line2) = (lines1.get(i), lines2.get(j));
+			i += 1;
+			j += 1;
+			match (line1, line2) {
+				(None, Some(line)) => {
+					print_diff(out, line.trim(), Diff::Plus)?;
+					has_diff = true;
+				},
+				(Some(line), None) => {
+					print_diff(out, line.trim(), Diff::Minus)?;
+					has_diff = true;
+				},
+				(Some(line1), Some(line2)) => {
+					match self.matcher.match_line(line1.to_string(), line2.to_string())? {
+						Some(captured) => {
+							vars.extend(captured);
+							writeln!(out, "{}", line1.trim())?;
+							if let Some(annotated) = self.show_matches.then(|| self.matcher.annotate_line(line1, line2)).flatten() {
+								print_matches(out, &annotated)?;
+							}
+						},
+						None => {
+							print_diff(out, line1.trim(), Diff::Minus)?;
+							print_diff(out, line2.trim(), Diff::Plus)?;
+							has_diff = true;
+						},
+					}
+				},
+				_ => {}
+			}
+		}
+
+		Ok(has_diff)
+	}
+
+	/// Find the closest pair of matching lines after the mismatched first ones within the window
+	/// and return how many expected and actual lines to mark as removed and added before it,
+	/// pairs with the same distance prefer changed lines over removed or added ones
+	/// The search stops at the statements of the expected output, which are handled by the caller
+	/// Equal lines without patterns match right away, others take the budget of the step until it runs out
+	fn find_alignment(
+		&self,
+		lines1: &[String],
+		lines2: &[String],
+		budget: &mut usize,
+		is_statement: impl Fn(&String) -> bool,
+	) -> Result<Option<(usize, usize)>> {
+		let is_marker = |line: &String| line == parser::IGNORE_START || line == parser::SKIP_LINES || is_statement(line);
+		let max1 = lines1.iter().take(ALIGN_WINDOW + 1).position(is_marker).unwrap_or(lines1.len().min(ALIGN_WINDOW + 1));
+		let max2 = lines2.len().min(ALIGN_WINDOW + 1);
+		for distance in 1..max1 + max2 {
+			let mut skips: Vec<(usize, usize)> = (0..=distance)
+				.map(|skip1| (skip1, distance - skip1))
+				.filter(|&(skip1, skip2)| skip1 < max1 && skip2 < max2)
+				.collect();
+			skips.sort_by_key(|&(skip1, skip2)| skip1.abs_diff(skip2));
+			for (skip1, skip2) in skips {
+				let (line1, line2) = (&lines1[skip1], &lines2[skip2]);
+				if line1 == line2 && !line1.contains("%{") && !line1.contains("#!/") {
+					return Ok(Some((skip1, skip2)));
+				}
+				if *budget == 0 {
+					continue;
+				}
+				*budget -= 1;
+				if !self.matcher.has_diff(line1.clone(), line2.clone())? {
+					return Ok(Some((skip1, skip2)));
+				}
+			}
+		}
+		Ok(None)
+	}
+
+	/// Find the anchors of the expected lines in the actual output in the same order with anything between them,
+	/// the expected lines are printed as is and the anchors that are not found as the diff
+	fn compare_anchors<'a>(
+		&self,
+		out: &mut impl WriteColor,
+		expected: &'a [String],
+		actual: &[String],
+		get_anchor: impl Fn(&'a str) -> Option<&'a str>,
+	) -> Result<bool> {
+		let mut has_diff = false;
+		let mut j = 0;
+		for line in expected {
+			let Some(anchor) = get_anchor(line) else {
+				writeln!(out, "{}", line)?;
+				continue;
+			};
+
+			let mut found = None;
+			for (pos, line2) in actual[j..].iter().enumerate() {
+				if !self.matcher.has_diff(anchor.to_string(), line2.to_string())? {
+					found = Some(pos);
+					break;
+				}
+			}
+			match found {
+				Some(pos) => {
+					writeln!(out, "{}", line)?;
+					j += pos + 1;
+				},
+				None => {
+					print_diff(out, line, Diff::Minus)?;
+					has_diff = true;
+				},
+			}
+		}
+		Ok(has_diff)
+	}
+}
+
+/// Load regexes of the lines to filter out from outputs, one per line, lines starting with # are comments
+pub fn load_ignore_lines(file_name: &str) -> Result<Vec<Regex>> {
+	if !Path::new(file_name).exists() {
+		return Ok(Vec::new());
+	}
+
+	let mut ignore_lines = Vec::new();
+	for line in BufReader::new(File::open(file_name)?).lines() {
+		let line = line?;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		ignore_lines.push(Regex::new(line)?);
+	}
+
+	Ok(ignore_lines)
+}
+
+fn move_cursor_to_line<R: BufRead + Seek>(reader: &mut R, command_prefix: &str) -> std::io::Result<()> {
+	let mut line = String::new();
+
+	loop {
+		let pos = reader.stream_position()?;
+		let len = reader.read_line(&mut line)?;
+
+		if len == 0 {
+			break;
+		}
+
+		if line.trim() == command_prefix {
+			reader.seek(SeekFrom::Start(pos))?;
+			break;
+		}
+
+		line.clear();
+	}
+
+	Ok(())
+}
+
+/// Start and end statements of the setup and teardown sections kept in the compiled test
+fn is_section_statement(line: &str) -> bool {
+	line == parser::SETUP_START || line == parser::TEARDOWN_START || line == parser::PARALLEL_END
+}
+
+/// Input lines of each step of the compiled test paired with the lines of the source they were compiled from,
+/// none when the source does not have the same lines
+fn get_input_sources<'a>(rec_content: &'a str, source: &'a str) -> Vec<Vec<(&'a str, &'a str)>> {
+	if rec_content.lines().count() != source.lines().count() {
+		return Vec::new();
+	}
+
+	let mut steps: Vec<Vec<(&str, &str)>> = Vec::new();
+	let mut in_input = false;
+	for (line, source_line) in rec_content.lines().zip(source.lines()) {
+		if line == parser::COMMAND_PREFIX {
+			steps.push(Vec::new());
+			in_input = true;
+		} else if parser::is_output_line(line) {
+			in_input = false;
+		} else if let Some(inputs) = steps.last_mut().filter(|_| in_input) {
+			inputs.push((line.trim(), source_line.trim()));
+		}
+	}
+	steps
+}
+
+/// Split the lines after the error statement off the output, None when the output has no error section
+fn split_error_section(lines: &mut Vec<String>) -> Option<Vec<String>> {
+	let pos = lines.iter().position(|line| line == parser::ERROR_STATEMENT)?;
+	let error = lines.split_off(pos + 1);
+	lines.pop();
+	Some(error)
+}
+
+/// Print the expected exit statement of the step and check it against the one saved by rec
+fn has_exit_diff(out: &mut impl WriteColor, expected: &Option<String>, actual: &Option<String>) -> Result<bool> {
+	let Some(expected) = expected else {
+		return Ok(false);
+	};
+
+	if actual.as_ref().and_then(|line| parser::parse_exit_line(line)) == parser::parse_exit_line(expected) {
+		writeln!(out, "{}", expected)?;
+		return Ok(false);
+	}
+
+	print_diff(out, expected, Diff::Minus)?;
+	print_diff(out, actual.as_deref().unwrap_or("exit code is unknown"), Diff::Plus)?;
+	Ok(true)
+}
+
+/// Print the actual line with the text consumed by patterns marked, prefixed with ~ so it is not taken for the diff
+fn print_matches(out: &mut impl WriteColor, line: &str) -> std::io::Result<()> {
+	out.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+	writeln!(out, "~ {}", line.trim())?;
+	out.reset()
+}
+
+fn print_diff(out: &mut impl WriteColor, line: &str, diff: Diff) -> std::io::Result<()> {
+	let (line, color) = match diff {
+		Diff::Plus => (format!("+ {}", line.trim()), Color::Green),
+		Diff::Minus => (format!("- {}", line.trim()), Color::Red),
+	};
+	out.set_color(ColorSpec::new().set_fg(Some(color)))?;
+	writeln!(out, "{}", line.trim())?;
+	out.reset()
+}
@@ -0,0 +1,52 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Record, replay and compare CLT tests from Rust without running the rec and cmp binaries,
+//! so other projects can run the tests in their own integration test harness
+//!
+//! Replaying and recording run bash in a pseudo terminal on the host and need the shell feature,
+//! which is enabled by default
+
+pub mod compare;
+pub mod locale;
+pub mod pattern;
+pub mod report;
+#[cfg(feature = "shell")]
+pub mod replay;
+#[cfg(feature = "shell")]
+pub mod shell;
+#[cfg(feature = "shell")]
+pub mod testing;
+
+pub use compare::{Comparator, Comparison};
+pub use report::{Report, TestResult};
+#[cfg(feature = "shell")]
+pub use replay::{record, replay, Options};
+
+/// Replay the test and compare its output with the expected one
+#[cfg(feature = "shell")]
+pub async fn run_test(rec_file: &str, options: &Options, comparator: &Comparator) -> anyhow::Result<TestResult> {
+	let output = replay(rec_file, options).await?;
+	let compiled = parser::compile_checked(rec_file, options.strict)?;
+	let mut diff = termcolor::Buffer::no_color();
+	let comparison = comparator.compare_with_source(&compiled.content, &compiled.source, &output, &mut diff)?;
+	Ok(TestResult {
+		file: rec_file.to_string(),
+		passed: !comparison.has_diff,
+		output,
+		diff: String::from_utf8_lossy(diff.as_slice()).to_string(),
+	})
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use unicode_width::UnicodeWidthChar;
+
+/// Localized month names mapped to the English abbreviation they normalize to
+const MONTHS: &[(&str, &[&str])] = &[
+	("Jan", &["january", "januar", "janvier", "janv", "enero", "gennaio", "janeiro", "январь", "января", "янв"]),
+	("Feb", &["february", "februar", "février", "févr", "febrero", "febbraio", "fevereiro", "февраль", "февраля", "фев"]),
+	("Mar", &["march", "märz", "mär", "mars", "marzo", "março", "март", "марта", "мар"]),
+	("Apr", &["april", "avril", "avr", "abril", "aprile", "апрель", "апреля", "апр"]),
+	("May", &["mai", "mayo", "maggio", "maio", "май", "мая"]),
+	("Jun", &["june", "juni", "juin", "junio", "giugno", "junho", "июнь", "июня", "июн"]),
+	("Jul", &["july", "juli", "juillet", "juil", "julio", "luglio", "julho", "июль", "июля", "июл"]),
+	("Aug", &["august", "août", "agosto", "август", "августа", "авг"]),
+	("Sep", &["september", "septembre", "sept", "septiembre", "settembre", "setembro", "сентябрь", "сентября", "сен"]),
+	("Oct", &["october", "oktober", "okt", "octobre", "octubre", "ottobre", "outubro", "октябрь", "октября", "окт"]),
+	("Nov", &["november", "novembre", "noviembre", "novembro", "ноябрь", "ноября", "ноя"]),
+	("Dec", &["december", "dezember", "dez", "décembre", "déc", "diciembre", "dicembre", "dezembro", "декабрь", "декабря", "дек"]),
+];
+
+/// Normalizes locale dependent parts of the output before comparison
+/// Options come from the CLT_NORMALIZE env variable as a comma separated list
+pub struct LocaleNormalizer {
+	pub decimal: bool,
+	pub months: bool,
+	pub collation: bool,
+	pub width: bool,
+	number_regex: Regex,
+	word_regex: Regex,
+	cell_regex: Regex,
+	border_regex: Regex,
+	table_border_regex: Regex,
+}
+
+impl LocaleNormalizer {
+	pub fn new(options: &str) -> Result<Self> {
+		let mut normalizer = Self {
+			decimal: false,
+			months: false,
+			collation: false,
+			width: false,
+			number_regex: Regex::new(r"[0-9]+(?:[.,'\x{00A0}\x{202F}][0-9]+)*")?,
+			word_regex: Regex::new(r"\p{L}+\.?")?,
+			cell_regex: Regex::new(r"[ \t\x{3000}]*\|[ \t\x{3000}]*")?,
+			border_regex: Regex::new(r"-*\+-*")?,
+			table_border_regex: Regex::new(r"^\+(?:-+\+)+$")?,
+		};
+
+		for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+			match option {
+				"decimal" => normalizer.decimal = true,
+				"months" => normalizer.months = true,
+				"collation" => normalizer.collation = true,
+				"width" => normalizer.width = true,
+				"all" => {
+					normalizer.decimal = true;
+					normalizer.months = true;
+					normalizer.collation = true;
+					normalizer.width = true;
+				},
+				_ => return Err(anyhow!("Unknown normalization option: {}", option)),
+			}
+		}
+
+		Ok(normalizer)
+	}
+
+	/// Apply enabled line normalizations, collation is handled on the whole output
+	pub fn normalize(&self, line: &str) -> String {
+		let mut line = line.to_string();
+		if self.decimal {
+			line = self.number_regex.replace_all(&line, |caps: &regex::Captures| {
+				normalize_number(&caps[0])
+			}).into_owned();
+		}
+
+		if self.months {
+			line = self.word_regex.replace_all(&line, |caps: &regex::Captures| {
+				let word = caps[0].trim_end_matches('.').to_lowercase();
+				match MONTHS.iter().find(|(_, names)| names.contains(&word.as_str())) {
+					Some((month, _)) => month.to_string(),
+					None => caps[0].to_string(),
+				}
+			}).into_owned();
+		}
+
+		if self.width {
+			line = self.normalize_width(&line);
+		}
+
+		line
+	}
+
+	/// Tools pad table cells by the width they think the characters take, and they disagree on
+	/// multibyte and fullwidth characters like CJK ones, so the padding of the rows with such characters
+	/// and the border lengths are dropped, e.g. both "| 日本 |" and "| 日本   |" become "|日本|" and "+------+" becomes "+-+"
+	/// Rows where each character takes one column keep their padding, so their alignment is still compared,
+	/// and pipes and pluses in other lines keep their spaces and dashes
+	fn normalize_width(&self, line: &str) -> String {
+		let trimmed = line.trim();
+		if self.table_border_regex.is_match(trimmed) {
+			return self.border_regex.replace_all(trimmed, |caps: &regex::Captures| {
+				let border = &caps[0];
+				let left = if border.starts_with('-') { "-" } else { "" };
+				let right = if border.ends_with('-') { "-" } else { "" };
+				format!("{}+{}", left, right)
+			}).into_owned();
+		}
+
+		let has_wide_chars = trimmed.chars().any(|c| c.width().is_some_and(|width| width != 1));
+		if has_wide_chars && trimmed.len() > 1 && trimmed.starts_with('|') && trimmed.ends_with('|') {
+			return self.cell_regex.replace_all(trimmed, "|").into_owned();
+		}
+
+		line.to_string()
+	}
+}
+
+/// Convert the number to the C locale form without grouping and with the dot as decimal separator
+/// When both dot and comma are used, the last one is the decimal separator, e.g. 1.234,5 and 1,234.5
+/// A single comma is treated as the decimal separator, so 1,5 and 1.5 are equal
+fn normalize_number(number: &str) -> String {
+	let number: String = number.chars().filter(|c| !matches!(c, '\'' | '\u{00A0}' | '\u{202F}')).collect();
+	let dots = number.matches('.').count();
+	let commas = number.matches(',').count();
+	if dots > 0 && commas > 0 {
+		let decimal_pos = number.rfind(['.', ',']).unwrap();
+		let integer: String = number[..decimal_pos].chars().filter(char::is_ascii_digit).collect();
+		return format!("{}.{}", integer, &number[decimal_pos + 1..]);
+	}
+
+	let separators = dots + commas;
+	if separators > 1 && is_grouped(&number) {
+		return number.replace([',', '.'], "");
+	}
+
+	if commas == 1 {
+		return number.replace(',', ".");
+	}
+
+	number
+}
+
+/// Check that the number is grouped by thousands like 1,234,567
+fn is_grouped(number: &str) -> bool {
+	let groups: Vec<&str> = number.split([',', '.']).collect();
+	(1..=3).contains(&groups[0].len()) && groups[1..].iter().all(|g| g.len() == 3)
+}
@@ -0,0 +1,487 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use crate::locale::LocaleNormalizer;
+
+enum MatchingPart {
+	Static(String),
+	Pattern(String),
+}
+
+/// Part of the expected line ready to match, the static text is normalized and the regex is compiled
+enum CompiledPart {
+	Static(String),
+	Pattern(Regex),
+}
+
+/// Expected line compiled once to match it against any number of actual lines
+struct CompiledLine {
+	/// Line with the patterns replaced with their regexes to build the line regex from when it is needed
+	line: String,
+	/// Regex of the whole line with the patterns wrapped into span groups and the number of them,
+	/// none when they can not be put into one regex
+	line_regex: OnceLock<(Option<Regex>, usize)>,
+	parts: Vec<CompiledPart>,
+}
+
+/// Number of compiled expected lines and patterns kept by the matcher, all of them are dropped when it is reached,
+/// which is rare, as they are looked up again mostly within the same step
+const COMPILED_LINES_LIMIT: usize = 10000;
+
+/// Prefix of the groups wrapping patterns in the regex of the whole line to find the spans they consume
+const SPAN_GROUP: &str = "clt_span_";
+
+/// Pattern variable like %{NAME:1:2:lazy:i>var} with the name, the parameters, the modifier,
+/// the flags of the regex and the name of the variable to capture the value into
+/// Tools finding patterns in the expected output should use it to recognize them the same way cmp does
+pub const VAR_REGEX: &str = r"%\{([A-Z]{1}[A-Z_0-9]*)((?::[0-9]+)*)(?::(greedy|lazy|anchored))?(?::([is]+))?(?:>([a-z_][a-z_0-9]*))?\}";
+
+/// Raw regex anchored to the end of the previous part with ^ after the inline flags like (?i) it may start with
+const ANCHORED_REGEX: &str = r"^((?:\(\?[a-zA-Z\-]+\))*)\^";
+
+/// Values captured by named patterns and the spans of the actual line consumed by each pattern
+type MatchedParts = (HashMap<String, String>, Vec<Range<usize>>);
+
+/// Matches the expected lines with %{PATTERN} variables and regexes against the actual output
+pub struct PatternMatcher {
+	pub config: HashMap<String, String>,
+	var_regex: Regex,
+	param_regex: Regex,
+	anchored_regex: Regex,
+	pub normalizer: LocaleNormalizer,
+	compiled_lines: Mutex<HashMap<String, Arc<CompiledLine>>>,
+	compiled_patterns: Mutex<HashMap<String, Regex>>,
+}
+
+impl PatternMatcher {
+	/// Initialize struct by using file name of the variables description for patterns
+	/// If the option is none, we just will have empty map of keys for pattersn
+	/// And in that case we will use only raw regexes to validate
+	pub fn new(file_name: Option<String>, normalizer: LocaleNormalizer) -> Result<Self> {
+		let var_regex = Regex::new(VAR_REGEX)?;
+		let param_regex = Regex::new(r"\{([A-Z])(?:,([A-Z])?)?\}")?;
+		let anchored_regex = Regex::new(ANCHORED_REGEX)?;
+		let mut matcher = Self {
+			config: HashMap::new(),
+			var_regex,
+			param_regex,
+			anchored_regex,
+			normalizer,
+			compiled_lines: Mutex::new(HashMap::new()),
+			compiled_patterns: Mutex::new(HashMap::new()),
+		};
+
+		if let Some(file_name) = file_name {
+			let raw_config = Self::parse_config(file_name)?;
+			for key in raw_config.keys() {
+				let pattern = matcher.expand_pattern(key, &raw_config, &mut Vec::new())?;
+				matcher.config.insert(key.to_string(), format!("#!/{}/!#", pattern));
+			}
+		}
+
+		// Expected outputs keep runtime variables like %{ALLOC_PORT}, they match the values set by the runner for this run
+		for (name, env_name) in parser::RUNTIME_VARIABLES {
+			if let Ok(value) = std::env::var(env_name) {
+				matcher.config.insert(name.to_string(), format!("#!/{}/!#", regex::escape(&value)));
+			}
+		}
+
+		Ok(matcher)
+	}
+
+	/// Validate line from .rec file and line from .rep file
+	/// by using open regex patterns and matched variables
+	/// and return true or false in case if we have diff or not
+	/// Fails when a regex of the expected line is invalid
+	pub fn has_diff(&self, rec_line: String, rep_line: String) -> Result<bool> {
+		Ok(self.match_line(rec_line, rep_line)?.is_none())
+	}
+
+	/// Match the line from .rec file against the line from .rep file
+	/// and return the values of named groups captured by patterns like %{NUMBER>rows} if they match
+	/// Locale normalization applies to the static parts only to keep the regexes intact
+	pub fn match_line(&self, rec_line: String, rep_line: String) -> Result<Option<HashMap<String, String>>> {
+		let rep_line = self.normalizer.normalize(&rep_line);
+		Ok(self.match_parts(rec_line, &rep_line)?.map(|(captured, _)| captured))
+	}
+
+	/// Mark the text of the actual line consumed by each pattern of the expected line like [%{NUMBER}=42],
+	/// so it is obvious when a greedy pattern swallows more than intended
+	/// Returns None when the lines do not match or the expected line has no patterns
+	pub fn annotate_line(&self, rec_line: &str, rep_line: &str) -> Option<String> {
+		let rep_line = self.normalizer.normalize(rep_line);
+		let (_, spans) = self.match_parts(rec_line.to_string(), &rep_line).ok().flatten()?;
+		if spans.is_empty() {
+			return None;
+		}
+
+		// Patterns are labeled as written, unknown %{NAME} variables are static text and have no span
+		let label_regex = Regex::new(&format!("{}|#!/.*?/!#", self.var_regex.as_str())).ok()?;
+		let mut labels: Vec<&str> = label_regex.captures_iter(rec_line)
+			.filter(|caps| caps.get(1).is_none_or(|name| self.config.contains_key(name.as_str())))
+			.map(|caps| caps.get(0).unwrap().as_str())
+			.collect();
+		// Regexes of patterns may contain the markers themselves, then spans are shown without labels
+		if labels.len() != spans.len() {
+			labels.clear();
+		}
+
+		let mut annotated = String::new();
+		let mut last_index = 0;
+		for (i, span) in spans.into_iter().enumerate() {
+			annotated.push_str(&rep_line[last_index..span.start]);
+			match labels.get(i) {
+				Some(label) => annotated.push_str(&format!("[{}={}]", label, &rep_line[span.clone()])),
+				None => annotated.push_str(&format!("[{}]", &rep_line[span.clone()])),
+			}
+			last_index = span.end;
+		}
+		annotated.push_str(&rep_line[last_index..]);
+		Some(annotated)
+	}
+
+	/// Match the expected line against the normalized actual line
+	/// and return the captured values with the spans of the actual line consumed by each pattern
+	fn match_parts(&self, rec_line: String, rep_line: &str) -> Result<Option<MatchedParts>> {
+		let compiled = self.compile_line(&rec_line)?;
+		// The first match of each pattern is what the line regex prefers too, so it is tried first to not compile
+		// the line regex for most lines, and lines that pass with it keep passing,
+		// e.g. when patterns of .patterns define the same named groups that can not be put into one regex
+		if let Some(matched) = Self::match_first(&compiled.parts, rep_line) {
+			return Ok(Some(matched));
+		}
+		if !Self::has_static_parts(&compiled.parts, rep_line) {
+			return Ok(None);
+		}
+		let (line_regex, span_count) = compiled.line_regex.get_or_init(|| self.get_line_regex(&self.split_into_parts(&compiled.line)));
+		Ok(line_regex.as_ref().and_then(|line_regex| Self::match_backtracking(line_regex, *span_count, rep_line)))
+	}
+
+	/// Compile the expected line or take the one compiled before, as the same lines are matched
+	/// against many actual ones when outputs are aligned, reordered or searched for anchors
+	/// Regexes of the parts are compiled before matching, so an invalid one fails whatever the actual line is
+	fn compile_line(&self, rec_line: &str) -> Result<Arc<CompiledLine>> {
+		if let Some(compiled) = self.compiled_lines.lock().unwrap_or_else(PoisonError::into_inner).get(rec_line) {
+			return Ok(compiled.clone());
+		}
+
+		let line = self.replace_vars_to_patterns(rec_line.to_string());
+		let parts = self.split_into_parts(&line).iter()
+			.map(|part| Ok(match part {
+				MatchingPart::Static(static_part) => CompiledPart::Static(self.normalizer.normalize(static_part)),
+				MatchingPart::Pattern(pattern) => CompiledPart::Pattern(self.compile_pattern(pattern)?),
+			}))
+			.collect::<Result<Vec<_>>>()?;
+
+		let compiled = Arc::new(CompiledLine { line, line_regex: OnceLock::new(), parts });
+		let mut compiled_lines = self.compiled_lines.lock().unwrap_or_else(PoisonError::into_inner);
+		if compiled_lines.len() >= COMPILED_LINES_LIMIT {
+			compiled_lines.clear();
+		}
+		compiled_lines.insert(rec_line.to_string(), compiled.clone());
+		Ok(compiled)
+	}
+
+	/// Compile the regex of the pattern or take the one compiled before, like %{NUMBER} used in many lines
+	fn compile_pattern(&self, pattern: &str) -> Result<Regex> {
+		let mut compiled_patterns = self.compiled_patterns.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some(regex) = compiled_patterns.get(pattern) {
+			return Ok(regex.clone());
+		}
+
+		let regex = Regex::new(pattern).map_err(|e| anyhow!("invalid regex #!/{}/!#: {}", pattern, e))?;
+		if compiled_patterns.len() >= COMPILED_LINES_LIMIT {
+			compiled_patterns.clear();
+		}
+		compiled_patterns.insert(pattern.to_string(), regex.clone());
+		Ok(regex)
+	}
+
+	/// Regex of the whole line with the number of patterns wrapped into span groups, so a pattern gives back
+	/// what the static parts after it need, e.g. #!/.*/!#:9312 matches host:9312,
+	/// the pattern starts after some text unless it begins with ^
+	/// None when the patterns can not be put into one regex
+	fn get_line_regex(&self, parts: &[MatchingPart]) -> (Option<Regex>, usize) {
+		let mut line_regex = String::from("^");
+		let mut count = 0;
+		for part in parts {
+			match part {
+				MatchingPart::Static(static_part) => line_regex.push_str(&regex::escape(&self.normalizer.normalize(static_part))),
+				MatchingPart::Pattern(pattern) => {
+					let (skip, pattern) = match self.strip_anchor(pattern) {
+						Some(pattern) => ("", pattern),
+						None => ("(?:.*?)", pattern.to_string()),
+					};
+					line_regex.push_str(&format!("{}(?P<{}{}>{})", skip, SPAN_GROUP, count, pattern));
+					count += 1;
+				},
+			}
+		}
+		line_regex.push('$');
+		(Regex::new(&line_regex).ok(), count)
+	}
+
+	/// Match the whole line with one regex, so a pattern gives back what the static parts after it need
+	fn match_backtracking(line_regex: &Regex, span_count: usize, rep_line: &str) -> Option<MatchedParts> {
+		let caps = line_regex.captures(rep_line)?;
+		let spans = (0..span_count)
+			.map(|i| caps.name(&format!("{}{}", SPAN_GROUP, i)).map(|m| m.range()))
+			.collect::<Option<Vec<_>>>()?;
+		let captured = line_regex.capture_names().flatten()
+			.filter(|name| !name.starts_with(SPAN_GROUP))
+			.filter_map(|name| caps.name(name).map(|value| (name.to_string(), value.as_str().to_string())))
+			.collect();
+		Some((captured, spans))
+	}
+
+	/// The raw regex without ^ when it is anchored, the inline flags before it are kept,
+	/// so #!/(?i)^warning/!# is anchored like #!/^(?i)warning/!#
+	fn strip_anchor(&self, pattern: &str) -> Option<String> {
+		let caps = self.anchored_regex.captures(pattern)?;
+		Some(format!("{}{}", &caps[1], &pattern[caps[0].len()..]))
+	}
+
+	/// Check that the static parts are in the actual line in their order, the first one at the start,
+	/// as the line regex needs it, so lines that do not match at all do not build it
+	fn has_static_parts(parts: &[CompiledPart], rep_line: &str) -> bool {
+		let mut rest = rep_line;
+		for (i, part) in parts.iter().enumerate() {
+			let CompiledPart::Static(static_part) = part else {
+				continue;
+			};
+			match rest.find(static_part.as_str()) {
+				Some(pos) if i > 0 || pos == 0 => rest = &rest[pos + static_part.len()..],
+				_ => return false,
+			}
+		}
+		true
+	}
+
+	/// Match the parts one by one with the first match of each pattern
+	fn match_first(parts: &[CompiledPart], rep_line: &str) -> Option<MatchedParts> {
+		let mut last_index = 0;
+		let mut captured = HashMap::new();
+		let mut spans = Vec::new();
+
+		for part in parts {
+			match part {
+				CompiledPart::Static(static_part) => {
+					if rep_line[last_index..].starts_with(static_part) {
+						last_index += static_part.len();
+					} else {
+						return None;
+					}
+				}
+				CompiledPart::Pattern(pattern_regex) => {
+					let caps = pattern_regex.captures(&rep_line[last_index..])?;
+					for name in pattern_regex.capture_names().flatten() {
+						if let Some(value) = caps.name(name) {
+							captured.insert(name.to_string(), value.as_str().to_string());
+						}
+					}
+					let matched = caps.get(0).unwrap();
+					spans.push(last_index + matched.start()..last_index + matched.end());
+					last_index += matched.end();
+				}
+			}
+		}
+
+		match last_index == rep_line.len() {
+			true => Some((captured, spans)),
+			false => None,
+		}
+	}
+
+	/// Reorder output lines to follow the expected ones when the order depends on the collation
+	/// Each expected line takes the first matching output line, the rest fill the gaps in their order
+	pub fn reorder_lines(&self, expected: &[String], lines: Vec<String>) -> Result<Vec<String>> {
+		let mut rest: Vec<Option<String>> = lines.into_iter().map(Some).collect();
+		let mut slots: Vec<Option<String>> = Vec::with_capacity(expected.len());
+		for expected_line in expected {
+			let mut slot = None;
+			for line in rest.iter_mut() {
+				let Some(text) = line else {
+					continue;
+				};
+				if !self.has_diff(expected_line.to_string(), text.to_string())? {
+					slot = line.take();
+					break;
+				}
+			}
+			slots.push(slot);
+		}
+
+		let mut rest = rest.into_iter().flatten();
+		for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+			*slot = rest.next();
+		}
+		Ok(slots.into_iter().flatten().chain(rest).collect())
+	}
+
+	/// Helper method to split line into parts
+	/// To make it possible to validate pattern matched vars and static parts
+	///
+	fn split_into_parts(&self, rec_line: &str) -> Vec<MatchingPart> {
+		let mut parts = Vec::new();
+
+		let first_splits: Vec<&str> = rec_line.split("#!/").collect();
+		for first_split in first_splits {
+			let second_splits: Vec<&str> = first_split.split("/!#").collect();
+			if second_splits.len() == 1 {
+				parts.push(MatchingPart::Static(second_splits.first().unwrap().to_string()));
+			} else {
+				for (i, second_split) in second_splits.iter().enumerate() {
+					if i % 2 == 1 {
+						parts.push(MatchingPart::Static(second_split.to_string()));
+					} else {
+						parts.push(MatchingPart::Pattern(second_split.to_string()));
+					}
+				}
+			}
+
+		}
+		parts
+	}
+
+	/// Helper function that go through matched variable patterns in line
+	/// And replace it all with values from our parsed config
+	/// So we have raw regex to validate as an output
+	/// Parameterized patterns like %{HEXID:7} get their arguments substituted,
+	/// patterns like %{NUMBER>rows} capture the matched value into the named group,
+	/// the modifier like %{ANY:lazy} makes the pattern lazy, greedy or anchored to the end of the previous part,
+	/// and the flags like %{HOSTNAME:i} make it case-insensitive with i and let . match new lines with s
+	fn replace_vars_to_patterns(&self, line: String) -> String {
+		let result = self.var_regex.replace_all(&line, |caps: &regex::Captures| {
+			let matched = caps[0].to_string();
+			let args: Vec<&str> = caps[2].split(':').skip(1).collect();
+			let pattern = match self.config.get(&caps[1]) {
+				Some(pattern) => self.apply_params(pattern, &args),
+				None => None,
+			};
+			let Some(pattern) = pattern else {
+				return matched;
+			};
+			if caps.get(3).is_none() && caps.get(4).is_none() && caps.get(5).is_none() {
+				return pattern;
+			}
+
+			let regex = pattern.trim_start_matches("#!/").trim_end_matches("/!#");
+			let regex = match caps.get(3).map(|m| m.as_str()) {
+				Some("lazy") => format!("(?U:{})", regex),
+				Some("greedy") => format!("(?-U:{})", regex),
+				_ => regex.to_string(),
+			};
+			let regex = match caps.get(4) {
+				Some(flags) => format!("(?{}:{})", flags.as_str(), regex),
+				None => regex,
+			};
+			let regex = match caps.get(5) {
+				Some(name) => format!("(?P<{}>{})", name.as_str(), regex),
+				None => regex,
+			};
+			let anchor = if caps.get(3).is_some_and(|m| m.as_str() == "anchored") { "^" } else { "" };
+			format!("#!/{}{}/!#", anchor, regex)
+		});
+
+		result.into_owned()
+	}
+
+	/// Substitute arguments into the placeholders of the pattern
+	/// Placeholders are single capital letters inside the regex quantifier like {N} or {N,M}
+	/// and they take arguments in order of their first appearance in the pattern
+	/// Returns None when the number of arguments does not match the placeholders
+	fn apply_params(&self, pattern: &str, args: &[&str]) -> Option<String> {
+		let mut params: Vec<String> = Vec::new();
+		for caps in self.param_regex.captures_iter(pattern) {
+			for param in [caps.get(1), caps.get(2)].into_iter().flatten() {
+				if !params.iter().any(|p| p == param.as_str()) {
+					params.push(param.as_str().to_string());
+				}
+			}
+		}
+
+		if params.len() != args.len() {
+			return None;
+		}
+
+		let result = self.param_regex.replace_all(pattern, |caps: &regex::Captures| {
+			let value = |m: regex::Match| {
+				let pos = params.iter().position(|p| p == m.as_str()).unwrap();
+				args[pos].to_string()
+			};
+			let min = value(caps.get(1).unwrap());
+			let max = caps.get(2).map(value).unwrap_or_default();
+			match caps[0].contains(',') {
+				true => format!("{{{},{}}}", min, max),
+				false => format!("{{{}}}", min),
+			}
+		});
+
+		Some(result.into_owned())
+	}
+
+	/// Recursively expand references to other patterns like %{SEMVER} inside the pattern
+	/// The stack holds the chain of patterns we are expanding to detect cycles
+	fn expand_pattern(
+		&self,
+		key: &str,
+		raw_config: &HashMap<String, String>,
+		stack: &mut Vec<String>,
+	) -> Result<String> {
+		if stack.iter().any(|k| k == key) {
+			return Err(anyhow!("Cyclic pattern reference: {} -> {}", stack.join(" -> "), key));
+		}
+		stack.push(key.to_string());
+
+		let pattern = &raw_config[key];
+		let mut result = String::new();
+		let mut last_index = 0;
+		for caps in self.var_regex.captures_iter(pattern) {
+			let matched = caps.get(0).unwrap();
+			result.push_str(&pattern[last_index..matched.start()]);
+			last_index = matched.end();
+
+			if !raw_config.contains_key(&caps[1]) {
+				result.push_str(matched.as_str());
+				continue;
+			}
+
+			let args: Vec<&str> = caps[2].split(':').skip(1).collect();
+			let expanded = self.expand_pattern(&caps[1], raw_config, stack)?;
+			// Flags of the reference apply to the pattern it refers to only
+			let flags = caps.get(4).map_or("", |flags| flags.as_str());
+			match self.apply_params(&expanded, &args) {
+				Some(expanded) => result.push_str(&format!("(?{}:{})", flags, expanded)),
+				None => result.push_str(matched.as_str()),
+			}
+		}
+		result.push_str(&pattern[last_index..]);
+
+		stack.pop();
+		Ok(result)
+	}
+
+	/// Helper to parse the variables into config map when we pass path to the file
+	/// Each line contains the name of the variable and the raw regex separated by whitespace
+	fn parse_config(file_name: String) -> Result<HashMap<String, String>> {
+		parser::read_patterns(&file_name)
+	}
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+use std::time::Duration;
+use anyhow::Result;
+use regex::Regex;
+use crate::shell::{clean_output, get_error_section, Shell};
+
+/// Step of the replay, the terminal is resized between commands with ––– resize: COLSxROWS –––,
+/// the command is skipped when the shell condition of ––– skip-if: condition ––– before it is true
+/// and interrupted when it runs longer than ––– timeout: 30s ––– before it,
+/// the output is encoded with base64 for the ––– output: base64 ––– check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+	Command {
+		command: String,
+		condition: Option<String>,
+		timeout: Option<Duration>,
+		base64: bool,
+	},
+	Resize(u16, u16),
+}
+
+/// Options of the replay that rec gets from its arguments
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	/// Prompts of interactive programs to detect the end of the command besides the shell one
+	pub prompts: Vec<String>,
+	/// Delay between commands in ms
+	pub delay: u64,
+	/// Terminal size as (cols, rows) to run commands in
+	pub terminal: Option<(u16, u16)>,
+	/// Fail on deprecated syntax of the test as CLT_STRICT does
+	pub strict: bool,
+}
+
+/// Split the compiled test into the steps to replay,
+/// commands without ––– timeout ––– get the one from the front matter of the test
+pub fn get_steps(content: &str) -> Result<Vec<Step>> {
+	let default_timeout = parser::parse_metadata(content)?.timeout;
+	let resize_regex = Regex::new(parser::RESIZE_REGEX)?;
+	let skip_if_regex = Regex::new(parser::SKIP_IF_REGEX)?;
+	let timeout_regex = Regex::new(parser::TIMEOUT_REGEX)?;
+	let mut steps = Vec::new();
+	let (mut condition, mut timeout) = (None, None);
+	let mut last_line = "";
+	for line in content.split('\n') {
+		if parser::is_output_line(line) {
+			steps.push(Step::Command {
+				command: last_line.to_string(),
+				condition: condition.take(),
+				timeout: timeout.take().or(default_timeout),
+				base64: parser::parse_output_check(line)? == Some(parser::OutputCheck::Base64),
+			});
+		} else if let Some(caps) = skip_if_regex.captures(line) {
+			condition = Some(caps[1].to_string());
+		} else if let Some(caps) = timeout_regex.captures(line) {
+			timeout = Some(parser::parse_timeout(&caps[1])?);
+		} else if let Some(caps) = resize_regex.captures(line) {
+			let (cols, rows) = parser::parse_terminal_size(&caps[1])?;
+			steps.push(Step::Resize(cols, rows));
+		}
+		last_line = line;
+	}
+
+	Ok(steps)
+}
+
+/// Wrap the command to print its output encoded with base64 in a single line keeping its exit code,
+/// so binary output passes the terminal intact
+pub fn get_base64_command(command: &str) -> String {
+	format!("(set -o pipefail; {} | base64 -w 0; code=$?; echo; exit $code)", command)
+}
+
+/// Replay the test and return the content of its .rep file with the values of the secrets written as their tokens
+/// The test is checked against the .policy file in the current directory when it exists, as rec does
+pub async fn replay(rec_file: &str, options: &Options) -> Result<String> {
+	let compiled = parser::compile_checked(rec_file, options.strict)?;
+	let secrets = parser::get_secrets(&compiled.source)?;
+	let content = compiled.content;
+	if Path::new(parser::POLICY_FILE).exists() {
+		parser::check_policy(&content, parser::POLICY_FILE)?;
+	}
+
+	let env = parser::parse_env(&content)?;
+	let steps = get_steps(&content)?;
+	// Stderr is recorded apart from the output only for the tests that expect it in error sections
+	let separate_stderr = content.lines().any(|line| line == parser::ERROR_STATEMENT);
+	let (output, total_duration) = run_steps(&steps, &env, options, separate_stderr).await?;
+	parser::hide_secrets(&clean_output(&output, total_duration, None, Some(&compiled.hash))?, &secrets)
+}
+
+/// Run the commands and return the content of the .rec file recorded from them,
+/// the way rec records the commands typed in the interactive session
+pub async fn record(commands: &[&str], options: &Options) -> Result<String> {
+	let steps: Vec<Step> = commands.iter()
+		.map(|command| Step::Command { command: command.to_string(), condition: None, timeout: None, base64: false })
+		.collect();
+	let (output, total_duration) = run_steps(&steps, &[], options, false).await?;
+	clean_output(&output, total_duration, options.terminal, None)
+}
+
+/// Run the steps in a new shell and return the raw output with the total duration of commands
+async fn run_steps(steps: &[Step], env: &[(String, String)], options: &Options, separate_stderr: bool) -> Result<(String, u128)> {
+	let mut shell = Shell::spawn(&options.prompts, options.terminal, env, separate_stderr).await?;
+	let mut output = String::new();
+	let mut total_duration = 0;
+	for step in steps {
+		match step {
+			Step::Command { command, condition, timeout, base64 } => {
+				let command = command.trim();
+				// The condition runs as a hidden command, and the step is skipped when it succeeds
+				if let Some(condition) = condition {
+					let result = shell.run(&format!("({}) > /dev/null 2>&1", condition), None).await?;
+					if result.exit_code == Some(0) {
+						output.push_str(&format!("\n{}\n{}\n{}\n{}\n", parser::COMMAND_PREFIX, command, parser::COMMAND_SEPARATOR, parser::SKIPPED_LINE));
+						continue;
+					}
+				}
+
+				let result = if *base64 {
+					shell.run(&get_base64_command(command), *timeout).await?
+				} else {
+					shell.run(command, *timeout).await?
+				};
+				output.push_str(&format!("\n{}\n{}\n{}\n", parser::COMMAND_PREFIX, command, parser::COMMAND_SEPARATOR));
+				output.push_str(&parser::escape_output(&result.output));
+				if result.timed_out {
+					output.push_str(&format!("\n{}\n", parser::TIMED_OUT_LINE));
+				}
+				if let Some(stderr) = &result.stderr {
+					output.push_str(&get_error_section(stderr));
+				}
+				total_duration += result.duration;
+				output.push_str(&parser::get_duration_line(parser::Duration { duration: result.duration, percentage: 0.0 }));
+				if let Some(code) = result.exit_code {
+					output.push_str(&format!("\n{}", parser::get_exit_line(code)));
+				}
+			},
+			Step::Resize(cols, rows) => shell.resize(*cols, *rows)?,
+		}
+
+		// Sleep for delay before process next command
+		if options.delay > 0 {
+			tokio::time::sleep(std::time::Duration::from_millis(options.delay)).await;
+		}
+	}
+	shell.kill().await?;
+
+	Ok((output, total_duration))
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{bail, Result};
+
+/// File in the results directory with a line per run of a test, the same as clt test --results saves
+pub const RESULTS_FILE: &str = "results";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	Passed,
+	Failed,
+	/// The test both passed and failed in runs of the report
+	Flaky,
+}
+
+impl fmt::Display for Status {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Status::Passed => write!(f, "passed"),
+			Status::Failed => write!(f, "failed"),
+			Status::Flaky => write!(f, "flaky"),
+		}
+	}
+}
+
+/// Result of the replay of the test compared with its expected output
+#[derive(Debug, Clone)]
+pub struct TestResult {
+	pub file: String,
+	pub passed: bool,
+	/// Content of the .rep file
+	pub output: String,
+	/// Expected output with the differences marked by - and +, the same as cmp prints
+	pub diff: String,
+}
+
+/// Tests that changed the status between two reports, as clt report compare prints them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+	pub failing: Vec<String>,
+	pub passing: Vec<String>,
+	pub flaky: Vec<String>,
+}
+
+/// Outcomes of test runs, a test may run several times to find flaky ones
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+	runs: Vec<(String, bool)>,
+}
+
+impl Report {
+	pub fn add(&mut self, result: &TestResult) {
+		self.runs.push((result.file.clone(), result.passed));
+	}
+
+	/// Load the report from the directory saved with clt test --results
+	pub fn load(dir: &Path) -> Result<Self> {
+		let mut report = Self::default();
+		for line in std::fs::read_to_string(dir.join(RESULTS_FILE))?.lines() {
+			let mut parts = line.splitn(3, ' ');
+			let (Some(_), Some(status), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+				bail!("Failed to parse the line of results: {}", line);
+			};
+			report.runs.push((file.to_string(), status == "passed"));
+		}
+		Ok(report)
+	}
+
+	/// Append the runs to the results in the directory, so clt report compare can use them
+	pub fn save(&self, dir: &Path) -> Result<()> {
+		std::fs::create_dir_all(dir)?;
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+		let mut file = OpenOptions::new().create(true).append(true).open(dir.join(RESULTS_FILE))?;
+		for (test, passed) in &self.runs {
+			let status = if *passed { Status::Passed } else { Status::Failed };
+			writeln!(file, "{} {} {}", timestamp, status, test)?;
+		}
+		Ok(())
+	}
+
+	/// Status of each test across all of its runs
+	pub fn get_statuses(&self) -> BTreeMap<String, Status> {
+		let mut statuses = BTreeMap::new();
+		for (file, passed) in &self.runs {
+			let status = if *passed { Status::Passed } else { Status::Failed };
+			statuses.entry(file.clone())
+				.and_modify(|current| if *current != status { *current = Status::Flaky })
+				.or_insert(status);
+		}
+		statuses
+	}
+
+	pub fn is_success(&self) -> bool {
+		self.runs.iter().all(|(_, passed)| *passed)
+	}
+
+	/// Find tests that are newly failing, passing or flaky in the newer report compared to this one
+	pub fn get_changes(&self, newer: &Report) -> Changes {
+		let old = self.get_statuses();
+		let mut changes = Changes::default();
+		for (file, status) in newer.get_statuses() {
+			let old = old.get(&file).copied();
+			match status {
+				Status::Failed if old != Some(Status::Failed) => changes.failing.push(file),
+				Status::Passed if matches!(old, Some(Status::Failed | Status::Flaky)) => changes.passing.push(file),
+				Status::Flaky if old != Some(Status::Flaky) => changes.flaky.push(file),
+				_ => {},
+			}
+		}
+		changes
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let statuses = self.get_statuses();
+		for (file, status) in &statuses {
+			writeln!(f, "{} {}", status, file)?;
+		}
+		let count = |status| statuses.values().filter(|s| **s == status).count();
+		write!(f, "Passed: {}, failed: {}, flaky: {}", count(Status::Passed), count(Status::Failed), count(Status::Flaky))
+	}
+}
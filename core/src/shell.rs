@@ -0,0 +1,356 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, BufWriter};
+use tokio::time::Instant;
+
+pub const OUTPUT_HEADER: &str = "You can use regex in the output sections.\nMore info here: https://github.com/manticoresoftware/clt#refine\n";
+pub const SHELL_CMD: &str = "/usr/bin/env";
+pub const SHELL_PROMPT: &str = "clt> ";
+pub const INIT_CMD: &[u8] = b"export PS1='clt> ';PROMPT_COMMAND='printf \"\\033]7777;%s\\007\" $?';export LANG='en_US.UTF-8' PATH='/bin:/usr/bin:/usr/local/bin:/sbin:/usr/local/sbin' COLUMNS=${CLT_COLUMNS:-10000};if [ -n \"$CLT_LINES\" ]; then export LINES=$CLT_LINES; fi;enable -n exit enable;exec 2>&1;if [ -n \"$CLT_SEED\" ]; then RANDOM=$CLT_SEED; fi;if [ -n \"$CLT_TZ\" ]; then export TZ=$CLT_TZ; fi;";
+
+/// Commands write stderr to the file in CLT_STDERR_FILE when the test has error sections, the DEBUG trap redirects it before each command
+/// and the prompt function restores the terminal, the trap skips the function itself, so the prompt still goes to the terminal
+pub const STDERR_INIT_CMD: &[u8] = b"clt_prompt() { local code=$?; exec 2>&1; printf \"\\033]7777;%s\\007\" $code; };PROMPT_COMMAND=clt_prompt;set -T;trap '[ \"$FUNCNAME\" = clt_prompt ] || exec 2>>\"$CLT_STDERR_FILE\"' DEBUG;";
+
+/// The shell reports the exit code of each command before the prompt with this private escape sequence
+pub const EXIT_CODE_REGEX: &str = r"\x1b\]7777;([0-9]+)\x07";
+
+/// Keys sent to the command that runs longer than its timeout: ^C first, then ^\ if it does not exit
+pub const INTERRUPT_KEYS: &[u8] = &[3, 28];
+/// Time the interrupted command has to exit before the next key is sent
+pub const INTERRUPT_GRACE: Duration = Duration::from_secs(5);
+
+/// Shells started by the same process get their own rc and stderr files, so tests can run in parallel
+static RCFILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Output of the command run in the shell with the prompt and the echoed command removed
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+	pub output: String,
+	pub exit_code: Option<i32>,
+	/// Duration of the command in ms
+	pub duration: u128,
+	/// The command was interrupted because it ran longer than the timeout
+	pub timed_out: bool,
+	/// Stderr of the command when the shell writes it apart from the output
+	pub stderr: Option<String>,
+}
+
+/// Bash running in a pseudo terminal with the same clean environment rec replays tests in
+pub struct Shell {
+	pty: pty_process::Pty,
+	child: tokio::process::Child,
+	prompts: Vec<String>,
+	last_line: String,
+	exit_code_regex: regex::bytes::Regex,
+	rcfile: String,
+	stderr_file: Option<String>,
+}
+
+impl Shell {
+	/// Start the shell and wait for its first prompt, the variables of the env statement are exported on start
+	/// With separate stderr the commands write it to the file that is read after each of them
+	pub async fn spawn(prompts: &[String], terminal: Option<(u16, u16)>, env: &[(String, String)], separate_stderr: bool) -> Result<Self> {
+		let pty = pty_process::Pty::new()?;
+		let pts = pty.pts()?;
+		if let Some((cols, rows)) = terminal {
+			pty.resize(pty_process::Size::new(rows, cols))?;
+		}
+
+		let stderr_file = if separate_stderr { Some(get_stderr_file().await?) } else { None };
+		let rcfile = get_bash_rcfile(env, stderr_file.as_deref()).await?;
+		let mut process = pty_process::Command::new(SHELL_CMD);
+		process.arg("-i")
+			.arg(format!("PS1={}", SHELL_PROMPT))
+			.args(get_runtime_env())
+			.args(get_terminal_env(terminal))
+			.arg("bash")
+			.arg("--noprofile")
+			.arg("--rcfile")
+			.arg(&rcfile);
+		let child = process.spawn(&pts)?;
+
+		let mut prompts = prompts.to_vec();
+		prompts.push(SHELL_PROMPT.to_string());
+		let mut shell = Self {
+			pty,
+			child,
+			prompts,
+			last_line: String::new(),
+			exit_code_regex: regex::bytes::Regex::new(EXIT_CODE_REGEX)?,
+			rcfile,
+			stderr_file,
+		};
+		shell.run("", None).await?;
+		Ok(shell)
+	}
+
+	/// Run the command and wait for the prompt to appear in the output, indicating that it has finished
+	/// The command is interrupted when it does not finish within the timeout
+	pub async fn run(&mut self, command: &str, timeout: Option<Duration>) -> Result<CommandOutput> {
+		let start = Instant::now();
+		if !command.is_empty() {
+			let mut bytes = command.as_bytes().to_vec();
+			bytes.push(13u8); // Add enter keystroke
+			self.pty.write_all(&bytes).await?;
+		}
+
+		let pattern = Regex::new(&get_pattern_string(regex::escape(command), &self.prompts))?;
+		let mut command_output = self.last_line.clone();
+		let mut exit_code = None;
+		let mut deadline = timeout.map(|timeout| start + timeout);
+		let mut interrupt_keys = INTERRUPT_KEYS.iter();
+		let mut timed_out = false;
+		loop {
+			let mut buf = [0_u8; 4096];
+			let read = self.pty.read(&mut buf);
+			let n = match deadline {
+				Some(at) => match tokio::time::timeout_at(at, read).await {
+					Ok(n) => n?,
+					Err(_) => {
+						let Some(key) = interrupt_keys.next() else {
+							bail!("The command does not exit after it was interrupted on timeout: {}", command);
+						};
+						timed_out = true;
+						self.pty.write_all(&[*key]).await?;
+						deadline = Some(Instant::now() + INTERRUPT_GRACE);
+						continue;
+					},
+				},
+				None => read.await?,
+			};
+			if n == 0 {
+				bail!("The shell exited while running the command: {}", command);
+			}
+
+			let mut bytes = buf[..n].to_vec();
+			if let Some(caps) = self.exit_code_regex.captures(&bytes) {
+				exit_code = std::str::from_utf8(&caps[1]).ok().and_then(|code| code.parse().ok());
+				bytes = self.exit_code_regex.replace_all(&bytes, &b""[..]).to_vec();
+			}
+			command_output.push_str(&String::from_utf8_lossy(&filter_stdout_buf(bytes)));
+
+			if pattern.is_match(&command_output) && is_prompting(&command_output, &self.prompts) {
+				break;
+			}
+		}
+
+		self.last_line = command_output.lines().last().unwrap_or("").to_string();
+		let mut output = filter_prompt(&command_output, &self.prompts);
+		if output.trim() == command || output.trim().starts_with(&format!("{}\n", command)) {
+			let start = output.find(command).unwrap_or(0) + command.len();
+			output = output[start..].to_string();
+		}
+
+		let stderr = match &self.stderr_file {
+			Some(stderr_file) => Some(take_stderr(stderr_file).await?),
+			None => None,
+		};
+		Ok(CommandOutput { output, exit_code, duration: start.elapsed().as_millis(), timed_out, stderr })
+	}
+
+	/// Resizing the pty sends SIGWINCH to the running program
+	pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+		self.pty.resize(pty_process::Size::new(rows, cols))?;
+		Ok(())
+	}
+
+	/// Stop the shell and wait for it to exit
+	pub async fn kill(mut self) -> Result<()> {
+		self.child.kill().await?;
+		Ok(())
+	}
+}
+
+impl Drop for Shell {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.rcfile);
+		if let Some(stderr_file) = &self.stderr_file {
+			let _ = std::fs::remove_file(stderr_file);
+		}
+	}
+}
+
+pub fn filter_stdout_buf(buf: Vec<u8>) -> Vec<u8> {
+	// Create new bytes vector and filter from buf zero bytes
+	// and also replace \n to \r int it due to we need return caret in terminal
+	let mut prev_byte = &0;
+	let mut bytes: Vec<u8> = Vec::new();
+	for byte in buf.iter() {
+		if *byte == b'\0' || *byte == 7u8 {
+			continue;
+		}
+
+		if *prev_byte != b'\r' && *byte == b'\n' {
+			bytes.push(b'\r');
+		}
+
+		bytes.push(*byte);
+		prev_byte = byte;
+	}
+	clean_escape_sequences(bytes)
+}
+
+pub fn filter_prompt(prompt: &str, prompts: &[String]) -> String {
+	let pattern_str = get_pattern_string(String::from(".*"), prompts);
+	let re = regex::Regex::new(&pattern_str).unwrap();
+	re.replace_all(prompt, "").to_string()
+}
+
+pub fn clean_escape_sequences(input: Vec<u8>) -> Vec<u8> {
+	let mut result = Vec::with_capacity(input.len());
+	let mut inside_escape = false;
+	let mut bytes = input.into_iter().peekable();
+
+	while let Some(byte) = bytes.next() {
+		if byte == 0x1B && bytes.peek() == Some(&b'[') {
+			inside_escape = true;
+			bytes.next(); // Skip the '[' byte
+		} else if inside_escape {
+			if byte.is_ascii_alphabetic() {
+				inside_escape = false;
+			}
+		} else {
+			result.push(byte);
+		}
+	}
+
+	result
+}
+
+pub fn is_prompting(output: &str, prompts: &[String]) -> bool {
+	let pattern_str: String = get_pattern_string(String::from(""), prompts);
+	let re = regex::Regex::new(&pattern_str).unwrap();
+	let last_line = output.lines().last().unwrap_or("");
+	re.is_match(last_line)
+}
+
+pub fn get_pattern_string(suffix: String, prompts: &[String]) -> String {
+	prompts.iter()
+		.map(|prompt| format!(r"(?m)^{}{}\r?$", regex::escape(prompt), suffix))
+		.collect::<Vec<_>>()
+		.join("|")
+}
+
+/// Remove empty lines and the last line containing "exit" to make the consistent output,
+/// add the header and calculate the share of each command in the total duration
+/// The terminal size is saved to the front matter of the recorded test to replay it with the same size
+/// and the hash of the replayed test to the header to find the outputs of its older versions
+pub fn clean_output(content: &str, total_duration: u128, terminal: Option<(u16, u16)>, test_hash: Option<&str>) -> Result<String> {
+	let mut non_empty_lines = Vec::new();
+	if let Some((cols, rows)) = terminal {
+		non_empty_lines.push(format!("---\nterminal: {}x{}\n---\n", cols, rows));
+	}
+	non_empty_lines.push(String::from(OUTPUT_HEADER));
+	non_empty_lines.push(format!("Time taken for test: {}ms\n", total_duration));
+	if let Ok(seed) = std::env::var("CLT_SEED") {
+		non_empty_lines.push(format!("Seed: {}\n", seed));
+	}
+	if let Some(test_hash) = test_hash {
+		non_empty_lines.push(format!("{}{}\n", parser::TEST_HASH_PREFIX, test_hash));
+	}
+	for line in content.lines() {
+		if !line.trim().is_empty() {
+			if parser::is_duration_line(line) {
+				let mut duration = parser::parse_duration_line(line).map_err(|e| anyhow!("{}", e))?;
+				duration.percentage = (duration.duration as f32 / total_duration as f32) * 100.0;
+				non_empty_lines.push(format!("{}\n", parser::get_duration_line(duration)));
+			} else {
+				non_empty_lines.push(format!("{}\n", line.trim()));
+			}
+		}
+	}
+
+	if let Some(last_line) = non_empty_lines.last() {
+		if last_line.trim().to_lowercase().contains("exit") {
+			non_empty_lines.pop();
+		}
+	}
+
+	Ok(non_empty_lines.concat())
+}
+
+/// Variables from the env statement of the test are exported after the defaults, so they can override them
+/// The stderr of commands goes to the stderr file when it is passed
+pub async fn get_bash_rcfile(env: &[(String, String)], stderr_file: Option<&str>) -> Result<String> {
+	let file_name = format!(".rec-bashrc-{}-{}", std::process::id(), RCFILE_COUNT.fetch_add(1, Ordering::Relaxed));
+	let file_path = std::env::temp_dir().join(file_name);
+
+	let file = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.open(&file_path)
+		.await?;
+	let mut writer = BufWriter::new(file);
+	writer.write_all(INIT_CMD).await?;
+	if let Some(stderr_file) = stderr_file {
+		writer.write_all(format!("CLT_STDERR_FILE='{}';", stderr_file.replace('\'', r"'\''")).as_bytes()).await?;
+		writer.write_all(STDERR_INIT_CMD).await?;
+	}
+	for (name, value) in env {
+		let export = format!("export {}='{}';", name, value.replace('\'', r"'\''"));
+		writer.write_all(export.as_bytes()).await?;
+	}
+	writer.flush().await?;
+
+	Ok(file_path.to_string_lossy().to_string())
+}
+
+/// Create the empty file for the stderr of commands run in the shell
+pub async fn get_stderr_file() -> Result<String> {
+	let file_name = format!(".rec-stderr-{}-{}", std::process::id(), RCFILE_COUNT.fetch_add(1, Ordering::Relaxed));
+	let file_path = std::env::temp_dir().join(file_name);
+	tokio::fs::File::create(&file_path).await?;
+	Ok(file_path.to_string_lossy().to_string())
+}
+
+/// Read the stderr written since the last call and empty the file, so each command gets only its own
+pub async fn take_stderr(stderr_file: &str) -> Result<String> {
+	let content = tokio::fs::read(stderr_file).await?;
+	tokio::fs::File::create(stderr_file).await?;
+	Ok(String::from_utf8_lossy(&clean_escape_sequences(content)).to_string())
+}
+
+/// The error section with the stderr of the command that is added after its output,
+/// nothing is added when the command wrote nothing to stderr
+pub fn get_error_section(stderr: &str) -> String {
+	if stderr.trim().is_empty() {
+		return String::new();
+	}
+	format!("\n{}\n{}", parser::ERROR_STATEMENT, parser::escape_output(stderr))
+}
+
+/// Pass the variables set by the runner into the shell because we start it with clean environment
+pub fn get_runtime_env() -> Vec<String> {
+	parser::RUNTIME_VARIABLES.iter()
+		.filter_map(|(_, env_name)| std::env::var(env_name).ok().map(|value| format!("{}={}", env_name, value)))
+		.collect()
+}
+
+/// Pass the terminal size to the shell to export it as COLUMNS and LINES instead of the wide default
+pub fn get_terminal_env(terminal: Option<(u16, u16)>) -> Vec<String> {
+	match terminal {
+		Some((cols, rows)) => vec![format!("CLT_COLUMNS={}", cols), format!("CLT_LINES={}", rows)],
+		None => Vec::new(),
+	}
+}
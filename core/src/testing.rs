@@ -0,0 +1,112 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use crate::compare::{Comparator, IGNORE_LINES_FILE};
+use crate::replay::Options;
+
+/// Turn the .rec file into a #[test] that replays it and fails with the diff printed by cmp
+///
+/// ```ignore
+/// clt_core::clt_test!(search, "tests/search.rec");
+/// ```
+#[macro_export]
+macro_rules! clt_test {
+	($name:ident, $file:expr) => {
+		#[test]
+		fn $name() {
+			$crate::testing::assert_test($file);
+		}
+	};
+}
+
+/// Replay the test and panic with the diff when it fails
+/// Paths are relative to the root of the package as cargo test runs there, and the runner
+/// variables CLT_PATTERNS, CLT_NORMALIZE, CLT_STRICT and CLT_PROMPTS are respected
+pub fn assert_test(rec_file: &str) {
+	let patterns = std::env::var("CLT_PATTERNS").unwrap_or(String::from(".patterns"));
+	let normalize = std::env::var("CLT_NORMALIZE").unwrap_or_default();
+	let comparator = Comparator::load(&patterns, &normalize, IGNORE_LINES_FILE)
+		.unwrap_or_else(|e| panic!("{:#}", e));
+	let options = Options {
+		prompts: std::env::var("CLT_PROMPTS").map(|prompts| prompts.split(',').map(String::from).collect()).unwrap_or_default(),
+		strict: std::env::var("CLT_STRICT").is_ok_and(|v| !v.is_empty() && v != "0"),
+		..Default::default()
+	};
+
+	let runtime = tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.expect("Failed to start the runtime");
+	let result = runtime.block_on(crate::run_test(rec_file, &options, &comparator))
+		.unwrap_or_else(|e| panic!("Failed to run {}: {:#}", rec_file, e));
+	assert!(result.passed, "The test {} failed:\n{}", rec_file, result.diff);
+}
+
+/// Generate a clt_test! for each .rec file in the directory and its subdirectories,
+/// call it from the build script and include the file into the test crate:
+///
+/// ```ignore
+/// // build.rs
+/// let out_file = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("clt_tests.rs");
+/// clt_core::testing::generate_tests("tests", &out_file).unwrap();
+///
+/// // tests/clt.rs
+/// include!(concat!(env!("OUT_DIR"), "/clt_tests.rs"));
+/// ```
+pub fn generate_tests(dir: &str, out_file: &Path) -> Result<()> {
+	let mut files = Vec::new();
+	find_rec_files(Path::new(dir), &mut files)?;
+	files.sort();
+
+	let mut content = String::new();
+	for file in &files {
+		let file = file.to_string_lossy();
+		writeln!(content, "clt_core::clt_test!({}, {:?});", get_test_name(&file), file)?;
+	}
+	std::fs::write(out_file, content)?;
+
+	// Tests are regenerated when files are added or removed
+	println!("cargo:rerun-if-changed={}", dir);
+	Ok(())
+}
+
+/// Make the name of the test function from the path of the file, like tests/search/basic.rec into tests_search_basic
+pub fn get_test_name(file: &str) -> String {
+	let file = file.strip_suffix(".rec").unwrap_or(file);
+	let name: String = file.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+		.collect();
+	let name = name.trim_matches('_').to_string();
+	match name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+		true => format!("test_{}", name),
+		false => name,
+	}
+}
+
+fn find_rec_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			find_rec_files(&path, files)?;
+		} else if path.extension().is_some_and(|ext| ext == "rec") {
+			files.push(path);
+		}
+	}
+	Ok(())
+}
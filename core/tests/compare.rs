@@ -0,0 +1,310 @@
+use clt_core::locale::LocaleNormalizer;
+use clt_core::pattern::PatternMatcher;
+use clt_core::Comparator;
+
+fn compare(rec_content: &str, rep_content: &str) -> (bool, String) {
+  compare_with(Comparator::new(PatternMatcher::new(None, LocaleNormalizer::new("").unwrap()).unwrap(), Vec::new()), rec_content, rep_content)
+}
+
+fn compare_with(comparator: Comparator, rec_content: &str, rep_content: &str) -> (bool, String) {
+  let mut out = termcolor::Buffer::no_color();
+  let comparison = comparator.compare(rec_content, rep_content, &mut out).unwrap();
+  (comparison.has_diff, String::from_utf8(out.into_inner()).unwrap())
+}
+
+#[test]
+fn test_compare_same_output() {
+  let rec = "––– input –––\necho hello\n––– output –––\nhello\n";
+  let rep = "––– input –––\necho hello\n––– output –––\nhello\n––– duration: 1ms (100.00%) –––\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+}
+
+#[test]
+fn test_compare_different_output() {
+  let rec = "––– input –––\necho 1\n––– output –––\n#!/[0-9]+/!#\n––– input –––\necho hello\n––– output –––\nhello\n";
+  let rep = "––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho hello\n––– output –––\nbye\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("––– output –––\n- hello\n+ bye"));
+}
+
+#[test]
+fn test_compare_exit_code() {
+  let rec = "––– input –––\nfalse\n––– output –––\n––– exit: 0 –––\n";
+  let rep = "––– input –––\nfalse\n––– output –––\n––– exit: 1 –––\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("- ––– exit: 0 –––\n+ ––– exit: 1 –––"));
+}
+
+#[test]
+fn test_compare_keeps_output_file() {
+  let rec = "––– input –––\ncat result.txt\n––– output: file=expected/result.txt –––\nline 1\nlast\n––– exit: 0 –––\n";
+  let rep = "––– input –––\ncat result.txt\n––– output –––\nline 1\nlast\n––– exit: 0 –––\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!("––– input –––\ncat result.txt\n––– output: file=expected/result.txt –––\n––– exit: 0 –––", out.trim_end());
+
+  let (has_diff, out) = compare(rec, &rep.replace("last", "changed"));
+  assert!(has_diff);
+  assert!(out.contains("––– output: file=expected/result.txt –––\nline 1\n- last\n+ changed\n"));
+}
+
+#[test]
+fn test_patterns_file_comments() {
+  let matcher = PatternMatcher::new(Some("tests/data/patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  let names: Vec<&String> = matcher.config.keys().collect();
+  assert_eq!(names, ["BUILDID"]);
+}
+
+#[test]
+fn test_compare_show_matches() {
+  let matcher = PatternMatcher::new(Some("tests/data/patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  let mut comparator = Comparator::new(matcher, Vec::new());
+  comparator.show_matches = true;
+  let rec = "––– input –––\nsearchd\n––– output –––\nbuild %{BUILDID} in #!/[0-9.]+/!#s\nready\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nbuild a1b2c3d in 1.5s\nready\n";
+  let mut out = termcolor::Buffer::no_color();
+  let comparison = comparator.compare(rec, rep, &mut out).unwrap();
+  assert!(!comparison.has_diff);
+  let out = String::from_utf8(out.into_inner()).unwrap();
+  assert!(out.contains("build %{BUILDID} in #!/[0-9.]+/!#s\n~ build [%{BUILDID}=a1b2c3d] in [#!/[0-9.]+/!#=1.5]s\nready\n"));
+}
+
+#[test]
+fn test_pattern_modifiers() {
+  let matcher = PatternMatcher::new(Some("tests/data/words-patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  assert!(!matcher.has_diff("#!/.*/!#:9312".to_string(), "localhost:9312".to_string()).unwrap());
+
+  let captured = matcher.match_line("%{WORDS>head}%{WORDS>tail}".to_string(), "ab cd".to_string()).unwrap().unwrap();
+  assert_eq!(("ab c", "d"), (captured["head"].as_str(), captured["tail"].as_str()));
+  let captured = matcher.match_line("%{WORDS:lazy>head}%{WORDS>tail}".to_string(), "ab cd".to_string()).unwrap().unwrap();
+  assert_eq!(("a", "b cd"), (captured["head"].as_str(), captured["tail"].as_str()));
+
+  assert!(matcher.has_diff("x %{WORDS:anchored}".to_string(), "x 1ab".to_string()).unwrap());
+
+  // Flags apply to the pattern only, the same way in raw regexes, modifiers and references in .patterns
+  assert!(matcher.has_diff("%{WORDS}".to_string(), "ab CD".to_string()).unwrap());
+  assert!(!matcher.has_diff("%{WORDS:i}".to_string(), "ab CD".to_string()).unwrap());
+  let captured = matcher.match_line("%{WORDS:lazy:is>head}%{WORDS>tail}".to_string(), "A bcd".to_string()).unwrap().unwrap();
+  assert_eq!(("A", " bcd"), (captured["head"].as_str(), captured["tail"].as_str()));
+  assert!(!matcher.has_diff("%{SHOUT}".to_string(), "HEY YOU!".to_string()).unwrap());
+  assert!(!matcher.has_diff("x #!/(?i)warning/!#".to_string(), "x Warning".to_string()).unwrap());
+  assert!(!matcher.has_diff("x #!/(?i)^warning/!#".to_string(), "x WARNING".to_string()).unwrap());
+  assert!(matcher.has_diff("x #!/(?i)^warning/!#".to_string(), "x 1Warning".to_string()).unwrap());
+  assert!(matcher.has_diff("x #!/(?i)w/!#arn".to_string(), "x WARN".to_string()).unwrap());
+}
+
+#[test]
+fn test_pattern_params() {
+  let matcher = PatternMatcher::new(Some("tests/data/params-patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  assert!(!matcher.has_diff("commit %{HEXID:7}".to_string(), "commit 1a2b3c4".to_string()).unwrap());
+  assert!(matcher.has_diff("commit %{HEXID:7}".to_string(), "commit 1a2b3c".to_string()).unwrap());
+  assert!(!matcher.has_diff("x %{WORD:2:5}!".to_string(), "x abc!".to_string()).unwrap());
+  assert!(matcher.has_diff("x %{WORD:2:5}!".to_string(), "x a!".to_string()).unwrap());
+
+  // The pattern is not expanded when the number of values does not match the placeholders
+  assert!(!matcher.has_diff("%{WORD:2}".to_string(), "%{WORD:2}".to_string()).unwrap());
+  assert!(matcher.has_diff("%{WORD:2}".to_string(), "ab".to_string()).unwrap());
+  assert!(matcher.has_diff("%{HEXID}".to_string(), "1a2b3c4".to_string()).unwrap());
+}
+
+#[test]
+fn test_pattern_references() {
+  let matcher = PatternMatcher::new(Some("tests/data/params-patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  assert!(!matcher.has_diff("%{VERSION_LINE}".to_string(), "Manticore 6.3.2 1a2b3c4".to_string()).unwrap());
+  assert!(matcher.has_diff("%{VERSION_LINE}".to_string(), "Manticore 6.3 1a2b3c4".to_string()).unwrap());
+  assert!(matcher.has_diff("%{VERSION_LINE}".to_string(), "Manticore 6.3.2 1a2b3c".to_string()).unwrap());
+
+  let err = PatternMatcher::new(Some("tests/data/cyclic-patterns".to_string()), LocaleNormalizer::new("").unwrap()).err().unwrap();
+  assert!(err.to_string().starts_with("Cyclic pattern reference: "), "{}", err);
+}
+
+#[test]
+fn test_compare_skip_lines() {
+  let rec = "––– input –––\nsearchd\n––– output –––\nstarting\n%{SKIP_LINES}\naccepting connections\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\nprecaching table 'a'\nprecaching table 'b'\naccepting connections\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\naccepting connections\n";
+  assert!(!compare(rec, rep).0);
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\nprecaching table 'a'\n";
+  assert!(compare(rec, rep).0);
+}
+
+#[test]
+fn test_compare_output_contains() {
+  let rec = "––– input –––\nsearchd\n––– output: contains –––\nlistening on port #!/[0-9]+/!#\naccepting connections\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\nlistening on port 9306\nprecaching table 'a'\naccepting connections\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  let rep = "––– input –––\nsearchd\n––– output –––\naccepting connections\nlistening on port 9306\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("- accepting connections"));
+}
+
+#[test]
+fn test_compare_error_section() {
+  let rec = "––– input –––\nls /missing\n––– output –––\n––– error –––\nls: cannot access '/missing': #!/.+/!#\n";
+  let rep = "––– input –––\nls /missing\n––– output –––\n––– error –––\nls: cannot access '/missing': No such file or directory\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  // Stderr is not mixed into the output when it is compared
+  let rep = "––– input –––\nls /missing\n––– output –––\nls: cannot access '/missing': No such file or directory\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.contains("––– output –––\n+ ls: cannot access"));
+  assert!(out.contains("––– error –––\n- ls: cannot access"));
+
+  // Steps without the error section do not check stderr
+  let rec = "––– input –––\necho ok\n––– output –––\nok\n";
+  let rep = "––– input –––\necho ok\n––– output –––\nok\n––– error –––\nwarning\n";
+  let (has_diff, _) = compare(rec, rep);
+  assert!(!has_diff);
+}
+
+#[test]
+fn test_compare_escaped_statements() {
+  let rec = "––– input –––\ncat notes\n––– output –––\n\\––– input –––\n\\––– exit: #!/[0-9]+/!# –––\n––– exit: 0 –––\n";
+  let rep = "––– input –––\ncat notes\n––– output –––\n\\––– input –––\n\\––– exit: 2 –––\n––– exit: 0 –––\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+}
+
+#[test]
+fn test_compare_aligns_changed_lines() {
+  let rec = "––– input –––\nls\n––– output –––\nid #!/[0-9]+/!#\nname a\nsize #!/[0-9]+/!#\n";
+  let rep = "––– input –––\nls\n––– output –––\nid 1\nwarning\nname a\nsize 2\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("––– output –––\nid #!/[0-9]+/!#\n+ warning\nname a\nsize #!/[0-9]+/!#"));
+
+  let rep = "––– input –––\nls\n––– output –––\nid 1\nname b\nsize 2\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("id #!/[0-9]+/!#\n- name a\n+ name b\nsize #!/[0-9]+/!#"));
+
+  let rep = "––– input –––\nls\n––– output –––\nid 1\nsize 2\n";
+  let (_, out) = compare(rec, rep);
+  assert!(out.trim_end().ends_with("id #!/[0-9]+/!#\n- name a\nsize #!/[0-9]+/!#"));
+}
+
+#[test]
+fn test_compare_aligns_huge_mismatched_output() {
+  let expected: String = (0..2000).map(|i| format!("line #!/[0-9]+/!# a{}\n", i)).collect();
+  let actual: String = (0..2000).map(|i| format!("other {} b{}\n", i, i)).collect();
+  let rec = format!("––– input –––\nseq\n––– output –––\n{}done\n", expected);
+  let rep = format!("––– input –––\nseq\n––– output –––\n{}extra\ndone\n", actual);
+  let (has_diff, out) = compare(&rec, &rep);
+  assert!(has_diff);
+  assert!(out.contains("- line #!/[0-9]+/!# a1999\n"));
+  assert!(out.trim_end().ends_with("+ other 1999 b1999\n+ extra\ndone"));
+}
+
+#[test]
+fn test_compare_writes_inputs_from_source() {
+  let matcher = PatternMatcher::new(None, LocaleNormalizer::new("").unwrap()).unwrap();
+  let comparator = Comparator::new(matcher, Vec::new());
+  let rec = "––– input –––\nmysql -h db.local\n––– output –––\nok\n";
+  let source = "––– input –––\nmysql -h %{HOST}\n––– output –––\nok\n";
+  let rep = "––– input –––\nmysql -h db.local\n––– output –––\nfailed\n";
+  let mut out = termcolor::Buffer::no_color();
+  assert!(comparator.compare_with_source(rec, source, rep, &mut out).unwrap().has_diff);
+  assert_eq!("––– input –––\nmysql -h %{HOST}\n––– output –––\n- ok\n+ failed", String::from_utf8(out.into_inner()).unwrap().trim_end());
+}
+
+#[test]
+fn test_compare_keeps_setup_and_teardown() {
+  let rec = parser::compile("../parser/tests/data/setup/test.rec").unwrap();
+  let rep = "––– input –––\napt-get install -y mysql-client\n––– output –––\ndone\n\
+    ––– input –––\nsearchd\n––– output –––\nstarting\n\
+    ––– input –––\nmysql -h0 -P9306 -e \"show tables\"\n––– output –––\n%{NUMBER} rows\n\
+    ––– input –––\nsearchd --stopwait\n––– output –––\nstopped\n";
+  let (has_diff, out) = compare(&rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+}
+
+#[test]
+fn test_compare_ignores_to_end_without_ignore_end() {
+  let rec = "––– input –––\nsearchd\n––– output –––\nstarting\n%{IGNORE_START}\nprecaching\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\nprecaching table 'a'\nprecaching table 'b'\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  let rep = "––– input –––\nsearchd\n––– output –––\nfailed\n";
+  assert!(compare(rec, rep).0);
+}
+
+#[test]
+fn test_compare_anchor_not_found() {
+  let rec = "––– input –––\nsearchd\n––– output –––\n%{ANCHORS}\n@listening on port #!/[0-9]+/!#\n@accepting connections\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nstarting\nlistening on port 9306\nprecaching table 'a'\naccepting connections\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  // Anchors are found in their order only
+  let rep = "––– input –––\nsearchd\n––– output –––\naccepting connections\nlistening on port 9306\n";
+  let (has_diff, out) = compare(rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("%{ANCHORS}\n@listening on port #!/[0-9]+/!#\n- @accepting connections"));
+}
+
+#[test]
+fn test_compare_counts_ignored_lines() {
+  let matcher = PatternMatcher::new(None, LocaleNormalizer::new("").unwrap()).unwrap();
+  let comparator = Comparator::new(matcher, vec![regex::Regex::new("^DEBUG ").unwrap()]);
+  let rec = "––– input –––\nsearchd\n––– output –––\nDEBUG config loaded\nstarting\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nDEBUG config loaded\nstarting\nDEBUG pid 12\nDEBUG port 9306\n";
+  let mut out = termcolor::Buffer::no_color();
+  let comparison = comparator.compare(rec, rep, &mut out).unwrap();
+  assert!(!comparison.has_diff);
+  assert_eq!(4, comparison.ignored_count);
+}
+
+#[test]
+fn test_compare_failing_assertion() {
+  let matcher = PatternMatcher::new(Some("tests/data/patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  let rec = "––– input –––\nsearchd\n––– output –––\nbuild %{BUILDID>build}\n––– assert: ${build} == \"a1b2c3d\" –––\n";
+  let rep = "––– input –––\nsearchd\n––– output –––\nbuild a1b2c3d\n";
+  let (has_diff, out) = compare_with(Comparator::new(matcher, Vec::new()), rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  let matcher = PatternMatcher::new(Some("tests/data/patterns".to_string()), LocaleNormalizer::new("").unwrap()).unwrap();
+  let rep = "––– input –––\nsearchd\n––– output –––\nbuild 0000000\n";
+  let (has_diff, out) = compare_with(Comparator::new(matcher, Vec::new()), rec, rep);
+  assert!(has_diff);
+  assert!(out.trim_end().ends_with("- ––– assert: ${build} == \"a1b2c3d\" –––\n+ assertion failed: ${build} == \"a1b2c3d\""));
+}
+
+#[test]
+fn test_compare_reorders_lines_by_collation() {
+  let rec = "––– input –––\nls\n––– output –––\nalpha\nBeta\ngamma\n";
+  let rep = "––– input –––\nls\n––– output –––\nBeta\nalpha\ngamma\n";
+  assert!(compare(rec, rep).0);
+
+  let comparator = Comparator::new(PatternMatcher::new(None, LocaleNormalizer::new("collation").unwrap()).unwrap(), Vec::new());
+  let (has_diff, out) = compare_with(comparator, rec, rep);
+  assert!(!has_diff);
+  assert_eq!(rec.trim_end(), out.trim_end());
+
+  // Lines missing from the output are still reported
+  let comparator = Comparator::new(PatternMatcher::new(None, LocaleNormalizer::new("collation").unwrap()).unwrap(), Vec::new());
+  let rep = "––– input –––\nls\n––– output –––\ngamma\nalpha\n";
+  let (has_diff, out) = compare_with(comparator, rec, rep);
+  assert!(has_diff);
+  assert!(out.contains("- Beta"));
+}
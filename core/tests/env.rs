@@ -0,0 +1,33 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use clt_core::locale::LocaleNormalizer;
+use clt_core::pattern::PatternMatcher;
+use clt_core::Comparator;
+
+// The tests change the environment of the process, so they run one at a time
+static ENV: Mutex<()> = Mutex::new(());
+
+fn lock_env() -> MutexGuard<'static, ()> {
+  ENV.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[test]
+fn test_compare_matches_runtime_variables_with_values_of_run() {
+  let _env = lock_env();
+  std::env::set_var("CLT_ALLOC_PORT", "9306");
+  let matcher = PatternMatcher::new(None, LocaleNormalizer::new("").unwrap());
+  std::env::remove_var("CLT_ALLOC_PORT");
+  let comparator = Comparator::new(matcher.unwrap(), Vec::new());
+
+  // The output keeps the variable on refine, so the port of the next run matches too
+  let rec = "––– input –––\nsearchd --port 9306\n––– output –––\nlistening on %{ALLOC_PORT}\n";
+  let source = "––– input –––\nsearchd --port %{ALLOC_PORT}\n––– output –––\nlistening on %{ALLOC_PORT}\n";
+  let rep = "––– input –––\nsearchd --port 9306\n––– output –––\nlistening on 9306\n";
+  let mut out = termcolor::Buffer::no_color();
+  let comparison = comparator.compare_with_source(rec, source, rep, &mut out).unwrap();
+  assert!(!comparison.has_diff);
+  assert_eq!(source, String::from_utf8(out.into_inner()).unwrap());
+
+  let rep = "––– input –––\nsearchd --port 9306\n––– output –––\nlistening on 9307\n";
+  let mut out = termcolor::Buffer::no_color();
+  assert!(comparator.compare_with_source(rec, source, rep, &mut out).unwrap().has_diff);
+}
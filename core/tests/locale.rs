@@ -0,0 +1,48 @@
+use clt_core::locale::LocaleNormalizer;
+
+#[test]
+fn test_normalize_width_of_tables() {
+  let normalizer = LocaleNormalizer::new("width").unwrap();
+  assert_eq!("+-+-+", normalizer.normalize("+------+----+"));
+  assert_eq!("|日本|1|", normalizer.normalize("| 日本   | 1  |"));
+  assert_eq!(normalizer.normalize("| 日本 | 1 |"), normalizer.normalize("| 日本   | 1  |"));
+  // Combining characters take no column
+  assert_eq!("|cafe\u{301}|1|", normalizer.normalize("| cafe\u{301}  | 1 |"));
+
+  // Rows where each character takes one column keep their padding, so their alignment is compared
+  assert_eq!("| ab   | 1 |", normalizer.normalize("| ab   | 1 |"));
+  assert_ne!(normalizer.normalize("| ab | 1 |"), normalizer.normalize("| ab   | 1 |"));
+
+  // Lines that are not borders or rows of tables are compared as is
+  assert_eq!("a | b", normalizer.normalize("a | b"));
+  assert_eq!("1 + 2 -- 3", normalizer.normalize("1 + 2 -- 3"));
+  assert_eq!("--+--", normalizer.normalize("--+--"));
+}
+
+#[test]
+fn test_normalize_decimal_numbers() {
+  let normalizer = LocaleNormalizer::new("decimal").unwrap();
+  assert_eq!("1.5", normalizer.normalize("1,5"));
+  assert_eq!("1234.5", normalizer.normalize("1.234,5"));
+  assert_eq!("1234.5", normalizer.normalize("1,234.5"));
+  assert_eq!("1234567", normalizer.normalize("1,234,567"));
+  assert_eq!("total 1234.5 ms", normalizer.normalize("total 1\u{00A0}234,5 ms"));
+  assert_eq!("1,5", LocaleNormalizer::new("").unwrap().normalize("1,5"));
+}
+
+#[test]
+fn test_normalize_month_names() {
+  let normalizer = LocaleNormalizer::new("months").unwrap();
+  assert_eq!("12 Mar 2024", normalizer.normalize("12 März 2024"));
+  assert_eq!("12 Dec 2024", normalizer.normalize("12 déc. 2024"));
+  assert_eq!("1 May", normalizer.normalize("1 мая"));
+  // Other words stay as they are
+  assert_eq!("Marathon", normalizer.normalize("Marathon"));
+}
+
+#[test]
+fn test_normalize_unknown_option() {
+  assert!(LocaleNormalizer::new("decimal,unknown").is_err());
+  let normalizer = LocaleNormalizer::new("all").unwrap();
+  assert!(normalizer.decimal && normalizer.months && normalizer.collation && normalizer.width);
+}
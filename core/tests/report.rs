@@ -0,0 +1,35 @@
+use clt_core::report::{Report, Status, TestResult};
+
+fn get_report(runs: &[(&str, bool)]) -> Report {
+  let mut report = Report::default();
+  for (file, passed) in runs {
+    report.add(&TestResult {
+      file: file.to_string(),
+      passed: *passed,
+      output: String::new(),
+      diff: String::new(),
+    });
+  }
+  report
+}
+
+#[test]
+fn test_report_statuses() {
+  let report = get_report(&[("a.rec", true), ("b.rec", false), ("c.rec", true), ("c.rec", false)]);
+  let statuses = report.get_statuses();
+  assert_eq!(Some(&Status::Passed), statuses.get("a.rec"));
+  assert_eq!(Some(&Status::Failed), statuses.get("b.rec"));
+  assert_eq!(Some(&Status::Flaky), statuses.get("c.rec"));
+  assert!(!report.is_success());
+  assert!(report.to_string().ends_with("Passed: 1, failed: 1, flaky: 1"));
+}
+
+#[test]
+fn test_report_changes() {
+  let old = get_report(&[("a.rec", true), ("b.rec", false), ("c.rec", true)]);
+  let new = get_report(&[("a.rec", false), ("b.rec", true), ("c.rec", true), ("c.rec", false)]);
+  let changes = old.get_changes(&new);
+  assert_eq!(vec!["a.rec"], changes.failing);
+  assert_eq!(vec!["b.rec"], changes.passing);
+  assert_eq!(vec!["c.rec"], changes.flaky);
+}
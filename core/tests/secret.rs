@@ -0,0 +1,36 @@
+use clt_core::locale::LocaleNormalizer;
+use clt_core::pattern::PatternMatcher;
+use clt_core::Comparator;
+
+#[test]
+fn test_compare_hides_secrets() {
+  std::env::set_var(parser::SECRET_KEY_ENV, "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+  let token = parser::encrypt_secret("license-key-123").unwrap();
+  let source = format!("––– input –––\necho {}\n––– output –––\nok\n", token);
+  let rec = parser::decrypt_secrets(&source).unwrap();
+  let rep = "––– input –––\necho license-key-123\n––– output –––\nlicense-key-123\n";
+
+  let matcher = PatternMatcher::new(None, LocaleNormalizer::new("").unwrap()).unwrap();
+  let comparator = Comparator::new(matcher, Vec::new());
+  let mut out = termcolor::Buffer::no_color();
+  assert!(comparator.compare_with_source(&rec, &source, rep, &mut out).unwrap().has_diff);
+  let expected = format!("––– input –––\necho {}\n––– output –––\n- ok\n+ {}", token, token);
+  assert_eq!(expected, String::from_utf8(out.into_inner()).unwrap().trim_end());
+}
+
+#[test]
+fn test_compare_reads_secrets_hidden_in_rep() {
+  std::env::set_var(parser::SECRET_KEY_ENV, "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+  let token = parser::encrypt_secret("license-key-123").unwrap();
+  let source = format!("––– input –––\necho {}\n––– output –––\nlicense-key-123\n", token);
+  let rec = parser::decrypt_secrets(&source).unwrap();
+  // rec writes the values of the secrets as their tokens to the .rep file
+  let rep = parser::hide_secrets(&rec, &parser::get_secrets(&source).unwrap()).unwrap();
+  assert!(!rep.contains("license-key-123"));
+
+  let matcher = PatternMatcher::new(None, LocaleNormalizer::new("").unwrap()).unwrap();
+  let comparator = Comparator::new(matcher, Vec::new());
+  let mut out = termcolor::Buffer::no_color();
+  assert!(!comparator.compare_with_source(&rec, &source, &rep, &mut out).unwrap().has_diff);
+  assert_eq!(format!("––– input –––\necho {}\n––– output –––\n{}\n", token, token), String::from_utf8(out.into_inner()).unwrap());
+}
@@ -0,0 +1,20 @@
+use clt_core::testing;
+
+#[test]
+fn test_get_test_name() {
+  assert_eq!("tests_search_basic", testing::get_test_name("tests/search/basic.rec"));
+  assert_eq!("test_2_full_text", testing::get_test_name("2-full text.rec"));
+}
+
+#[test]
+fn test_generate_tests() {
+  let out_file = std::env::temp_dir().join(format!("clt_tests_{}.rs", std::process::id()));
+  testing::generate_tests("tests/data/suite", &out_file).unwrap();
+  let content = std::fs::read_to_string(&out_file).unwrap();
+  std::fs::remove_file(&out_file).unwrap();
+  assert_eq!(
+    "clt_core::clt_test!(tests_data_suite_basic, \"tests/data/suite/basic.rec\");\n\
+    clt_core::clt_test!(tests_data_suite_search_2_full_text, \"tests/data/suite/search/2-full text.rec\");\n",
+    content
+  );
+}
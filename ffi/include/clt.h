@@ -0,0 +1,48 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// C ABI of the CLT pattern matcher, link with libclt built by cargo build --release in the ffi directory
+
+#ifndef CLT_H
+#define CLT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CltMatcher CltMatcher;
+
+// Create the matcher with the patterns from the file and the normalization options like "decimal,months",
+// both may be NULL to match with raw regexes only and without normalization
+// Returns NULL on failure with the reason available from clt_last_error()
+CltMatcher *clt_matcher_new(const char *patterns_file, const char *normalize);
+
+// Free the matcher created with clt_matcher_new(), NULL is ignored
+void clt_matcher_free(CltMatcher *matcher);
+
+// Match the expected line with %{PATTERN} variables and #!/regex/!# against the actual one
+// Returns 0 when they match, 1 when they differ and -1 on invalid arguments or regexes with the reason in clt_last_error()
+int clt_matcher_has_diff(const CltMatcher *matcher, const char *expected, const char *actual);
+
+// Reason of the last failure in the current thread or NULL if there was none,
+// the string is owned by the library and valid until the next failing call in the thread
+const char *clt_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
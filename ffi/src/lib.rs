@@ -0,0 +1,139 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C ABI of the pattern matcher, so harnesses written in C or C++ match the outputs
+//! with the same .patterns file and #!/regex/!# semantics as cmp does, see include/clt.h
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use clt_core::locale::LocaleNormalizer;
+use clt_core::pattern::PatternMatcher;
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle of the matcher for the C side
+pub struct CltMatcher(PatternMatcher);
+
+fn set_last_error(error: impl std::fmt::Display) {
+	let error = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+	LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(error));
+}
+
+/// Run the body of the exported function and return the fallback with the reason set on a panic,
+/// so a bug never unwinds into the C side and aborts the host process
+fn catch_panic<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+	catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|panic| {
+		let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+			.or_else(|| panic.downcast_ref::<String>().cloned())
+			.unwrap_or_default();
+		set_last_error(format!("internal error: {}", message));
+		fallback
+	})
+}
+
+/// Convert the C string into &str, NULL is None
+unsafe fn get_str<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>, String> {
+	if value.is_null() {
+		return Ok(None);
+	}
+	CStr::from_ptr(value).to_str()
+		.map(Some)
+		.map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+/// Create the matcher with the patterns from the file and the normalization options like "decimal,months",
+/// both may be NULL to match with raw regexes only and without normalization
+/// Returns NULL on failure with the reason available from clt_last_error()
+///
+/// # Safety
+/// The arguments should be NULL or valid NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn clt_matcher_new(patterns_file: *const c_char, normalize: *const c_char) -> *mut CltMatcher {
+	let new_matcher = || -> Result<CltMatcher, String> {
+		let patterns_file = get_str(patterns_file, "patterns_file")?;
+		let normalize = get_str(normalize, "normalize")?.unwrap_or_default();
+		let normalizer = LocaleNormalizer::new(normalize)
+			.map_err(|e| format!("Failed to parse normalization options: {}", e))?;
+		let matcher = PatternMatcher::new(patterns_file.map(String::from), normalizer)
+			.map_err(|e| format!("Failed to load patterns: {}", e))?;
+		Ok(CltMatcher(matcher))
+	};
+
+	catch_panic(ptr::null_mut(), || match new_matcher() {
+		Ok(matcher) => Box::into_raw(Box::new(matcher)),
+		Err(e) => {
+			set_last_error(e);
+			ptr::null_mut()
+		},
+	})
+}
+
+/// Free the matcher created with clt_matcher_new(), NULL is ignored
+///
+/// # Safety
+/// The matcher should be NULL or created with clt_matcher_new() and not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn clt_matcher_free(matcher: *mut CltMatcher) {
+	catch_panic((), || {
+		if !matcher.is_null() {
+			drop(Box::from_raw(matcher));
+		}
+	})
+}
+
+/// Match the expected line with %{PATTERN} variables and #!/regex/!# against the actual one
+/// Returns 0 when they match, 1 when they differ and -1 on invalid arguments or regexes with the reason in clt_last_error()
+///
+/// # Safety
+/// The matcher should be created with clt_matcher_new() and the lines should be valid NUL-terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn clt_matcher_has_diff(matcher: *const CltMatcher, expected: *const c_char, actual: *const c_char) -> c_int {
+	catch_panic(-1, || {
+		let lines = get_str(expected, "expected").and_then(|expected| Ok((expected, get_str(actual, "actual")?)));
+		match (matcher.as_ref(), lines) {
+			(Some(matcher), Ok((Some(expected), Some(actual)))) => match matcher.0.has_diff(expected.to_string(), actual.to_string()) {
+				Ok(has_diff) => has_diff as c_int,
+				Err(e) => {
+					set_last_error(e);
+					-1
+				},
+			},
+			(None, _) => {
+				set_last_error("matcher is NULL");
+				-1
+			},
+			(_, Ok(_)) => {
+				set_last_error("lines should not be NULL");
+				-1
+			},
+			(_, Err(e)) => {
+				set_last_error(e);
+				-1
+			},
+		}
+	})
+}
+
+/// Reason of the last failure in the current thread or NULL if there was none,
+/// the string is owned by the library and valid until the next failing call in the thread
+#[no_mangle]
+pub extern "C" fn clt_last_error() -> *const c_char {
+	catch_panic(ptr::null(), || LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr())))
+}
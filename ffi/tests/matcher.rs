@@ -0,0 +1,43 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+use clt::{clt_last_error, clt_matcher_free, clt_matcher_has_diff, clt_matcher_new};
+
+fn has_diff(patterns_file: Option<&str>, expected: &str, actual: &str) -> i32 {
+  let patterns_file = patterns_file.map(|file| CString::new(file).unwrap());
+  let (expected, actual) = (CString::new(expected).unwrap(), CString::new(actual).unwrap());
+  unsafe {
+    let matcher = clt_matcher_new(patterns_file.as_ref().map_or(ptr::null(), |file| file.as_ptr()), ptr::null());
+    assert!(!matcher.is_null());
+    let result = clt_matcher_has_diff(matcher, expected.as_ptr(), actual.as_ptr());
+    clt_matcher_free(matcher);
+    result
+  }
+}
+
+#[test]
+fn test_matcher_has_diff() {
+  assert_eq!(0, has_diff(None, "hello", "hello"));
+  assert_eq!(1, has_diff(None, "hello", "bye"));
+  assert_eq!(0, has_diff(None, "rows: #!/[0-9]+/!#", "rows: 42"));
+  assert_eq!(0, has_diff(Some("tests/data/.patterns"), "rows: %{NUMBER}", "rows: 42"));
+  assert_eq!(1, has_diff(Some("tests/data/.patterns"), "rows: %{NUMBER}", "rows: many"));
+}
+
+#[test]
+fn test_matcher_errors() {
+  let missing = CString::new("tests/data/missing").unwrap();
+  let line = CString::new("hello").unwrap();
+  unsafe {
+    assert!(clt_matcher_new(missing.as_ptr(), ptr::null()).is_null());
+    let error = CStr::from_ptr(clt_last_error()).to_str().unwrap();
+    assert!(error.starts_with("Failed to load patterns"));
+
+    assert_eq!(-1, clt_matcher_has_diff(ptr::null(), line.as_ptr(), line.as_ptr()));
+    let matcher = clt_matcher_new(ptr::null(), ptr::null());
+    assert_eq!(-1, clt_matcher_has_diff(matcher, line.as_ptr(), ptr::null()));
+    let invalid = CString::new("x #!/[/!#").unwrap();
+    assert_eq!(-1, clt_matcher_has_diff(matcher, invalid.as_ptr(), line.as_ptr()));
+    assert!(CStr::from_ptr(clt_last_error()).to_str().unwrap().starts_with("invalid regex #!/[/!#"));
+    clt_matcher_free(matcher);
+  }
+}
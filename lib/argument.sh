@@ -24,6 +24,16 @@ argument_parse_docker_image() {
 
 	docker_image="${!#}"
 
+	# The image is built from the Dockerfile by the runner, so there is nothing to pull
+	if [[ "$docker_image" == build:* ]]; then
+		if [ ! -f "${docker_image#build:}" ]; then
+			>&2 echo "Failed to find the Dockerfile to build the image: ${docker_image#build:}" && exit 1
+		fi
+
+		echo "$docker_image"
+		return 0
+	fi
+
 	image_exists=0
 	if docker images --format "{{.Repository}}:{{.Tag}}" | grep "$docker_image" 1> /dev/null 2>&1; then
 		image_exists=1
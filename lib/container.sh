@@ -21,11 +21,230 @@ set -e
 ARCH=$(arch)
 bin_path="$PROJECT_DIR/bin/${ARCH/arm64/aarch64}"
 
+# Build the image referenced as build:path/to/Dockerfile and print its tag
+# The tag is the hash of the files in the build context, so the image is rebuilt only when they change
+# Other images are printed as is
+resolve_image() {
+	image=$1
+	if [[ "$image" != build:* ]]; then
+		echo "$image"
+		return 0
+	fi
+
+	dockerfile=${image#build:}
+	if [ ! -f "$dockerfile" ]; then
+		>&2 echo "Failed to find the Dockerfile to build the image: $dockerfile" && return 1
+	fi
+
+	context=$(dirname "$dockerfile")
+	context_hash=$( (cd "$context" && find . -type f -print0 | sort -z | xargs -0 sha256sum) | sha256sum | cut -c1-12)
+	tag="clt-build:$context_hash"
+	if ! docker image inspect "$tag" > /dev/null 2>&1; then
+		>&2 echo "Building the image $tag from $dockerfile"
+		docker build -q -f "$dockerfile" -t "$tag" "$context" 1>&2 || return 1
+	fi
+
+	echo "$tag"
+}
+
+# Print directories with blocks shared between suites one per line, tests use them for blocks not found next to them
+# They come from CLT_BLOCK_PATH separated by colons or the block-path key of .clt/config
+block_path_dirs() {
+	block_path=${CLT_BLOCK_PATH:-$(sed -n 's/^block-path:[[:space:]]*//p' .clt/config 2> /dev/null | head -n 1)}
+	tr ':' '\n' <<< "$block_path" | while read -r dir; do
+		if [ -z "$dir" ]; then
+			continue
+		fi
+		if [ ! -d "$dir" ]; then
+			>&2 echo "Directory with blocks does not exist: $dir"
+			continue
+		fi
+		realpath "$dir"
+	done
+}
+
+# Print the block path as rec and cmp see it in the container where the directories are mounted
+container_block_path() {
+	block_path_dirs | awk -v dir="$DOCKER_PROJECT_DIR/.blocks" '{ printf "%s%s/%d", (NR > 1 ? ":" : ""), dir, NR - 1 }'
+}
+
+# Print the style of the statements from CLT_STATEMENT_STYLE or the statement-style key of .clt/config
+statement_style() {
+	echo "${CLT_STATEMENT_STYLE:-$(sed -n 's/^statement-style:[[:space:]]*//p' .clt/config 2> /dev/null | head -n 1 | tr -d '[:space:]')}"
+}
+
+# Print the absolute path to the file with values of variables substituted into inputs as %{NAME},
+# it is the one passed with --vars or .clt/variables of the project
+variables_file() {
+	file=${CLT_VARS_FILE:-.clt/variables}
+	if [ -f "$file" ]; then
+		realpath "$file"
+	fi
+}
+
+# Merge the base patterns with the .patterns of the project that extend them into a temporary file and print its path
+patterns_file() {
+	temp_file=$(mktemp)
+	cat "$PROJECT_DIR/.patterns" > "$temp_file"
+	if [ -f ".patterns" ]; then
+		cat .patterns >> "$temp_file"
+	fi
+	echo "$temp_file"
+}
+
+# Print the image that runs clt-cmp for the commands that do not take one,
+# it is set with CLT_TOOLS_IMAGE or the tools-image key of .clt/config
+tools_image() {
+	image=${CLT_TOOLS_IMAGE:-$(sed -n 's/^tools-image:[[:space:]]*//p' .clt/config 2> /dev/null | head -n 1 | tr -d '[:space:]')}
+	echo "${image:-debian:stable-slim}"
+}
+
+# Print arguments to mount the binaries, the directory with tests, patterns and templates into the container
+container_mounts() {
+	directory=$1
+	temp_file=$(patterns_file)
+
+	# Mount project templates that are expanded on compile
+	templates=
+	if [ -d ".templates" ]; then
+		templates="-v \"$PWD/.templates:$DOCKER_PROJECT_DIR/.templates\""
+	fi
+
+	# Mount the policy that rec checks commands against before replaying them
+	policy=
+	if [ -f ".policy" ]; then
+		policy="-v \"$PWD/.policy:$DOCKER_PROJECT_DIR/.policy\""
+	fi
+
+	# Mount regexes of noisy lines that cmp filters out before comparing
+	ignore_lines=
+	if [ -f ".ignore-lines" ]; then
+		ignore_lines="-v \"$PWD/.ignore-lines:$DOCKER_PROJECT_DIR/.ignore-lines\""
+	fi
+
+	# Mount the values of variables for inputs
+	variables=
+	if [ -n "$(variables_file)" ]; then
+		variables="-v \"$(variables_file):$DOCKER_PROJECT_DIR/.variables\""
+	fi
+
+	# Mount shared blocks in the order of the block path
+	blocks=
+	block_index=0
+	while read -r dir; do
+		blocks="$blocks -v \"$dir:$DOCKER_PROJECT_DIR/.blocks/$block_index\""
+		block_index=$((block_index + 1))
+	done < <(block_path_dirs)
+
+	echo -v \"$bin_path/rec:/usr/bin/clt-rec\" \
+		-v \"$bin_path/cmp:/usr/bin/clt-cmp\" \
+		-v \"$PWD/$directory:$DOCKER_PROJECT_DIR/$directory\" \
+		-v \"$temp_file:$DOCKER_PROJECT_DIR/.patterns\" \
+		$templates \
+		$policy \
+		$ignore_lines \
+		$variables \
+		$blocks
+}
+
+# Start the long-lived fixture container that tests run in instead of a new container each
+# The start command should return once daemons are started in background
+fixture_start() {
+	image=$1
+	directory=$2
+	container_name=$3
+	start_command=$4
+	image=$(resolve_image "$image")
+	mounts=$(container_mounts "$directory")
+	eval "docker run -d --name \"$container_name\" $mounts -w \"$DOCKER_PROJECT_DIR\" $RUN_ARGS \
+		--entrypoint /bin/bash \"$image\" -c 'while :; do sleep 3600; done'" > /dev/null
+
+	if [ -n "$start_command" ]; then
+		echo "Starting the fixture: $start_command"
+		docker exec "$container_name" /bin/bash -c "$start_command"
+	fi
+}
+
+# Print the value of the key from the fixture file with lines like "reset: command"
+fixture_get() {
+	sed -n "s/^$2:[[:space:]]*//p" "$1" | head -n 1
+}
+
+# Print the path to clt-cmp that runs on the host, or nothing when there is none
+# It is the bundled binary on Linux or clt-cmp found in PATH on other systems, e.g. built from source on macOS
+host_cmp() {
+	if [ "$(uname -s)" = Linux ] && [ -x "$bin_path/cmp" ]; then
+		echo "$bin_path/cmp"
+	elif command -v clt-cmp > /dev/null 2>&1; then
+		command -v clt-cmp
+	fi
+}
+
+# Run clt-cmp with the arguments and print its output as is
+# It runs on the host when host_cmp finds the binary, so the merge driver and undo work without docker,
+# otherwise it runs in the container of the image, as the binaries are built for Linux
+# The project is mounted at the same path as on the host, so paths, blocks and the history of tests stay the same,
+# and there is no terminal, so the output can be captured and the input piped
+cmp_exec() {
+	image=$1
+	shift
+
+	patterns=${CLT_PATTERNS:-}
+	temp_patterns=
+	if [ -z "$patterns" ]; then
+		patterns=$(patterns_file)
+		temp_patterns=$patterns
+	fi
+	patterns=$(realpath "$patterns")
+	vars=$(variables_file)
+
+	exit_code=0
+	cmp=$(host_cmp)
+	if [ -n "$cmp" ]; then
+		env_args=("CLT_PATTERNS=$patterns")
+		if [ -n "$vars" ]; then
+			env_args+=("CLT_VARS_FILE=$vars")
+		fi
+		env "${env_args[@]}" "$cmp" "$@" || exit_code=$?
+	elif ! command -v docker > /dev/null 2>&1; then
+		>&2 echo "Failed to run clt-cmp: install docker or put clt-cmp built for this system into PATH"
+		exit_code=1
+	elif image=$(resolve_image "$image"); then
+		mounts=(-v "$bin_path/cmp:/usr/bin/clt-cmp" -v "$PWD:$PWD" -v "$patterns:$patterns")
+		env_args=(-e "CLT_PATTERNS=$patterns")
+		while read -r dir; do
+			mounts+=(-v "$dir:$dir")
+		done < <(block_path_dirs 2> /dev/null)
+
+		if [ -n "$vars" ]; then
+			mounts+=(-v "$vars:$vars")
+			env_args+=(-e "CLT_VARS_FILE=$vars")
+		fi
+
+		# Variables that are not set on the host are not set in the container either
+		for name in CLT_BLOCK_PATH CLT_STATEMENT_STYLE CLT_HISTORY_DIR CLT_SECRET_KEY CLT_NORMALIZE CLT_STRICT CLT_SHOW_MATCHES CLT_PROMPTS CLT_SEED NO_COLOR; do
+			env_args+=(-e "$name")
+		done
+
+		docker run --rm -i "${mounts[@]}" "${env_args[@]}" \
+			--user "$(id -u):$(id -g)" -w "$PWD" \
+			--entrypoint /usr/bin/clt-cmp "$image" "$@" || exit_code=$?
+	else
+		exit_code=1
+	fi
+
+	if [ -n "$temp_patterns" ]; then
+		rm -f "$temp_patterns"
+	fi
+	return $exit_code
+}
+
 container_exec() {
 	image=$1
 	command=$2
 	directory=${3:-tests}
 	interactive=${4:-}
+	container_name=${5:-}
 	if [ ! -d "$directory" ]; then
 		>&2 echo "Directory with tests does not exist: $directory" && exit 1
 	fi
@@ -33,30 +252,65 @@ container_exec() {
 	if [ -z "$image" ] || [ -z "$command" ]; then
 		>&2 echo 'Usage: container_exec "image" "command"' && exit 1
 	fi
-
-	# Merge base of patterns
-	temp_file=$(mktemp)
-	cat "$PROJECT_DIR/.patterns" > "$temp_file"
-
-	# Merge project .patterns to extend original
-	if [ -f ".patterns" ]; then
-		cat .patterns >> "$temp_file"
-	fi
+	image=$(resolve_image "$image")
 
 	flag=
 	if [ -n "$interactive" ]; then
 		flag="-i"
 	fi
-	process=$(echo docker run \
-		-v \"$bin_path/rec:/usr/bin/clt-rec\" \
-		-v \"$bin_path/cmp:/usr/bin/clt-cmp\" \
-		-v \"$PWD/$directory:$DOCKER_PROJECT_DIR/$directory\" \
-		-v \"$temp_file:$DOCKER_PROJECT_DIR/.patterns\" \
-		-w \"$DOCKER_PROJECT_DIR\" \
-		$RUN_ARGS \
-		--entrypoint /bin/bash \
-		--rm $flag -t \"$image\" \
-		-i -c \"$command\")
+
+	# Named containers are kept after exit to commit them as an image
+	rm_flag="--rm"
+	if [ -n "$container_name" ]; then
+		rm_flag="--name \"$container_name\""
+	fi
+
+	# Pass the variables the runner set for the test
+	env_args=
+	if [ -n "$CLT_ALLOC_PORT" ]; then
+		env_args="$env_args -e CLT_ALLOC_PORT=$CLT_ALLOC_PORT"
+	fi
+
+	if [ -n "$CLT_SEED" ]; then
+		env_args="$env_args -e CLT_SEED=$CLT_SEED"
+	fi
+
+	if [ -n "$(variables_file)" ]; then
+		env_args="$env_args -e CLT_VARS_FILE=$DOCKER_PROJECT_DIR/.variables"
+	fi
+
+	block_path=$(container_block_path 2> /dev/null)
+	if [ -n "$block_path" ]; then
+		env_args="$env_args -e CLT_BLOCK_PATH=$block_path"
+	fi
+
+	style=$(statement_style)
+	if [ -n "$style" ]; then
+		env_args="$env_args -e CLT_STATEMENT_STYLE=$style"
+	fi
+
+	# The key is passed by name only to keep it out of the process list
+	if [ -n "$CLT_SECRET_KEY" ]; then
+		env_args="$env_args -e CLT_SECRET_KEY"
+	fi
+
+	# Tests share the running fixture container when it is started by the runner
+	if [ -n "$CLT_FIXTURE_CONTAINER" ]; then
+		process=$(echo docker exec \
+			$env_args \
+			-w \"$DOCKER_PROJECT_DIR\" \
+			$flag -t \"$CLT_FIXTURE_CONTAINER\" \
+			/bin/bash -i -c \"$command\")
+	else
+		process=$(echo docker run \
+			$(container_mounts "$directory") \
+			$env_args \
+			-w \"$DOCKER_PROJECT_DIR\" \
+			$RUN_ARGS \
+			--entrypoint /bin/bash \
+			$rm_flag $flag -t \"$image\" \
+			-i -c \"$command\")
+	fi
 
 	if [ -n "$interactive" ]; then
 		eval "$process"
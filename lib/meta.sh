@@ -0,0 +1,96 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+# Metadata of the test lives in the front matter at the very beginning of the .rec file:
+# ---
+# tags: smoke, slow
+# ---
+
+# Print the value of the key from the front matter of the test
+meta_get() {
+	file=$1
+	key=$2
+	awk -v key="$key" '
+		NR == 1 && $0 != "---" { exit }
+		NR == 1 { next }
+		$0 == "---" { exit }
+		index($0, key ":") == 1 {
+			value = substr($0, length(key) + 2)
+			gsub(/^[ \t]+|[ \t]+$/, "", value)
+			print value
+			exit
+		}
+	' "$file"
+}
+
+# Print the front matter of the test including the enclosing lines, if it has one
+meta_front_matter() {
+	awk '
+		NR == 1 && $0 != "---" { exit }
+		{ print }
+		NR > 1 && $0 == "---" { exit }
+	' "$1"
+}
+
+# Print the list of values for the key, both "a, b" and "[a, b]" forms are supported
+meta_get_list() {
+	meta_get "$1" "$2" | tr -d '[]"' | tr ',' '\n' | sed 's/^[[:space:]]*//;s/[[:space:]]*$//' | grep -v '^$' || true
+}
+
+# Print tags of the test one per line
+meta_tags() {
+	meta_get_list "$1" tags
+}
+
+# Check that the test matches the filter like "smoke,!slow"
+# The test should have any of the listed tags and none of the excluded ones
+meta_match_tags() {
+	file=$1
+	filter=$2
+
+	mapfile -t tags < <(meta_tags "$file")
+	has_included=0
+	matched=0
+	IFS=',' read -ra filters <<< "$filter"
+	for tag in "${filters[@]}"; do
+		tag="${tag// /}"
+		if [ -z "$tag" ]; then
+			continue
+		fi
+
+		if [[ "$tag" == !* ]]; then
+			for test_tag in "${tags[@]}"; do
+				if [ "$test_tag" = "${tag:1}" ]; then
+					return 1
+				fi
+			done
+		else
+			has_included=1
+			for test_tag in "${tags[@]}"; do
+				if [ "$test_tag" = "$tag" ]; then
+					matched=1
+				fi
+			done
+		fi
+	done
+
+	if [ $has_included -eq 1 ] && [ $matched -eq 0 ]; then
+		return 1
+	fi
+
+	return 0
+}
@@ -0,0 +1,161 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+# Owners of the test to reach when it fails come from the owner key of its front matter
+# or from the CODEOWNERS file of the repository when the test does not name them
+# Requires lib/meta.sh to be sourced
+
+# Print the path to the CODEOWNERS file in the places GitHub and GitLab look for it
+owners_find_codeowners() {
+	for file in .github/CODEOWNERS CODEOWNERS docs/CODEOWNERS .gitlab/CODEOWNERS; do
+		if [ -f "$file" ]; then
+			echo "$file"
+			return 0
+		fi
+	done
+}
+
+# Print owners of the file from the last matching rule of CODEOWNERS, one per line
+# Patterns follow the rules of .gitignore: a pattern without a slash matches at any depth,
+# * does not cross directories, ** does, and a directory matches everything under it
+owners_match() {
+	codeowners_file=$1
+	file=${2#./}
+	awk -v file="$file" '
+		function to_regex(pattern,   anchored, regex, i, c) {
+			anchored = substr(pattern, 1, 1) == "/" || index(substr(pattern, 1, length(pattern) - 1), "/") > 0
+			sub(/^\//, "", pattern)
+			sub(/\/$/, "", pattern)
+			regex = ""
+			for (i = 1; i <= length(pattern); i++) {
+				c = substr(pattern, i, 1)
+				if (substr(pattern, i, 3) == "**/") {
+					regex = regex "(.*/)?"
+					i += 2
+				} else if (substr(pattern, i, 2) == "**") {
+					regex = regex ".*"
+					i++
+				} else if (c == "*") {
+					regex = regex "[^/]*"
+				} else if (c == "?") {
+					regex = regex "[^/]"
+				} else if (index("\\.+()|^$[]{}", c)) {
+					regex = regex "\\" c
+				} else {
+					regex = regex c
+				}
+			}
+			return (anchored ? "^" : "^(.*/)?") regex "(/.*)?$"
+		}
+		/^[[:space:]]*(#|$)/ || /^\[/ {
+			next
+		}
+		file ~ to_regex($1) {
+			owners = ""
+			for (i = 2; i <= NF && $i !~ /^#/; i++) {
+				owners = owners $i "\n"
+			}
+		}
+		END {
+			printf "%s", owners
+		}
+	' "$codeowners_file"
+}
+
+# Print owners of the test one per line
+owners_get() {
+	owners=()
+	if [ -f "$1" ]; then
+		mapfile -t owners < <(meta_get_list "$1" owner)
+	fi
+	if [ ${#owners[@]} -gt 0 ]; then
+		printf '%s\n' "${owners[@]}"
+		return 0
+	fi
+
+	codeowners_file=$(owners_find_codeowners)
+	if [ -n "$codeowners_file" ]; then
+		owners_match "$codeowners_file" "$1"
+	fi
+}
+
+# Print the commands of the steps that differ in the saved output of cmp
+owners_failed_steps() {
+	if [ ! -f "$1" ]; then
+		return 0
+	fi
+
+	awk '
+		{
+			colored = ($0 ~ /\033\[3[12]m/)
+			if (colored) {
+				has_colors = 1
+			}
+			gsub(/\033\[[0-9;]*m/, "")
+		}
+		/^––– input –––$/ {
+			getline command
+			gsub(/\033\[[0-9;]*m/, "", command)
+			next
+		}
+		(has_colors ? colored : 1) && /^[-+] / && command != printed {
+			print command
+			printed = command
+		}
+	' "$1"
+}
+
+# Escape the value to put it into a JSON string
+owners_json_escape() {
+	local value=$1
+	value=${value//\\/\\\\}
+	value=${value//\"/\\\"}
+	value=${value//$'\t'/\\t}
+	value=${value//$'\n'/\\n}
+	printf '%s' "$value"
+}
+
+# Print a JSON array of the lines read from stdin
+owners_json_array() {
+	local separator= line
+	printf '['
+	while IFS= read -r line; do
+		printf '%s"%s"' "$separator" "$(owners_json_escape "$line")"
+		separator=', '
+	done
+	printf ']'
+}
+
+# Post the failed tests with their owners and failed steps to the webhook
+# The text field holds the summary, so the payload is accepted by Slack and Mattermost incoming webhooks as is
+owners_notify() {
+	url=$1
+	total=$2
+	shift 2
+
+	text="$# of $total tests failed:"
+	tests=
+	for file in "$@"; do
+		owners=$(owners_get "$file" | paste -sd' ' -)
+		text+=$'\n'"$file${owners:+ ($owners)}"
+		tests+="${tests:+, }{\"test\": \"$(owners_json_escape "$file")\""
+		tests+=", \"owners\": $(owners_get "$file" | owners_json_array)"
+		tests+=", \"steps\": $(owners_failed_steps "${file%.*}.cmp" | owners_json_array)}"
+	done
+
+	payload="{\"text\": \"$(owners_json_escape "$text")\", \"failed\": [$tests]}"
+	curl -fsS -m 10 -X POST -H 'Content-Type: application/json' -d "$payload" "$url" > /dev/null
+}
@@ -17,6 +17,38 @@
 
 set -e
 source "$PROJECT_DIR/lib/container.sh"
+source "$PROJECT_DIR/lib/meta.sh"
+
+# Find a free port on the host and reserve it, so tests running in parallel do not get the same one
+allocate_port() {
+	reserve_dir="${TMPDIR:-/tmp}/clt-ports"
+	mkdir -p "$reserve_dir"
+	for _ in {1..100}; do
+		port=$(( 20000 + RANDOM % 40000 ))
+		if (echo > "/dev/tcp/127.0.0.1/$port") > /dev/null 2>&1; then
+			continue
+		fi
+
+		if mkdir "$reserve_dir/$port" 2> /dev/null; then
+			echo "$port"
+			return 0
+		fi
+	done
+
+	>&2 echo "Failed to allocate a free port for the test"
+	return 1
+}
+
+# Release the port reserved with allocate_port
+release_port() {
+	rmdir "${TMPDIR:-/tmp}/clt-ports/$1" 2> /dev/null || true
+}
+
+# Print the image to run the test in, the test may override the one passed to the runner with the image key
+get_test_image() {
+	image=$(meta_get "$2" image)
+	echo "${image:-$1}"
+}
 
 # Run recording of a new test in container with specified Docker image
 record() {
@@ -40,7 +72,13 @@ record() {
 	echo "Recording data to file: $record_file"
 	echo "Run commands one by one and after you finish press ^D to save"
 
-	container_exec "$image" "clt-rec -O '$record_file'" "$record_dir" "1"
+	# Save the size of the terminal to the test to replay it with the same wrapping width
+	terminal=
+	if size=$(stty size 2> /dev/null); then
+		read -r rows cols <<< "$size"
+		terminal=" -T ${cols}x${rows}"
+	fi
+	container_exec "$image" "clt-rec -O '$record_file'$terminal" "$record_dir" "1"
 }
 
 # Replay recorded test from the file
@@ -48,6 +86,7 @@ replay() {
 	image=$1
 	record_file=$2
 	delay=${3:-$DEFAULT_DELAY}
+	container_name=${4:-}
 	if [ -z "$image" ] || [ -z "$record_file" ]; then
 		>&2 echo 'Usage: replay "image" "record_file"' && exit 1
 	fi
@@ -56,6 +95,7 @@ replay() {
 		>&2 echo "The record file does not exist: $record_file" && exit 1
 	fi
 
+	image=$(get_test_image "$image" "$record_file")
 	record_dir=$(dirname "${record_file}" | cut -d/ -f1)
 	replay_file="${record_file%.*}.rep"
 	echo "Replaying data from the file: $record_file"
@@ -65,10 +105,52 @@ replay() {
 		echo "Error: CLT_PROMPTS is not an array" >&2
 	fi
 	cmd=("clt-rec" "-I" "$record_file" "-O" "$replay_file" "-D" "$delay")
+	if [ -n "$CLT_STRICT" ]; then
+		cmd=("CLT_STRICT=1" "${cmd[@]}")
+	fi
+	if [ -n "$CLT_FIX_STATEMENTS" ]; then
+		cmd=("CLT_FIX_STATEMENTS=1" "${cmd[@]}")
+	fi
+
+	# The timezone pinned by the test takes precedence over the one set for the run
+	timezone=$(meta_get "$record_file" timezone)
+	timezone=${timezone:-$CLT_TZ}
+	if [ -n "$timezone" ]; then
+		cmd=("CLT_TZ=$timezone" "${cmd[@]}")
+	fi
 	for prompt in "${CLT_PROMPTS[@]}"; do
 		cmd+=("-p" "$prompt")
 	done
-	container_exec "$image" "${cmd[*]}" "$record_dir"
+
+	terminal=$(meta_get "$record_file" terminal)
+	if [ -n "$terminal" ]; then
+		cmd+=("-T" "$terminal")
+	fi
+
+	# Save the terminal screen after each step to show it when the test fails
+	if [ -n "$CLT_SCREENS" ]; then
+		cmd+=("-S" "${record_file%.*}.screens")
+		if [ "$CLT_SCREENS" = svg ]; then
+			cmd+=("--svg")
+		fi
+	fi
+	container_exec "$image" "${cmd[*]}" "$record_dir" "" "$container_name"
+}
+
+# Append the growth of the writable layer of the container and the size of files
+# the test left in the directory with tests to the disk usage report, then remove the container
+record_disk_usage() {
+	container_name=$1
+	record_file=$2
+	marker_file=$3
+	layer_size=$(docker container inspect --size --format '{{.SizeRw}}' "$container_name" 2> /dev/null || echo 0)
+	docker rm -f "$container_name" > /dev/null 2>&1 || true
+
+	record_dir=$(dirname "${record_file}" | cut -d/ -f1)
+	leftover_size=$(find "$record_dir" -type f -newer "$marker_file" ! -name '*.rep' ! -name '*.cmp' -printf '%s\n' \
+		| awk '{ size += $1 } END { print size + 0 }')
+	rm -f "$marker_file"
+	echo "$layer_size $leftover_size $record_file" >> "$CLT_DISK_USAGE_FILE"
 }
 
 # Run compare binary
@@ -86,11 +168,102 @@ compare() {
 		prefix="NO_COLOR=1 "
 	fi
 
+	if [ -n "$CLT_STRICT" ]; then
+		prefix="${prefix}CLT_STRICT=1 "
+	fi
+
+	if [ -n "$CLT_SHOW_MATCHES" ]; then
+		prefix="${prefix}CLT_SHOW_MATCHES=1 "
+	fi
+
+	timezone=$(meta_get "$record_file" timezone)
+	timezone=${timezone:-$CLT_TZ}
+	if [ -n "$timezone" ]; then
+		prefix="${prefix}CLT_TZ=$timezone "
+	fi
+
+	# Locale normalization is taken from the test metadata or the environment
+	normalize=$(meta_get_list "$record_file" normalize | paste -sd, -)
+	normalize=${normalize:-$CLT_NORMALIZE}
+	if [ -n "$normalize" ]; then
+		prefix="${prefix}CLT_NORMALIZE=$normalize "
+	fi
+
+	image=$(get_test_image "$image" "$record_file")
 	record_dir=$(dirname "${record_file}" | cut -d/ -f1)
 	# We validate file existence in cmp tool, so it's fine to skip it here
 	container_exec "$image" "${prefix}clt-cmp '$record_file' '$replay_file'" "$record_dir"
 }
 
+# Pack everything needed to reproduce the failed test into the tarball that clt repro runs
+# The test is compiled with secrets kept encrypted and runtime variables left as placeholders
+bundle() {
+	image=$1
+	record_file=$2
+	bundle_dir=$3
+	delay=${4:-$DEFAULT_DELAY}
+
+	image=$(get_test_image "$image" "$record_file")
+	name=${record_file%.*}
+	bundle_file="$bundle_dir/${name//\//_}.tar"
+	work_dir=$(mktemp -d)
+	mkdir -p "$work_dir/tests"
+	if ! (unset CLT_ALLOC_PORT CLT_SEED CLT_TZ; cmp_exec "$image" --compile "$record_file") > "$work_dir/tests/test.rec"; then
+		rm -rf "$work_dir"
+		return 1
+	fi
+
+	# The files of the run are packed with the values of the secrets written as their tokens
+	files=()
+	for ext in rep cmp; do
+		if [ -f "$name.$ext" ]; then
+			files+=("$name.$ext:tests/test.$ext")
+		fi
+	done
+	if [ -d "$name.screens" ]; then
+		cp -r "$name.screens" "$work_dir/tests/test.screens"
+		for file in "$name.screens"/*.txt; do
+			if [ -f "$file" ]; then
+				files+=("$file:tests/test.screens/${file##*/}")
+			fi
+		done
+	fi
+	for file in "${files[@]}"; do
+		if ! cmp_exec "$image" --hide-secrets "$record_file" < "${file%%:*}" > "$work_dir/${file#*:}"; then
+			rm -rf "$work_dir"
+			return 1
+		fi
+	done
+
+	cat "$PROJECT_DIR/.patterns" > "$work_dir/.patterns"
+	if [ -f ".patterns" ]; then
+		cat .patterns >> "$work_dir/.patterns"
+	fi
+	for file in .ignore-lines .policy; do
+		if [ -f "$file" ]; then
+			cp "$file" "$work_dir/$file"
+		fi
+	done
+
+	digest=$(docker image inspect --format '{{if .RepoDigests}}{{index .RepoDigests 0}}{{else}}{{.Id}}{{end}}' "$image" 2> /dev/null || true)
+	{
+		echo "test: $record_file"
+		echo "image: $image"
+		echo "digest: $digest"
+		echo "seed: $CLT_SEED"
+		echo "timezone: $CLT_TZ"
+		echo "normalize: $CLT_NORMALIZE"
+		echo "strict: $CLT_STRICT"
+		echo "delay: $delay"
+		echo "created: $(date -u +%Y-%m-%dT%H:%M:%SZ)"
+	} > "$work_dir/manifest"
+
+	mkdir -p "$bundle_dir"
+	tar -cf "$bundle_file" -C "$work_dir" .
+	rm -rf "$work_dir"
+	echo "The bundle to reproduce the failure is saved to: $bundle_file"
+}
+
 # Replay recorded test and launch refine
 refine() {
 	# Check that we have required software installed for this command
@@ -115,14 +288,60 @@ refine() {
 		>&2 echo "The record file does not exist: $record_file" && exit 1
 	fi
 
+	# Groups are replayed expanded into other steps, so refine could not write them back
+	if ! cmp_exec "$image" --check-refine "$record_file"; then
+		exit 1
+	fi
+
 	replay_file="${record_file%.*}.rep"
 
+	CLT_ALLOC_PORT=$(allocate_port)
+	CLT_SEED=${CLT_SEED:-$RANDOM}
+	export CLT_ALLOC_PORT CLT_SEED
 	replay "$image" "$record_file"
-	compare "$image" "$record_file" "$replay_file" "1" > "$record_file.cmp" 2>&1 || true
+	# Warnings of cmp go to the terminal to keep them out of the refined file
+	# and the front matter is kept as cmp prints only the steps
+	{
+		meta_front_matter "$record_file"
+		compare "$image" "$record_file" "$replay_file" "1" || true
+	} > "$record_file.cmp"
+	release_port "$CLT_ALLOC_PORT"
 	mv -f "$record_file.cmp" "$record_file"
 	$editor "$record_file"
 }
 
+# Replay the setup test and save the state of the container as the image to start other tests from
+# Only the file system is saved, so daemons started by the setup should be started again by the tests
+snapshot() {
+	image=$1
+	setup_file=$2
+	snapshot_image=$3
+	if [ -z "$image" ] || [ -z "$setup_file" ] || [ -z "$snapshot_image" ]; then
+		>&2 echo 'Usage: snapshot "image" "setup_file" "snapshot_image"' && exit 1
+	fi
+
+	if [ ! -f "$setup_file" ]; then
+		>&2 echo "The setup file does not exist: $setup_file" && exit 1
+	fi
+
+	record_dir=$(dirname "${setup_file}" | cut -d/ -f1)
+	replay_file="${setup_file%.*}.rep"
+	container_name="clt-snapshot-$$"
+	echo "Running the setup to snapshot: $setup_file"
+	container_exec "$image" "clt-rec -I '$setup_file' -O '$replay_file' -D $DEFAULT_DELAY" "$record_dir" "" "$container_name"
+
+	exit_code=0
+	compare "$image" "$setup_file" "$replay_file" > "${setup_file%.*}.cmp" 2>&1 || exit_code=$?
+	if [ $exit_code -eq 0 ]; then
+		docker commit "$container_name" "$snapshot_image" > /dev/null
+	else
+		>&2 echo "The setup failed, check the diff in ${setup_file%.*}.cmp"
+	fi
+
+	docker rm -f "$container_name" > /dev/null
+	return $exit_code
+}
+
 # Replay and test against record file with cmp tool
 test() {
 	# Validate input args
@@ -139,12 +358,46 @@ test() {
 	fi
 
 	replay_file="${record_file%.*}.rep"
+	screens_dir="${record_file%.*}.screens"
+	rm -rf "$screens_dir"
+
+	# Each test gets its own free port to use as %{ALLOC_PORT}
+	CLT_ALLOC_PORT=$(allocate_port)
+	export CLT_ALLOC_PORT
+
+	# Keep the container after replay to measure how much the test wrote to disk
+	container_name=
+	if [ -n "$CLT_DISK_USAGE_FILE" ]; then
+		container_name="clt-replay-$BASHPID-$RANDOM"
+		marker_file=$(mktemp)
+	fi
+
+	replay "$image" "$record_file" "$delay" "$container_name"
+	if [ -n "$container_name" ]; then
+		record_disk_usage "$container_name" "$record_file" "$marker_file"
+	fi
 
-	replay "$image" "$record_file" "$delay"
 	output="${record_file%.*}.cmp"
+	exit_code=0
 	if [ "$show_diff" -eq 1 ]; then
-		compare "$image" "$record_file" "$replay_file" 2>&1
+		compare "$image" "$record_file" "$replay_file" 2>&1 || exit_code=$?
 	else
-		compare "$image" "$record_file" "$replay_file" > "$output" 2>&1
+		compare "$image" "$record_file" "$replay_file" > "$output" 2>&1 || exit_code=$?
+	fi
+
+	if [ $exit_code -ne 0 ] && [ -n "$CLT_BUNDLE_DIR" ]; then
+		bundle "$image" "$record_file" "$CLT_BUNDLE_DIR" "$delay" || >&2 echo "Failed to save the bundle of the test: $record_file"
+	fi
+
+	# Screens are only worth keeping to diagnose the failure
+	if [ -d "$screens_dir" ]; then
+		if [ $exit_code -eq 0 ]; then
+			rm -rf "$screens_dir"
+		else
+			echo "Terminal screens after each step are saved to: $screens_dir"
+		fi
 	fi
+
+	release_port "$CLT_ALLOC_PORT"
+	return $exit_code
 }
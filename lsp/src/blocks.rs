@@ -0,0 +1,104 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use anyhow::{bail, Result};
+use lsp_types::{
+	DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, Position, Range,
+	RenameFile, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+use parser::BlockReference;
+use regex::Regex;
+use crate::server::{get_path, get_utf16_column, Server};
+
+/// Directories that never have tests in them
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Block included on the line of the document at the position, if any
+pub fn get_block_reference(server: &Server, uri: &Url, position: Position) -> Result<Option<BlockReference>> {
+	let references = parser::find_block_references(&get_path(uri)?, &server.get_text(uri)?);
+	Ok(references.into_iter().find(|reference| reference.line == position.line as usize + 1))
+}
+
+/// Range of the name of the block file without the directory in the statement including it
+pub fn get_stem_range(reference: &BlockReference, line: &str) -> Range {
+	let stem_start = reference.start + reference.name.rfind('/').map_or(0, |i| reference.name[..=i].chars().count());
+	let line_number = reference.line as u32 - 1;
+	Range::new(
+		Position::new(line_number, get_utf16_column(line, stem_start)),
+		Position::new(line_number, get_utf16_column(line, reference.end)),
+	)
+}
+
+/// Rename the .recb file of the block and change the statements including it in all tests and blocks of the project,
+/// only the name of the file can be changed, so the statements keep their directories
+pub fn rename_block(server: &Server, reference: &BlockReference, new_stem: &str) -> Result<WorkspaceEdit> {
+	if !Regex::new(r"^[\.a-zA-Z0-9\-\_]+$")?.is_match(new_stem) {
+		bail!("invalid name of the block \"{}\", only the name of its file without the directory can be changed", new_stem);
+	}
+	if !reference.exists {
+		bail!("block file {} does not exist", reference.path.display());
+	}
+	let new_block_path = reference.path.with_file_name(format!("{}.recb", new_stem));
+	if new_block_path.exists() {
+		bail!("block file {} already exists", new_block_path.display());
+	}
+
+	let mut files: BTreeSet<Url> = server.get_documents().keys().cloned().collect();
+	collect_tests(&std::env::current_dir()?, &mut files)?;
+	let mut operations = Vec::new();
+	for file in files {
+		let text = server.get_text(&file)?;
+		let lines: Vec<&str> = text.lines().collect();
+		let edits: Vec<OneOf<TextEdit, _>> = parser::find_block_references(&get_path(&file)?, &text).into_iter()
+			.filter(|other| other.path == reference.path)
+			.map(|other| OneOf::Left(TextEdit::new(get_stem_range(&other, lines[other.line - 1]), new_stem.to_string())))
+			.collect();
+		if !edits.is_empty() {
+			let text_document = OptionalVersionedTextDocumentIdentifier { uri: file, version: None };
+			operations.push(DocumentChangeOperation::Edit(TextDocumentEdit { text_document, edits }));
+		}
+	}
+
+	operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(RenameFile {
+		old_uri: get_uri(&reference.path)?,
+		new_uri: get_uri(&new_block_path)?,
+		options: None,
+		annotation_id: None,
+	})));
+	Ok(WorkspaceEdit { document_changes: Some(DocumentChanges::Operations(operations)), ..Default::default() })
+}
+
+/// Find .rec and .recb files in the directory skipping hidden directories and the ones with build artifacts
+fn collect_tests(dir: &Path, files: &mut BTreeSet<Url>) -> Result<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let path = entry?.path();
+		let name = path.file_name().unwrap_or_default().to_string_lossy();
+		if path.is_dir() {
+			if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+				collect_tests(&path, files)?;
+			}
+		} else if path.extension().is_some_and(|extension| extension == "rec" || extension == "recb") {
+			files.insert(get_uri(&path)?);
+		}
+	}
+	Ok(())
+}
+
+fn get_uri(path: &Path) -> Result<Url> {
+	Url::from_file_path(path).map_err(|_| anyhow::anyhow!("invalid path {}", path.display()))
+}
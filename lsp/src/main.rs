@@ -0,0 +1,74 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Language server for .rec and .recb files talking to the editor over stdin and stdout
+
+use lsp_server::{Connection, Message};
+use lsp_types::{
+	CompletionOptions, HoverProviderCapability, InitializeParams, OneOf, RenameOptions, ServerCapabilities,
+	TextDocumentSyncCapability, TextDocumentSyncKind,
+};
+
+use lsp::server::Server;
+
+fn get_capabilities() -> ServerCapabilities {
+	ServerCapabilities {
+		text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+		hover_provider: Some(HoverProviderCapability::Simple(true)),
+		completion_provider: Some(CompletionOptions {
+			trigger_characters: Some(vec!["{".to_string(), "–".to_string(), " ".to_string()]),
+			..Default::default()
+		}),
+		definition_provider: Some(OneOf::Left(true)),
+		rename_provider: Some(OneOf::Right(RenameOptions { prepare_provider: Some(true), work_done_progress_options: Default::default() })),
+		..Default::default()
+	}
+}
+
+fn main() -> anyhow::Result<()> {
+	let (connection, io_threads) = Connection::stdio();
+	let params: InitializeParams = serde_json::from_value(connection.initialize(serde_json::to_value(get_capabilities())?)?)?;
+
+	// .patterns, .clt/config and the tests to rename blocks in are looked up from the root of the project like the clt script does
+	#[allow(deprecated)]
+	let root = params.workspace_folders.and_then(|folders| folders.into_iter().next()).map(|folder| folder.uri).or(params.root_uri);
+	if let Some(root) = root.and_then(|uri| uri.to_file_path().ok()) {
+		std::env::set_current_dir(root)?;
+	}
+
+	let mut server = Server::new();
+	for message in &connection.receiver {
+		match message {
+			Message::Request(request) => {
+				if connection.handle_shutdown(&request)? {
+					break;
+				}
+				connection.sender.send(server.handle_request(request).into())?;
+			},
+			Message::Notification(notification) => {
+				for notification in server.handle_notification(notification) {
+					connection.sender.send(notification.into())?;
+				}
+			},
+			Message::Response(_) => {},
+		}
+	}
+
+	// The writer thread stops once the connection is dropped
+	drop(connection);
+	io_threads.join()?;
+	Ok(())
+}
@@ -0,0 +1,278 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Context, Result};
+use lsp_server::{ErrorCode, Notification, Request, Response};
+use lsp_types::notification::{
+	DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+	Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, PrepareRenameRequest, Rename, Request as _};
+use lsp_types::{
+	CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse, CompletionTextEdit, Diagnostic,
+	DiagnosticSeverity, Documentation, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+	HoverParams, InsertTextFormat, Location, MarkupContent, MarkupKind, Position, PrepareRenameResponse,
+	PublishDiagnosticsParams, Range, RenameParams, TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::blocks;
+
+/// Statements that take an argument, completed with a placeholder for it
+const STATEMENTS_WITH_ARGUMENT: &[&str] = &["block", "format", "repeat", "table", "assert", "resize", "exit", "skip-if", "timeout"];
+
+/// Keeps the content of the documents open in the editor, the other files are read from the disk
+#[derive(Default)]
+pub struct Server {
+	documents: HashMap<Url, String>,
+}
+
+impl Server {
+	pub fn new() -> Self {
+		Self { documents: HashMap::new() }
+	}
+
+	pub fn handle_request(&mut self, request: Request) -> Response {
+		match request.method.as_str() {
+			HoverRequest::METHOD => respond(request, |params: HoverParams| self.get_hover(params.text_document_position_params)),
+			Completion::METHOD => respond(request, |params: CompletionParams| self.get_completions(params.text_document_position)),
+			GotoDefinition::METHOD => respond(request, |params: GotoDefinitionParams| self.get_definition(params.text_document_position_params)),
+			PrepareRenameRequest::METHOD => respond(request, |params: TextDocumentPositionParams| self.prepare_rename(params)),
+			Rename::METHOD => respond(request, |params: RenameParams| self.rename(params)),
+			method => {
+				let message = format!("method {} is not supported", method);
+				Response::new_err(request.id, ErrorCode::MethodNotFound as i32, message)
+			},
+		}
+	}
+
+	/// Track the changes of the documents and return the diagnostics to publish for them
+	pub fn handle_notification(&mut self, notification: Notification) -> Vec<Notification> {
+		let uri = match notification.method.as_str() {
+			DidOpenTextDocument::METHOD => extract::<DidOpenTextDocument>(notification).map(|params| {
+				self.documents.insert(params.text_document.uri.clone(), params.text_document.text);
+				params.text_document.uri
+			}),
+			// The whole content is sent on each change as we ask for the full sync
+			DidChangeTextDocument::METHOD => extract::<DidChangeTextDocument>(notification).map(|mut params| {
+				if let Some(change) = params.content_changes.pop() {
+					self.documents.insert(params.text_document.uri.clone(), change.text);
+				}
+				params.text_document.uri
+			}),
+			DidSaveTextDocument::METHOD => extract::<DidSaveTextDocument>(notification).map(|params| params.text_document.uri),
+			DidCloseTextDocument::METHOD => {
+				return extract::<DidCloseTextDocument>(notification).into_iter().map(|params| {
+					self.documents.remove(&params.text_document.uri);
+					get_diagnostics_notification(params.text_document.uri, Vec::new())
+				}).collect();
+			},
+			_ => None,
+		};
+
+		uri.map(|uri| {
+			let diagnostics = self.get_diagnostics(&uri);
+			get_diagnostics_notification(uri, diagnostics)
+		}).into_iter().collect()
+	}
+
+	/// Content of the document open in the editor or of the file on the disk
+	pub fn get_text(&self, uri: &Url) -> Result<String> {
+		if let Some(text) = self.documents.get(uri) {
+			return Ok(text.clone());
+		}
+		let path = get_path(uri)?;
+		std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))
+	}
+
+	pub fn get_documents(&self) -> &HashMap<Url, String> {
+		&self.documents
+	}
+
+	fn get_line(&self, position: &TextDocumentPositionParams) -> Result<Option<String>> {
+		let text = self.get_text(&position.text_document.uri)?;
+		Ok(text.lines().nth(position.position.line as usize).map(str::to_string))
+	}
+
+	/// Lint issues of the test reported on the whole lines they are found at
+	fn get_diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+		let (Ok(path), Ok(text)) = (get_path(uri), self.get_text(uri)) else {
+			return Vec::new();
+		};
+		let lines: Vec<&str> = text.lines().collect();
+		parser::lint_content(&path, &text).into_iter().map(|issue| {
+			let line = issue.line.saturating_sub(1);
+			let length = lines.get(line).map_or(0, |line| get_utf16_length(line));
+			Diagnostic {
+				range: Range::new(Position::new(line as u32, 0), Position::new(line as u32, length)),
+				severity: Some(DiagnosticSeverity::WARNING),
+				source: Some("clt".to_string()),
+				message: issue.message,
+				..Default::default()
+			}
+		}).collect()
+	}
+
+	fn get_hover(&self, position: TextDocumentPositionParams) -> Result<Option<Hover>> {
+		let Some(line) = self.get_line(&position)? else {
+			return Ok(None);
+		};
+		let text = self.get_text(&position.text_document.uri)?;
+		let column = get_char_column(&line, position.position.character);
+		let hover = parser::get_hover(&text, position.position.line as usize, column, &load_patterns()?);
+		Ok(hover.map(|hover| Hover {
+			contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::PlainText, value: hover.documentation }),
+			range: Some(Range::new(
+				Position::new(position.position.line, get_utf16_column(&line, hover.start)),
+				Position::new(position.position.line, get_utf16_column(&line, hover.end)),
+			)),
+		}))
+	}
+
+	/// Complete patterns after %{, statements at the beginning of the line and checks of the output statement
+	fn get_completions(&self, position: TextDocumentPositionParams) -> Result<Option<CompletionResponse>> {
+		let Some(line) = self.get_line(&position)? else {
+			return Ok(None);
+		};
+		let prefix: String = line.chars().take(get_char_column(&line, position.position.character)).collect();
+		let line_number = position.position.line;
+
+		if Regex::new(r"%\{[A-Za-z_0-9]*$")?.is_match(&prefix) {
+			let mut items: Vec<CompletionItem> = load_patterns()?.into_iter().map(|(name, pattern)| CompletionItem {
+				label: name,
+				kind: Some(CompletionItemKind::CONSTANT),
+				detail: Some(pattern),
+				..Default::default()
+			}).collect();
+			items.sort_by(|a, b| a.label.cmp(&b.label));
+			items.extend(parser::PATTERN_DOCS.iter().map(|(name, documentation)| CompletionItem {
+				label: name.to_string(),
+				kind: Some(CompletionItemKind::KEYWORD),
+				documentation: Some(Documentation::String(documentation.to_string())),
+				..Default::default()
+			}));
+			return Ok(Some(CompletionResponse::Array(items)));
+		}
+
+		if Regex::new(r"^––– output: [a-z]*$")?.is_match(&prefix) {
+			let items = parser::OUTPUT_CHECK_DOCS.iter().map(|(name, documentation)| CompletionItem {
+				label: name.to_string(),
+				kind: Some(CompletionItemKind::KEYWORD),
+				documentation: Some(Documentation::String(documentation.to_string())),
+				..Default::default()
+			}).collect();
+			return Ok(Some(CompletionResponse::Array(items)));
+		}
+
+		// The typed part of the statement is replaced with the whole statement in the canonical form
+		if Regex::new(r"^[–\-]+ ?[a-z\-]*$")?.is_match(&prefix) {
+			let range = Range::new(Position::new(line_number, 0), position.position);
+			let items = parser::STATEMENT_DOCS.iter().map(|(name, documentation)| {
+				let new_text = match STATEMENTS_WITH_ARGUMENT.contains(name) {
+					true => format!("––– {}: $1 –––", name),
+					false => format!("––– {} –––", name),
+				};
+				CompletionItem {
+					label: name.to_string(),
+					kind: Some(CompletionItemKind::KEYWORD),
+					documentation: Some(Documentation::String(documentation.to_string())),
+					filter_text: Some(prefix.clone() + name),
+					insert_text_format: Some(InsertTextFormat::SNIPPET),
+					text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text))),
+					..Default::default()
+				}
+			}).collect();
+			return Ok(Some(CompletionResponse::Array(items)));
+		}
+
+		Ok(None)
+	}
+
+	/// Go to the .recb file of the block included on the line
+	fn get_definition(&self, position: TextDocumentPositionParams) -> Result<Option<GotoDefinitionResponse>> {
+		let reference = blocks::get_block_reference(self, &position.text_document.uri, position.position)?;
+		let Some(reference) = reference.filter(|reference| reference.exists) else {
+			return Ok(None);
+		};
+		let uri = Url::from_file_path(&reference.path).map_err(|_| anyhow::anyhow!("invalid path of the block {}", reference.path.display()))?;
+		Ok(Some(GotoDefinitionResponse::Scalar(Location::new(uri, Range::default()))))
+	}
+
+	/// Range of the file name of the block on the line that the rename changes
+	fn prepare_rename(&self, position: TextDocumentPositionParams) -> Result<Option<PrepareRenameResponse>> {
+		let (Some(line), Some(reference)) = (self.get_line(&position)?, blocks::get_block_reference(self, &position.text_document.uri, position.position)?) else {
+			return Ok(None);
+		};
+		Ok(Some(PrepareRenameResponse::Range(blocks::get_stem_range(&reference, &line))))
+	}
+
+	fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+		let position = params.text_document_position;
+		let Some(reference) = blocks::get_block_reference(self, &position.text_document.uri, position.position)? else {
+			return Ok(None);
+		};
+		blocks::rename_block(self, &reference, &params.new_name).map(Some)
+	}
+}
+
+fn respond<P: DeserializeOwned, R: Serialize>(request: Request, handler: impl FnOnce(P) -> Result<R>) -> Response {
+	let result = serde_json::from_value(request.params).map_err(anyhow::Error::from).and_then(handler);
+	match result {
+		Ok(result) => Response::new_ok(request.id, result),
+		Err(e) => Response::new_err(request.id, ErrorCode::RequestFailed as i32, format!("{:#}", e)),
+	}
+}
+
+fn extract<N: lsp_types::notification::Notification>(notification: Notification) -> Option<N::Params> {
+	notification.extract(N::METHOD).ok()
+}
+
+fn get_diagnostics_notification(uri: Url, diagnostics: Vec<Diagnostic>) -> Notification {
+	Notification::new(PublishDiagnostics::METHOD.to_string(), PublishDiagnosticsParams { uri, diagnostics, version: None })
+}
+
+/// Patterns of the project, the ones defined in .patterns of its root
+fn load_patterns() -> Result<HashMap<String, String>> {
+	if !Path::new(".patterns").exists() {
+		return Ok(HashMap::new());
+	}
+	parser::read_patterns(".patterns").context("Failed to read .patterns")
+}
+
+pub fn get_path(uri: &Url) -> Result<String> {
+	let path = uri.to_file_path().map_err(|_| anyhow::anyhow!("{} is not a file", uri))?;
+	Ok(path.to_string_lossy().to_string())
+}
+
+/// Positions in the protocol count UTF-16 code units, while the parser counts characters
+pub fn get_char_column(line: &str, character: u32) -> usize {
+	let mut units = 0;
+	line.chars().take_while(|c| {
+		units += c.len_utf16() as u32;
+		units <= character
+	}).count()
+}
+
+pub fn get_utf16_column(line: &str, column: usize) -> u32 {
+	line.chars().take(column).map(|c| c.len_utf16() as u32).sum()
+}
+
+fn get_utf16_length(line: &str) -> u32 {
+	line.encode_utf16().count() as u32
+}
@@ -0,0 +1,45 @@
+use lsp::server::{get_char_column, get_utf16_column, Server};
+use lsp_server::Notification;
+use lsp_types::notification::{DidOpenTextDocument, Notification as _, PublishDiagnostics};
+use lsp_types::{DidOpenTextDocumentParams, PublishDiagnosticsParams, TextDocumentItem, Url};
+
+#[test]
+fn test_columns_count_utf16_units() {
+  let line = "––– input ––– 😀x";
+  assert_eq!(0, get_char_column(line, 0));
+  assert_eq!(4, get_char_column(line, 4));
+  // The emoji takes two UTF-16 units and one character
+  assert_eq!(14, get_char_column(line, 14));
+  assert_eq!(14, get_char_column(line, 15));
+  assert_eq!(15, get_char_column(line, 16));
+  assert_eq!(16, get_char_column(line, 100));
+
+  assert_eq!(14, get_utf16_column(line, 14));
+  assert_eq!(16, get_utf16_column(line, 15));
+  assert_eq!(17, get_utf16_column(line, 100));
+  for column in [0, 3, 14, 15, 16] {
+    assert_eq!(column, get_char_column(line, get_utf16_column(line, column)));
+  }
+}
+
+#[test]
+fn test_diagnostics_of_invalid_test() {
+  let path = std::fs::canonicalize("tests/data/invalid.rec").unwrap();
+  let uri = Url::from_file_path(&path).unwrap();
+  let text = std::fs::read_to_string(&path).unwrap();
+  let mut server = Server::new();
+  let params = DidOpenTextDocumentParams {
+    text_document: TextDocumentItem { uri: uri.clone(), language_id: "clt".to_string(), version: 1, text },
+  };
+  let notifications = server.handle_notification(Notification::new(DidOpenTextDocument::METHOD.to_string(), params));
+  assert_eq!(1, notifications.len());
+  assert_eq!(PublishDiagnostics::METHOD, notifications[0].method);
+
+  let params: PublishDiagnosticsParams = serde_json::from_value(notifications[0].params.clone()).unwrap();
+  assert_eq!(uri, params.uri);
+  let messages: Vec<(u32, &str)> = params.diagnostics.iter().map(|d| (d.range.start.line, d.message.as_str())).collect();
+  assert!(messages.contains(&(2, "unknown statement \"––– ouput –––\", did you mean \"––– output –––\"?")));
+  // The diagnostic spans the whole line in UTF-16 units
+  let diagnostic = params.diagnostics.iter().find(|d| d.range.start.line == 2).unwrap();
+  assert_eq!(13, diagnostic.range.end.character);
+}
@@ -0,0 +1,362 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Node.js bindings to read, write, validate and compare CLT tests with access to the filesystem,
+//! so server-side tools do not need to pass the files of the test around themselves
+
+use std::collections::HashMap;
+use clt_core::Comparator;
+use napi::{Error, Result};
+use napi_derive::napi;
+
+/// Step of the test: the input, the expected output and the argument of the output statement like lines>=100
+#[napi(object)]
+pub struct Step {
+	pub input: String,
+	pub output: String,
+	pub check: Option<String>,
+	/// Expected exit code of the command when the output has ––– exit: N –––, ignored on write
+	pub exit_code: Option<i32>,
+	/// Time the command took from the duration statement of steps read from .rep files
+	pub duration_ms: Option<u32>,
+	/// Shell comment lines documenting the step written before it, set when the test is read with comments
+	pub comment: Option<String>,
+}
+
+/// Metadata of the test from its front matter, keys without a dedicated field are kept in values
+#[napi(object)]
+pub struct Metadata {
+	pub tags: Vec<String>,
+	pub owner: Option<String>,
+	pub image: Option<String>,
+	pub timeout_ms: Option<u32>,
+	pub values: HashMap<String, String>,
+}
+
+/// Test split into steps, the preamble holds everything before the first input like the front matter
+#[napi(object)]
+pub struct Test {
+	pub preamble: String,
+	pub steps: Vec<Step>,
+	/// Metadata parsed from the front matter in the preamble, missing when it is invalid, ignored on write
+	pub metadata: Option<Metadata>,
+	/// Blocks included by the test when it is read with blocks, written back to their .recb files
+	pub blocks: Option<Vec<Block>>,
+}
+
+/// Block included with ––– block: name ––– read as a test of its own
+#[napi(object)]
+pub struct Block {
+	pub name: String,
+	pub test: Test,
+}
+
+#[napi(object)]
+pub struct Comparison {
+	pub has_diff: bool,
+	/// Expected output with the differences marked by - and + as cmp prints it
+	pub diff: String,
+	pub ignored_count: u32,
+}
+
+/// Statement including the block with the absolute path of its file, the line is numbered from 1
+/// and the name spans from start to end in characters
+#[napi(object)]
+pub struct BlockReference {
+	pub file: String,
+	pub line: u32,
+	pub start: u32,
+	pub end: u32,
+	pub name: String,
+	pub path: String,
+	pub exists: bool,
+}
+
+/// Conditions the steps found by findSteps meet, the input and output are regexes,
+/// the check is the name of the output check or none for the output compared line by line
+#[napi(object)]
+pub struct StepSelector {
+	pub input: Option<String>,
+	pub output: Option<String>,
+	pub check: Option<String>,
+	pub statement: Option<String>,
+	pub block: Option<String>,
+}
+
+/// Content the file would be written with and the unified diff against its current content, empty when it stays the same
+#[napi(object)]
+pub struct WritePreview {
+	pub file: String,
+	pub content: String,
+	pub diff: String,
+}
+
+impl From<parser::WritePreview> for WritePreview {
+	fn from(preview: parser::WritePreview) -> Self {
+		Self { file: preview.file, content: preview.content, diff: preview.diff }
+	}
+}
+
+/// Documentation of the statement, the output check or the pattern under the cursor with its range in the line
+#[napi(object)]
+pub struct Hover {
+	pub start: u32,
+	pub end: u32,
+	pub documentation: String,
+}
+
+#[napi(object)]
+pub struct CompareOptions {
+	pub patterns: Option<String>,
+	pub normalize: Option<String>,
+	pub ignore_lines: Option<String>,
+}
+
+impl From<parser::TestMetadata> for Metadata {
+	fn from(metadata: parser::TestMetadata) -> Self {
+		Self {
+			tags: metadata.tags,
+			owner: metadata.owner,
+			image: metadata.image,
+			timeout_ms: metadata.timeout.map(|timeout| timeout.as_millis() as u32),
+			values: metadata.values.into_iter().collect(),
+		}
+	}
+}
+
+impl From<parser::RecFile> for Test {
+	fn from(rec_file: parser::RecFile) -> Self {
+		let metadata = rec_file.get_metadata().ok().map(Metadata::from);
+		let steps = rec_file.steps.into_iter().map(|step| Step {
+			exit_code: step.get_exit_code(),
+			duration_ms: step.duration_ms.map(|duration| duration as u32),
+			input: step.input,
+			output: step.output,
+			check: step.check,
+			comment: step.comment,
+		}).collect();
+		let blocks = rec_file.blocks.into_iter().map(|block| Block { name: block.name, test: block.file.into() }).collect::<Vec<_>>();
+		Self { preamble: rec_file.preamble, steps, metadata, blocks: (!blocks.is_empty()).then_some(blocks) }
+	}
+}
+
+impl From<Test> for parser::RecFile {
+	fn from(test: Test) -> Self {
+		let steps = test.steps.into_iter()
+			.map(|step| parser::Step {
+				input: step.input,
+				check: step.check,
+				output: step.output,
+				duration_ms: step.duration_ms.map(u128::from),
+				comment: step.comment,
+			})
+			.collect();
+		let blocks = test.blocks.unwrap_or_default().into_iter()
+			.map(|block| parser::Block { name: block.name, file: block.test.into() })
+			.collect();
+		Self { preamble: test.preamble, steps, blocks }
+	}
+}
+
+fn get_error(e: impl std::fmt::Display) -> Error {
+	Error::from_reason(e.to_string())
+}
+
+/// Parse the content of the test into steps
+#[napi]
+pub fn parse_test(content: String) -> Test {
+	parser::RecFile::parse(&content).into()
+}
+
+/// Convert the test into the content of the file
+#[napi]
+pub fn format_test(test: Test) -> String {
+	parser::RecFile::from(test).to_string()
+}
+
+/// Convert the test into YAML that is easier to edit than the statements with en dashes
+#[napi]
+pub fn format_test_yaml(test: Test) -> Result<String> {
+	parser::RecFile::from(test).to_yaml().map_err(get_error)
+}
+
+/// Parse the test converted into YAML with formatTestYaml
+#[napi]
+pub fn parse_test_yaml(content: String) -> Result<Test> {
+	parser::RecFile::from_yaml(&content).map(Test::from).map_err(|e| get_error(format!("Failed to parse YAML: {}", e)))
+}
+
+/// Escape the lines of the output printed by the command that look like statements, as rec does before writing it
+#[napi]
+pub fn escape_output(output: String) -> String {
+	parser::escape_output(&output)
+}
+
+/// Get the output as the command printed it from the one escaped in the test
+#[napi]
+pub fn unescape_output(output: String) -> String {
+	parser::unescape_output(&output)
+}
+
+/// Read the test from the file, with compile set blocks, templates and groups are expanded first,
+/// with blocks set the blocks it includes are read into the blocks of the test to edit them,
+/// with comments set the steps with only shell comments are attached to the steps after them
+#[napi]
+pub fn read_test(path: String, compile: Option<bool>, blocks: Option<bool>, comments: Option<bool>) -> Result<Test> {
+	let rec_file = match (compile.unwrap_or(false), blocks.unwrap_or(false)) {
+		(true, _) => parser::compile_keeping_secrets(&path, false).map(|compiled| parser::RecFile::parse(&compiled.content)),
+		(false, true) => parser::RecFile::read_with_blocks(&path),
+		(false, false) => parser::RecFile::read(&path),
+	};
+	let mut rec_file = rec_file.map_err(|e| get_error(format!("Failed to read {}: {}", path, e)))?;
+	if comments.unwrap_or(false) {
+		rec_file.attach_comments();
+	}
+	Ok(rec_file.into())
+}
+
+/// Write the test to the file and its blocks, if it has any, to their .recb files,
+/// with the version from getFileVersion it throws when the file was changed by others since then,
+/// with dryRun it writes nothing and returns the previews of the files it would write
+#[napi]
+pub fn write_test(path: String, test: Test, version: Option<String>, dry_run: Option<bool>) -> Result<Option<Vec<WritePreview>>> {
+	let error = |e: anyhow::Error| get_error(format!("Failed to write {}: {}", path, e));
+	if dry_run.unwrap_or(false) {
+		let previews = parser::preview_write_test(&path, &test.into()).map_err(error)?;
+		return Ok(Some(previews.into_iter().map(WritePreview::from).collect()));
+	}
+	parser::write_test_file_checked(&path, &test.into(), version.as_deref()).map_err(error)?;
+	Ok(None)
+}
+
+/// Version of the file to take before reading the test and pass to writeTest, empty when the file does not exist
+#[napi]
+pub fn get_file_version(path: String) -> Result<String> {
+	parser::get_file_version(&path).map_err(|e| get_error(format!("Failed to read {}: {}", path, e)))
+}
+
+/// Indices of the steps of the test meeting all conditions of the selector to pass to insertSteps or pick the ones to remove
+#[napi]
+pub fn find_steps(test: Test, selector: StepSelector) -> Result<Vec<u32>> {
+	let selector = parser::StepSelector {
+		input: selector.input,
+		output: selector.output,
+		check: selector.check,
+		statement: selector.statement,
+		block: selector.block,
+	};
+	let rec_file: parser::RecFile = test.into();
+	let indices = rec_file.find_steps(&selector).map_err(|e| get_error(format!("Invalid selector: {}", e)))?;
+	Ok(indices.into_iter().map(|i| i as u32).collect())
+}
+
+/// Insert the steps into the test file before the step at the index, throws when any of them is invalid,
+/// with dryRun it returns the preview of the test instead of writing it
+#[napi]
+pub fn insert_steps(path: String, index: u32, steps: Vec<Step>, dry_run: Option<bool>) -> Result<Option<WritePreview>> {
+	let steps: Vec<parser::Step> = steps.into_iter()
+		.map(|step| parser::Step { input: step.input, check: step.check, output: step.output, duration_ms: None, comment: step.comment })
+		.collect();
+	let error = |e: anyhow::Error| get_error(format!("Failed to insert steps into {}: {}", path, e));
+	if dry_run.unwrap_or(false) {
+		return Ok(Some(parser::preview_insert_steps(&path, index as usize, &steps).map_err(error)?.into()));
+	}
+	parser::insert_steps(&path, index as usize, &steps).map_err(error)?;
+	Ok(None)
+}
+
+/// Remove the steps following each other from the test file, throws when they are not found or found several times,
+/// with dryRun it returns the preview of the test instead of writing it
+#[napi]
+pub fn remove_steps(path: String, steps: Vec<Step>, dry_run: Option<bool>) -> Result<Option<WritePreview>> {
+	let steps: Vec<parser::Step> = steps.into_iter()
+		.map(|step| parser::Step { input: step.input, check: step.check, output: step.output, duration_ms: None, comment: step.comment })
+		.collect();
+	let error = |e: anyhow::Error| get_error(format!("Failed to remove steps from {}: {}", path, e));
+	if dry_run.unwrap_or(false) {
+		return Ok(Some(parser::preview_remove_steps(&path, &steps).map_err(error)?.into()));
+	}
+	parser::remove_steps(&path, &steps).map_err(error)?;
+	Ok(None)
+}
+
+/// Restore the test file as it was before the last edit saved to .clt/history, throws when there is none
+#[napi]
+pub fn revert_last_edit(path: String) -> Result<()> {
+	parser::revert_last_edit(&path).map_err(|e| get_error(format!("Failed to revert the last edit of {}: {}", path, e)))
+}
+
+/// Check that the test compiles and return the warnings about deprecated syntax and the stale .rep file next to it,
+/// throws the reason when the test is invalid, in strict mode deprecated syntax is invalid too
+#[napi]
+pub fn validate_test(path: String, strict: Option<bool>) -> Result<Vec<String>> {
+	let compiled = parser::compile_keeping_secrets(&path, strict.unwrap_or(false))
+		.map_err(|e| get_error(format!("Failed to compile {}: {}", path, e)))?;
+	let mut warnings: Vec<String> = compiled.deprecations.iter().map(|deprecation| deprecation.to_string()).collect();
+	warnings.extend(parser::check_replay_hash(&path, &parser::get_rep_file_path(&path), &compiled));
+	Ok(warnings)
+}
+
+/// Compare the test with the output of its replay the same way cmp does
+#[napi]
+pub fn compare_test(rec_file: String, rep_file: String, options: Option<CompareOptions>) -> Result<Comparison> {
+	let options = options.unwrap_or(CompareOptions { patterns: None, normalize: None, ignore_lines: None });
+	let comparator = Comparator::load(
+		options.patterns.as_deref().unwrap_or(".patterns"),
+		options.normalize.as_deref().unwrap_or(""),
+		options.ignore_lines.as_deref().unwrap_or(clt_core::compare::IGNORE_LINES_FILE),
+	).map_err(|e| get_error(format!("{:#}", e)))?;
+	let compiled = parser::compile_checked(&rec_file, false)
+		.map_err(|e| get_error(format!("Failed to compile {}: {}", rec_file, e)))?;
+	let output = std::fs::read_to_string(&rep_file).map_err(|e| get_error(format!("Failed to read {}: {}", rep_file, e)))?;
+
+	let mut diff = termcolor::Buffer::no_color();
+	let comparison = comparator.compare_with_source(&compiled.content, &compiled.source, &output, &mut diff)
+		.map_err(|e| get_error(format!("{:#}", e)))?;
+	Ok(Comparison {
+		has_diff: comparison.has_diff,
+		diff: String::from_utf8_lossy(diff.as_slice()).to_string(),
+		ignored_count: comparison.ignored_count as u32,
+	})
+}
+
+/// Find the documentation of the statement, the output check or the pattern at the line and the column of the test,
+/// both from 0, to show hovers in editors, user patterns are taken from .patterns or the file passed
+#[napi]
+pub fn get_hover(content: String, line: u32, column: u32, patterns: Option<String>) -> Result<Option<Hover>> {
+	let patterns_file = patterns.as_deref().unwrap_or(".patterns");
+	let patterns = match std::path::Path::new(patterns_file).exists() {
+		true => parser::read_patterns(patterns_file).map_err(|e| get_error(format!("Failed to read {}: {}", patterns_file, e)))?,
+		false => HashMap::new(),
+	};
+	let hover = parser::get_hover(&content, line as usize, column as usize, &patterns);
+	Ok(hover.map(|hover| Hover { start: hover.start as u32, end: hover.end as u32, documentation: hover.documentation }))
+}
+
+/// List the blocks included in the test and in the blocks it includes with the paths of their files
+#[napi]
+pub fn get_block_references(path: String) -> Result<Vec<BlockReference>> {
+	let references = parser::get_block_references(&path).map_err(|e| get_error(format!("Failed to read {}: {}", path, e)))?;
+	Ok(references.into_iter().map(|reference| BlockReference {
+		file: reference.file,
+		line: reference.line as u32,
+		start: reference.start as u32,
+		end: reference.end as u32,
+		name: reference.name,
+		path: reference.path.to_string_lossy().to_string(),
+		exists: reference.exists,
+	}).collect())
+}
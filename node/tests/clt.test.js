@@ -0,0 +1,61 @@
+const assert = require('node:assert');
+const fs = require('node:fs');
+const os = require('node:os');
+const path = require('node:path');
+const test = require('node:test');
+
+const clt = require('..');
+
+const dataFile = (name) => path.join(__dirname, 'data', name);
+
+test('reads steps and metadata', () => {
+  const search = clt.readTest(dataFile('search.rec'));
+  assert.deepStrictEqual(search.steps.map((step) => step.input), ['echo hello', 'false']);
+  assert.strictEqual(search.steps[0].output, 'hello\n');
+  assert.strictEqual(search.steps[1].exitCode, 1);
+  assert.deepStrictEqual(search.metadata.tags, ['smoke', 'search']);
+  assert.strictEqual(search.metadata.owner, 'search-team');
+  assert.strictEqual(search.metadata.timeoutMs, 30000);
+
+  const content = fs.readFileSync(dataFile('search.rec'), 'utf8');
+  assert.strictEqual(clt.formatTest(clt.parseTest(content)), content);
+  assert.throws(() => clt.readTest(dataFile('missing.rec')));
+});
+
+test('validates tests', () => {
+  assert.deepStrictEqual(clt.validateTest(dataFile('search.rec')), []);
+  assert.throws(() => clt.validateTest(dataFile('invalid.rec')), /Failed to compile/);
+});
+
+test('compares tests with replays', () => {
+  const passed = clt.compareTest(dataFile('search.rec'), dataFile('search.rep'));
+  assert.strictEqual(passed.hasDiff, false);
+
+  const failed = clt.compareTest(dataFile('search.rec'), dataFile('failed.rep'));
+  assert.strictEqual(failed.hasDiff, true);
+  assert.match(failed.diff, /- hello\n\+ bye/);
+  assert.throws(() => clt.compareTest(dataFile('search.rec'), dataFile('missing.rep')));
+});
+
+test('inserts and removes steps', () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), 'clt-node-'));
+  process.env.CLT_HISTORY_DIR = path.join(dir, 'history');
+  const file = path.join(dir, 'test.rec');
+  fs.copyFileSync(dataFile('search.rec'), file);
+  try {
+    const content = fs.readFileSync(file, 'utf8');
+    const preview = clt.insertSteps(file, 1, [{ input: 'echo middle', output: 'middle' }], true);
+    assert.match(preview.diff, /\+echo middle/);
+    assert.strictEqual(fs.readFileSync(file, 'utf8'), content);
+
+    clt.insertSteps(file, 1, [{ input: 'echo middle', output: 'middle' }]);
+    assert.deepStrictEqual(clt.readTest(file).steps.map((step) => step.input), ['echo hello', 'echo middle', 'false']);
+    assert.throws(() => clt.insertSteps(file, 0, [{ input: '', output: 'empty' }]));
+
+    clt.removeSteps(file, [{ input: 'echo middle', output: 'middle' }]);
+    assert.strictEqual(fs.readFileSync(file, 'utf8'), content);
+    assert.throws(() => clt.removeSteps(file, [{ input: 'echo missing', output: '' }]));
+  } finally {
+    fs.rmSync(dir, { recursive: true, force: true });
+  }
+});
@@ -2,53 +2,2805 @@ use anyhow::Result;
 use std::fs::{File, read_to_string};
 use std::io::{BufRead, BufReader};
 use std::error::Error;
+use std::collections::HashMap;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
 use regex::Regex;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 pub const COMMAND_PREFIX: &str = "––– input –––";
 pub const COMMAND_SEPARATOR: &str = "––– output –––";
 pub const BLOCK_REGEX: &str = r"(?m)^––– block: ([\.a-zA-Z0-9\-\/\_]+) –––$";
 pub const DURATION_REGEX: &str = r"(?m)^––– duration: ([0-9\.]+)ms \(([0-9\.]+)%\) –––$";
+pub const TEMPLATE_REGEX: &str = r"^%\{\{([A-Z]{1}[A-Z_0-9]*)\}\}$";
+pub const OUTPUT_CHECK_REGEX: &str = r"^––– output: (.+) –––$";
+pub const OUTPUT_FILE_REGEX: &str = r"^––– output: file=(.+) –––$";
+pub const FORMAT_REGEX: &str = r"^––– format: ([0-9]+) –––$";
+pub const TEMPLATES_DIR: &str = ".templates";
+pub const TEMPLATE_VARIABLE_REGEX: &str = r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}";
+pub const SECRET_REGEX: &str = r"%\{SECRET:([A-Za-z0-9+/=]+)\}";
+pub const SECRET_KEY_ENV: &str = "CLT_SECRET_KEY";
+pub const POLICY_FILE: &str = ".policy";
+pub const CONFIG_FILE: &str = ".clt/config";
+/// Statements written with ASCII equal signs like === input === that editors do not mangle,
+/// they are recognized only when statement-style: ascii is set in .clt/config
+pub const ASCII_STATEMENT_REGEX: &str = r"^=== ([a-z][a-z\-]*)(?:: (.+?))? ===$";
+pub const BLOCK_PATH_ENV: &str = "CLT_BLOCK_PATH";
+pub const STATEMENT_STYLE_ENV: &str = "CLT_STATEMENT_STYLE";
+pub const VARIABLES_FILE: &str = ".clt/variables";
+pub const VARIABLES_FILE_ENV: &str = "CLT_VARS_FILE";
+/// Snapshots of the test files taken before each edit to revert it, kept when the project has the .clt directory
+pub const HISTORY_DIR: &str = ".clt/history";
+pub const HISTORY_DIR_ENV: &str = "CLT_HISTORY_DIR";
+/// Number of snapshots kept for each file, the oldest ones are removed first
+pub const HISTORY_LIMIT: usize = 20;
+pub const IGNORE_START: &str = "%{IGNORE_START}";
+pub const IGNORE_END: &str = "%{IGNORE_END}";
+/// The line of the expected output that matches any number of lines up to the one matching the next expected line
+pub const SKIP_LINES: &str = "%{SKIP_LINES}";
+pub const ANCHORS: &str = "%{ANCHORS}";
+pub const ANCHOR_PREFIX: char = '@';
+/// Prefix of the output lines printed by commands that would be read as statements otherwise, like ––– input –––
+pub const ESCAPE_PREFIX: char = '\\';
+pub const LEGACY_STATEMENT_REGEX: &str = r"(?i)^\s*[–—-]{2,}\s*(input|output|block|duration|format|parallel|repeat|table|end|assert|resize|env|exit|skip-if|timeout|setup|teardown|error)\s*(?::\s*(.*?))?\s*[–—-]{2,}\s*$";
+pub const STATEMENT_LIKE_REGEX: &str = r"(?i)^\s*[–—-]{2,}\s*([a-z][a-z_\-]*)\s*(?::\s*(.*?))?\s*[–—-]{2,}\s*$";
+pub const STATEMENTS: &[&str] = &["input", "output", "block", "duration", "format", "parallel", "repeat", "table", "end", "assert", "resize", "env", "exit", "skip-if", "timeout", "setup", "teardown", "error"];
+pub const PARALLEL_START: &str = "––– parallel –––";
+pub const PARALLEL_END: &str = "––– end –––";
+/// Steps between ––– setup ––– or ––– teardown ––– and ––– end ––– are replayed, but their output is not compared
+pub const SETUP_START: &str = "––– setup –––";
+pub const TEARDOWN_START: &str = "––– teardown –––";
+pub const REPEAT_REGEX: &str = r"^––– repeat: ([0-9]+) –––$";
+pub const TABLE_REGEX: &str = r"^––– table: (.+) –––$";
+pub const ASSERT_REGEX: &str = r"^––– assert: (.+) –––$";
+pub const RESIZE_REGEX: &str = r"^––– resize: (.+) –––$";
+pub const ENV_STATEMENT: &str = "––– env –––";
+pub const EXIT_REGEX: &str = r"^––– exit: ([0-9]+) –––$";
+pub const SKIP_IF_REGEX: &str = r"^––– skip-if: (.+) –––$";
+/// The line rec writes to the output of the step skipped by ––– skip-if: condition –––
+pub const SKIPPED_LINE: &str = "––– skipped –––";
+pub const TIMEOUT_REGEX: &str = r"^––– timeout: (.+) –––$";
+/// The line rec writes to the output of the command interrupted by ––– timeout: 30s –––
+pub const TIMED_OUT_LINE: &str = "––– timed out –––";
+/// Separates the expected stderr of the command from its stdout in the output section
+pub const ERROR_STATEMENT: &str = "––– error –––";
+pub const FRONT_MATTER_LINE: &str = "---";
+/// The line of the .rep header with the hash of the test it was replayed from
+pub const TEST_HASH_PREFIX: &str = "Test hash: ";
+
+/// Variables that the runner sets for each test through the environment
+/// and that we substitute into inputs and outputs on compile as %{NAME}
+pub const RUNTIME_VARIABLES: &[(&str, &str)] = &[
+	("ALLOC_PORT", "CLT_ALLOC_PORT"),
+	("SEED", "CLT_SEED"),
+	("TZ", "CLT_TZ"),
+];
+
+/// The latest version of the .rec format this parser understands
+/// The version is bumped each time a new statement is added to the format,
+/// tests that use it declare the version with ––– format: N ––– line,
+/// so older binaries fail fast instead of misparsing the file
+/// - 1: input, output, block and duration statements
+/// - 2: output statement with the check like ––– output: lines>=100 –––
+///   or the external file with expected output like ––– output: file=expected/result.txt –––
+/// - 3: parallel groups of inputs between ––– parallel ––– and ––– end –––
+/// - 4: repeated groups of steps between ––– repeat: N ––– and ––– end –––
+/// - 5: groups of steps instantiated per row of the table between ––– table: path ––– and ––– end –––
+/// - 6: assertions on captured variables like ––– assert: ${rows} > 0 –––
+/// - 7: resizing the terminal between steps like ––– resize: 120x40 –––
+/// - 8: environment variables of the test as NAME=value lines after ––– env –––
+/// - 9: expected exit code of the command after its output like ––– exit: 1 –––
+/// - 10: steps skipped when the shell condition is true like ––– skip-if: [ "$(arch)" = aarch64 ] –––
+/// - 11: time limit of the next command like ––– timeout: 30s –––
+/// - 12: steps with unchecked output between ––– setup ––– or ––– teardown ––– and ––– end –––
+/// - 13: statements written with ASCII equal signs like === input === when enabled in .clt/config
+/// - 14: output check for the lines the output contains like ––– output: contains –––
+/// - 15: expected stderr of the command after its output and ––– error –––
+/// - 16: output of the command kept base64-encoded to compare binary files like ––– output: base64 –––
+pub const FORMAT_VERSION: u32 = 16;
 
 pub struct Duration {
   pub duration: u128,
   pub percentage: f32,
 }
 
-/// Compile the input rec file into String that
-/// - contains expanded blocks with --- block: file –––
-/// TODO: - contains expanded patterns from .patterns file into raw regex ()
-pub fn compile(rec_file_path: &str) -> Result<String> {
-	let input_file = File::open(rec_file_path)?;
+/// Statement line written with legacy or loose spelling
+/// that was accepted and converted into the canonical form
+pub struct Deprecation {
+	pub file: String,
+	pub line: usize,
+	pub found: String,
+	pub expected: String,
+}
+
+impl std::fmt::Display for Deprecation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}: deprecated statement \"{}\", use \"{}\" instead", self.file, self.line, self.found, self.expected)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+	/// The line looks like a statement with a misspelled name
+	UnknownStatement,
+	/// The statement has wrong dashes or spacing in strict mode
+	LegacyStatement,
+	/// The argument of the statement is invalid like the output check or the timeout
+	InvalidArgument,
+	/// The test requires a newer version of the format
+	UnsupportedFormat,
+}
+
+/// Error in the line of the test with the position to highlight it in editors,
+/// compile returns it wrapped into anyhow::Error, so get it with downcast_ref
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub file: String,
+	pub line: usize,
+	/// Column of the first character of the invalid part in characters starting from 1
+	pub column: usize,
+	/// The line as it is written in the file
+	pub snippet: String,
+	pub kind: ParseErrorKind,
+	pub message: String,
+}
+
+impl ParseError {
+	/// Point at the part of the line or its first non-space character
+	fn new(file: &str, line_number: usize, line: &str, part: Option<&str>, kind: ParseErrorKind, message: impl std::fmt::Display) -> Self {
+		let offset = part.filter(|part| !part.is_empty()).and_then(|part| line.find(part))
+			.unwrap_or_else(|| line.len() - line.trim_start().len());
+		Self {
+			file: file.to_string(),
+			line: line_number,
+			column: line[..offset].chars().count() + 1,
+			snippet: line.to_string(),
+			kind,
+			message: message.to_string(),
+		}
+	}
+}
+
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}: {}", self.file, self.line, self.message)
+	}
+}
+
+impl Error for ParseError {}
+
+/// The way to check the output instead of matching it line by line
+/// It is set as the argument of the output statement
+#[derive(Debug, PartialEq)]
+pub enum OutputCheck {
+	/// The number of output lines is in the range, e.g. lines>=100, lines<=5, lines=3 or lines=1..10
+	Lines { min: usize, max: Option<usize> },
+	/// The expected output is the sha256 hash of the actual output lines joined with new lines
+	Sha256,
+	/// The actual output contains the expected lines in the same order with any lines between them
+	Contains,
+	/// The output is encoded with base64 by the replay, so binary output is compared byte for byte
+	Base64,
+}
+
+impl std::fmt::Display for OutputCheck {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			OutputCheck::Lines { min, max: None } => write!(f, "lines>={}", min),
+			OutputCheck::Lines { min: 0, max: Some(max) } => write!(f, "lines<={}", max),
+			OutputCheck::Lines { min, max: Some(max) } if min == max => write!(f, "lines={}", min),
+			OutputCheck::Lines { min, max: Some(max) } => write!(f, "lines={}..{}", min, max),
+			OutputCheck::Sha256 => write!(f, "sha256"),
+			OutputCheck::Contains => write!(f, "contains"),
+			OutputCheck::Base64 => write!(f, "base64"),
+		}
+	}
+}
+
+impl OutputCheck {
+	/// Check the actual output lines against the expected ones
+	/// Returns what was expected and what we got when they do not pass
+	pub fn check(&self, expected: &[String], lines: &[String]) -> Option<(String, String)> {
+		match self {
+			OutputCheck::Lines { min, max } => {
+				let count = lines.len();
+				if count < *min || max.is_some_and(|max| count > max) {
+					return Some((format!("expected {}", self), format!("got {} lines", count)));
+				}
+				None
+			},
+			OutputCheck::Sha256 => {
+				let expected = expected.iter().find(|line| !line.is_empty()).cloned().unwrap_or_default();
+				let actual = get_output_hash(lines);
+				if expected != actual {
+					return Some((expected, actual));
+				}
+				None
+			},
+			// Lines are compared as is here, cmp matches them with patterns
+			OutputCheck::Contains => {
+				let mut actual = lines.iter();
+				let missing = expected.iter()
+					.filter(|line| !line.is_empty())
+					.find(|line| !actual.any(|actual_line| actual_line == *line))?;
+				Some((missing.clone(), "the line is missing".to_string()))
+			},
+			// Line breaks the encoded output may be wrapped with do not matter
+			OutputCheck::Base64 => {
+				let expected: String = expected.concat();
+				let actual: String = lines.concat();
+				let decode = |encoded: &str| BASE64.decode(encoded.split_whitespace().collect::<String>()).ok();
+				match (decode(&expected), decode(&actual)) {
+					(Some(expected_bytes), Some(actual_bytes)) if expected_bytes == actual_bytes => None,
+					_ => Some((expected, actual)),
+				}
+			},
+		}
+	}
+}
+
+/// Step of the test as written in the file: the input, the argument of the output statement
+/// like lines>=100 and the lines of the expected output with the statements inside it kept as is,
+/// steps of .rep files also have the time the command took from the duration statement in the output
+/// Fields are serialized in the order they are declared with the missing ones as null,
+/// so tools hashing or diffing the JSON of the test get the same one for the same test
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+	pub input: String,
+	#[serde(default)]
+	pub check: Option<String>,
+	#[serde(default)]
+	pub output: String,
+	#[serde(default)]
+	pub duration_ms: Option<u128>,
+	/// Shell comment lines documenting the step, written as a step of their own right before it,
+	/// only set by RecFile::attach_comments, so they move with the step when it is replaced
+	#[serde(default)]
+	pub comment: Option<String>,
+}
+
+impl Step {
+	/// Expected exit code of the command when the output has ––– exit: N –––
+	pub fn get_exit_code(&self) -> Option<i32> {
+		self.output.lines().find_map(parse_exit_line)
+	}
+}
+
+/// Test split into steps to generate or analyze it with tools,
+/// the preamble holds everything before the first input like the front matter or the env statement,
+/// blocks are only filled by read_with_blocks, the statements including them stay in the steps
+/// All fields are serialized even when they are empty like the ones of the steps
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RecFile {
+	#[serde(default)]
+	pub preamble: String,
+	#[serde(default)]
+	pub steps: Vec<Step>,
+	#[serde(default)]
+	pub blocks: Vec<Block>,
+}
+
+/// Conditions of RecFile::find_steps, the steps found meet all the ones that are set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepSelector {
+	/// Regex the input of the step matches
+	#[serde(default)]
+	pub input: Option<String>,
+	/// Regex the expected output of the step matches
+	#[serde(default)]
+	pub output: Option<String>,
+	/// Name of the output check like lines, sha256, contains or file, none for the output compared line by line
+	#[serde(default)]
+	pub check: Option<String>,
+	/// Statement in the output of the step, like exit, timeout or repeat written after it
+	#[serde(default)]
+	pub statement: Option<String>,
+	/// Name of the block included in the output of the step as written in the statement, e.g. auth/login
+	#[serde(default)]
+	pub block: Option<String>,
+}
+
+/// Block included with ––– block: name ––– read as a test of its own, so its steps can be edited
+/// and written back to the .recb file with write_test_file_with_blocks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+	pub name: String,
+	pub file: RecFile,
+}
+
+impl RecFile {
+	/// Split the content of the test into steps, written back it gives the same content
+	pub fn parse(content: &str) -> Self {
+		let mut steps = StepIterator::new(content.as_bytes());
+		// Reading from memory fails only on invalid UTF-8 that str cannot have
+		let steps_read: Vec<Step> = steps.by_ref().map_while(Result::ok).collect();
+		Self { preamble: steps.preamble, steps: steps_read, blocks: Vec::new() }
+	}
+
+	pub fn read(rec_file_path: &str) -> Result<Self> {
+		let mut steps = StepIterator::new(BufReader::new(File::open(rec_file_path)?));
+		let steps_read = steps.by_ref().collect::<Result<Vec<Step>>>()?;
+		Ok(Self { preamble: steps.preamble, steps: steps_read, blocks: Vec::new() })
+	}
+
+	/// Read the test with the blocks it includes and the blocks they include in turn,
+	/// blocks are found the same way as when the test is compiled
+	pub fn read_with_blocks(rec_file_path: &str) -> Result<Self> {
+		let input_dir = Path::new(rec_file_path).parent().unwrap_or(Path::new(""));
+		let mut rec_file = Self::read(rec_file_path)?;
+		rec_file.read_blocks(input_dir, &mut Vec::new())?;
+		Ok(rec_file)
+	}
+
+	fn read_blocks(&mut self, input_dir: &Path, included: &mut Vec<String>) -> Result<()> {
+		for name in self.get_block_names() {
+			if included.contains(&name) {
+				anyhow::bail!("block {} includes itself", name);
+			}
+			let block_path = find_block(input_dir, &name);
+			let mut file = Self::read(&block_path.to_string_lossy())
+				.map_err(|e| anyhow::anyhow!("Failed to read block {}: {}", block_path.display(), e))?;
+			included.push(name.clone());
+			file.read_blocks(input_dir, included)?;
+			included.pop();
+			self.blocks.push(Block { name, file });
+		}
+		Ok(())
+	}
+
+	/// Names of the blocks included by the test in the order of their statements, each one once
+	pub fn get_block_names(&self) -> Vec<String> {
+		let block_re = Regex::new(BLOCK_REGEX).expect("block regex is valid");
+		let mut names: Vec<String> = Vec::new();
+		let sections = std::iter::once(&self.preamble).chain(self.steps.iter().map(|step| &step.output));
+		for caps in sections.flat_map(|section| block_re.captures_iter(section)) {
+			if !names.iter().any(|name| name == &caps[1]) {
+				names.push(caps[1].to_string());
+			}
+		}
+		names
+	}
+
+	/// Read the test recognizing statements written with ASCII hyphens or loose spacing like --- input ---,
+	/// they are converted into the canonical form and returned as deprecations to warn about
+	pub fn read_tolerant(rec_file_path: &str) -> Result<(Self, Vec<Deprecation>)> {
+		let (content, deprecations) = fix_statements(&read_to_string(rec_file_path)?, rec_file_path)?;
+		Ok((Self::parse(&content), deprecations))
+	}
+
+	/// Convert the test into YAML that is easier to edit than the statements with en dashes,
+	/// the statements inside the sections like blocks are kept as is, so the conversion is lossless
+	pub fn to_yaml(&self) -> Result<String> {
+		Ok(serde_yaml::to_string(self)?)
+	}
+
+	pub fn from_yaml(content: &str) -> Result<Self> {
+		Ok(serde_yaml::from_str(content)?)
+	}
+
+	/// Move the steps that only have shell comments in the input and no output into the comment of the step after them
+	/// in the test and its blocks, of the comment steps following each other only the last one is attached,
+	/// the test is written back the same way
+	pub fn attach_comments(&mut self) {
+		for block in &mut self.blocks {
+			block.file.attach_comments();
+		}
+		let mut steps: Vec<Step> = Vec::with_capacity(self.steps.len());
+		for mut step in std::mem::take(&mut self.steps) {
+			let attachable = step.comment.is_none() && !is_comment_step(&step);
+			if let Some(previous) = steps.pop_if(|previous| attachable && is_comment_step(previous)) {
+				step.comment = Some(previous.input);
+			}
+			steps.push(step);
+		}
+		self.steps = steps;
+	}
+
+	/// Parse the front matter kept in the preamble
+	pub fn get_metadata(&self) -> Result<TestMetadata> {
+		parse_metadata(&self.preamble)
+	}
+
+	/// Indices of the steps meeting all conditions of the selector counted from 0,
+	/// the way insert_steps counts them, fails when its regexes are invalid
+	pub fn find_steps(&self, selector: &StepSelector) -> Result<Vec<usize>> {
+		let input_re = selector.input.as_deref().map(Regex::new).transpose()?;
+		let output_re = selector.output.as_deref().map(Regex::new).transpose()?;
+		let statement_re = Regex::new(r"^––– ([a-z][a-z\-]*)(?:: (.+))? –––$")?;
+		let block_re = Regex::new(BLOCK_REGEX)?;
+		let indices = self.steps.iter().enumerate().filter(|(_, step)| {
+			input_re.as_ref().is_none_or(|re| re.is_match(&step.input))
+				&& output_re.as_ref().is_none_or(|re| re.is_match(&step.output))
+				&& selector.check.as_deref().is_none_or(|name| get_check_name(step) == name)
+				&& selector.statement.as_deref().is_none_or(|name| {
+					step.output.lines().any(|line| statement_re.captures(line).is_some_and(|caps| &caps[1] == name))
+				})
+				&& selector.block.as_deref().is_none_or(|name| block_re.captures_iter(&step.output).any(|caps| &caps[1] == name))
+		}).map(|(i, _)| i).collect();
+		Ok(indices)
+	}
+}
+
+/// Directories to look for blocks in when they are not next to the test, e.g. a library of blocks shared
+/// between suites, taken from CLT_BLOCK_PATH separated by colons or the block-path key of .clt/config
+pub fn get_block_path() -> Vec<PathBuf> {
+	let block_path = std::env::var(BLOCK_PATH_ENV).ok().or_else(|| {
+		read_to_string(CONFIG_FILE).ok()?
+			.lines()
+			.find_map(|line| line.strip_prefix("block-path:").map(|value| value.trim().to_string()))
+	});
+	block_path.unwrap_or_default().split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from).collect()
+}
+
+/// Style of the statement lines, the classic one with en dashes is the default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementStyle {
+	Classic,
+	Ascii,
+}
+
+/// Style of the statements from CLT_STATEMENT_STYLE or the statement-style: line of .clt/config,
+/// the runner passes the one of the config in the env variable to the container, which has no config
+pub fn get_statement_style() -> StatementStyle {
+	let style = std::env::var(STATEMENT_STYLE_ENV).ok().filter(|style| !style.is_empty()).or_else(|| {
+		read_to_string(CONFIG_FILE).ok()?
+			.lines()
+			.find_map(|line| line.strip_prefix("statement-style:").map(|value| value.trim().to_string()))
+	});
+	match style.as_deref() {
+		Some("ascii") => StatementStyle::Ascii,
+		_ => StatementStyle::Classic,
+	}
+}
+
+/// Write the statements of the content in the style, e.g. to keep them ASCII after fixing them
+/// The content is expected to have the statements in the classic style as fix_statements returns them
+pub fn apply_statement_style(content: &str, style: StatementStyle) -> String {
+	if style == StatementStyle::Classic {
+		return content.to_string();
+	}
+
+	let legacy_re = Regex::new(LEGACY_STATEMENT_REGEX).expect("legacy statement regex is valid");
+	content.split_inclusive('\n').map(|line| {
+		let stripped = line.trim_end_matches(['\r', '\n']);
+		match legacy_re.captures(stripped) {
+			Some(caps) if get_statement_line(&caps[1], caps.get(2).map(|m| m.as_str())) == stripped => {
+				let statement = stripped.trim_start_matches("––– ").trim_end_matches(" –––");
+				format!("=== {} ==={}", statement, &line[stripped.len()..])
+			},
+			_ => line.to_string(),
+		}
+	}).collect()
+}
+
+/// Path to the .recb file of the block relative to the directory of the test or the first directory
+/// of the block path that has it, the one next to the test is returned when none has it to report it missing
+pub fn find_block(input_dir: &Path, name: &str) -> PathBuf {
+	let block_name = format!("{}.recb", name);
+	let block_path = input_dir.join(&block_name);
+	if block_path.exists() {
+		return block_path;
+	}
+	get_block_path().into_iter()
+		.map(|dir| dir.join(&block_name))
+		.find(|path| path.exists())
+		.unwrap_or(block_path)
+}
+
+/// The test file was changed by another writer since the version the changes were made to
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteConflict {
+	pub file: String,
+	pub expected_version: String,
+	pub version: String,
+}
+
+impl std::fmt::Display for WriteConflict {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} was changed by another writer since it was read, read it again and reapply the changes", self.file)
+	}
+}
+
+impl Error for WriteConflict {}
+
+/// Version of the file to detect the changes made to it by others before writing it back,
+/// the hash of its content or an empty string when it does not exist
+pub fn get_file_version(path: &str) -> Result<String> {
+	match read_to_string(path) {
+		Ok(content) => Ok(get_hash(&content)),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Content a file would be written with and the unified diff against its current content,
+/// which is empty when the file stays the same and has all lines added when it does not exist yet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WritePreview {
+	pub file: String,
+	pub content: String,
+	pub diff: String,
+}
+
+impl WritePreview {
+	fn new(file: &str, content: String) -> Result<Self> {
+		let existing = match read_to_string(file) {
+			Ok(existing) => existing,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e.into()),
+		};
+		let diff = similar::TextDiff::from_lines(&existing, &content)
+			.unified_diff()
+			.header(file, file)
+			.to_string();
+		Ok(Self { file: file.to_string(), content, diff })
+	}
+}
+
+/// Lock the test file for writing until the returned file is dropped, so concurrent writers wait for each other,
+/// the lock is taken on a file in the temporary directory as the test file itself is replaced on each write
+fn lock_file(path: &str) -> Result<File> {
+	let lock = File::create(std::env::temp_dir().join(format!("clt-{}.lock", get_file_id(path)?)))?;
+	lock.lock()?;
+	Ok(lock)
+}
+
+/// Identifier of the file that stays the same whatever path it is accessed by, even before the file is created
+fn get_file_id(path: &str) -> Result<String> {
+	let path = Path::new(path);
+	let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+	let id = get_hash(&std::fs::canonicalize(dir)?.join(path.file_name().unwrap_or_default()).to_string_lossy());
+	Ok(id[..16].to_string())
+}
+
+/// Directory with the snapshots of the file, taken from CLT_HISTORY_DIR or .clt/history of the project,
+/// none when the project has no .clt directory
+fn get_history_dir(path: &str) -> Result<Option<PathBuf>> {
+	let root = match std::env::var(HISTORY_DIR_ENV) {
+		Ok(dir) => PathBuf::from(dir),
+		Err(_) if Path::new(HISTORY_DIR).parent().is_some_and(Path::is_dir) => PathBuf::from(HISTORY_DIR),
+		Err(_) => return Ok(None),
+	};
+	let name = Path::new(path).file_name().unwrap_or_default().to_string_lossy();
+	Ok(Some(root.join(format!("{}-{}", name, get_file_id(path)?))))
+}
+
+/// Numbers of the snapshots in the history directory of the file, the latest one last
+fn get_snapshots(history_dir: &Path) -> Result<Vec<usize>> {
+	if !history_dir.is_dir() {
+		return Ok(Vec::new());
+	}
+	let mut snapshots = Vec::new();
+	for entry in std::fs::read_dir(history_dir)? {
+		if let Ok(number) = entry?.file_name().to_string_lossy().parse::<usize>() {
+			snapshots.push(number);
+		}
+	}
+	snapshots.sort_unstable();
+	Ok(snapshots)
+}
+
+/// Save the current content of the file to its history to revert the edit about to be made with revert_last_edit,
+/// only the last HISTORY_LIMIT snapshots are kept, nothing is saved for a missing file or without the history directory
+pub fn save_history(path: &str) -> Result<()> {
+	let Some(history_dir) = get_history_dir(path)? else {
+		return Ok(());
+	};
+	let content = match read_to_string(path) {
+		Ok(content) => content,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e.into()),
+	};
+
+	std::fs::create_dir_all(&history_dir)?;
+	let snapshots = get_snapshots(&history_dir)?;
+	let number = snapshots.last().map_or(1, |last| last + 1);
+	std::fs::write(history_dir.join(format!("{:06}", number)), content)?;
+	for old in &snapshots[..(snapshots.len() + 1).saturating_sub(HISTORY_LIMIT)] {
+		std::fs::remove_file(history_dir.join(format!("{:06}", old)))?;
+	}
+	Ok(())
+}
+
+/// Restore the content the file had before the last edit saved to its history, e.g. a bad change made by a tool,
+/// reverting again goes further back
+pub fn revert_last_edit(path: &str) -> Result<()> {
+	let _lock = lock_file(path)?;
+	let history_dir = get_history_dir(path)?
+		.ok_or_else(|| anyhow::anyhow!("no history is kept without the .clt directory in the project, run clt init"))?;
+	let Some(last) = get_snapshots(&history_dir)?.pop() else {
+		anyhow::bail!("{} has no edits to revert", path);
+	};
+	let snapshot = history_dir.join(format!("{:06}", last));
+	write_atomically(Path::new(path), &read_to_string(&snapshot)?)?;
+	std::fs::remove_file(snapshot)?;
+	Ok(())
+}
+
+/// Write the file atomically saving its current content to the history first when it changes
+fn write_with_history(path: &Path, content: &str) -> Result<()> {
+	if read_to_string(path).is_ok_and(|existing| existing != content) {
+		save_history(&path.to_string_lossy())?;
+	}
+	write_atomically(path, content)
+}
+
+/// Write the file through a temporary one in the same directory renamed over it, so readers never see it half written
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+	static WRITE_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+	let count = WRITE_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+	let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+	let temp_path = path.with_file_name(format!(".{}.{}-{}.tmp", file_name, std::process::id(), count));
+	std::fs::write(&temp_path, content)?;
+	if let Ok(metadata) = std::fs::metadata(path) {
+		std::fs::set_permissions(&temp_path, metadata.permissions())?;
+	}
+	std::fs::rename(&temp_path, path).inspect_err(|_| {
+		let _ = std::fs::remove_file(&temp_path);
+	})?;
+	Ok(())
+}
+
+/// Write the test and the steps of its blocks back to their .recb files, e.g. after editing the test read
+/// with RecFile::read_with_blocks, fails without writing anything when the block included several times
+/// is changed differently in the places it is included
+pub fn write_test_file_with_blocks(rec_file_path: &str, rec_file: &RecFile) -> Result<()> {
+	write_test_file_checked(rec_file_path, rec_file, None)
+}
+
+/// Write the test with its blocks like write_test_file_with_blocks, failing with WriteConflict
+/// when the test file is not of the version returned by get_file_version before reading it
+pub fn write_test_file_checked(rec_file_path: &str, rec_file: &RecFile, version: Option<&str>) -> Result<()> {
+	let _lock = lock_file(rec_file_path)?;
+	check_file_version(rec_file_path, version)?;
+	for (path, content) in get_test_writes(rec_file_path, rec_file)? {
+		write_with_history(&path, &content)
+			.map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+	}
+	Ok(())
+}
+
+/// Changes write_test_file_with_blocks would make to the test and its blocks without writing them,
+/// the blocks are listed only when they are changed and the test comes last
+pub fn preview_write_test(rec_file_path: &str, rec_file: &RecFile) -> Result<Vec<WritePreview>> {
+	get_test_writes(rec_file_path, rec_file)?.into_iter()
+		.map(|(path, content)| WritePreview::new(&path.to_string_lossy(), content))
+		.collect()
+}
+
+/// Files to write the test and its changed blocks to with their contents, the blocks go first,
+/// so the test is written last and never includes the blocks that are not written yet
+fn get_test_writes(rec_file_path: &str, rec_file: &RecFile) -> Result<Vec<(PathBuf, String)>> {
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or(Path::new(""));
+	let mut contents: Vec<(String, String)> = Vec::new();
+	let mut pending: Vec<&Block> = rec_file.blocks.iter().collect();
+	while let Some(block) = pending.pop() {
+		let content = block.file.to_string();
+		match contents.iter().find(|(name, _)| name == &block.name) {
+			Some((_, existing)) if existing != &content => {
+				anyhow::bail!("block {} is changed differently in the places it is included", block.name);
+			},
+			Some(_) => {},
+			None => contents.push((block.name.clone(), content)),
+		}
+		pending.extend(&block.file.blocks);
+	}
+
+	let mut writes = Vec::new();
+	for (name, content) in contents {
+		let block_path = find_block(input_dir, &name);
+		// Unchanged blocks are not touched to keep their modification time
+		if !read_to_string(&block_path).is_ok_and(|existing| existing == content) {
+			writes.push((block_path, content));
+		}
+	}
+	writes.push((PathBuf::from(rec_file_path), rec_file.to_string()));
+	Ok(writes)
+}
+
+fn check_file_version(path: &str, expected_version: Option<&str>) -> Result<()> {
+	let Some(expected_version) = expected_version else {
+		return Ok(());
+	};
+	let version = get_file_version(path)?;
+	if version != expected_version {
+		return Err(WriteConflict { file: path.to_string(), expected_version: expected_version.to_string(), version }.into());
+	}
+	Ok(())
+}
+
+/// Insert the steps into the test before the step at the index counted from 0, the number of steps appends them,
+/// steps are checked first, so the file is not changed when any of them would not be read back as it is
+pub fn insert_steps(rec_file_path: &str, index: usize, steps: &[Step]) -> Result<()> {
+	let _lock = lock_file(rec_file_path)?;
+	let rec_file = get_inserted_steps(rec_file_path, index, steps)?;
+	write_with_history(Path::new(rec_file_path), &rec_file.to_string())
+}
+
+/// Test file insert_steps would write without writing it
+pub fn preview_insert_steps(rec_file_path: &str, index: usize, steps: &[Step]) -> Result<WritePreview> {
+	WritePreview::new(rec_file_path, get_inserted_steps(rec_file_path, index, steps)?.to_string())
+}
+
+fn get_inserted_steps(rec_file_path: &str, index: usize, steps: &[Step]) -> Result<RecFile> {
+	let mut rec_file = RecFile::read(rec_file_path)?;
+	if index > rec_file.steps.len() {
+		anyhow::bail!("cannot insert steps at {}, the test has {} steps", index, rec_file.steps.len());
+	}
+	for (i, step) in steps.iter().enumerate() {
+		check_step(step).map_err(|e| anyhow::anyhow!("step {} to insert is invalid: {}", i + 1, e))?;
+	}
+
+	rec_file.steps.splice(index..index, steps.iter().cloned());
+	Ok(rec_file)
+}
+
+/// Remove the steps that follow each other in the test, they are found ignoring the formatting like in diff_structures,
+/// fails when the steps are not found or found several times, as the ones to remove would be ambiguous
+pub fn remove_steps(rec_file_path: &str, steps: &[Step]) -> Result<()> {
+	let _lock = lock_file(rec_file_path)?;
+	let rec_file = get_removed_steps(rec_file_path, steps)?;
+	write_with_history(Path::new(rec_file_path), &rec_file.to_string())
+}
+
+/// Test file remove_steps would write without writing it
+pub fn preview_remove_steps(rec_file_path: &str, steps: &[Step]) -> Result<WritePreview> {
+	WritePreview::new(rec_file_path, get_removed_steps(rec_file_path, steps)?.to_string())
+}
+
+fn get_removed_steps(rec_file_path: &str, steps: &[Step]) -> Result<RecFile> {
+	if steps.is_empty() {
+		anyhow::bail!("no steps to remove");
+	}
+	let mut rec_file = RecFile::read(rec_file_path)?;
+	let keys: Vec<_> = steps.iter().map(get_step_key).collect();
+	let found: Vec<usize> = rec_file.steps.windows(steps.len())
+		.enumerate()
+		.filter(|(_, window)| window.iter().map(get_step_key).eq(keys.iter().cloned()))
+		.map(|(i, _)| i)
+		.collect();
+	let index = match found.as_slice() {
+		[] => anyhow::bail!("the steps to remove are not found in the test"),
+		[index] => *index,
+		_ => anyhow::bail!("the steps to remove are found {} times in the test, add the steps around them to tell them apart", found.len()),
+	};
+
+	rec_file.steps.drain(index..index + steps.len());
+	Ok(rec_file)
+}
+
+/// Step with only shell comments in the input and no output, e.g. documentation of the step after it
+fn is_comment_step(step: &Step) -> bool {
+	step.comment.is_none() && step.check.is_none() && step.output.is_empty()
+		&& !step.input.is_empty() && step.input.lines().all(|line| line.starts_with('#'))
+}
+
+/// Name of the output check of the step as in OUTPUT_CHECK_DOCS or none when the output is compared line by line
+fn get_check_name(step: &Step) -> &str {
+	step.check.as_deref().map_or("none", |check| check.split(['=', '<', '>']).next().unwrap_or_default())
+}
+
+/// Check that the step written into the test is read back as one step with the same input and output
+fn check_step(step: &Step) -> Result<()> {
+	if step.input.trim().is_empty() {
+		anyhow::bail!("the input is empty");
+	}
+	if let Some(line) = step.comment.as_deref().and_then(|comment| comment.lines().find(|line| !line.starts_with('#'))) {
+		anyhow::bail!("the comment has the \"{}\" line that is not a shell comment", line);
+	}
+	for (section, content) in [("input", &step.input), ("output", &step.output)] {
+		if let Some(line) = content.lines().find(|line| *line == COMMAND_PREFIX || is_output_line(line)) {
+			anyhow::bail!("the {} has the \"{}\" statement, split it into steps", section, line);
+		}
+	}
+	if let Some(check) = step.check.as_deref().filter(|check| !check.starts_with("file=")) {
+		parse_output_check(&format!("––– output: {} –––", check))?;
+	}
+	Ok(())
+}
+
+/// Step of the test with the blocks inlined, along with the file it is written in
+/// and the chain of blocks it is included through, the outermost one first
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatStep {
+	pub step: Step,
+	pub file: String,
+	pub blocks: Vec<String>,
+	/// Number of the step in its file starting from 1
+	pub number: usize,
+}
+
+impl std::fmt::Display for FlatStep {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} step {}", self.file, self.number)?;
+		if !self.blocks.is_empty() {
+			write!(f, " (block {})", self.blocks.join(" > "))?;
+		}
+		Ok(())
+	}
+}
+
+/// List steps of the test in the order they run with the steps of its blocks in place of their statements,
+/// so errors can point to the file the step is written in instead of its index in the compiled test
+/// Only the blocks read with read_with_blocks are inlined
+pub fn flatten(rec_file_path: &str, rec_file: &RecFile) -> Vec<FlatStep> {
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or(Path::new(""));
+	let block_re = Regex::new(BLOCK_REGEX).expect("block regex is valid");
+	let mut steps = Vec::new();
+	flatten_into(&mut steps, rec_file, rec_file_path, &mut Vec::new(), input_dir, &block_re);
+	steps
+}
+
+fn flatten_into(steps: &mut Vec<FlatStep>, rec_file: &RecFile, file: &str, blocks: &mut Vec<String>, input_dir: &Path, block_re: &Regex) {
+	// Blocks included in the preamble run before the first step, the ones in the output of the step right after it
+	let sections = std::iter::once((None, &rec_file.preamble))
+		.chain(rec_file.steps.iter().enumerate().map(|(i, step)| (Some(i), &step.output)));
+	for (i, section) in sections {
+		if let Some(i) = i {
+			steps.push(FlatStep { step: rec_file.steps[i].clone(), file: file.to_string(), blocks: blocks.clone(), number: i + 1 });
+		}
+
+		for caps in block_re.captures_iter(section) {
+			let Some(block) = rec_file.blocks.iter().find(|block| block.name == caps[1]) else {
+				continue;
+			};
+			let block_file = find_block(input_dir, &block.name).to_string_lossy().to_string();
+			blocks.push(block.name.clone());
+			flatten_into(steps, &block.file, &block_file, blocks, input_dir, block_re);
+			blocks.pop();
+		}
+	}
+}
+
+/// Statement including the block with the absolute path of the .recb file it resolves to,
+/// the line is numbered from 1 and the span of the name in it is in characters from 0
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockReference {
+	pub file: String,
+	pub line: usize,
+	pub start: usize,
+	pub end: usize,
+	pub name: String,
+	pub path: PathBuf,
+	pub exists: bool,
+}
+
+/// Find the blocks included in the content of the file, e.g. the unsaved one open in the editor
+pub fn find_block_references(file_path: &str, content: &str) -> Vec<BlockReference> {
+	let input_dir = Path::new(file_path).parent().unwrap_or(Path::new(""));
+	let block_re = Regex::new(BLOCK_REGEX).expect("block regex is valid");
+	content.lines().enumerate().filter_map(|(i, line)| {
+		let name = block_re.captures(line.trim_end())?.get(1)?;
+		let path = find_block(input_dir, name.as_str());
+		let exists = path.exists();
+		let path = match exists {
+			true => std::fs::canonicalize(&path).unwrap_or(path),
+			false => std::env::current_dir().map(|dir| dir.join(&path)).unwrap_or(path),
+		};
+		Some(BlockReference {
+			file: file_path.to_string(),
+			line: i + 1,
+			start: line[..name.start()].chars().count(),
+			end: line[..name.end()].chars().count(),
+			name: name.as_str().to_string(),
+			path,
+			exists,
+		})
+	}).collect()
+}
+
+/// Find the blocks included in the test and in the blocks it includes in turn, each file is read once,
+/// references in the blocks have the absolute path of the block as the file
+pub fn get_block_references(rec_file_path: &str) -> Result<Vec<BlockReference>> {
+	let mut references = find_block_references(rec_file_path, &read_to_string(rec_file_path)?);
+	let mut visited: Vec<PathBuf> = Vec::new();
+	let mut i = 0;
+	while i < references.len() {
+		let reference = &references[i];
+		i += 1;
+		if !reference.exists || visited.contains(&reference.path) {
+			continue;
+		}
+		let block_file = reference.path.to_string_lossy().to_string();
+		visited.push(reference.path.clone());
+		let content = read_to_string(&reference.path)
+			.map_err(|e| anyhow::anyhow!("Failed to read block {}: {}", block_file, e))?;
+		references.extend(find_block_references(&block_file, &content));
+	}
+	Ok(references)
+}
+
+/// Change of the step between two versions of the test, steps are numbered from 1
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum StepChange {
+	Added { number: usize, step: Step },
+	Removed { number: usize, step: Step },
+	/// The step at the same place got another input, output or check, the old number is the one in the first version
+	Modified { old_number: usize, number: usize, old: Step, step: Step },
+}
+
+impl std::fmt::Display for StepChange {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let get_command = |step: &Step| step.input.lines().next().unwrap_or_default().to_string();
+		match self {
+			StepChange::Added { number, step } => write!(f, "step {} added: {}", number, get_command(step)),
+			StepChange::Removed { number, step } => write!(f, "step {} removed: {}", number, get_command(step)),
+			StepChange::Modified { old_number, number, old, step } => {
+				let parts: Vec<&str> = [
+					("input", normalize_section(&old.input) != normalize_section(&step.input)),
+					("check", old.check.as_deref().map(str::trim) != step.check.as_deref().map(str::trim)),
+					("output", normalize_section(&old.output) != normalize_section(&step.output)),
+				].into_iter().filter_map(|(part, changed)| changed.then_some(part)).collect();
+				write!(f, "step {} changed its {}: {}", number, parts.join(", "), get_command(step))?;
+				if old_number != number {
+					write!(f, " (step {} before)", old_number)?;
+				}
+				Ok(())
+			},
+		}
+	}
+}
+
+/// Changes between two versions of the test that ignore the formatting,
+/// like trailing spaces or empty lines at the end of the sections
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StructureDiff {
+	pub preamble_changed: bool,
+	pub changes: Vec<StepChange>,
+}
+
+impl StructureDiff {
+	pub fn is_empty(&self) -> bool {
+		!self.preamble_changed && self.changes.is_empty()
+	}
+}
+
+impl std::fmt::Display for StructureDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.preamble_changed {
+			writeln!(f, "preamble changed")?;
+		}
+		for change in &self.changes {
+			writeln!(f, "{}", change)?;
+		}
+		Ok(())
+	}
+}
+
+/// Find the steps added, removed and modified in the second version of the test,
+/// steps are matched by their longest common sequence and the unmatched ones
+/// at the same place in both versions are reported as modified
+pub fn diff_structures(a: &RecFile, b: &RecFile) -> StructureDiff {
+	let mut changes = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	for (matched_i, matched_j) in match_steps(&a.steps, &b.steps).into_iter().chain([(a.steps.len(), b.steps.len())]) {
+		push_step_changes(&mut changes, a, b, (i..matched_i).collect(), (j..matched_j).collect());
+		(i, j) = (matched_i + 1, matched_j + 1);
+	}
+
+	StructureDiff { preamble_changed: normalize_section(&a.preamble) != normalize_section(&b.preamble), changes }
+}
+
+/// Prefix of the inputs of the steps marking conflicts, they are shell comments, so the merged test still runs
+pub const CONFLICT_MARKER_PREFIX: &str = "# ";
+
+/// Test merged from two versions, conflicts are the number of places changed differently in them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergedStructure {
+	pub file: RecFile,
+	pub conflicts: usize,
+	/// The preamble was changed differently in both versions, the merged test keeps the one of ours
+	pub preamble_conflict: bool,
+}
+
+/// Merge the edits made in two versions of the test since their common base, e.g. edits made by hand
+/// and the patterns refined by a tool at the same time, steps with the same inputs are merged one by one,
+/// and the ones changed differently in both versions are kept from both of them
+/// between the steps with the inputs # <<<<<<< ours, # ======= and # >>>>>>> theirs
+pub fn merge_structures(base: &RecFile, ours: &RecFile, theirs: &RecFile) -> MergedStructure {
+	let theirs_matches: HashMap<usize, usize> = match_steps(&base.steps, &theirs.steps).into_iter().collect();
+	// Steps of the base kept in both versions split them into chunks that are merged independently
+	let stable = match_steps(&base.steps, &ours.steps).into_iter()
+		.filter_map(|(i, j)| theirs_matches.get(&i).map(|&k| (i, j, k)))
+		.chain([(base.steps.len(), ours.steps.len(), theirs.steps.len())]);
+
+	let mut merged = MergedStructure::default();
+	let (mut i, mut j, mut k) = (0, 0, 0);
+	for (stable_i, stable_j, stable_k) in stable {
+		merge_chunk(&mut merged, &base.steps[i..stable_i], &ours.steps[j..stable_j], &theirs.steps[k..stable_k]);
+		merged.file.steps.extend(ours.steps.get(stable_j).cloned());
+		(i, j, k) = (stable_i + 1, stable_j + 1, stable_k + 1);
+	}
+
+	let (base_preamble, ours_preamble, theirs_preamble) = (normalize_section(&base.preamble), normalize_section(&ours.preamble), normalize_section(&theirs.preamble));
+	merged.file.preamble = match ours_preamble == base_preamble {
+		true => theirs.preamble.clone(),
+		false => ours.preamble.clone(),
+	};
+	merged.preamble_conflict = ours_preamble != base_preamble && theirs_preamble != base_preamble && ours_preamble != theirs_preamble;
+	merged
+}
+
+/// Merge the steps changed between the same stable steps of both versions,
+/// when all of them still run the same commands, the steps are merged one by one by their inputs,
+/// so only the steps changed differently in both versions conflict instead of the whole chunk
+fn merge_chunk(merged: &mut MergedStructure, base: &[Step], ours: &[Step], theirs: &[Step]) {
+	let same = |a: &[Step], b: &[Step]| a.iter().map(get_step_key).eq(b.iter().map(get_step_key));
+	let same_inputs = |a: &[Step], b: &[Step]| a.iter().map(|step| normalize_section(&step.input)).eq(b.iter().map(|step| normalize_section(&step.input)));
+	if same(ours, base) {
+		merged.file.steps.extend_from_slice(theirs);
+	} else if same(theirs, base) || same(ours, theirs) {
+		merged.file.steps.extend_from_slice(ours);
+	} else if base.len() > 1 && same_inputs(ours, base) && same_inputs(theirs, base) {
+		for i in 0..base.len() {
+			merge_chunk(merged, &base[i..=i], &ours[i..=i], &theirs[i..=i]);
+		}
+	} else {
+		merged.conflicts += 1;
+		let marker = |text: &str| Step { input: format!("{}{}\n", CONFLICT_MARKER_PREFIX, text), ..Default::default() };
+		merged.file.steps.push(marker("<<<<<<< ours"));
+		merged.file.steps.extend_from_slice(ours);
+		merged.file.steps.push(marker("======="));
+		merged.file.steps.extend_from_slice(theirs);
+		merged.file.steps.push(marker(">>>>>>> theirs"));
+	}
+}
+
+/// Pairs of the indexes of the same steps in both lists in their longest common sequence
+fn match_steps(a: &[Step], b: &[Step]) -> Vec<(usize, usize)> {
+	let (a_keys, b_keys): (Vec<_>, Vec<_>) = (a.iter().map(get_step_key).collect(), b.iter().map(get_step_key).collect());
+
+	// Length of the longest common sequence of the steps starting from i in a and j in b
+	let mut common = vec![vec![0; b_keys.len() + 1]; a_keys.len() + 1];
+	for i in (0..a_keys.len()).rev() {
+		for j in (0..b_keys.len()).rev() {
+			common[i][j] = match a_keys[i] == b_keys[j] {
+				true => common[i + 1][j + 1] + 1,
+				false => common[i + 1][j].max(common[i][j + 1]),
+			};
+		}
+	}
+
+	let mut matches = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < a_keys.len() && j < b_keys.len() {
+		if a_keys[i] == b_keys[j] {
+			matches.push((i, j));
+			i += 1;
+			j += 1;
+		} else if common[i][j + 1] >= common[i + 1][j] {
+			j += 1;
+		} else {
+			i += 1;
+		}
+	}
+	matches
+}
+
+/// The step as compared by diff_structures and merge_structures, ignoring the formatting
+fn get_step_key(step: &Step) -> (String, Option<String>, String) {
+	(normalize_section(&step.input), step.check.as_deref().map(str::trim).map(str::to_string), normalize_section(&step.output))
+}
+
+/// Pair the steps removed and added between two matching steps as modified ones
+fn push_step_changes(changes: &mut Vec<StepChange>, a: &RecFile, b: &RecFile, removed: Vec<usize>, added: Vec<usize>) {
+	let paired = removed.len().min(added.len());
+	for (&i, &j) in removed.iter().zip(&added) {
+		changes.push(StepChange::Modified { old_number: i + 1, number: j + 1, old: a.steps[i].clone(), step: b.steps[j].clone() });
+	}
+	for &i in &removed[paired..] {
+		changes.push(StepChange::Removed { number: i + 1, step: a.steps[i].clone() });
+	}
+	for &j in &added[paired..] {
+		changes.push(StepChange::Added { number: j + 1, step: b.steps[j].clone() });
+	}
+}
+
+/// Section of the test without trailing spaces and empty lines at the end
+fn normalize_section(section: &str) -> String {
+	let lines: Vec<&str> = section.lines().map(str::trim_end).collect();
+	let count = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |i| i + 1);
+	lines[..count].join("\n")
+}
+
+/// Reads steps of the test one by one, so huge files like .rep with log dumps
+/// are processed without loading them into memory as a whole
+pub struct StepIterator<R> {
+	lines: std::io::Lines<R>,
+	next_line: Option<String>,
+	preamble: String,
+	has_preamble: bool,
+}
+
+impl<R: BufRead> StepIterator<R> {
+	pub fn new(reader: R) -> Self {
+		Self { lines: reader.lines(), next_line: None, preamble: String::new(), has_preamble: false }
+	}
+
+	/// Lines before the first input like the front matter or the env statement
+	pub fn preamble(&mut self) -> Result<&str> {
+		self.read_preamble()?;
+		Ok(&self.preamble)
+	}
+
+	fn read_preamble(&mut self) -> Result<()> {
+		if self.has_preamble {
+			return Ok(());
+		}
+		self.has_preamble = true;
+		while let Some(line) = self.read_line_if(|line| line != COMMAND_PREFIX)? {
+			self.preamble.push_str(&line);
+			self.preamble.push('\n');
+		}
+		Ok(())
+	}
+
+	/// Read the next line when it passes the check, otherwise keep it for the next call
+	fn read_line_if(&mut self, check: impl Fn(&str) -> bool) -> Result<Option<String>> {
+		let line = match self.next_line.take() {
+			Some(line) => line,
+			None => match self.lines.next() {
+				Some(line) => line?,
+				None => return Ok(None),
+			},
+		};
+		if check(&line) {
+			return Ok(Some(line));
+		}
+		self.next_line = Some(line);
+		Ok(None)
+	}
+
+	fn read_step(&mut self) -> Result<Option<Step>> {
+		self.read_preamble()?;
+		if self.read_line_if(|_| true)?.is_none() {
+			return Ok(None);
+		}
+
+		let mut input = Vec::new();
+		while let Some(line) = self.read_line_if(|line| !is_output_line(line))? {
+			input.push(line);
+		}
+		let check = self.read_line_if(|_| true)?
+			.and_then(|line| line.strip_prefix("––– output: ").and_then(|arg| arg.strip_suffix(" –––")).map(String::from));
+		let mut output = String::new();
+		let mut duration_ms = None;
+		while let Some(line) = self.read_line_if(|line| line != COMMAND_PREFIX)? {
+			if is_duration_line(&line) {
+				duration_ms = parse_duration_line(&line).ok().map(|duration| duration.duration);
+			}
+			output.push_str(&line);
+			output.push('\n');
+		}
+		Ok(Some(Step { input: input.join("\n"), check, output, duration_ms, comment: None }))
+	}
+}
+
+impl<R: BufRead> Iterator for StepIterator<R> {
+	type Item = Result<Step>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.read_step().transpose()
+	}
+}
+
+impl std::fmt::Display for RecFile {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		// Sections set by tools may lack the trailing new line
+		let write_section = |f: &mut std::fmt::Formatter, section: &str| match section.is_empty() || section.ends_with('\n') {
+			true => write!(f, "{}", section),
+			false => writeln!(f, "{}", section),
+		};
+
+		write_section(f, &self.preamble)?;
+		for step in &self.steps {
+			if let Some(comment) = &step.comment {
+				writeln!(f, "{}", COMMAND_PREFIX)?;
+				write_section(f, comment)?;
+				writeln!(f, "{}", COMMAND_SEPARATOR)?;
+			}
+			writeln!(f, "{}", COMMAND_PREFIX)?;
+			write_section(f, &step.input)?;
+			match &step.check {
+				Some(check) => writeln!(f, "––– output: {} –––", check)?,
+				None => writeln!(f, "{}", COMMAND_SEPARATOR)?,
+			}
+			write_section(f, &step.output)?;
+		}
+		Ok(())
+	}
+}
+
+/// Metadata of the test from the front matter enclosed between two --- lines at its very beginning,
+/// keys without a dedicated field like locks or terminal are kept in values as written
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestMetadata {
+	pub tags: Vec<String>,
+	pub owner: Option<String>,
+	/// Image the test requires to run in, set with the image key
+	pub image: Option<String>,
+	/// Default time limit of each command unless the step sets its own with ––– timeout –––
+	pub timeout: Option<std::time::Duration>,
+	/// Files or directories outside the test it depends on, like configs of daemons, relative to the test
+	pub depends: Vec<String>,
+	/// Config templates relative to the test with the paths in the container to render them to before the test starts
+	pub templates: Vec<(String, String)>,
+	/// Tests relative to this one that should run before it, e.g. the one creating the schema
+	pub requires: Vec<String>,
+	pub values: Vec<(String, String)>,
+}
+
+impl TestMetadata {
+	/// Get the value of the key as written in the front matter
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.values.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+	}
+}
+
+/// Parse the front matter of the test, the test without it has empty metadata
+/// Lists are supported in both "a, b" and "[a, b]" forms as the runner reads them
+pub fn parse_metadata(content: &str) -> Result<TestMetadata> {
+	let mut metadata = TestMetadata::default();
+	let mut lines = content.lines();
+	if lines.next() != Some(FRONT_MATTER_LINE) {
+		return Ok(metadata);
+	}
+
+	let mut is_closed = false;
+	for line in lines.by_ref() {
+		if line == FRONT_MATTER_LINE {
+			is_closed = true;
+			break;
+		}
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let (key, value) = line.split_once(':')
+			.ok_or_else(|| anyhow::anyhow!("expected key: value in the front matter, got \"{}\"", line))?;
+		let (key, value) = (key.trim(), value.trim());
+		match key {
+			"tags" => metadata.tags = parse_metadata_list(value),
+			"owner" => metadata.owner = Some(value.to_string()),
+			"image" => metadata.image = Some(value.to_string()),
+			"timeout" => metadata.timeout = Some(parse_timeout(value)?),
+			"depends" => metadata.depends = parse_metadata_list(value),
+			"requires" => metadata.requires = parse_metadata_list(value),
+			"templates" => metadata.templates = parse_metadata_list(value).iter().map(|item| parse_template_item(item)).collect::<Result<_>>()?,
+			_ => {},
+		}
+		metadata.values.push((key.to_string(), value.to_string()));
+	}
+
+	if !is_closed {
+		anyhow::bail!("the front matter is not closed with the {} line", FRONT_MATTER_LINE);
+	}
+
+	Ok(metadata)
+}
+
+/// Parse the item of the templates list written as template:/path/in/container
+fn parse_template_item(item: &str) -> Result<(String, String)> {
+	match item.split_once(':') {
+		Some((template, destination)) if !template.trim().is_empty() && destination.trim().starts_with('/') => {
+			Ok((template.trim().to_string(), destination.trim().to_string()))
+		},
+		_ => anyhow::bail!("expected template:/path/in/container in templates, got \"{}\"", item),
+	}
+}
+
+fn parse_metadata_list(value: &str) -> Vec<String> {
+	value.replace(['[', ']', '"'], "")
+		.split(',')
+		.map(|item| item.trim().to_string())
+		.filter(|item| !item.is_empty())
+		.collect()
+}
+
+/// Result of the compilation with the deprecations we found on the way
+pub struct Compiled {
+	pub content: String,
+	pub deprecations: Vec<Deprecation>,
+	/// Hash of the content without values of runtime variables, which change from run to run,
+	/// and with secrets encrypted, rec saves it to the .rep file to find the ones replayed from another version of the test
+	pub hash: String,
+	/// The content with %{NAME} of variables and runtime variables and %{SECRET:...} tokens kept in inputs,
+	/// it has the same lines as the content, so refine writes the inputs back as the test has them instead of their values
+	pub source: String,
+}
+
+/// Compile the input rec file into String that
+/// - contains expanded blocks with --- block: file –––
+/// - contains expanded templates with %{{NAME}} from the .templates folder
+/// - contains expected outputs loaded from external files after their ––– output: file=path ––– statements
+/// - contains repeated groups expanded N times with %{I} set to the number of the iteration
+/// - contains table groups expanded for each row of the CSV or JSON file with %{column} set to its values
+/// - contains parallel groups converted into a single step that runs their inputs concurrently
+/// - contains statements in canonical form even if they were written with legacy spelling
+/// - contains values of runtime variables like %{ALLOC_PORT} or %{SEED} set by the runner except in expected outputs,
+///   where the comparison matches them with the values of the run
+/// - contains values of variables from .clt/variables or the file in CLT_VARS_FILE in inputs as %{NAME}
+/// - contains decrypted values of %{SECRET:...} tokens
+///
+/// TODO: contains expanded patterns from .patterns file into raw regex ()
+pub fn compile(rec_file_path: &str) -> Result<String> {
+	Ok(compile_checked(rec_file_path, false)?.content)
+}
+
+/// Same as compile but also returns the deprecations found
+/// In strict mode any legacy spelling of the statement is an error
+pub fn compile_checked(rec_file_path: &str, strict: bool) -> Result<Compiled> {
+	let compiled = compile_keeping_secrets(rec_file_path, strict)?;
+	Ok(Compiled { content: decrypt_secrets(&compiled.content)?, ..compiled })
+}
+
+/// Same as compile_checked but keeps %{SECRET:...} tokens encrypted,
+/// so the result can be shared, e.g. in the bundle to reproduce the failure
+pub fn compile_keeping_secrets(rec_file_path: &str, strict: bool) -> Result<Compiled> {
+	let input_file = File::open(rec_file_path)?;
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or_else(|| Path::new(""));
+	let reader = BufReader::new(input_file);
+	let mut result = String::new();
+	let mut checker = StatementChecker::new(strict)?;
+
+	let block_re = Regex::new(BLOCK_REGEX)?;
+	let duration_re = Regex::new(DURATION_REGEX)?;
+	let template_re = Regex::new(TEMPLATE_REGEX)?;
+	let format_re = Regex::new(FORMAT_REGEX)?;
+	let output_file_re = Regex::new(OUTPUT_FILE_REGEX)?;
+	let resize_re = Regex::new(RESIZE_REGEX)?;
+	let timeout_re = Regex::new(TIMEOUT_REGEX)?;
+	// The expected output is loaded from the file, so only statements may follow its statement
+	let mut in_output_file = false;
+	for (i, line) in reader.lines().enumerate() {
+		let line = checker.check(line?, rec_file_path, i + 1)?;
+		if let Some(caps) = output_file_re.captures(&line) {
+			let output_path = input_dir.join(caps[1].trim());
+			let output_content = read_to_string(&output_path)
+				.map_err(|e| anyhow::anyhow!("{}:{}: failed to read expected output {}: {}", rec_file_path, i + 1, output_path.display(), e))?;
+			result.push_str(&line);
+			result.push('\n');
+			result.push_str(output_content.trim_end());
+			result.push('\n');
+			in_output_file = true;
+			continue;
+		}
+
+		if in_output_file && line.starts_with("––– ") {
+			in_output_file = false;
+		} else if in_output_file && !line.trim().is_empty() {
+			let message = "the expected output is loaded from the file of the output statement, move this line into the file".to_string();
+			return Err(ParseError::new(rec_file_path, i + 1, &line, None, ParseErrorKind::InvalidArgument, message).into());
+		}
+
+		let invalid_argument = |arg: &str, e: String| ParseError::new(rec_file_path, i + 1, &line, Some(arg), ParseErrorKind::InvalidArgument, e);
+		if let Err(e) = parse_output_check(&line) {
+			let arg = line.trim_start_matches("––– output:").trim_end_matches('–').trim();
+			return Err(invalid_argument(arg, e.to_string()).into());
+		}
+
+		if line.starts_with("––– exit:") && parse_exit_line(&line).is_none() {
+			let arg = line.trim_start_matches("––– exit:").trim_end_matches('–').trim();
+			return Err(invalid_argument(arg, format!("expected exit code like ––– exit: 1 –––, got \"{}\"", line)).into());
+		}
+
+		if let Some(caps) = resize_re.captures(&line) {
+			if let Err(e) = parse_terminal_size(&caps[1]) {
+				return Err(invalid_argument(&caps[1], e.to_string()).into());
+			}
+		}
+
+		if let Some(caps) = timeout_re.captures(&line) {
+			if let Err(e) = parse_timeout(&caps[1]) {
+				return Err(invalid_argument(&caps[1], e.to_string()).into());
+			}
+		}
+
+		if let Some(caps) = format_re.captures(&line) {
+			let version: u32 = caps[1].parse()?;
+			if version > FORMAT_VERSION {
+				let message = format!(
+					"test requires format v{}, but this version of CLT supports up to v{}, please update it",
+					version, FORMAT_VERSION
+				);
+				return Err(ParseError::new(rec_file_path, i + 1, &line, Some(&caps[1]), ParseErrorKind::UnsupportedFormat, message).into());
+			}
+		} else if let Some(caps) = block_re.captures(&line) {
+			let block_path = find_block(input_dir, caps.get(1).map_or("", |m| m.as_str()));
+			let absolute_path = std::fs::canonicalize(block_path)?;
+			let block_file = absolute_path.to_string_lossy().to_string();
+			let block_content = read_to_string(absolute_path)?;
+			let block_trimmed = block_content.trim();
+			let line_offset = block_content[..block_content.len() - block_content.trim_start().len()].matches('\n').count();
+			for (j, block_line) in block_trimmed.lines().enumerate() {
+				let block_line = checker.check(block_line.to_string(), &block_file, line_offset + j + 1)?;
+				result.push_str(&block_line);
+				result.push('\n');
+			}
+			continue;
+		} else if let Some(caps) = template_re.captures(line.trim()) {
+			let template_path = Path::new(TEMPLATES_DIR).join(&caps[1]);
+			let template_content = read_to_string(&template_path)
+				.map_err(|e| anyhow::anyhow!("Failed to read template {}: {}", template_path.display(), e))?;
+			result.push_str(template_content.trim());
+			result.push('\n');
+			continue;
+		} else if duration_re.is_match(&line) {
+			continue;
+		}
+
+		result.push_str(&line);
+		result.push('\n');
+	}
+
+	let variables = load_variables()?;
+	let expand = |content: &str, variables: &[(String, String)]| -> Result<String> {
+		let lines: Vec<&str> = content.lines().collect();
+		let result = GroupExpander::new(input_dir)?.expand(&lines, &mut 0, false)?;
+		let result = substitute_variables(&result, variables);
+		let result = expand_unchecked_sections(&result)?;
+		expand_parallel_groups(&result)
+	};
+	let source = expand(&result, &[])?;
+	let mut hash_content = expand(&result, &variables)?;
+
+	let result = expand(&substitute_runtime_variables(&result), &variables)?;
+	parse_env(&result).map_err(|e| anyhow::anyhow!("{}: {}", rec_file_path, e))?;
+	let metadata = parse_metadata(&result).map_err(|e| anyhow::anyhow!("{}: {}", rec_file_path, e))?;
+
+	// Files the test declares to depend on are not part of it, but a change of them changes the result too
+	for file in get_declared_files(input_dir, &metadata).map_err(|e| anyhow::anyhow!("{}: {}", rec_file_path, e))? {
+		hash_content.push_str(&file.to_string_lossy());
+		hash_content.push_str(&String::from_utf8_lossy(&std::fs::read(&file).unwrap_or_default()));
+	}
+	let hash = get_hash(&hash_content);
+
+	Ok(Compiled { content: result, deprecations: checker.deprecations, hash, source })
+}
+
+/// List the files the test reads besides itself: blocks, expected output files, tables
+/// and the files declared in the depends key of its front matter, so the test is rerun when any of them changes
+pub fn get_dependencies(rec_file_path: &str) -> Result<Vec<PathBuf>> {
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or_else(|| Path::new(""));
+	let block_re = Regex::new(BLOCK_REGEX)?;
+	let output_file_re = Regex::new(OUTPUT_FILE_REGEX)?;
+	let table_re = Regex::new(TABLE_REGEX)?;
+	let mut checker = StatementChecker::new(false)?;
+	let content = read_to_string(rec_file_path)?;
+
+	let mut files = Vec::new();
+	let mut lines: Vec<String> = content.lines().map(String::from).collect();
+	let mut i = 0;
+	while i < lines.len() {
+		let line = checker.check(lines[i].clone(), rec_file_path, i + 1).unwrap_or_else(|_| lines[i].clone());
+		i += 1;
+		// Paths in blocks are relative to the test, as the block is compiled into it
+		if let Some(caps) = block_re.captures(&line) {
+			let block_path = find_block(input_dir, &caps[1]);
+			if files.contains(&block_path) {
+				continue;
+			}
+			let block_content = read_to_string(&block_path)
+				.map_err(|e| anyhow::anyhow!("Failed to read block {}: {}", block_path.display(), e))?;
+			lines.splice(i..i, block_content.lines().map(String::from));
+			files.push(block_path);
+		} else if let Some(caps) = output_file_re.captures(&line).or_else(|| table_re.captures(&line)) {
+			files.push(input_dir.join(caps[1].trim()));
+		}
+	}
+	files.extend(get_declared_files(input_dir, &parse_metadata(&content)?)?);
+	files.sort();
+	files.dedup();
+
+	Ok(files)
+}
+
+/// Find the first parallel, repeat or table group in the test or the blocks it includes
+/// and return the file and the line of its statement, refine can not write such groups back
+/// as the replay runs them expanded into other steps
+pub fn find_group(rec_file_path: &str) -> Result<Option<(String, usize)>> {
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or_else(|| Path::new(""));
+	let block_re = Regex::new(BLOCK_REGEX)?;
+	let group_re = Regex::new(&[REPEAT_REGEX, TABLE_REGEX].join("|"))?;
+	let mut files = vec![PathBuf::from(rec_file_path)];
+	while let Some(file) = files.pop() {
+		let content = read_to_string(&file).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+		let file = file.to_string_lossy().to_string();
+		let mut checker = StatementChecker::new(false)?;
+		for (i, line) in content.lines().enumerate() {
+			let line = checker.check(line.to_string(), &file, i + 1).unwrap_or_else(|_| line.to_string());
+			if line == PARALLEL_START || group_re.is_match(&line) {
+				return Ok(Some((file, i + 1)));
+			}
+			// Blocks are included into the test as they are, so their groups are expanded the same way
+			if let Some(caps) = block_re.captures(&line).filter(|_| file == rec_file_path) {
+				files.insert(0, find_block(input_dir, &caps[1]));
+			}
+		}
+	}
+
+	Ok(None)
+}
+
+/// Order the tests so each one comes after the tests it requires, adding the required ones that are not in the list,
+/// tests are kept in the given order otherwise, and the ones that require each other in a cycle are an error
+pub fn get_execution_plan(rec_file_paths: &[String]) -> Result<Vec<String>> {
+	let mut plan = Vec::new();
+	let mut planned = Vec::new();
+	for rec_file_path in rec_file_paths {
+		add_to_plan(rec_file_path, &mut plan, &mut planned, &mut Vec::new())?;
+	}
+
+	Ok(plan)
+}
+
+/// Add the tests the test requires and then the test itself to the plan unless it is there already
+/// Tests are identified by their canonical paths, as the same test can be required by different relative paths
+fn add_to_plan(rec_file_path: &str, plan: &mut Vec<String>, planned: &mut Vec<PathBuf>, requiring: &mut Vec<(PathBuf, String)>) -> Result<()> {
+	let id = std::fs::canonicalize(rec_file_path).map_err(|e| anyhow::anyhow!("failed to read the test {}: {}", rec_file_path, e))?;
+	if planned.contains(&id) {
+		return Ok(());
+	}
+	if let Some(pos) = requiring.iter().position(|(requiring_id, _)| *requiring_id == id) {
+		let cycle: Vec<&str> = requiring[pos..].iter().map(|(_, path)| path.as_str()).chain([rec_file_path]).collect();
+		anyhow::bail!("tests require each other in a cycle: {}", cycle.join(" -> "));
+	}
+
+	let metadata = parse_metadata(&read_to_string(rec_file_path)?).map_err(|e| anyhow::anyhow!("{}: {}", rec_file_path, e))?;
+	let input_dir = Path::new(rec_file_path).parent().unwrap_or_else(|| Path::new(""));
+	requiring.push((id.clone(), rec_file_path.to_string()));
+	for required in &metadata.requires {
+		add_to_plan(&input_dir.join(required).to_string_lossy(), plan, planned, requiring)?;
+	}
+	requiring.pop();
+
+	planned.push(id);
+	plan.push(rec_file_path.to_string());
+	Ok(())
+}
+
+/// Files declared in the depends key of the front matter with directories replaced by the files in them
+/// The ones that do not exist are kept as they are, e.g. the files outside the project are not mounted into the container,
+/// they only select the tests and change the hash when they appear
+fn get_declared_files(input_dir: &Path, metadata: &TestMetadata) -> Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	for path in &metadata.depends {
+		collect_files(&input_dir.join(path), &mut files)?;
+	}
+	for (template, _) in &metadata.templates {
+		let path = input_dir.join(template);
+		if !path.is_file() {
+			anyhow::bail!("the template {} does not exist", path.display());
+		}
+		files.push(path);
+	}
+
+	Ok(files)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+	if !path.is_dir() {
+		files.push(path.to_path_buf());
+		return Ok(());
+	}
+
+	let mut entries = std::fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<std::io::Result<Vec<_>>>()?;
+	entries.sort();
+	for entry in entries {
+		collect_files(&entry, files)?;
+	}
+
+	Ok(())
+}
+
+/// Read variables to substitute into inputs from the file in CLT_VARS_FILE or .clt/variables when it exists
+pub fn load_variables() -> Result<Vec<(String, String)>> {
+	match std::env::var(VARIABLES_FILE_ENV) {
+		Ok(path) if !path.is_empty() => read_variables(&path),
+		_ if Path::new(VARIABLES_FILE).exists() => read_variables(VARIABLES_FILE),
+		_ => Ok(Vec::new()),
+	}
+}
+
+/// Read variables from the file with NAME=value lines, empty lines and lines starting with # are skipped
+pub fn read_variables(path: &str) -> Result<Vec<(String, String)>> {
+	let content = read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read variables from {}: {}", path, e))?;
+	let name_re = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$")?;
+	let mut variables = Vec::new();
+	for (i, line) in content.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		match line.split_once('=') {
+			Some((name, value)) if name_re.is_match(name.trim()) => variables.push((name.trim().to_string(), value.trim().to_string())),
+			_ => anyhow::bail!("{}:{}: expected NAME=value, got \"{}\"", path, i + 1, line),
+		}
+	}
+	Ok(variables)
+}
+
+/// Replace %{NAME} in inputs with the values of the variables, outputs are left as is
+/// since the same syntax is used for patterns there
+/// Substitute the values of runtime variables set by the runner everywhere except the expected outputs,
+/// where %{NAME} is kept and matched with the value of the run, so refine does not write the value into the test
+fn substitute_runtime_variables(content: &str) -> String {
+	let values: Vec<(String, String)> = RUNTIME_VARIABLES.iter()
+		.filter_map(|(name, env_name)| std::env::var(env_name).ok().map(|value| (format!("%{{{}}}", name), value)))
+		.collect();
+	if values.is_empty() {
+		return content.to_string();
+	}
+
+	let mut result = String::with_capacity(content.len());
+	let mut in_output = false;
+	for line in content.lines() {
+		if line == COMMAND_PREFIX {
+			in_output = false;
+		} else if is_output_line(line) {
+			in_output = true;
+		}
+
+		let mut line = line.to_string();
+		if !in_output || line.starts_with("–––") {
+			for (placeholder, value) in &values {
+				line = line.replace(placeholder, value);
+			}
+		}
+		result.push_str(&line);
+		result.push('\n');
+	}
+	result
+}
+
+fn substitute_variables(content: &str, variables: &[(String, String)]) -> String {
+	if variables.is_empty() {
+		return content.to_string();
+	}
+
+	let mut result = String::with_capacity(content.len());
+	let mut in_input = false;
+	for line in content.lines() {
+		if line == COMMAND_PREFIX {
+			in_input = true;
+		} else if is_output_line(line) {
+			in_input = false;
+		} else if in_input && line.contains("%{") {
+			let mut line = line.to_string();
+			for (name, value) in variables {
+				line = line.replace(&format!("%{{{}}}", name), value);
+			}
+			result.push_str(&line);
+			result.push('\n');
+			continue;
+		}
+		result.push_str(line);
+		result.push('\n');
+	}
+	result
+}
+
+/// Convert statements written with ASCII hyphens or loose spacing into the canonical form
+/// and return the fixed content with the deprecations found to warn about them,
+/// misspelled statements are errors as they are in compile
+pub fn fix_statements(content: &str, file: &str) -> Result<(String, Vec<Deprecation>)> {
+	let mut checker = StatementChecker::new(false)?;
+	let mut fixed = String::with_capacity(content.len());
+	for (i, line) in content.split_inclusive('\n').enumerate() {
+		let (line, ending) = match line.strip_suffix("\r\n").or_else(|| line.strip_suffix('\n')) {
+			Some(stripped) => (stripped, &line[stripped.len()..]),
+			None => (line, ""),
+		};
+		fixed.push_str(&checker.check(line.to_string(), file, i + 1)?);
+		fixed.push_str(ending);
+	}
+	Ok((fixed, checker.deprecations))
+}
+
+/// Lowest version of the format that has all statements of the content written in the classic style
+pub fn get_required_format(content: &str) -> u32 {
+	let versions: &[(&str, u32)] = &[
+		("––– output: lines", 2), ("––– output: sha256", 2), ("––– output: file=", 2),
+		(PARALLEL_START, 3), ("––– repeat:", 4), ("––– table:", 5), ("––– assert:", 6), ("––– resize:", 7),
+		(ENV_STATEMENT, 8), ("––– exit:", 9), ("––– skip-if:", 10), ("––– timeout:", 11),
+		(SETUP_START, 12), (TEARDOWN_START, 12), ("––– output: contains", 14), (ERROR_STATEMENT, 15),
+		("––– output: base64", 16),
+	];
+	content.lines()
+		.filter_map(|line| versions.iter().find(|(prefix, _)| line.starts_with(prefix)).map(|(_, version)| *version))
+		.max()
+		.unwrap_or(1)
+}
+
+/// Upgrade the test written for an older CLT: statements written with ASCII hyphens or loose spacing are fixed,
+/// all statements are written in the style of the project, and the tests, unlike blocks, declare the version
+/// of the format their statements need with the line after the front matter, the declared higher version is kept
+pub fn migrate_test(content: &str, file: &str) -> Result<(String, Vec<Deprecation>)> {
+	let (fixed, deprecations) = fix_statements(content, file)?;
+	let style = get_statement_style();
+	if file.ends_with(".recb") {
+		return Ok((apply_statement_style(&fixed, style), deprecations));
+	}
+
+	let mut required = get_required_format(&fixed);
+	if style == StatementStyle::Ascii && content.lines().any(|line| line.starts_with("=== ")) {
+		required = required.max(13);
+	}
+
+	let format_re = Regex::new(FORMAT_REGEX)?;
+	let declared = fixed.lines().find_map(|line| format_re.captures(line).and_then(|caps| caps[1].parse::<u32>().ok()));
+	let format_line = format!("––– format: {} –––", required);
+	let migrated = match declared {
+		Some(version) if version >= required => fixed,
+		Some(_) => fixed.lines().map(|line| match format_re.is_match(line) {
+			true => format!("{}\n", format_line),
+			false => format!("{}\n", line),
+		}).collect(),
+		None => {
+			// The format line goes right after the front matter that has to stay at the very beginning
+			let lines: Vec<&str> = fixed.lines().collect();
+			let insert_at = match lines.first() {
+				Some(&FRONT_MATTER_LINE) => lines.iter().skip(1).position(|line| *line == FRONT_MATTER_LINE).map_or(0, |pos| pos + 2),
+				_ => 0,
+			};
+			let mut migrated = String::new();
+			for (i, line) in lines.iter().enumerate() {
+				if i == insert_at {
+					migrated.push_str(&format_line);
+					migrated.push('\n');
+				}
+				migrated.push_str(line);
+				migrated.push('\n');
+			}
+			if insert_at >= lines.len() {
+				migrated.push_str(&format_line);
+				migrated.push('\n');
+			}
+			migrated
+		},
+	};
+
+	Ok((apply_statement_style(&migrated, style), deprecations))
+}
+
+/// Problem in the structure of the test found by lint_test
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintIssue {
+	pub file: String,
+	pub line: usize,
+	pub message: String,
+}
+
+impl std::fmt::Display for LintIssue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}: {}", self.file, self.line, self.message)
+	}
+}
+
+/// Check the structure of the test as written and return all problems found instead of failing on the first one:
+/// - statements written with ASCII hyphens or wrong spacing and misspelled statements
+/// - inputs without outputs and outputs without inputs before them
+/// - unknown checks in the output statement like ––– output: lines>>1 –––
+/// - blocks referring to files that do not exist
+///
+/// Issues are reported with the line numbers in the file, the file that cannot be read is reported at line 0
+pub fn lint_test(rec_file_path: &str) -> Vec<LintIssue> {
+	match File::open(rec_file_path) {
+		Ok(file) => lint_lines(rec_file_path, BufReader::new(file).lines()),
+		Err(e) => vec![LintIssue { file: rec_file_path.to_string(), line: 0, message: format!("failed to read the file: {}", e) }],
+	}
+}
+
+/// Check the content of the test the same way as lint_test, e.g. the unsaved one open in the editor,
+/// the path is used to find the blocks it includes
+pub fn lint_content(rec_file_path: &str, content: &str) -> Vec<LintIssue> {
+	lint_lines(rec_file_path, content.lines().map(|line| Ok(line.to_string())))
+}
+
+fn lint_lines(rec_file_path: &str, lines: impl Iterator<Item = std::io::Result<String>>) -> Vec<LintIssue> {
 	let input_dir = Path::new(rec_file_path).parent().unwrap_or_else(|| Path::new(""));
-	let reader = BufReader::new(input_file);
+	let mut checker = StatementChecker::new(false).expect("statement regexes are valid");
+	let block_re = Regex::new(BLOCK_REGEX).expect("block regex is valid");
+	let mut issues = Vec::new();
+	let mut issue = |line: usize, message: String| issues.push(LintIssue { file: rec_file_path.to_string(), line, message });
+
+	// Line of the last input or output statement to find the ones without a pair
+	let (mut last_input, mut last_output) = (None, None);
+	// Inputs of setup and teardown sections do not need outputs as they are not compared
+	let mut in_unchecked = false;
+	for (i, line) in lines.enumerate() {
+		let line_number = i + 1;
+		let line = match line {
+			Ok(line) => line,
+			Err(e) => {
+				issue(line_number, format!("failed to read the line: {}", e));
+				break;
+			},
+		};
+		// Statements in the ASCII style enabled in .clt/config are as valid as the classic ones
+		let line = match checker.get_statement(&line) {
+			Some((expected, true)) => {
+				issue(line_number, format!("statement \"{}\" has wrong dashes or spacing, use \"{}\"", line.trim(), expected));
+				expected
+			},
+			Some((statement, false)) => statement,
+			None => {
+				// Output text that looks like a statement is compared as is
+				let suggestion = if checker.is_output_text(&line) { None } else { checker.suggest_statement(&line) };
+				if let Some(suggestion) = suggestion {
+					issue(line_number, format!("unknown statement \"{}\", did you mean \"{}\"?", line.trim(), suggestion));
+				} else if line.contains(SKIP_LINES) && line.trim() != SKIP_LINES {
+					issue(line_number, format!("{} matches whole lines and must be on a line of its own", SKIP_LINES));
+				}
+				continue;
+			},
+		};
+
+		if line == SETUP_START || line == TEARDOWN_START {
+			in_unchecked = true;
+		} else if line == PARALLEL_END && in_unchecked {
+			in_unchecked = false;
+			last_input = None;
+		} else if line == COMMAND_PREFIX {
+			match last_input.replace(line_number) {
+				Some(input_line) if !in_unchecked => issue(input_line, "input has no output".to_string()),
+				_ => {},
+			}
+			last_output = None;
+		} else if is_output_line(&line) {
+			match (last_input.take(), last_output) {
+				(Some(_), _) => {},
+				(None, Some(output_line)) => issue(line_number, format!("output repeats the one at line {} without an input between them", output_line)),
+				(None, None) => issue(line_number, "output has no input before it".to_string()),
+			}
+			last_output = Some(line_number);
+			if !line.starts_with("––– output: file=") {
+				if let Err(e) = parse_output_check(&line) {
+					issue(line_number, e.to_string());
+				}
+			}
+		} else if line == ERROR_STATEMENT && last_output.is_none() {
+			issue(line_number, "error has no output before it".to_string());
+		} else if let Some(caps) = block_re.captures(&line) {
+			let block_path = find_block(input_dir, &caps[1]);
+			if !block_path.exists() {
+				issue(line_number, format!("block file {} does not exist", block_path.display()));
+			}
+			// The block holds steps of its own, so outputs after it do not repeat the ones before
+			last_output = None;
+		}
+	}
+
+	if let Some(input_line) = last_input {
+		issue(input_line, "input has no output".to_string());
+	}
+
+	issues
+}
+
+/// Expands groups of steps between ––– repeat: N ––– or ––– table: path ––– and ––– end –––
+/// Inner groups are expanded first, so variables refer to the closest group, while parallel, setup and teardown groups are kept as is
+struct GroupExpander<'a> {
+	input_dir: &'a Path,
+	repeat_re: Regex,
+	table_re: Regex,
+}
+
+impl<'a> GroupExpander<'a> {
+	fn new(input_dir: &'a Path) -> Result<Self> {
+		Ok(Self {
+			input_dir,
+			repeat_re: Regex::new(REPEAT_REGEX)?,
+			table_re: Regex::new(TABLE_REGEX)?,
+		})
+	}
+
+	fn expand(&self, lines: &[&str], pos: &mut usize, in_group: bool) -> Result<String> {
+		let mut result = String::new();
+		let mut kept_depth = 0;
+		while *pos < lines.len() {
+			let line = lines[*pos];
+			*pos += 1;
+			let rows = if let Some(caps) = self.repeat_re.captures(line) {
+				let count: usize = caps[1].parse()?;
+				(1..=count).map(|i| vec![("I".to_string(), i.to_string())]).collect()
+			} else if let Some(caps) = self.table_re.captures(line) {
+				self.read_table(caps[1].trim())?
+			} else {
+				if line == PARALLEL_START || line == SETUP_START || line == TEARDOWN_START {
+					kept_depth += 1;
+				} else if line == PARALLEL_END && kept_depth > 0 {
+					kept_depth -= 1;
+				} else if line == PARALLEL_END && in_group {
+					return Ok(result);
+				}
+
+				result.push_str(line);
+				result.push('\n');
+				continue;
+			};
+
+			let body = self.expand(lines, pos, true)?;
+			for row in rows {
+				let mut instance = body.clone();
+				for (name, value) in row {
+					instance = instance.replace(&format!("%{{{}}}", name), &value);
+				}
+				result.push_str(&instance);
+			}
+		}
+
+		if in_group {
+			anyhow::bail!("the group of steps is not closed with {}", PARALLEL_END);
+		}
+
+		Ok(result)
+	}
+
+	/// Read rows of the table as pairs of the column name and the value
+	/// The table is a CSV file with the header or a JSON array of objects
+	fn read_table(&self, table: &str) -> Result<Vec<Vec<(String, String)>>> {
+		let table_path = self.input_dir.join(table);
+		let table_content = read_to_string(&table_path)
+			.map_err(|e| anyhow::anyhow!("Failed to read table {}: {}", table_path.display(), e))?;
+
+		if table.ends_with(".json") {
+			let rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_str(&table_content)
+				.map_err(|e| anyhow::anyhow!("Failed to parse table {}: {}", table_path.display(), e))?;
+			return Ok(rows.into_iter().map(|row| {
+				row.into_iter().map(|(name, value)| match value {
+					serde_json::Value::String(value) => (name, value),
+					value => (name, value.to_string()),
+				}).collect()
+			}).collect());
+		}
+
+		let mut reader = csv::Reader::from_reader(table_content.as_bytes());
+		let headers = reader.headers()?.clone();
+		let mut rows = Vec::new();
+		for record in reader.records() {
+			let record = record?;
+			rows.push(headers.iter().zip(record.iter()).map(|(name, value)| (name.to_string(), value.to_string())).collect());
+		}
+
+		Ok(rows)
+	}
+}
+
+/// Replace the expected output of each step between ––– setup ––– or ––– teardown ––– and ––– end –––
+/// with the ignored region, so the commands are replayed as usual, but cmp never reports a diff in them
+/// The steps may have no output at all, and their exit codes and assertions are not checked either
+/// The start and end statements are kept for cmp to print them as they are, so refine keeps the sections
+fn expand_unchecked_sections(content: &str) -> Result<String> {
+	if !content.lines().any(|line| line == SETUP_START || line == TEARDOWN_START) {
+		return Ok(content.to_string());
+	}
+
+	let step_statement_re = Regex::new(&[RESIZE_REGEX, SKIP_IF_REGEX, TIMEOUT_REGEX].join("|"))?;
+	let unchecked_output = format!("{}\n{}\n", COMMAND_SEPARATOR, IGNORE_START);
 	let mut result = String::new();
+	let mut section: Option<&str> = None;
+	let mut in_input = false;
+	for line in content.lines() {
+		let Some(start) = section else {
+			if line == SETUP_START || line == TEARDOWN_START {
+				section = Some(line);
+			}
+			result.push_str(line);
+			result.push('\n');
+			continue;
+		};
 
-	let block_re = Regex::new(BLOCK_REGEX)?;
-	let duration_re = Regex::new(DURATION_REGEX)?;
-	for line in reader.lines() {
-		let line = line.unwrap();
-		if let Some(caps) = block_re.captures(&line) {
-			let block_name = format!("{}.recb", caps.get(1).map_or("", |m| m.as_str()));
-			let relative_path = Path::new(&block_name);
-			let block_path = input_dir.join(relative_path);
-			let absolute_path = std::fs::canonicalize(block_path)?;
-			let block_content = read_to_string(absolute_path)?;
-			result.push_str(block_content.trim());
+		if line == PARALLEL_START || line == SETUP_START || line == TEARDOWN_START {
+			anyhow::bail!("groups can not be nested in {}", start);
+		} else if line == PARALLEL_END || line == COMMAND_PREFIX {
+			if in_input {
+				result.push_str(&unchecked_output);
+			}
+			in_input = line == COMMAND_PREFIX;
+			if !in_input {
+				section = None;
+			}
+			result.push_str(line);
+			result.push('\n');
+		} else if is_output_line(line) {
+			in_input = false;
+			result.push_str(&unchecked_output);
+		} else if in_input || step_statement_re.is_match(line) {
+			// Statements of the next step are kept for rec, the rest of the expected output is dropped
+			result.push_str(line);
 			result.push('\n');
+		}
+	}
+
+	if let Some(start) = section {
+		anyhow::bail!("{} is not closed with {}", start, PARALLEL_END);
+	}
+
+	Ok(result)
+}
+
+/// Convert each group between ––– parallel ––– and ––– end ––– into the single step
+/// The input of the step runs the inputs of the group concurrently and then prints their outputs in order,
+/// each labeled with the number and the input of its sub-step, so the expected output is deterministic
+fn expand_parallel_groups(content: &str) -> Result<String> {
+	if !content.lines().any(|line| line == PARALLEL_START) {
+		return Ok(content.to_string());
+	}
+
+	let mut result = String::new();
+	let mut group: Option<Vec<(String, Vec<String>)>> = None;
+	let mut in_input = false;
+	// The setup and teardown sections with their end statements are kept by expand_unchecked_sections
+	let mut in_section = false;
+	for line in content.lines() {
+		let Some(steps) = group.as_mut() else {
+			if line == PARALLEL_START {
+				group = Some(Vec::new());
+			} else if line == PARALLEL_END && !in_section {
+				anyhow::bail!("{} without {}", PARALLEL_END, PARALLEL_START);
+			} else {
+				in_section = match line {
+					SETUP_START | TEARDOWN_START => true,
+					PARALLEL_END => false,
+					_ => in_section,
+				};
+				result.push_str(line);
+				result.push('\n');
+			}
 			continue;
-		} else if let Some(_) = duration_re.captures(&line) {
+		};
+
+		if line == PARALLEL_START {
+			anyhow::bail!("parallel groups can not be nested");
+		} else if line == PARALLEL_END {
+			result.push_str(&get_parallel_step(steps));
+			group = None;
+		} else if line == COMMAND_PREFIX {
+			in_input = true;
+		} else if line == COMMAND_SEPARATOR || line.starts_with("––– output: file=") {
+			in_input = false;
+		} else if is_output_line(line) {
+			anyhow::bail!("output checks are not supported in parallel groups: {}", line);
+		} else if in_input {
+			steps.push((line.to_string(), Vec::new()));
+		} else if let Some((_, output)) = steps.last_mut() {
+			output.push(line.to_string());
+		}
+	}
+
+	if group.is_some() {
+		anyhow::bail!("{} is not closed with {}", PARALLEL_START, PARALLEL_END);
+	}
+
+	Ok(result)
+}
+
+fn get_parallel_step(steps: &[(String, Vec<String>)]) -> String {
+	let quote = |value: &str| format!("'{}'", value.replace('\'', "'\\''"));
+	let mut command = String::from("(__clt_dir=$(mktemp -d); ");
+	for (i, (input, _)) in steps.iter().enumerate() {
+		command.push_str(&format!("({}) > \"$__clt_dir/{}\" 2>&1 & ", input, i + 1));
+	}
+	command.push_str("wait; ");
+	for (i, (input, _)) in steps.iter().enumerate() {
+		command.push_str(&format!("echo {}; awk 1 \"$__clt_dir/{}\"; ", quote(&format!("[{}] {}", i + 1, input)), i + 1));
+	}
+	command.push_str("rm -rf \"$__clt_dir\")");
+
+	let mut step = format!("{}\n{}\n{}\n", COMMAND_PREFIX, command, COMMAND_SEPARATOR);
+	for (i, (input, output)) in steps.iter().enumerate() {
+		step.push_str(&format!("[{}] {}\n", i + 1, input));
+		let end = output.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |pos| pos + 1);
+		for line in &output[..end] {
+			step.push_str(line);
+			step.push('\n');
+		}
+	}
+
+	step
+}
+
+/// Encrypt the value into the %{SECRET:...} token to store in the test
+/// The key is taken from the CLT_SECRET_KEY env variable as base64 encoded 32 bytes
+pub fn encrypt_secret(value: &str) -> Result<String> {
+	let cipher = get_secret_cipher()?;
+	let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+	let encrypted = cipher.encrypt(&nonce, value.as_bytes())
+		.map_err(|_| anyhow::anyhow!("Failed to encrypt the secret"))?;
+
+	let mut token = nonce.to_vec();
+	token.extend_from_slice(&encrypted);
+	Ok(format!("%{{SECRET:{}}}", BASE64.encode(token)))
+}
+
+/// Replace all %{SECRET:...} tokens in the content with decrypted values
+pub fn decrypt_secrets(content: &str) -> Result<String> {
+	let secret_re = Regex::new(SECRET_REGEX)?;
+	if !secret_re.is_match(content) {
+		return Ok(content.to_string());
+	}
+
+	let cipher = get_secret_cipher()?;
+	let mut result = String::new();
+	let mut last_index = 0;
+	for caps in secret_re.captures_iter(content) {
+		let matched = caps.get(0).unwrap();
+		result.push_str(&content[last_index..matched.start()]);
+		result.push_str(&decrypt_token(&cipher, &caps)?);
+		last_index = matched.end();
+	}
+	result.push_str(&content[last_index..]);
+
+	Ok(result)
+}
+
+/// Decrypted values of the %{SECRET:...} tokens in the content paired with the tokens,
+/// the longest values go first to hide them in the text before the ones they contain
+pub fn get_secrets(content: &str) -> Result<Vec<(String, String)>> {
+	let secret_re = Regex::new(SECRET_REGEX)?;
+	if !secret_re.is_match(content) {
+		return Ok(Vec::new());
+	}
+
+	let cipher = get_secret_cipher()?;
+	let mut secrets = Vec::new();
+	for caps in secret_re.captures_iter(content) {
+		let value = decrypt_token(&cipher, &caps)?;
+		if !value.is_empty() && !secrets.iter().any(|(known, _)| *known == value) {
+			secrets.push((value, caps[0].to_string()));
+		}
+	}
+	secrets.sort_by_key(|(value, _): &(String, String)| std::cmp::Reverse(value.len()));
+
+	Ok(secrets)
+}
+
+/// Replace the values of the secrets in the text with their tokens in one pass,
+/// so a value is not found again inside the token put before
+pub fn hide_secrets(text: &str, secrets: &[(String, String)]) -> Result<String> {
+	if secrets.is_empty() {
+		return Ok(text.to_string());
+	}
+
+	let values: Vec<String> = secrets.iter().map(|(value, _)| regex::escape(value)).collect();
+	let values_re = Regex::new(&values.join("|"))?;
+	Ok(values_re.replace_all(text, |caps: &regex::Captures| {
+		secrets.iter().find(|(value, _)| *value == caps[0]).map_or(String::new(), |(_, token)| token.clone())
+	}).into_owned())
+}
+
+fn decrypt_token(cipher: &ChaCha20Poly1305, caps: &regex::Captures) -> Result<String> {
+	let token = BASE64.decode(&caps[1])?;
+	if token.len() < 12 {
+		anyhow::bail!("Invalid secret token: {}", &caps[0]);
+	}
+
+	let (nonce, encrypted) = token.split_at(12);
+	let decrypted = cipher.decrypt(Nonce::from_slice(nonce), encrypted)
+		.map_err(|_| anyhow::anyhow!("Failed to decrypt the secret, check the key in {}", SECRET_KEY_ENV))?;
+	Ok(String::from_utf8(decrypted)?)
+}
+
+fn get_secret_cipher() -> Result<ChaCha20Poly1305> {
+	let key = std::env::var(SECRET_KEY_ENV)
+		.map_err(|_| anyhow::anyhow!("The test contains secrets, set {} to decrypt them", SECRET_KEY_ENV))?;
+	let key = BASE64.decode(key.trim())?;
+	if key.len() != 32 {
+		anyhow::bail!("{} should contain base64 encoded 32 bytes", SECRET_KEY_ENV);
+	}
+
+	Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Check commands of the compiled test against the policy file before replaying them,
+/// the commands are the lines of the inputs and the conditions of the skip-if statements
+/// Each line of the policy is "deny REGEX" or "allow REGEX", lines starting with # are comments
+/// A command fails when it matches any deny rule, or when there are allow rules and it matches none of them
+pub fn check_policy(content: &str, policy_file: &str) -> Result<()> {
+	let mut deny = Vec::new();
+	let mut allow = Vec::new();
+	for (i, line) in std::fs::read_to_string(policy_file)?.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
 			continue;
 		}
 
-		result.push_str(&line);
-		result.push('\n');
+		let (action, rule) = line.split_once(char::is_whitespace)
+			.ok_or_else(|| anyhow::anyhow!("{}:{}: expected \"deny REGEX\" or \"allow REGEX\"", policy_file, i + 1))?;
+		let regex = Regex::new(rule.trim())
+			.map_err(|e| anyhow::anyhow!("{}:{}: invalid regex: {}", policy_file, i + 1, e))?;
+		match action {
+			"deny" => deny.push(regex),
+			"allow" => allow.push(regex),
+			_ => anyhow::bail!("{}:{}: unknown policy action \"{}\", use deny or allow", policy_file, i + 1, action),
+		}
+	}
+
+	// Each line of the inputs and the conditions of ––– skip-if: ... ––– run in the shell of the test
+	let skip_if_re = Regex::new(SKIP_IF_REGEX)?;
+	let mut in_input = false;
+	for line in content.lines() {
+		let command = if line == COMMAND_PREFIX {
+			in_input = true;
+			continue;
+		} else if is_output_line(line) {
+			in_input = false;
+			continue;
+		} else if in_input {
+			line.trim()
+		} else if let Some(caps) = skip_if_re.captures(line) {
+			caps.get(1).map_or("", |m| m.as_str().trim())
+		} else {
+			continue;
+		};
+		if command.is_empty() {
+			continue;
+		}
+
+		if let Some(rule) = deny.iter().find(|rule| rule.is_match(command)) {
+			anyhow::bail!("policy violation: command \"{}\" is denied by rule \"{}\"", command, rule);
+		}
+
+		if !allow.is_empty() && !allow.iter().any(|rule| rule.is_match(command)) {
+			anyhow::bail!("policy violation: command \"{}\" is not allowed by any rule", command);
+		}
+	}
+
+	Ok(())
+}
+
+/// Hash of the output lines as they are compared: trimmed and without trailing empty lines
+pub fn get_output_hash(lines: &[String]) -> String {
+	let end = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(0, |pos| pos + 1);
+	let output = lines[..end].iter().map(|line| line.trim()).collect::<Vec<_>>().join("\n");
+	get_hash(&output)
+}
+
+fn get_hash(content: &str) -> String {
+	Sha256::digest(content.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Get the hash of the test the .rep file was replayed from out of its header, the files of older versions have none
+pub fn parse_test_hash(rep_content: &str) -> Option<&str> {
+	rep_content.lines()
+		.take_while(|line| *line != COMMAND_PREFIX)
+		.find_map(|line| line.strip_prefix(TEST_HASH_PREFIX))
+		.map(str::trim)
+}
+
+/// Warn when the .rep file next to the test was replayed from another version of it,
+/// e.g. the test or its blocks were edited after the replay, so the result is stale
+pub fn check_replay_hash(rec_file_path: &str, rep_file_path: &str, compiled: &Compiled) -> Option<String> {
+	let rep_content = read_to_string(rep_file_path).ok()?;
+	match parse_test_hash(&rep_content) {
+		Some(hash) if hash != compiled.hash => Some(format!(
+			"{} was replayed from another version of {}, replay the test to update it", rep_file_path, rec_file_path
+		)),
+		_ => None,
+	}
+}
+
+/// Path of the .rep file that the replay of the test saves its output to
+pub fn get_rep_file_path(rec_file_path: &str) -> String {
+	Path::new(rec_file_path).with_extension("rep").to_string_lossy().to_string()
+}
+
+/// Evaluate the assertion like ${rows} > 0 with variables captured from outputs
+/// Both sides are compared as integers when they are numbers and as strings otherwise
+pub fn evaluate_assertion(expression: &str, vars: &HashMap<String, String>) -> Result<bool> {
+	static VAR_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\{([a-z_][a-z_0-9]*)\}").expect("assertion variable regex is valid"));
+	static EXPRESSION_RE: LazyLock<Regex> = LazyLock::new(|| {
+		Regex::new(r"^\s*(.*?)\s*(==|!=|>=|<=|>|<)\s*(.*?)\s*$").expect("assertion expression regex is valid")
+	});
+
+	let mut unknown = None;
+	let expression = VAR_RE.replace_all(expression, |caps: &regex::Captures| {
+		vars.get(&caps[1]).cloned().unwrap_or_else(|| {
+			unknown = Some(caps[1].to_string());
+			String::new()
+		})
+	});
+	if let Some(name) = unknown {
+		anyhow::bail!("unknown variable ${{{}}}, capture it with a pattern like %{{NUMBER>{}}}", name, name);
+	}
+
+	let caps = EXPRESSION_RE.captures(&expression)
+		.ok_or_else(|| anyhow::anyhow!("expected comparison like ${{name}} > 0, got \"{}\"", expression))?;
+	let unquote = |value: &str| value.trim_matches('"').to_string();
+	let (left, op, right) = (unquote(&caps[1]), &caps[2], unquote(&caps[3]));
+	let ordering = match (left.parse::<i64>(), right.parse::<i64>()) {
+		(Ok(left), Ok(right)) => left.cmp(&right),
+		_ => left.cmp(&right),
+	};
+
+	Ok(match op {
+		"==" => ordering.is_eq(),
+		"!=" => ordering.is_ne(),
+		">=" => ordering.is_ge(),
+		"<=" => ordering.is_le(),
+		">" => ordering.is_gt(),
+		_ => ordering.is_lt(),
+	})
+}
+
+/// Parse variables declared as NAME=value lines between ––– env ––– and the first input
+pub fn parse_env(content: &str) -> Result<Vec<(String, String)>> {
+	let name_re = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$")?;
+	let mut env = Vec::new();
+	let mut in_env = false;
+	let mut has_input = false;
+	for line in content.lines() {
+		if line == ENV_STATEMENT {
+			if has_input {
+				anyhow::bail!("the env statement should come before the first input");
+			}
+			in_env = true;
+		} else if line.starts_with("––– ") && line.ends_with(" –––") {
+			in_env = false;
+			has_input = has_input || line == COMMAND_PREFIX;
+		} else if in_env && !line.trim().is_empty() {
+			let (name, value) = line.trim().split_once('=')
+				.ok_or_else(|| anyhow::anyhow!("expected NAME=value in the env statement, got \"{}\"", line))?;
+			if !name_re.is_match(name) {
+				anyhow::bail!("invalid name of the environment variable \"{}\"", name);
+			}
+			env.push((name.to_string(), value.to_string()));
+		}
+	}
+
+	Ok(env)
+}
+
+/// Render the config template replacing {{ NAME }} with the value of the variable, the unknown variable is an error
+pub fn render_template(template: &str, vars: &HashMap<String, String>) -> Result<String> {
+	let variable_re = Regex::new(TEMPLATE_VARIABLE_REGEX)?;
+	let mut result = String::new();
+	let mut last = 0;
+	for caps in variable_re.captures_iter(template) {
+		let placeholder = caps.get(0).unwrap();
+		let value = vars.get(&caps[1]).ok_or_else(|| anyhow::anyhow!("unknown variable {} in the template", &caps[1]))?;
+		result.push_str(&template[last..placeholder.start()]);
+		result.push_str(value);
+		last = placeholder.end();
 	}
+	result.push_str(&template[last..]);
 
 	Ok(result)
 }
 
+/// Variables of config templates: the ones from .clt/variables, runtime variables like ALLOC_PORT or SEED set by the runner,
+/// the ones from the env statement of the test, each overriding the previous, and TEST_DIR with the directory of the test
+pub fn get_template_variables(rec_file_path: &str, content: &str) -> Result<HashMap<String, String>> {
+	let mut vars: HashMap<String, String> = load_variables()?.into_iter().collect();
+	for (name, env_name) in RUNTIME_VARIABLES {
+		if let Ok(value) = std::env::var(env_name) {
+			vars.insert(name.to_string(), value);
+		}
+	}
+	vars.extend(parse_env(content)?);
+	let test_dir = std::fs::canonicalize(rec_file_path)?.parent().map(|dir| dir.to_string_lossy().to_string()).unwrap_or_default();
+	vars.insert("TEST_DIR".to_string(), test_dir);
+
+	Ok(vars)
+}
+
+/// Parse the terminal size written as COLSxROWS like 120x40 into columns and rows
+pub fn parse_terminal_size(size: &str) -> Result<(u16, u16)> {
+	let invalid = || anyhow::anyhow!("expected terminal size like 120x40, got \"{}\"", size);
+	let (cols, rows) = size.trim().split_once('x').ok_or_else(invalid)?;
+	let (cols, rows): (u16, u16) = (cols.parse().map_err(|_| invalid())?, rows.parse().map_err(|_| invalid())?);
+	if cols == 0 || rows == 0 {
+		return Err(invalid());
+	}
+
+	Ok((cols, rows))
+}
+
+/// Parse the time limit of the command like 500ms, 30s or 5m
+pub fn parse_timeout(timeout: &str) -> Result<std::time::Duration> {
+	let invalid = || anyhow::anyhow!("expected timeout like 30s, 500ms or 5m, got \"{}\"", timeout);
+	let timeout = timeout.trim();
+	let split = timeout.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+	let value: u64 = timeout[..split].parse().map_err(|_| invalid())?;
+	let duration = match &timeout[split..] {
+		"ms" => std::time::Duration::from_millis(value),
+		"s" => std::time::Duration::from_secs(value),
+		"m" => value.checked_mul(60).map(std::time::Duration::from_secs)
+			.ok_or_else(|| anyhow::anyhow!("timeout \"{}\" is too long", timeout))?,
+		_ => return Err(invalid()),
+	};
+	if duration.is_zero() {
+		return Err(invalid());
+	}
+
+	Ok(duration)
+}
+
+pub fn get_exit_line(code: i32) -> String {
+	format!("––– exit: {} –––", code)
+}
+
+/// Parse the exit code from the ––– exit: N ––– line
+pub fn parse_exit_line(line: &str) -> Option<i32> {
+	static EXIT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(EXIT_REGEX).expect("exit regex is valid"));
+	EXIT_RE.captures(line.trim()).and_then(|caps| caps[1].parse().ok())
+}
+
+/// Escape the lines of the output of the command that look like statements, so they are compared as its output
+/// instead of breaking the test, rec and the replay do it before writing the output, unescape_output reverses it
+pub fn escape_output(output: &str) -> String {
+	let markers = OutputMarkers::new();
+	output.split_inclusive('\n')
+		.map(|line| match markers.is_escaped(line) {
+			true => format!("{}{}", ESCAPE_PREFIX, line),
+			false => line.to_string(),
+		})
+		.collect()
+}
+
+/// Get the output as the command printed it from the one escaped with escape_output
+pub fn unescape_output(output: &str) -> String {
+	let markers = OutputMarkers::new();
+	output.split_inclusive('\n')
+		.map(|line| match line.strip_prefix(ESCAPE_PREFIX) {
+			Some(unescaped) if markers.is_escaped(unescaped) => unescaped,
+			_ => line,
+		})
+		.collect()
+}
+
+/// Lines of the output that would be read as statements: the ones starting with the en dash prefix,
+/// statements with hyphens that fix_statements converts and the ASCII ones, with any backslashes before them,
+/// so a line the command printed with the escape prefix itself comes back the same
+struct OutputMarkers {
+	legacy_re: Regex,
+	ascii_re: Regex,
+}
+
+impl OutputMarkers {
+	fn new() -> Self {
+		Self {
+			legacy_re: Regex::new(LEGACY_STATEMENT_REGEX).expect("legacy statement regex is valid"),
+			ascii_re: Regex::new(ASCII_STATEMENT_REGEX).expect("ascii statement regex is valid"),
+		}
+	}
+
+	fn is_escaped(&self, line: &str) -> bool {
+		let line = line.trim_start_matches(ESCAPE_PREFIX).trim();
+		line.starts_with("––– ")
+			|| self.legacy_re.is_match(line)
+			|| self.ascii_re.captures(line).is_some_and(|caps| STATEMENTS.contains(&&caps[1]))
+	}
+}
+
+/// Check if the line is the output statement with or without the check
+pub fn is_output_line(line: &str) -> bool {
+	line == COMMAND_SEPARATOR || (line.starts_with("––– output: ") && line.ends_with(" –––"))
+}
+
+/// Parse the check from the argument of the output statement
+/// Returns None for the plain output statement, the one with the file of the expected output or any other line
+pub fn parse_output_check(line: &str) -> Result<Option<OutputCheck>> {
+	static OUTPUT_CHECK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(OUTPUT_CHECK_REGEX).expect("output check regex is valid"));
+	let Some(caps) = OUTPUT_CHECK_RE.captures(line.trim_end()) else {
+		return Ok(None);
+	};
+
+	// The expected output loaded from the file is compared as the plain one
+	let arg = caps[1].trim();
+	if arg.starts_with("file=") {
+		return Ok(None);
+	}
+
+	let parse_number = |value: &str| value.trim().parse::<usize>()
+		.map_err(|_| anyhow::anyhow!("invalid number of lines in the output check \"{}\"", arg));
+	let check = if let Some(value) = arg.strip_prefix("lines>=") {
+		OutputCheck::Lines { min: parse_number(value)?, max: None }
+	} else if let Some(value) = arg.strip_prefix("lines<=") {
+		OutputCheck::Lines { min: 0, max: Some(parse_number(value)?) }
+	} else if let Some(value) = arg.strip_prefix("lines=") {
+		match value.split_once("..") {
+			Some((min, max)) => OutputCheck::Lines { min: parse_number(min)?, max: Some(parse_number(max)?) },
+			None => {
+				let count = parse_number(value)?;
+				OutputCheck::Lines { min: count, max: Some(count) }
+			},
+		}
+	} else if arg == "sha256" {
+		OutputCheck::Sha256
+	} else if arg == "contains" {
+		OutputCheck::Contains
+	} else if arg == "base64" {
+		OutputCheck::Base64
+	} else {
+		anyhow::bail!("unknown output check \"{}\", use lines>=N, lines<=N, lines=N, lines=N..M, sha256, contains, base64 or file=path", arg);
+	};
+
+	Ok(Some(check))
+}
+
+/// Short documentation of the statements shown by editors on hover and in completions
+pub const STATEMENT_DOCS: &[(&str, &str)] = &[
+	("input", "Starts the step, the lines after it are run as the command in the shell of the test"),
+	("output", "Expected output of the command matched line by line with %{PATTERNS} and #!/regex/!#, or checked the way its argument says"),
+	("block", "Includes the steps of the .recb file found next to the test or in CLT_BLOCK_PATH"),
+	("duration", "Time the command took, written to .rep files by the replay"),
+	("format", "The lowest version of the format the test needs, so older binaries fail instead of misparsing it"),
+	("parallel", "Runs the inputs of the steps up to ––– end ––– at the same time and prints their outputs in the order of the steps"),
+	("repeat", "Repeats the steps up to ––– end ––– N times with %{I} replaced with the number of the iteration"),
+	("table", "Repeats the steps up to ––– end ––– for each row of the CSV or JSON file with %{column} replaced with its values"),
+	("end", "Ends the parallel, repeat, table, setup or teardown group"),
+	("assert", "Checks the variables captured with %{PATTERN>name}, e.g. ––– assert: ${rows} > 0 –––"),
+	("resize", "Resizes the terminal to COLSxROWS before the next input, the running program gets SIGWINCH"),
+	("env", "NAME=value lines after it are exported in the shell before the first input"),
+	("exit", "Expected exit code of the command, put after its output"),
+	("skip-if", "Skips the step when the shell command succeeds in the container of the test"),
+	("timeout", "Interrupts the next command when it runs longer than the time in ms, s or m"),
+	("setup", "Runs the steps up to ––– end ––– without comparing their output, exit codes and assertions"),
+	("teardown", "Runs the steps up to ––– end ––– without comparing their output, exit codes and assertions"),
+	("error", "Expected stderr of the command, recorded apart from the output once the test has the statement"),
+];
+
+/// Short documentation of the arguments of the output statement
+pub const OUTPUT_CHECK_DOCS: &[(&str, &str)] = &[
+	("lines", "lines>=N, lines<=N, lines=N or lines=N..M: the number of output lines is in the range, the expected output is not compared"),
+	("sha256", "The expected output is the sha256 hash of the actual output lines joined with new lines"),
+	("contains", "The actual output contains the expected lines in the same order with any lines between them"),
+	("base64", "The output is encoded with base64 by the replay and compared byte for byte, e.g. to cat binary files"),
+	("file", "file=path: the expected output is kept in the file relative to the test"),
+];
+
+/// Short documentation of the patterns that are not defined in .patterns
+pub const PATTERN_DOCS: &[(&str, &str)] = &[
+	("IGNORE_START", "Skips the lines of the actual output up to the one matching the line after %{IGNORE_END}"),
+	("IGNORE_END", "Ends the region skipped from %{IGNORE_START}"),
+	("SKIP_LINES", "Matches any number of lines up to the one matching the next expected line, put it on a line of its own"),
+	("ANCHORS", "The first line of the output where only the lines prefixed with @ must be found in the same order"),
+	("SECRET", "Value encrypted with ./clt secret encrypt and decrypted with the key from CLT_SECRET_KEY"),
+	("ALLOC_PORT", "Free port reserved for the test by the runner, also set in CLT_ALLOC_PORT"),
+	("SEED", "Seed of the run, also set in CLT_SEED and used to seed $RANDOM"),
+	("TZ", "Timezone of the test from its metadata or CLT_TZ"),
+	("I", "Number of the iteration of the closest ––– repeat: N ––– group from 1 to N"),
+];
+
+/// Documentation of the statement, the output check or the pattern at the position in the test
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hover {
+	/// Range of the hovered text in the line in characters
+	pub start: usize,
+	pub end: usize,
+	pub documentation: String,
+}
+
+/// Read the patterns from the file with the name and the raw regex separated by whitespace on each line
+pub fn read_patterns(path: &str) -> Result<HashMap<String, String>> {
+	let mut patterns = HashMap::new();
+	for line in BufReader::new(File::open(path)?).lines() {
+		let line = line?;
+		let line = line.trim();
+		// Lines starting with # are comments like in .ignore-lines
+		if line.starts_with('#') {
+			continue;
+		}
+		if let Some((name, pattern)) = line.split_once(char::is_whitespace) {
+			let pattern = pattern.trim();
+			if !pattern.is_empty() {
+				patterns.insert(name.to_string(), pattern.to_string());
+			}
+		}
+	}
+	Ok(patterns)
+}
+
+/// Find the documentation to show when the cursor is at the line and the column of the content, both from 0,
+/// the column is counted in characters, patterns are the ones read from .patterns with read_patterns
+pub fn get_hover(content: &str, line: usize, column: usize, patterns: &HashMap<String, String>) -> Option<Hover> {
+	let text = content.lines().nth(line)?;
+	let chars: Vec<char> = text.chars().collect();
+	if column >= chars.len() {
+		return None;
+	}
+
+	let pattern_re = Regex::new(r"%\{([A-Za-z][A-Za-z_0-9]*)[^}]*\}").ok()?;
+	for caps in pattern_re.captures_iter(text) {
+		let whole = caps.get(0)?;
+		let (start, end) = (text[..whole.start()].chars().count(), text[..whole.end()].chars().count());
+		if column < start || column >= end {
+			continue;
+		}
+
+		let name = &caps[1];
+		let documentation = match (patterns.get(name), get_doc(PATTERN_DOCS, name)) {
+			(Some(pattern), _) => format!("%{{{}}} matches {}", name, pattern),
+			(None, Some(doc)) => format!("%{{{}}}: {}", name, doc),
+			(None, None) if name.starts_with(|c: char| c.is_ascii_lowercase()) => format!("%{{{}}}: value of the column of the table in each row", name),
+			(None, None) => format!("%{{{}}} is not defined in .patterns and is compared literally", name),
+		};
+		return Some(Hover { start, end, documentation });
+	}
+
+	let caps = Regex::new(r"^––– ([a-z][a-z\-]*)(?:: (.+))? –––$").ok()?.captures(text)?;
+	let statement = &caps[1];
+	if let Some(arg) = caps.get(2).filter(|_| statement == "output") {
+		let (start, end) = (text[..arg.start()].chars().count(), text[..arg.end()].chars().count());
+		if (start..end).contains(&column) {
+			let name = arg.as_str().split(['=', '<', '>']).next().unwrap_or_default();
+			let documentation = format!("{}: {}", arg.as_str(), get_doc(OUTPUT_CHECK_DOCS, name)?);
+			return Some(Hover { start, end, documentation });
+		}
+	}
+	let documentation = format!("––– {} –––: {}", statement, get_doc(STATEMENT_DOCS, statement)?);
+	Some(Hover { start: 0, end: chars.len(), documentation })
+}
+
+fn get_doc(docs: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+	docs.iter().find(|(doc_name, _)| *doc_name == name).map(|(_, doc)| *doc)
+}
+
+/// Return canonical form of the statement line if it looks like a statement
+/// written with loose spelling: extra spaces, other case or hyphens instead of en dashes
+pub fn normalize_statement(line: &str) -> Option<String> {
+	let legacy_re = Regex::new(LEGACY_STATEMENT_REGEX).ok()?;
+	normalize_statement_with(&legacy_re, line)
+}
+
+fn normalize_statement_with(legacy_re: &Regex, line: &str) -> Option<String> {
+	let caps = legacy_re.captures(line)?;
+	Some(get_statement_line(&caps[1].to_lowercase(), caps.get(2).map(|m| m.as_str())))
+}
+
+fn get_statement_line(statement: &str, arg: Option<&str>) -> String {
+	match arg {
+		Some(arg) if !arg.is_empty() => format!("––– {}: {} –––", statement, arg),
+		_ => format!("––– {} –––", statement),
+	}
+}
+
+/// Validates statement lines while we compile the file
+/// Legacy spellings are converted into canonical form and remembered as deprecations,
+/// lines that look like a misspelled statement are reported as errors
+struct StatementChecker {
+	legacy_re: Regex,
+	similar_re: Regex,
+	/// Recognizes ASCII statements when they are enabled in .clt/config
+	ascii_re: Option<Regex>,
+	strict: bool,
+	deprecations: Vec<Deprecation>,
+	/// The lines are in the output section and whether its statement was written with loose spelling
+	in_output: bool,
+	loose_output: bool,
+}
+
+impl StatementChecker {
+	fn new(strict: bool) -> Result<Self> {
+		let ascii_re = match get_statement_style() {
+			StatementStyle::Ascii => Some(Regex::new(ASCII_STATEMENT_REGEX)?),
+			StatementStyle::Classic => None,
+		};
+		Ok(Self {
+			legacy_re: Regex::new(LEGACY_STATEMENT_REGEX)?,
+			similar_re: Regex::new(STATEMENT_LIKE_REGEX)?,
+			ascii_re,
+			strict,
+			deprecations: Vec::new(),
+			in_output: false,
+			loose_output: false,
+		})
+	}
+
+	/// The output written after the canonical statement ends only with a canonical statement,
+	/// so the text like -- Output -- in it stays output and is not taken for a loose statement
+	fn in_canonical_output(&self) -> bool {
+		self.in_output && !self.loose_output
+	}
+
+	/// The line is a loose statement in the output that is kept as its text
+	fn is_output_text(&self, line: &str) -> bool {
+		self.in_canonical_output() && self.legacy_re.is_match(line)
+	}
+
+	/// Canonical form of the statement on the line with the flag whether it was written with loose spelling,
+	/// or None when the line is not a statement in its position
+	fn get_statement(&mut self, line: &str) -> Option<(String, bool)> {
+		let (statement, loose) = match self.get_ascii_statement(line) {
+			Some(statement) => (statement, false),
+			None => {
+				let statement = normalize_statement_with(&self.legacy_re, line)?;
+				let loose = statement != line.trim_end();
+				if loose && self.in_canonical_output() {
+					return None;
+				}
+				(statement, loose)
+			}
+		};
+
+		if is_output_line(&statement) {
+			self.in_output = true;
+			self.loose_output = loose;
+		} else if statement != ERROR_STATEMENT {
+			self.in_output = false;
+		}
+		Some((statement, loose))
+	}
+
+	/// Convert the known statement written in the enabled ASCII style into the classic one
+	fn get_ascii_statement(&self, line: &str) -> Option<String> {
+		let caps = self.ascii_re.as_ref()?.captures(line.trim_end())?;
+		STATEMENTS.contains(&&caps[1]).then(|| get_statement_line(&caps[1], caps.get(2).map(|m| m.as_str())))
+	}
+
+	fn check(&mut self, line: String, file: &str, line_number: usize) -> Result<String> {
+		let expected = match self.get_statement(&line) {
+			Some((statement, false)) if statement == line.trim_end() => return Ok(line),
+			Some((statement, false)) => return Ok(statement),
+			Some((expected, true)) => expected,
+			None => {
+				// A misspelled statement in the output is its text for the comparison, lint_test reports it
+				if self.in_output {
+					return Ok(line);
+				}
+				if let Some(suggestion) = self.suggest_statement(&line) {
+					let message = format!("unknown statement \"{}\", did you mean \"{}\"?", line.trim(), suggestion);
+					return Err(ParseError::new(file, line_number, &line, None, ParseErrorKind::UnknownStatement, message).into());
+				}
+				return Ok(line);
+			}
+		};
+
+		let deprecation = Deprecation {
+			file: file.to_string(),
+			line: line_number,
+			found: line.trim().to_string(),
+			expected: expected.clone(),
+		};
+		if self.strict {
+			let message = format!(
+				"statement \"{}\" has wrong dashes or spacing, did you mean \"{}\"? (strict mode)",
+				deprecation.found, deprecation.expected
+			);
+			return Err(ParseError::new(file, line_number, &line, None, ParseErrorKind::LegacyStatement, message).into());
+		}
+		self.deprecations.push(deprecation);
+
+		Ok(expected)
+	}
+
+	/// Find the known statement that is close to the misspelled one
+	fn suggest_statement(&self, line: &str) -> Option<String> {
+		let caps = self.similar_re.captures(line)?;
+		let name = caps[1].to_lowercase();
+		STATEMENTS.iter()
+			.map(|statement| (statement, get_edit_distance(&name, statement)))
+			.filter(|(_, distance)| *distance <= 2)
+			.min_by_key(|(_, distance)| *distance)
+			.map(|(statement, _)| get_statement_line(statement, caps.get(2).map(|m| m.as_str())))
+	}
+}
+
+/// Levenshtein distance between two strings
+fn get_edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut prev: Vec<usize> = (0..=b.len()).collect();
+	for (i, ca) in a.chars().enumerate() {
+		let mut curr = vec![i + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let cost = if ca == *cb { 0 } else { 1 };
+			curr.push((prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1));
+		}
+		prev = curr;
+	}
+	prev[b.len()]
+}
+
 /// Generate duration line normally for writing it to the replay file
 pub fn get_duration_line(duration: Duration) -> String {
 	format!("––– duration: {}ms ({:.2}%) –––", duration.duration, duration.percentage)
@@ -5,4 +5,563 @@ fn test_compile_includes_blocks() {
   let output = parser::compile("./tests/data/blocks/test.rec").unwrap();
   let expected = read_to_string("./tests/data/blocks/test.recc").unwrap();
   assert_eq!(expected, output);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_compile_normalizes_legacy_statements() {
+  let compiled = parser::compile_checked("./tests/data/legacy/test.rec", false).unwrap();
+  assert_eq!("––– input –––\nwhoami\n––– output –––\nroot\n", compiled.content);
+  assert_eq!(2, compiled.deprecations.len());
+  assert_eq!(1, compiled.deprecations[0].line);
+  assert_eq!("––– output –––", compiled.deprecations[1].expected);
+}
+
+#[test]
+fn test_compile_keeps_legacy_statements_in_outputs() {
+  let content = read_to_string("./tests/data/legacy-output/test.rec").unwrap();
+  let compiled = parser::compile_checked("./tests/data/legacy-output/test.rec", true).unwrap();
+  assert_eq!(content, compiled.content);
+  assert!(compiled.deprecations.is_empty());
+  assert_eq!(content, parser::fix_statements(&content, "test.rec").unwrap().0);
+  assert!(parser::lint_content("test.rec", &content).is_empty());
+
+  // The output written after a loose statement still ends with a loose one
+  let (fixed, deprecations) = parser::fix_statements("--- input ---\nls\n--- output ---\nfile\n--- input ---\npwd\n", "test.rec").unwrap();
+  assert_eq!("––– input –––\nls\n––– output –––\nfile\n––– input –––\npwd\n", fixed);
+  assert_eq!(3, deprecations.len());
+}
+
+#[test]
+fn test_compile_keeps_misspelled_statements_in_outputs() {
+  let content = read_to_string("./tests/data/misspelled-output/test.rec").unwrap();
+  assert_eq!(content, parser::compile("./tests/data/misspelled-output/test.rec").unwrap());
+  assert!(parser::fix_statements("––– input –––\n-- inputs --\n", "test.rec").is_err());
+
+  let issues: Vec<String> = parser::lint_test("tests/data/misspelled-output/test.rec").iter().map(ToString::to_string).collect();
+  assert_eq!(issues, [
+    "tests/data/misspelled-output/test.rec:4: unknown statement \"-- inputs --\", did you mean \"––– input –––\"?",
+    "tests/data/misspelled-output/test.rec:5: unknown statement \"--- inputs ---\", did you mean \"––– input –––\"?",
+  ]);
+}
+
+#[test]
+fn test_read_tolerant_fixes_legacy_statements() {
+  let (rec_file, deprecations) = parser::RecFile::read_tolerant("./tests/data/legacy/test.rec").unwrap();
+  assert_eq!(1, rec_file.steps.len());
+  assert_eq!("whoami", rec_file.steps[0].input);
+  assert_eq!(2, deprecations.len());
+  assert_eq!(0, parser::RecFile::parse(&read_to_string("./tests/data/legacy/test.rec").unwrap()).steps.len());
+
+  let (fixed, deprecations) = parser::fix_statements("--- input ---\r\nls\n––– output –––\n", "test.rec").unwrap();
+  assert_eq!("––– input –––\r\nls\n––– output –––\n", fixed);
+  assert_eq!("test.rec:1: deprecated statement \"--- input ---\", use \"––– input –––\" instead", deprecations[0].to_string());
+  assert!(parser::fix_statements("--- ouput ---\n", "test.rec").is_err());
+}
+
+#[test]
+fn test_migrate_test() {
+  let content = "---\nowner: search\n---\n--- input ---\nfalse\n––– output –––\n––– exit: 1 –––\n";
+  let (migrated, deprecations) = parser::migrate_test(content, "test.rec").unwrap();
+  assert_eq!("---\nowner: search\n---\n––– format: 9 –––\n––– input –––\nfalse\n––– output –––\n––– exit: 1 –––\n", migrated);
+  assert_eq!(1, deprecations.len());
+  assert_eq!(migrated, parser::migrate_test(&migrated, "test.rec").unwrap().0);
+
+  let (migrated, _) = parser::migrate_test("––– format: 2 –––\n––– repeat: 2 –––\n––– end –––\n", "test.rec").unwrap();
+  assert_eq!("––– format: 4 –––\n––– repeat: 2 –––\n––– end –––\n", migrated);
+  let (migrated, _) = parser::migrate_test("--- input ---\nls\n", "block.recb").unwrap();
+  assert_eq!("––– input –––\nls\n", migrated);
+}
+
+#[test]
+fn test_apply_statement_style() {
+  let content = "––– input –––\r\nls\n––– output: lines>=1 –––\nsome ––– text –––\n";
+  let ascii = parser::apply_statement_style(content, parser::StatementStyle::Ascii);
+  assert_eq!("=== input ===\r\nls\n=== output: lines>=1 ===\nsome ––– text –––\n", ascii);
+  assert_eq!(content, parser::apply_statement_style(content, parser::StatementStyle::Classic));
+}
+
+#[test]
+fn test_compile_rejects_legacy_statements_in_strict_mode() {
+  assert!(parser::compile_checked("./tests/data/legacy/test.rec", true).is_err());
+}
+
+#[test]
+fn test_compile_fails_on_newer_format() {
+  let err = parser::compile("./tests/data/format/test.rec").unwrap_err();
+  assert!(err.to_string().contains("test requires format v99"));
+}
+
+#[test]
+fn test_compile_suggests_misspelled_statement() {
+  let err = parser::compile("./tests/data/typo/test.rec").unwrap_err();
+  assert!(err.to_string().ends_with(":3: unknown statement \"--- ouput ---\", did you mean \"––– output –––\"?"));
+}
+
+#[test]
+fn test_compile_error_has_position() {
+  let err = parser::compile("./tests/data/typo/test.rec").unwrap_err();
+  let err = err.downcast_ref::<parser::ParseError>().unwrap();
+  assert_eq!((3, 1, parser::ParseErrorKind::UnknownStatement), (err.line, err.column, err.kind));
+  assert_eq!("--- ouput ---", err.snippet);
+
+  let err = parser::compile("./tests/data/lint/test.rec").unwrap_err();
+  let err = err.downcast_ref::<parser::ParseError>().unwrap();
+  assert_eq!((9, 13, parser::ParseErrorKind::InvalidArgument), (err.line, err.column, err.kind));
+
+  let err = parser::compile("./tests/data/format/test.rec").unwrap_err();
+  assert_eq!(parser::ParseErrorKind::UnsupportedFormat, err.downcast_ref::<parser::ParseError>().unwrap().kind);
+}
+
+#[test]
+fn test_compile_expands_parallel_groups() {
+  let content = parser::compile("tests/data/parallel/test.rec").unwrap();
+  let lines: Vec<&str> = content.lines().collect();
+  assert_eq!(lines[4], "––– input –––");
+  assert!(lines[5].contains("(sleep 0.2; echo slow) > \"$__clt_dir/1\" 2>&1 &"));
+  assert!(lines[5].contains("echo '[2] echo '\\''fast'\\'''"));
+  assert_eq!(&lines[6..], &["––– output –––", "[1] sleep 0.2; echo slow", "slow", "[2] echo 'fast'", "fast"]);
+}
+
+#[test]
+fn test_compile_expands_repeat_groups() {
+  let content = parser::compile("tests/data/repeat/test.rec").unwrap();
+  let inputs: Vec<&str> = content.lines().filter(|line| line.starts_with("echo")).collect();
+  assert_eq!(inputs, ["echo row 1", "echo inner 1", "echo inner 2", "echo row 2", "echo inner 1", "echo inner 2"]);
+  assert!(content.contains("––– output –––\nrow 2\n"));
+}
+
+#[test]
+fn test_compile_expands_table_groups() {
+  let content = parser::compile("tests/data/table/test.rec").unwrap();
+  let inputs: Vec<&str> = content.lines().filter(|line| line.starts_with("mysql")).collect();
+  assert_eq!(inputs, ["mysql -e \"SELECT 1\"", "mysql -e \"SELECT 1, 2\"", "mysql -e \"SELECT 3\""]);
+  assert!(content.contains("––– output –––\ncolumns: 3\n"));
+}
+
+#[test]
+fn test_parse_env() {
+  let content = parser::compile("tests/data/env/test.rec").unwrap();
+  let env = parser::parse_env(&content).unwrap();
+  assert_eq!(env, [
+    ("TZ".to_string(), "UTC".to_string()),
+    ("LANG".to_string(), "C".to_string()),
+    ("GREETING".to_string(), "hello world".to_string()),
+  ]);
+  assert!(parser::compile("tests/data/env/late.rec").is_err());
+  assert!(parser::parse_env("––– env –––\nNOT VALID\n").is_err());
+}
+
+#[test]
+fn test_rec_file_round_trip() {
+  let content = read_to_string("./tests/data/env/test.rec").unwrap();
+  let rec_file = parser::RecFile::parse(&content);
+  assert_eq!(1, rec_file.steps.len());
+  assert!(rec_file.preamble.starts_with(parser::ENV_STATEMENT));
+  assert_eq!(content, rec_file.to_string());
+}
+
+#[test]
+fn test_rec_file_steps() {
+  let rec_file = parser::RecFile::parse("––– input –––\nls\n––– output: lines>=1 –––\n––– input –––\nfalse\n––– output –––\n––– exit: 1 –––\n");
+  assert_eq!(2, rec_file.steps.len());
+  assert_eq!(Some("lines>=1".to_string()), rec_file.steps[0].check);
+  assert_eq!("false", rec_file.steps[1].input);
+  assert_eq!(Some(1), rec_file.steps[1].get_exit_code());
+
+  let step = parser::Step { input: "echo 1".to_string(), check: None, output: "1".to_string(), duration_ms: None, comment: None };
+  let rec_file = parser::RecFile { steps: vec![step], ..Default::default() };
+  assert_eq!("––– input –––\necho 1\n––– output –––\n1\n", rec_file.to_string());
+}
+
+#[test]
+fn test_attach_comments() {
+  let content = concat!(
+    "––– input –––\n# first\n––– output –––\n––– input –––\n# starts the daemon\n# on the default port\n––– output –––\n",
+    "––– input –––\nsearchd\n––– output –––\nstarted\n––– input –––\n# leftover\n––– output –––\n",
+  );
+  let mut rec_file = parser::RecFile::parse(content);
+  rec_file.attach_comments();
+  assert_eq!(vec!["# first", "searchd", "# leftover"], rec_file.steps.iter().map(|step| step.input.as_str()).collect::<Vec<_>>());
+  assert_eq!(Some("# starts the daemon\n# on the default port"), rec_file.steps[1].comment.as_deref());
+  assert_eq!(content, rec_file.to_string());
+
+  rec_file.steps[1] = parser::Step { input: "searchd --console".to_string(), ..rec_file.steps[1].clone() };
+  assert!(rec_file.to_string().contains("# on the default port\n––– output –––\n––– input –––\nsearchd --console\n"));
+}
+
+#[test]
+fn test_compile_hash_ignores_runtime_variables() {
+  let hash = parser::compile_checked("tests/data/hash/test.rec", false).unwrap().hash;
+  std::env::set_var("CLT_SEED", "42");
+  let compiled = parser::compile_checked("tests/data/hash/test.rec", false).unwrap();
+  std::env::remove_var("CLT_SEED");
+  assert!(compiled.content.contains("echo 42"));
+  assert_eq!(hash, compiled.hash);
+
+  let rep_content = std::fs::read_to_string("tests/data/hash/test.rep").unwrap();
+  assert_eq!(Some("0".repeat(64).as_str()), parser::parse_test_hash(&rep_content));
+  assert_eq!("tests/data/hash/test.rep", parser::get_rep_file_path("tests/data/hash/test.rec"));
+  let warning = parser::check_replay_hash("tests/data/hash/test.rec", "tests/data/hash/test.rep", &compiled).unwrap();
+  assert!(warning.contains("replayed from another version"));
+}
+
+#[test]
+fn test_get_dependencies() {
+  let files = parser::get_dependencies("tests/data/depends/test.rec").unwrap();
+  let files: Vec<String> = files.iter().map(|file| file.to_string_lossy().to_string()).collect();
+  assert_eq!(files, ["tests/data/depends/config/searchd.conf", "tests/data/depends/expected/searchd.conf", "tests/data/depends/start.recb"]);
+  assert!(parser::compile("tests/data/depends/test.rec").is_ok());
+  assert!(parser::compile("tests/data/depends/missing.rec").is_ok());
+  let files = parser::get_dependencies("tests/data/depends/missing.rec").unwrap();
+  assert_eq!(files, [std::path::PathBuf::from("tests/data/depends/config/missing.conf")]);
+}
+
+#[test]
+fn test_render_template() {
+  let content = parser::compile("tests/data/templates/test.rec").unwrap();
+  let metadata = parser::parse_metadata(&content).unwrap();
+  assert_eq!(metadata.templates, [("manticore.conf.tpl".to_string(), "/etc/manticoresearch/manticore.conf".to_string())]);
+
+  let mut vars = parser::get_template_variables("tests/data/templates/test.rec", &content).unwrap();
+  assert_eq!(Some("/var/lib/manticore"), vars.get("DATA_DIR").map(String::as_str));
+  assert!(vars.get("TEST_DIR").is_some_and(|dir| dir.ends_with("tests/data/templates")));
+
+  let template = std::fs::read_to_string("tests/data/templates/manticore.conf.tpl").unwrap();
+  assert!(parser::render_template(&template, &vars).unwrap_err().to_string().contains("ALLOC_PORT"));
+  vars.insert("ALLOC_PORT".to_string(), "9306".to_string());
+  assert_eq!(
+    "searchd {\n  listen = 9306:mysql\n  data_dir = /var/lib/manticore\n}\n",
+    parser::render_template(&template, &vars).unwrap()
+  );
+  assert!(parser::parse_metadata("---\ntemplates: manticore.conf\n---\n").is_err());
+}
+
+#[test]
+fn test_get_execution_plan() {
+  let tests = ["tests/data/requires/insert.rec".to_string(), "tests/data/requires/schema.rec".to_string()];
+  assert_eq!(parser::get_execution_plan(&tests).unwrap(), [
+    "tests/data/requires/schema.rec",
+    "tests/data/requires/search.rec",
+    "tests/data/requires/insert.rec",
+  ]);
+
+  let error = parser::get_execution_plan(&["tests/data/requires/cycle-a.rec".to_string()]).unwrap_err();
+  assert!(error.to_string().contains("cycle-a.rec -> tests/data/requires/cycle-b.rec -> tests/data/requires/cycle-a.rec"));
+}
+
+#[test]
+fn test_rep_file_step_durations() {
+  let content = "––– input –––\nls\n––– output –––\nfile\n––– duration: 12ms (0.00%) –––\n––– input –––\nfalse\n––– output –––\n––– duration: 3ms (0.00%) –––\n––– exit: 1 –––\n";
+  let rec_file = parser::RecFile::parse(content);
+  let durations: Vec<Option<u128>> = rec_file.steps.iter().map(|step| step.duration_ms).collect();
+  assert_eq!(durations, [Some(12), Some(3)]);
+  assert_eq!(content, rec_file.to_string());
+  assert_eq!(rec_file, parser::RecFile::from_yaml(&rec_file.to_yaml().unwrap()).unwrap());
+  assert_eq!(None, parser::RecFile::parse("––– input –––\nls\n––– output –––\n").steps[0].duration_ms);
+}
+
+#[test]
+fn test_compile_ignores_output_of_setup_and_teardown() {
+  let content = parser::compile("tests/data/setup/test.rec").unwrap();
+  assert_eq!(
+    "––– setup –––\n––– input –––\napt-get install -y mysql-client\n––– output –––\n%{IGNORE_START}\n\
+    ––– input –––\nsearchd\n––– output –––\n%{IGNORE_START}\n––– timeout: 5s –––\n––– end –––\n\
+    ––– input –––\nmysql -h0 -P9306 -e \"show tables\"\n––– output –––\n%{NUMBER} rows\n\
+    ––– teardown –––\n––– input –––\nsearchd --stopwait\n––– output –––\n%{IGNORE_START}\n––– end –––\n",
+    content
+  );
+  assert!(parser::lint_test("tests/data/setup/test.rec").is_empty());
+}
+
+#[test]
+fn test_flatten_keeps_block_provenance() {
+  let rec_file = parser::RecFile::read_with_blocks("tests/data/flatten/test.rec").unwrap();
+  let steps = parser::flatten("tests/data/flatten/test.rec", &rec_file);
+  let inputs: Vec<&str> = steps.iter().map(|step| step.step.input.as_str()).collect();
+  assert_eq!(inputs, ["whoami", "echo outer", "echo inner", "echo done"]);
+  assert_eq!("tests/data/flatten/inner.recb step 1 (block outer > inner)", steps[2].to_string());
+  assert_eq!("tests/data/flatten/test.rec step 2", steps[3].to_string());
+}
+
+#[test]
+fn test_get_block_references() {
+  let references = parser::get_block_references("tests/data/flatten/test.rec").unwrap();
+  let outer = std::fs::canonicalize("tests/data/flatten/outer.recb").unwrap();
+  assert_eq!(2, references.len());
+  assert_eq!(("tests/data/flatten/test.rec", 5, 11, 16, "outer"), (references[0].file.as_str(), references[0].line, references[0].start, references[0].end, references[0].name.as_str()));
+  assert_eq!((outer.clone(), true), (references[0].path.clone(), references[0].exists));
+  assert_eq!((outer.to_string_lossy().as_ref(), 5), (references[1].file.as_str(), references[1].line));
+  assert_eq!(std::fs::canonicalize("tests/data/flatten/inner.recb").unwrap(), references[1].path);
+
+  let references = parser::find_block_references("tests/data/flatten/test.rec", "––– block: missing –––\n");
+  assert!(!references[0].exists && references[0].path.is_absolute());
+}
+
+#[test]
+fn test_write_test_file_with_blocks() {
+  let dir = std::env::temp_dir().join(format!("clt-blocks-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  for file in ["test.rec", "block1.recb", "block2.recb"] {
+    std::fs::copy(format!("./tests/data/blocks/{}", file), dir.join(file)).unwrap();
+  }
+  let test_path = dir.join("test.rec").to_string_lossy().to_string();
+
+  let mut rec_file = parser::RecFile::read_with_blocks(&test_path).unwrap();
+  assert_eq!(rec_file.get_block_names(), ["block1", "block2"]);
+  assert_eq!("echo block2", rec_file.blocks[1].file.steps[0].input);
+  rec_file.blocks[1].file.steps[0].output = "changed\n".to_string();
+  parser::write_test_file_with_blocks(&test_path, &rec_file).unwrap();
+  assert_eq!("––– input –––\necho block2\n––– output –––\nchanged\n", read_to_string(dir.join("block2.recb")).unwrap());
+  assert_eq!(read_to_string("./tests/data/blocks/block1.recb").unwrap(), read_to_string(dir.join("block1.recb")).unwrap());
+
+  let mut diverged = rec_file.blocks[1].clone();
+  diverged.file.steps[0].output = "other\n".to_string();
+  rec_file.blocks[0].file.blocks.push(diverged);
+  assert!(parser::write_test_file_with_blocks(&test_path, &rec_file).is_err());
+  std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_insert_steps() {
+  let path = std::env::temp_dir().join(format!("clt-insert-{}.rec", std::process::id())).to_string_lossy().to_string();
+  std::fs::write(&path, "––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho 3\n––– output –––\n3\n").unwrap();
+  let step = |input: &str, output: &str| parser::Step { input: input.to_string(), output: output.to_string(), ..Default::default() };
+
+  parser::insert_steps(&path, 1, &[step("echo 2\n", "2\n")]).unwrap();
+  parser::insert_steps(&path, 3, &[step("echo 4", "4")]).unwrap();
+  let inputs: Vec<String> = parser::RecFile::read(&path).unwrap().steps.into_iter().map(|step| step.input).collect();
+  assert_eq!(inputs, ["echo 1", "echo 2", "echo 3", "echo 4"]);
+
+  let content = read_to_string(&path).unwrap();
+  assert!(parser::insert_steps(&path, 5, &[step("echo 5", "")]).is_err());
+  assert!(parser::insert_steps(&path, 0, &[step("", "")]).is_err());
+  let error = parser::insert_steps(&path, 0, &[step("echo 0", "0\n––– input –––\necho 1")]).unwrap_err();
+  assert_eq!("step 1 to insert is invalid: the output has the \"––– input –––\" statement, split it into steps", error.to_string());
+  let invalid_check = parser::Step { check: Some("lines>>1".to_string()), ..step("echo 0", "") };
+  assert!(parser::insert_steps(&path, 0, &[invalid_check]).is_err());
+  assert_eq!(content, read_to_string(&path).unwrap());
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_remove_steps() {
+  let path = std::env::temp_dir().join(format!("clt-remove-{}.rec", std::process::id())).to_string_lossy().to_string();
+  std::fs::write(&path, "––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho 2\n––– output –––\n2\n––– input –––\necho 1\n––– output –––\n1\n").unwrap();
+  let step = |input: &str, output: &str| parser::Step { input: input.to_string(), output: output.to_string(), ..Default::default() };
+
+  assert!(parser::remove_steps(&path, &[step("echo 3", "3")]).is_err());
+  assert!(parser::remove_steps(&path, &[step("echo 1", "1")]).unwrap_err().to_string().contains("found 2 times"));
+  parser::remove_steps(&path, &[step("echo 2  \n", "2\n\n"), step("echo 1", "1")]).unwrap();
+  assert_eq!("––– input –––\necho 1\n––– output –––\n1\n", read_to_string(&path).unwrap());
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_concurrent_writes() {
+  let path = std::env::temp_dir().join(format!("clt-concurrent-{}.rec", std::process::id())).to_string_lossy().to_string();
+  std::fs::write(&path, "––– input –––\necho 0\n––– output –––\n0\n").unwrap();
+  let threads: Vec<_> = (1..=8).map(|i| {
+    let path = path.clone();
+    std::thread::spawn(move || {
+      let step = parser::Step { input: format!("echo {}", i), output: format!("{}", i), ..Default::default() };
+      parser::insert_steps(&path, 1, &[step]).unwrap();
+    })
+  }).collect();
+  threads.into_iter().for_each(|thread| thread.join().unwrap());
+  assert_eq!(9, parser::RecFile::read(&path).unwrap().steps.len());
+
+  let version = parser::get_file_version(&path).unwrap();
+  let rec_file = parser::RecFile::read(&path).unwrap();
+  parser::remove_steps(&path, &rec_file.steps[..1]).unwrap();
+  let error = parser::write_test_file_checked(&path, &rec_file, Some(&version)).unwrap_err();
+  let conflict = error.downcast_ref::<parser::WriteConflict>().unwrap();
+  assert_eq!((version.as_str(), parser::get_file_version(&path).unwrap()), (conflict.expected_version.as_str(), conflict.version.clone()));
+  parser::write_test_file_checked(&path, &rec_file, Some(&conflict.version)).unwrap();
+  assert_eq!(rec_file, parser::RecFile::read(&path).unwrap());
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_find_steps() {
+  let rec_file = parser::RecFile::parse(concat!(
+    "––– input –––\nsearchd\n––– output –––\nstarted\n––– block: auth/login –––\n",
+    "––– input –––\nmysql -e 'show tables'\n––– output: lines>=2 –––\n",
+    "––– input –––\nmysql -e 'select 1'\n––– output –––\n1\n––– exit: 0 –––\n",
+  ));
+  let find = |selector: parser::StepSelector| rec_file.find_steps(&selector).unwrap();
+
+  assert_eq!(vec![0, 1, 2], find(Default::default()));
+  assert_eq!(vec![1, 2], find(parser::StepSelector { input: Some("^mysql".to_string()), ..Default::default() }));
+  assert_eq!(vec![2], find(parser::StepSelector { input: Some("^mysql".to_string()), output: Some("(?m)^1$".to_string()), ..Default::default() }));
+  assert_eq!(vec![1], find(parser::StepSelector { check: Some("lines".to_string()), ..Default::default() }));
+  assert_eq!(vec![0, 2], find(parser::StepSelector { check: Some("none".to_string()), ..Default::default() }));
+  assert_eq!(vec![2], find(parser::StepSelector { statement: Some("exit".to_string()), ..Default::default() }));
+  assert_eq!(vec![0], find(parser::StepSelector { block: Some("auth/login".to_string()), ..Default::default() }));
+  assert!(find(parser::StepSelector { block: Some("auth".to_string()), ..Default::default() }).is_empty());
+  assert!(rec_file.find_steps(&parser::StepSelector { input: Some("(".to_string()), ..Default::default() }).is_err());
+}
+
+#[test]
+fn test_revert_last_edit() {
+  let dir = std::env::temp_dir().join(format!("clt-history-{}", std::process::id()));
+  std::env::set_var(parser::HISTORY_DIR_ENV, &dir);
+  let path = std::env::temp_dir().join(format!("clt-revert-{}.rec", std::process::id())).to_string_lossy().to_string();
+  let content = "––– input –––\necho 0\n––– output –––\n0\n";
+  std::fs::write(&path, content).unwrap();
+  let step = |i: usize| parser::Step { input: format!("echo {}", i), output: i.to_string(), ..Default::default() };
+
+  parser::insert_steps(&path, 1, &[step(1)]).unwrap();
+  let inserted = read_to_string(&path).unwrap();
+  parser::remove_steps(&path, &[step(0)]).unwrap();
+  parser::revert_last_edit(&path).unwrap();
+  assert_eq!(inserted, read_to_string(&path).unwrap());
+  parser::revert_last_edit(&path).unwrap();
+  assert_eq!(content, read_to_string(&path).unwrap());
+  assert!(parser::revert_last_edit(&path).unwrap_err().to_string().contains("no edits to revert"));
+
+  for i in 1..=parser::HISTORY_LIMIT + 5 {
+    parser::insert_steps(&path, i, &[step(i)]).unwrap();
+  }
+  for _ in 0..parser::HISTORY_LIMIT {
+    parser::revert_last_edit(&path).unwrap();
+  }
+  assert_eq!(6, parser::RecFile::read(&path).unwrap().steps.len());
+  assert!(parser::revert_last_edit(&path).is_err());
+  std::fs::remove_file(path).unwrap();
+  // Other tests running at the same time may still write their snapshots there
+  std::env::remove_var(parser::HISTORY_DIR_ENV);
+  std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_preview_writes() {
+  let path = std::env::temp_dir().join(format!("clt-preview-{}.rec", std::process::id())).to_string_lossy().to_string();
+  let content = "––– input –––\necho 1\n––– output –––\n1\n";
+  std::fs::write(&path, content).unwrap();
+  let step = parser::Step { input: "echo 2".to_string(), output: "2".to_string(), ..Default::default() };
+
+  let preview = parser::preview_insert_steps(&path, 1, std::slice::from_ref(&step)).unwrap();
+  assert_eq!(content, read_to_string(&path).unwrap());
+  assert_eq!(format!("{}––– input –––\necho 2\n––– output –––\n2\n", content), preview.content);
+  assert_eq!(
+    format!("--- {0}\n+++ {0}\n@@ -2,3 +2,7 @@\n echo 1\n ––– output –––\n 1\n+––– input –––\n+echo 2\n+––– output –––\n+2\n", path),
+    preview.diff
+  );
+  assert!(parser::preview_remove_steps(&path, &[step]).is_err());
+
+  let rec_file = parser::RecFile::read(&path).unwrap();
+  let previews = parser::preview_write_test(&path, &rec_file).unwrap();
+  assert_eq!(vec![(path.clone(), content.to_string(), String::new())], previews.into_iter().map(|preview| (preview.file, preview.content, preview.diff)).collect::<Vec<_>>());
+  std::fs::remove_file(&path).unwrap();
+  assert!(parser::preview_write_test(&path, &rec_file).unwrap()[0].diff.contains("@@ -0,0 +1,4 @@"));
+}
+
+#[test]
+fn test_parse_metadata() {
+  let content = parser::compile("tests/data/metadata/test.rec").unwrap();
+  let metadata = parser::parse_metadata(&content).unwrap();
+  assert_eq!(metadata.tags, ["smoke", "daemon"]);
+  assert_eq!(Some("search-team".to_string()), metadata.owner);
+  assert_eq!(Some("build:./docker/test.Dockerfile".to_string()), metadata.image);
+  assert_eq!(Some(std::time::Duration::from_secs(30)), metadata.timeout);
+  assert_eq!(Some("port-9306"), metadata.get("locks"));
+  assert_eq!(metadata, parser::RecFile::parse(&content).get_metadata().unwrap());
+
+  assert_eq!(parser::TestMetadata::default(), parser::parse_metadata("––– input –––\nls\n").unwrap());
+  assert!(parser::compile("tests/data/metadata/invalid.rec").is_err());
+  assert!(parser::parse_metadata("---\ntags: smoke\n").is_err());
+}
+
+#[test]
+fn test_rec_file_json_is_stable() {
+  let rec_file = parser::RecFile::parse("––– input –––\necho 1\n––– output –––\n1\n");
+  assert_eq!(
+    r#"{"preamble":"","steps":[{"input":"echo 1","check":null,"output":"1\n","duration_ms":null,"comment":null}],"blocks":[]}"#,
+    serde_json::to_string(&rec_file).unwrap()
+  );
+  // Missing fields are read as empty and written back explicitly
+  let from_json: parser::RecFile = serde_json::from_str(r#"{"steps":[{"input":"echo 1","output":"1\n"}]}"#).unwrap();
+  assert_eq!(rec_file, from_json);
+
+  let issue = parser::LintIssue { file: "test.rec".to_string(), line: 2, message: "input without output".to_string() };
+  assert_eq!(r#"{"file":"test.rec","line":2,"message":"input without output"}"#, serde_json::to_string(&issue).unwrap());
+}
+
+#[test]
+fn test_rec_file_yaml_round_trip() {
+  let content = read_to_string("./tests/data/blocks/test.rec").unwrap().trim_end().to_string() + "\n––– input –––\nls\n––– output: lines>=1 –––\n";
+  let rec_file = parser::RecFile::parse(&content);
+  let yaml = rec_file.to_yaml().unwrap();
+  assert!(yaml.contains("check: lines>=1"));
+  let from_yaml = parser::RecFile::from_yaml(&yaml).unwrap();
+  assert_eq!(rec_file, from_yaml);
+  assert_eq!(content, from_yaml.to_string());
+
+  let rec_file = parser::RecFile::from_yaml("steps:\n- input: echo 1\n  output: |\n    1\n").unwrap();
+  assert_eq!("––– input –––\necho 1\n––– output –––\n1\n", rec_file.to_string());
+}
+
+#[test]
+fn test_diff_structures() {
+  let a = parser::RecFile::parse("––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho 2\n––– output –––\n2\n––– input –––\necho 3\n––– output –––\n3\n");
+  let b = parser::RecFile::parse("––– input –––\necho 1  \n––– output –––\n1\n\n––– input –––\necho 3\n––– output –––\n%{NUMBER}\n––– input –––\necho 4\n––– output –––\n4\n");
+  assert!(parser::diff_structures(&a, &a.clone()).is_empty());
+
+  let diff = parser::diff_structures(&a, &b);
+  assert!(!diff.preamble_changed);
+  assert_eq!(diff.to_string(), "step 2 changed its input, output: echo 3\nstep 3 changed its input, output: echo 4\n");
+  let b = parser::RecFile::parse("––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho 3\n––– output –––\n3\n––– input –––\necho 4\n––– output: lines=1 –––\n");
+  assert_eq!(parser::diff_structures(&a, &b).to_string(), "step 2 removed: echo 2\nstep 3 added: echo 4\n");
+}
+
+#[test]
+fn test_merge_structures() {
+  let test = |outputs: [&str; 3]| parser::RecFile::parse(&outputs.iter().enumerate()
+    .map(|(i, output)| format!("––– input –––\necho {}\n––– output –––\n{}\n", i + 1, output))
+    .collect::<String>());
+  let base = test(["1", "2", "3"]);
+
+  let merged = parser::merge_structures(&base, &test(["%{NUMBER}", "2", "3"]), &test(["1", "2", "#!/[0-9]/!#"]));
+  assert_eq!((0, false), (merged.conflicts, merged.preamble_conflict));
+  assert_eq!(test(["%{NUMBER}", "2", "#!/[0-9]/!#"]), merged.file);
+
+  let merged = parser::merge_structures(&base, &test(["1", "two", "3"]), &test(["1", "%{NUMBER}", "3"]));
+  assert_eq!(1, merged.conflicts);
+  let inputs: Vec<&str> = merged.file.steps.iter().map(|step| step.input.trim_end()).collect();
+  assert_eq!(inputs, ["echo 1", "# <<<<<<< ours", "echo 2", "# =======", "echo 2", "# >>>>>>> theirs", "echo 3"]);
+  assert_eq!("two\n", merged.file.steps[2].output);
+
+  // Adjacent steps changed in different versions are merged by their inputs
+  let merged = parser::merge_structures(&base, &test(["%{NUMBER}", "two", "3"]), &test(["1", "2", "three"]));
+  assert_eq!(0, merged.conflicts);
+  assert_eq!(test(["%{NUMBER}", "two", "three"]), merged.file);
+  let merged = parser::merge_structures(&base, &test(["one", "two", "3"]), &test(["1", "%{NUMBER}", "3"]));
+  assert_eq!(1, merged.conflicts);
+  let inputs: Vec<&str> = merged.file.steps.iter().map(|step| step.input.trim_end()).collect();
+  assert_eq!(inputs, ["echo 1", "# <<<<<<< ours", "echo 2", "# =======", "echo 2", "# >>>>>>> theirs", "echo 3"]);
+  assert_eq!("one\n", merged.file.steps[0].output);
+}
+
+#[test]
+fn test_step_iterator() {
+  let file = std::fs::File::open("./tests/data/env/test.rec").unwrap();
+  let mut steps = parser::StepIterator::new(std::io::BufReader::new(file));
+  assert!(steps.preamble().unwrap().starts_with(parser::ENV_STATEMENT));
+  let steps: Vec<parser::Step> = steps.collect::<anyhow::Result<_>>().unwrap();
+  assert_eq!(parser::RecFile::read("./tests/data/env/test.rec").unwrap().steps, steps);
+
+  let content = "––– input –––\necho 1\n––– output –––\n1\n––– input –––\necho 2\n";
+  let steps: Vec<parser::Step> = parser::StepIterator::new(content.as_bytes()).map(Result::unwrap).collect();
+  assert_eq!(2, steps.len());
+  assert_eq!(None, steps[1].check);
+  assert_eq!("", steps[1].output);
+}
+
+#[test]
+fn test_find_group() {
+  assert_eq!(Some(("tests/data/parallel/test.rec".to_string(), 5)), parser::find_group("tests/data/parallel/test.rec").unwrap());
+  let (file, line) = parser::find_group("tests/data/refine/test.rec").unwrap().unwrap();
+  assert!(file.ends_with("refine/group.recb"));
+  assert_eq!(5, line);
+  assert_eq!(None, parser::find_group("tests/data/legacy/test.rec").unwrap());
+}
@@ -0,0 +1,4 @@
+searchd {
+  listen = {{ ALLOC_PORT }}:mysql
+  data_dir = {{DATA_DIR}}
+}
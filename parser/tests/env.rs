@@ -0,0 +1,59 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// The tests change the environment of the process, so they run one at a time
+// and apart from the other tests that read it
+static ENV: Mutex<()> = Mutex::new(());
+
+fn lock_env() -> MutexGuard<'static, ()> {
+  ENV.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[test]
+fn test_compile_finds_blocks_in_block_path() {
+  let _env = lock_env();
+  assert!(parser::compile("tests/data/block-path/suite/test.rec").is_err());
+  std::env::set_var(parser::BLOCK_PATH_ENV, "tests/data/missing:tests/data/block-path/library");
+  let content = parser::compile("tests/data/block-path/suite/test.rec");
+  std::env::remove_var(parser::BLOCK_PATH_ENV);
+  assert_eq!("––– input –––\nwhoami\n––– output –––\nroot\n––– input –––\necho shared\n––– output –––\nshared\n", content.unwrap());
+}
+
+#[test]
+fn test_compile_substitutes_variables_in_inputs() {
+  let _env = lock_env();
+  std::env::set_var(parser::VARIABLES_FILE_ENV, "tests/data/variables/local");
+  let compiled = parser::compile_checked("tests/data/variables/test.rec", false);
+  std::env::remove_var(parser::VARIABLES_FILE_ENV);
+  let compiled = compiled.unwrap();
+  assert_eq!("––– input –––\nmysql -h db.local -P 9306 -e \"select %{I}\"\n––– output –––\n%{HOST} %{NUMBER}\n", compiled.content);
+  assert_eq!("––– input –––\nmysql -h %{HOST} -P %{PORT} -e \"select %{I}\"\n––– output –––\n%{HOST} %{NUMBER}\n", compiled.source);
+
+  let error = parser::read_variables("tests/data/variables/invalid").unwrap_err();
+  assert_eq!("tests/data/variables/invalid:1: expected NAME=value, got \"HOST db.local\"", error.to_string());
+}
+
+#[test]
+fn test_statement_style_from_env() {
+  let _env = lock_env();
+  assert_eq!(parser::StatementStyle::Classic, parser::get_statement_style());
+  std::env::set_var(parser::STATEMENT_STYLE_ENV, "ascii");
+  let style = parser::get_statement_style();
+  let content = parser::compile("tests/data/ascii/test.rec");
+  std::env::remove_var(parser::STATEMENT_STYLE_ENV);
+  assert_eq!(parser::StatementStyle::Ascii, style);
+  assert_eq!("––– input –––\necho hello\n––– output –––\nhello\n", content.unwrap());
+}
+
+#[test]
+fn test_compile_keeps_runtime_variables_in_outputs() {
+  let _env = lock_env();
+  std::env::set_var("CLT_ALLOC_PORT", "9306");
+  std::env::set_var("CLT_TZ", "Europe/Berlin");
+  let compiled = parser::compile_checked("tests/data/runtime/test.rec", false);
+  std::env::remove_var("CLT_ALLOC_PORT");
+  std::env::remove_var("CLT_TZ");
+  assert_eq!(
+    "––– input –––\nsearchd --port 9306\n––– output –––\nlistening on %{ALLOC_PORT}\n––– skip-if: [ 9306 = 0 ] –––\n––– input –––\necho Europe/Berlin\n––– output –––\n%{TZ}\n",
+    compiled.unwrap().content
+  );
+}
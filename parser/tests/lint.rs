@@ -0,0 +1,49 @@
+#[test]
+fn test_lint_test() {
+  let issues: Vec<String> = parser::lint_test("tests/data/lint/test.rec").iter().map(ToString::to_string).collect();
+  assert_eq!(issues, [
+    "tests/data/lint/test.rec:1: statement \"--- input ---\" has wrong dashes or spacing, use \"––– input –––\"",
+    "tests/data/lint/test.rec:5: input has no output",
+    "tests/data/lint/test.rec:9: unknown output check \"lines>>1\", use lines>=N, lines<=N, lines=N, lines=N..M, sha256, contains, base64 or file=path",
+    "tests/data/lint/test.rec:11: output repeats the one at line 9 without an input between them",
+    "tests/data/lint/test.rec:12: %{SKIP_LINES} matches whole lines and must be on a line of its own",
+    "tests/data/lint/test.rec:14: unknown statement \"––– blokc: setup –––\", did you mean \"––– block: setup –––\"?",
+    "tests/data/lint/test.rec:15: block file tests/data/lint/missing.recb does not exist",
+    "tests/data/lint/test.rec:16: input has no output",
+  ]);
+}
+
+#[test]
+fn test_lint_valid_test() {
+  assert!(parser::lint_test("tests/data/blocks/test.rec").is_empty());
+  assert!(parser::lint_test("tests/data/repeat/test.rec").is_empty());
+  assert_eq!(0, parser::lint_test("tests/data/missing.rec")[0].line);
+}
+
+#[test]
+fn test_lint_content() {
+  let content = std::fs::read_to_string("tests/data/lint/test.rec").unwrap();
+  assert_eq!(parser::lint_test("tests/data/lint/test.rec"), parser::lint_content("tests/data/lint/test.rec", &content));
+  assert!(parser::lint_content("tests/data/lint/new.rec", "––– input –––\necho 1\n––– output –––\n1\n").is_empty());
+}
+
+#[test]
+fn test_get_hover() {
+  let patterns = std::collections::HashMap::from([("NUMBER".to_string(), "[0-9]+".to_string())]);
+  let content = "––– input –––\necho 42\n––– output: lines>=1 –––\nvalue: %{NUMBER>rows} %{UNKNOWN}\n%{SKIP_LINES}\n";
+
+  let hover = parser::get_hover(content, 0, 2, &patterns).unwrap();
+  assert_eq!((0, 13), (hover.start, hover.end));
+  assert!(hover.documentation.starts_with("––– input –––: "));
+  let hover = parser::get_hover(content, 2, 14, &patterns).unwrap();
+  assert_eq!((12, 20), (hover.start, hover.end));
+  assert!(hover.documentation.starts_with("lines>=1: "));
+  assert!(parser::get_hover(content, 2, 4, &patterns).unwrap().documentation.starts_with("––– output –––: "));
+
+  let hover = parser::get_hover(content, 3, 10, &patterns).unwrap();
+  assert_eq!((7, 21, "%{NUMBER} matches [0-9]+"), (hover.start, hover.end, hover.documentation.as_str()));
+  assert!(parser::get_hover(content, 3, 25, &patterns).unwrap().documentation.contains("compared literally"));
+  assert!(parser::get_hover(content, 4, 0, &patterns).unwrap().documentation.starts_with("%{SKIP_LINES}: "));
+  assert_eq!(None, parser::get_hover(content, 1, 0, &patterns));
+  assert_eq!(None, parser::get_hover(content, 3, 2, &patterns));
+}
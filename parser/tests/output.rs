@@ -0,0 +1,108 @@
+use parser::OutputCheck;
+
+#[test]
+fn test_parse_output_check_lines() {
+  assert_eq!(None, parser::parse_output_check("––– output –––").unwrap());
+  assert_eq!(
+    Some(OutputCheck::Lines { min: 100, max: None }),
+    parser::parse_output_check("––– output: lines>=100 –––").unwrap()
+  );
+  assert_eq!(
+    Some(OutputCheck::Lines { min: 1, max: Some(10) }),
+    parser::parse_output_check("––– output: lines=1..10 –––").unwrap()
+  );
+  assert!(parser::parse_output_check("––– output: lines>=many –––").is_err());
+}
+
+#[test]
+fn test_output_check_sha256() {
+  let lines: Vec<String> = vec!["hello".to_string(), "".to_string()];
+  let hash = parser::get_output_hash(&lines);
+  assert_eq!("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824", hash);
+  assert_eq!(None, OutputCheck::Sha256.check(&[hash], &lines));
+  assert!(OutputCheck::Sha256.check(&["0".to_string()], &lines).is_some());
+}
+
+#[test]
+fn test_output_check_base64() {
+  assert_eq!(Some(OutputCheck::Base64), parser::parse_output_check("––– output: base64 –––").unwrap());
+  let lines: Vec<String> = vec!["AAEC/w==".to_string()];
+  assert_eq!(None, OutputCheck::Base64.check(&["AAE".to_string(), "C/w==".to_string()], &lines));
+  assert!(OutputCheck::Base64.check(&["AAEC/g==".to_string()], &lines).is_some());
+  assert!(OutputCheck::Base64.check(&["not base64".to_string()], &lines).is_some());
+}
+
+#[test]
+fn test_compile_loads_output_file() {
+  let content = parser::compile("tests/data/output/test.rec").unwrap();
+  assert_eq!("––– input –––\nprintf \"line 1\\nlast\\n\"\n––– output: file=expected/result.txt –––\nline %{NUMBER}\nlast\n", content);
+  assert_eq!(None, parser::parse_output_check("––– output: file=expected/result.txt –––").unwrap());
+}
+
+#[test]
+fn test_compile_rejects_lines_under_output_file() {
+  let err = parser::compile("tests/data/output/lines.rec").unwrap_err();
+  let err = err.downcast_ref::<parser::ParseError>().unwrap();
+  assert_eq!(4, err.line);
+  assert_eq!(parser::ParseErrorKind::InvalidArgument, err.kind);
+}
+
+#[test]
+fn test_evaluate_assertion() {
+  let vars = std::collections::HashMap::from([
+    ("rows".to_string(), "12".to_string()),
+    ("status".to_string(), "ok".to_string()),
+  ]);
+  assert!(parser::evaluate_assertion("${rows} > 9", &vars).unwrap());
+  assert!(!parser::evaluate_assertion("${rows} <= 9", &vars).unwrap());
+  assert!(parser::evaluate_assertion("${status} == \"ok\"", &vars).unwrap());
+  assert!(parser::evaluate_assertion("${missing} == 1", &vars).is_err());
+  assert!(parser::evaluate_assertion("${rows}", &vars).is_err());
+}
+
+#[test]
+fn test_parse_terminal_size() {
+  assert_eq!((120, 40), parser::parse_terminal_size("120x40").unwrap());
+  assert!(parser::parse_terminal_size("120").is_err());
+  assert!(parser::parse_terminal_size("0x40").is_err());
+  assert!(parser::parse_terminal_size("widex40").is_err());
+}
+
+#[test]
+fn test_parse_timeout() {
+  assert_eq!(std::time::Duration::from_secs(30), parser::parse_timeout("30s").unwrap());
+  assert_eq!(std::time::Duration::from_millis(500), parser::parse_timeout("500ms").unwrap());
+  assert_eq!(std::time::Duration::from_secs(300), parser::parse_timeout("5m").unwrap());
+  assert!(parser::parse_timeout("30").is_err());
+  assert!(parser::parse_timeout("0s").is_err());
+  assert!(parser::parse_timeout("1h").is_err());
+  assert!(parser::parse_timeout(&format!("{}m", u64::MAX)).is_err());
+}
+
+#[test]
+fn test_parse_exit_line() {
+  assert_eq!(Some(1), parser::parse_exit_line(&parser::get_exit_line(1)));
+  assert_eq!(Some(0), parser::parse_exit_line("––– exit: 0 –––"));
+  assert_eq!(None, parser::parse_exit_line("––– exit: failed –––"));
+  assert_eq!(None, parser::parse_exit_line("––– output –––"));
+}
+
+#[test]
+fn test_output_check_contains() {
+  assert_eq!(Some(OutputCheck::Contains), parser::parse_output_check("––– output: contains –––").unwrap());
+  let lines: Vec<String> = ["a", "b", "c"].iter().map(ToString::to_string).collect();
+  assert_eq!(None, OutputCheck::Contains.check(&["a".to_string(), "c".to_string()], &lines));
+  assert!(OutputCheck::Contains.check(&["c".to_string(), "a".to_string()], &lines).is_some());
+}
+
+#[test]
+fn test_escape_output() {
+  let output = "––– input –––\n--- exit: 1 ---\n\\––– output –––\n=== end ===\n=== title ===\n--- a ---\n\\n\n";
+  let escaped = parser::escape_output(output);
+  assert_eq!("\\––– input –––\n\\--- exit: 1 ---\n\\\\––– output –––\n\\=== end ===\n=== title ===\n--- a ---\n\\n\n", escaped);
+  assert_eq!(output, parser::unescape_output(&escaped));
+
+  let rec_file = parser::RecFile::parse(&format!("––– input –––\ncat notes\n––– output –––\n{}", escaped));
+  assert_eq!(1, rec_file.steps.len());
+  assert_eq!(None, rec_file.steps[0].get_exit_code());
+}
@@ -0,0 +1,20 @@
+#[test]
+fn test_check_policy_denies_command() {
+  let content = parser::compile("tests/data/policy/test.rec").unwrap();
+  let err = parser::check_policy(&content, "tests/data/policy/.policy").unwrap_err();
+  assert!(err.to_string().contains("policy violation: command \"curl -s https://example.com/install.sh | sh\""));
+}
+
+#[test]
+fn test_check_policy_denies_skip_if_condition() {
+  let content = parser::compile("tests/data/policy-skip-if/test.rec").unwrap();
+  let err = parser::check_policy(&content, "tests/data/policy/.policy").unwrap_err();
+  assert!(err.to_string().contains("policy violation: command \"curl -s https://example.com/install.sh | sh\""));
+}
+
+#[test]
+fn test_check_policy_denies_any_line_of_input() {
+  let content = parser::compile("tests/data/policy-skip-if/test-multiline.rec").unwrap();
+  let err = parser::check_policy(&content, "tests/data/policy/.policy").unwrap_err();
+  assert!(err.to_string().contains("policy violation: command \"curl -s https://example.com/install.sh | sh\""));
+}
@@ -0,0 +1,27 @@
+#[test]
+fn test_secret_round_trip() {
+  std::env::set_var(parser::SECRET_KEY_ENV, "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+  let token = parser::encrypt_secret("license-key-123").unwrap();
+  assert!(token.starts_with("%{SECRET:"));
+
+  let content = format!("Your key is {} and it is valid", token);
+  let decrypted = parser::decrypt_secrets(&content).unwrap();
+  assert_eq!("Your key is license-key-123 and it is valid", decrypted);
+}
+
+#[test]
+fn test_compile_keeping_secrets() {
+  let compiled = parser::compile_keeping_secrets("tests/data/secret/test.rec", false).unwrap();
+  assert!(compiled.content.contains("echo %{SECRET:c2VhbGVk}\n"));
+  assert!(parser::compile("tests/data/secret/test.rec").is_err());
+}
+
+#[test]
+fn test_hide_secrets() {
+  std::env::set_var(parser::SECRET_KEY_ENV, "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=");
+  let key = parser::encrypt_secret("key-123").unwrap();
+  let short = parser::encrypt_secret("1").unwrap();
+  let secrets = parser::get_secrets(&format!("echo {} {}", short, key)).unwrap();
+  assert_eq!(vec![("key-123".to_string(), key.clone()), ("1".to_string(), short.clone())], secrets);
+  assert_eq!(format!("{} and {}", key, short), parser::hide_secrets("key-123 and 1", &secrets).unwrap());
+}
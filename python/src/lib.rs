@@ -0,0 +1,434 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Python bindings to read, write, validate and compare CLT tests without running the binaries
+
+use std::collections::HashMap;
+use clt_core::Comparator;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+/// Step of the test: the input, the expected output and the argument of the output statement like lines>=100,
+/// steps read from .rep files also have the time the command took in duration_ms
+#[pyclass(get_all, set_all, module = "clt")]
+#[derive(Clone)]
+struct Step {
+	input: String,
+	output: String,
+	check: Option<String>,
+	duration_ms: Option<u128>,
+	/// Shell comment lines documenting the step written before it, set when the test is read with comments=True
+	comment: Option<String>,
+}
+
+#[pymethods]
+impl Step {
+	#[new]
+	#[pyo3(signature = (input, output = String::new(), check = None, comment = None))]
+	fn new(input: String, output: String, check: Option<String>, comment: Option<String>) -> Self {
+		Self { input, output, check, duration_ms: None, comment }
+	}
+
+	/// Expected exit code of the command when the output has ––– exit: N –––
+	#[getter]
+	fn exit_code(&self) -> Option<i32> {
+		parser::Step::from(self.clone()).get_exit_code()
+	}
+
+	fn __repr__(&self) -> String {
+		format!("Step(input={:?}, output={:?}, check={:?})", self.input, self.output, self.check)
+	}
+}
+
+impl From<Step> for parser::Step {
+	fn from(step: Step) -> Self {
+		Self { input: step.input, check: step.check, output: step.output, duration_ms: step.duration_ms, comment: step.comment }
+	}
+}
+
+impl From<parser::Step> for Step {
+	fn from(step: parser::Step) -> Self {
+		Self { input: step.input, output: step.output, check: step.check, duration_ms: step.duration_ms, comment: step.comment }
+	}
+}
+
+/// Metadata of the test from its front matter, keys without a dedicated field are kept in values
+#[pyclass(get_all, module = "clt")]
+#[derive(Clone)]
+struct Metadata {
+	tags: Vec<String>,
+	owner: Option<String>,
+	image: Option<String>,
+	timeout: Option<std::time::Duration>,
+	values: HashMap<String, String>,
+}
+
+#[pymethods]
+impl Metadata {
+	fn __repr__(&self) -> String {
+		format!("Metadata(tags={:?})", self.tags.join(", "))
+	}
+}
+
+impl From<parser::TestMetadata> for Metadata {
+	fn from(metadata: parser::TestMetadata) -> Self {
+		Self {
+			tags: metadata.tags,
+			owner: metadata.owner,
+			image: metadata.image,
+			timeout: metadata.timeout,
+			values: metadata.values.into_iter().collect(),
+		}
+	}
+}
+
+/// Content the file would be written with and the unified diff against its current content, empty when it stays the same
+#[pyclass(get_all, module = "clt")]
+struct WritePreview {
+	file: String,
+	content: String,
+	diff: String,
+}
+
+#[pymethods]
+impl WritePreview {
+	fn __repr__(&self) -> String {
+		format!("WritePreview({:?})", self.file)
+	}
+}
+
+impl From<parser::WritePreview> for WritePreview {
+	fn from(preview: parser::WritePreview) -> Self {
+		Self { file: preview.file, content: preview.content, diff: preview.diff }
+	}
+}
+
+/// Statement including the block with the absolute path of its file, the line is numbered from 1
+/// and the name spans from start to end in characters
+#[pyclass(get_all, module = "clt")]
+struct BlockReference {
+	file: String,
+	line: usize,
+	start: usize,
+	end: usize,
+	name: String,
+	path: String,
+	exists: bool,
+}
+
+#[pymethods]
+impl BlockReference {
+	fn __repr__(&self) -> String {
+		format!("BlockReference({}:{} {:?})", self.file, self.line, self.name)
+	}
+}
+
+impl From<parser::BlockReference> for BlockReference {
+	fn from(reference: parser::BlockReference) -> Self {
+		Self {
+			file: reference.file,
+			line: reference.line,
+			start: reference.start,
+			end: reference.end,
+			name: reference.name,
+			path: reference.path.to_string_lossy().to_string(),
+			exists: reference.exists,
+		}
+	}
+}
+
+/// Test split into steps, the preamble holds everything before the first input like the front matter,
+/// blocks are filled when the test is read with blocks=True
+#[pyclass(get_all, set_all, module = "clt")]
+#[derive(Clone)]
+struct RecFile {
+	preamble: String,
+	steps: Vec<Step>,
+	blocks: Vec<Block>,
+}
+
+/// Block included with ––– block: name ––– read as a test of its own
+#[pyclass(get_all, set_all, module = "clt")]
+#[derive(Clone)]
+struct Block {
+	name: String,
+	file: RecFile,
+}
+
+#[pymethods]
+impl Block {
+	fn __repr__(&self) -> String {
+		format!("Block(name={:?}, steps={})", self.name, self.file.steps.len())
+	}
+}
+
+#[pymethods]
+impl RecFile {
+	#[new]
+	#[pyo3(signature = (steps = Vec::new(), preamble = String::new()))]
+	fn new(steps: Vec<Step>, preamble: String) -> Self {
+		Self { preamble, steps, blocks: Vec::new() }
+	}
+
+	/// Metadata parsed from the front matter in the preamble
+	#[getter]
+	fn metadata(&self) -> PyResult<Metadata> {
+		parser::parse_metadata(&self.preamble)
+			.map(Metadata::from)
+			.map_err(|e| PyValueError::new_err(format!("Failed to parse the front matter: {}", e)))
+	}
+
+	/// Test as YAML that is easier to edit than the statements with en dashes
+	fn to_yaml(&self) -> PyResult<String> {
+		parser::RecFile::from(self.clone()).to_yaml().map_err(|e| PyValueError::new_err(e.to_string()))
+	}
+
+	/// Indices of the steps matching all the conditions given, the input and output are regexes,
+	/// the check is the name of the output check or none, raises ValueError when a regex is invalid
+	#[pyo3(signature = (input = None, output = None, check = None, statement = None, block = None))]
+	fn find_steps(&self, input: Option<String>, output: Option<String>, check: Option<String>, statement: Option<String>, block: Option<String>) -> PyResult<Vec<usize>> {
+		let selector = parser::StepSelector { input, output, check, statement, block };
+		let rec_file: parser::RecFile = self.clone().into();
+		rec_file.find_steps(&selector).map_err(|e| PyValueError::new_err(format!("Invalid selector: {}", e)))
+	}
+
+	/// Write the test to the file and its blocks, if it has any, to their .recb files,
+	/// with the version from file_version it raises ValueError when the file was changed by others since then,
+	/// with dry_run it writes nothing and returns the previews of the files it would write
+	#[pyo3(signature = (path, version = None, dry_run = false))]
+	fn write(&self, path: &str, version: Option<&str>, dry_run: bool) -> PyResult<Option<Vec<WritePreview>>> {
+		let error = |e: anyhow::Error| match e.downcast_ref::<parser::WriteConflict>() {
+			Some(conflict) => PyValueError::new_err(conflict.to_string()),
+			None => PyOSError::new_err(format!("Failed to write {}: {}", path, e)),
+		};
+		if dry_run {
+			let previews = parser::preview_write_test(path, &self.clone().into()).map_err(error)?;
+			return Ok(Some(previews.into_iter().map(WritePreview::from).collect()));
+		}
+		parser::write_test_file_checked(path, &self.clone().into(), version).map_err(error)?;
+		Ok(None)
+	}
+
+	/// Content of the test as it is written to the file
+	fn __str__(&self) -> String {
+		parser::RecFile::from(self.clone()).to_string()
+	}
+
+	fn __repr__(&self) -> String {
+		format!("RecFile(steps={})", self.steps.len())
+	}
+}
+
+impl From<RecFile> for parser::RecFile {
+	fn from(rec_file: RecFile) -> Self {
+		Self {
+			preamble: rec_file.preamble,
+			steps: rec_file.steps.into_iter().map(parser::Step::from).collect(),
+			blocks: rec_file.blocks.into_iter().map(|block| parser::Block { name: block.name, file: block.file.into() }).collect(),
+		}
+	}
+}
+
+impl From<parser::RecFile> for RecFile {
+	fn from(rec_file: parser::RecFile) -> Self {
+		Self {
+			preamble: rec_file.preamble,
+			steps: rec_file.steps.into_iter().map(Step::from).collect(),
+			blocks: rec_file.blocks.into_iter().map(|block| Block { name: block.name, file: block.file.into() }).collect(),
+		}
+	}
+}
+
+/// Result of the comparison, the diff is the expected output with the differences marked by - and + as cmp prints it
+#[pyclass(get_all, module = "clt")]
+struct Comparison {
+	has_diff: bool,
+	diff: String,
+	ignored_count: usize,
+}
+
+#[pymethods]
+impl Comparison {
+	fn __bool__(&self) -> bool {
+		!self.has_diff
+	}
+}
+
+/// Parse the content of the test into steps
+#[pyfunction]
+fn parse(content: &str) -> RecFile {
+	parser::RecFile::parse(content).into()
+}
+
+/// Parse the test converted into YAML with RecFile.to_yaml()
+#[pyfunction]
+fn from_yaml(content: &str) -> PyResult<RecFile> {
+	parser::RecFile::from_yaml(content)
+		.map(RecFile::from)
+		.map_err(|e| PyValueError::new_err(format!("Failed to parse YAML: {}", e)))
+}
+
+/// Read the test from the file, with compile=True blocks, templates and groups are expanded first,
+/// with blocks=True the blocks it includes are read into the blocks of the test to edit them,
+/// with comments=True the steps with only shell comments are attached to the steps after them
+#[pyfunction]
+#[pyo3(signature = (path, compile = false, blocks = false, comments = false))]
+fn read(path: &str, compile: bool, blocks: bool, comments: bool) -> PyResult<RecFile> {
+	let rec_file = match (compile, blocks) {
+		(true, _) => parser::compile_keeping_secrets(path, false).map(|compiled| parser::RecFile::parse(&compiled.content)),
+		(false, true) => parser::RecFile::read_with_blocks(path),
+		(false, false) => parser::RecFile::read(path),
+	};
+	let mut rec_file = rec_file.map_err(|e| PyValueError::new_err(format!("Failed to read {}: {}", path, e)))?;
+	if comments {
+		rec_file.attach_comments();
+	}
+	Ok(rec_file.into())
+}
+
+/// Write the test to the file
+#[pyfunction]
+#[pyo3(signature = (rec_file, path, version = None, dry_run = false))]
+fn write(rec_file: &RecFile, path: &str, version: Option<&str>, dry_run: bool) -> PyResult<Option<Vec<WritePreview>>> {
+	rec_file.write(path, version, dry_run)
+}
+
+/// Version of the file to take before reading the test and pass to write, empty when the file does not exist
+#[pyfunction]
+fn file_version(path: &str) -> PyResult<String> {
+	parser::get_file_version(path).map_err(|e| PyOSError::new_err(format!("Failed to read {}: {}", path, e)))
+}
+
+/// Check that the test compiles and return the warnings about deprecated syntax and the stale .rep file next to it,
+/// raises ValueError with the reason when the test is invalid, in strict mode deprecated syntax is invalid too
+#[pyfunction]
+#[pyo3(signature = (path, strict = false))]
+fn validate(path: &str, strict: bool) -> PyResult<Vec<String>> {
+	let compiled = parser::compile_keeping_secrets(path, strict)
+		.map_err(|e| PyValueError::new_err(format!("Failed to compile {}: {}", path, e)))?;
+	let mut warnings: Vec<String> = compiled.deprecations.iter().map(|deprecation| deprecation.to_string()).collect();
+	warnings.extend(parser::check_replay_hash(path, &parser::get_rep_file_path(path), &compiled));
+	Ok(warnings)
+}
+
+/// Insert the steps into the test file before the step at the index, raises ValueError when any of them is invalid,
+/// with dry_run it returns the preview of the test instead of writing it
+#[pyfunction]
+#[pyo3(signature = (path, index, steps, dry_run = false))]
+fn insert_steps(path: &str, index: usize, steps: Vec<Step>, dry_run: bool) -> PyResult<Option<WritePreview>> {
+	let steps: Vec<parser::Step> = steps.into_iter().map(parser::Step::from).collect();
+	let error = |e: anyhow::Error| PyValueError::new_err(format!("Failed to insert steps into {}: {}", path, e));
+	if dry_run {
+		return Ok(Some(parser::preview_insert_steps(path, index, &steps).map_err(error)?.into()));
+	}
+	parser::insert_steps(path, index, &steps).map_err(error)?;
+	Ok(None)
+}
+
+/// Remove the steps following each other from the test file, raises ValueError when they are not found or found several times,
+/// with dry_run it returns the preview of the test instead of writing it
+#[pyfunction]
+#[pyo3(signature = (path, steps, dry_run = false))]
+fn remove_steps(path: &str, steps: Vec<Step>, dry_run: bool) -> PyResult<Option<WritePreview>> {
+	let steps: Vec<parser::Step> = steps.into_iter().map(parser::Step::from).collect();
+	let error = |e: anyhow::Error| PyValueError::new_err(format!("Failed to remove steps from {}: {}", path, e));
+	if dry_run {
+		return Ok(Some(parser::preview_remove_steps(path, &steps).map_err(error)?.into()));
+	}
+	parser::remove_steps(path, &steps).map_err(error)?;
+	Ok(None)
+}
+
+/// Restore the test file as it was before the last edit saved to .clt/history, raises ValueError when there is none
+#[pyfunction]
+fn revert_last_edit(path: &str) -> PyResult<()> {
+	parser::revert_last_edit(path).map_err(|e| PyValueError::new_err(format!("Failed to revert the last edit of {}: {}", path, e)))
+}
+
+/// Escape the lines of the output printed by the command that look like statements, as rec does before writing it
+#[pyfunction]
+fn escape_output(output: &str) -> String {
+	parser::escape_output(output)
+}
+
+/// Get the output as the command printed it from the one escaped in the test
+#[pyfunction]
+fn unescape_output(output: &str) -> String {
+	parser::unescape_output(output)
+}
+
+/// Compare the test with the output of its replay the same way cmp does
+#[pyfunction]
+#[pyo3(signature = (rec_file, rep_file, patterns = ".patterns", normalize = "", ignore_lines = ".ignore-lines"))]
+fn compare(rec_file: &str, rep_file: &str, patterns: &str, normalize: &str, ignore_lines: &str) -> PyResult<Comparison> {
+	let error = |e: anyhow::Error| PyValueError::new_err(format!("{:#}", e));
+	let comparator = Comparator::load(patterns, normalize, ignore_lines).map_err(error)?;
+	let compiled = parser::compile_checked(rec_file, false).map_err(error)?;
+	let output = std::fs::read_to_string(rep_file).map_err(|e| PyOSError::new_err(format!("Failed to read {}: {}", rep_file, e)))?;
+
+	let mut diff = termcolor::Buffer::no_color();
+	let comparison = comparator.compare_with_source(&compiled.content, &compiled.source, &output, &mut diff).map_err(error)?;
+	Ok(Comparison {
+		has_diff: comparison.has_diff,
+		diff: String::from_utf8_lossy(diff.as_slice()).to_string(),
+		ignored_count: comparison.ignored_count,
+	})
+}
+
+/// Find the documentation of the statement, the output check or the pattern at the line and the column of the test,
+/// both from 0, to show hovers in editors, returns the start and the end of the hovered text in the line with it
+#[pyfunction]
+#[pyo3(signature = (content, line, column, patterns = ".patterns"))]
+fn hover(content: &str, line: usize, column: usize, patterns: &str) -> PyResult<Option<(usize, usize, String)>> {
+	let patterns = match std::path::Path::new(patterns).exists() {
+		true => parser::read_patterns(patterns).map_err(|e| PyOSError::new_err(format!("Failed to read {}: {}", patterns, e)))?,
+		false => Default::default(),
+	};
+	Ok(parser::get_hover(content, line, column, &patterns).map(|hover| (hover.start, hover.end, hover.documentation)))
+}
+
+/// List the blocks included in the test and in the blocks it includes with the paths of their files
+#[pyfunction]
+fn block_references(path: &str) -> PyResult<Vec<BlockReference>> {
+	let references = parser::get_block_references(path).map_err(|e| PyOSError::new_err(format!("Failed to read {}: {}", path, e)))?;
+	Ok(references.into_iter().map(BlockReference::from).collect())
+}
+
+#[pymodule]
+fn clt(m: &Bound<'_, PyModule>) -> PyResult<()> {
+	m.add_class::<Step>()?;
+	m.add_class::<RecFile>()?;
+	m.add_class::<Block>()?;
+	m.add_class::<Metadata>()?;
+	m.add_class::<Comparison>()?;
+	m.add_class::<BlockReference>()?;
+	m.add_class::<WritePreview>()?;
+	m.add_function(wrap_pyfunction!(parse, m)?)?;
+	m.add_function(wrap_pyfunction!(from_yaml, m)?)?;
+	m.add_function(wrap_pyfunction!(read, m)?)?;
+	m.add_function(wrap_pyfunction!(write, m)?)?;
+	m.add_function(wrap_pyfunction!(file_version, m)?)?;
+	m.add_function(wrap_pyfunction!(validate, m)?)?;
+	m.add_function(wrap_pyfunction!(insert_steps, m)?)?;
+	m.add_function(wrap_pyfunction!(remove_steps, m)?)?;
+	m.add_function(wrap_pyfunction!(revert_last_edit, m)?)?;
+	m.add_function(wrap_pyfunction!(escape_output, m)?)?;
+	m.add_function(wrap_pyfunction!(unescape_output, m)?)?;
+	m.add_function(wrap_pyfunction!(compare, m)?)?;
+	m.add_function(wrap_pyfunction!(hover, m)?)?;
+	m.add_function(wrap_pyfunction!(block_references, m)?)?;
+	Ok(())
+}
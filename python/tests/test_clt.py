@@ -0,0 +1,108 @@
+import datetime
+import os
+import shutil
+import tempfile
+import unittest
+
+import clt
+
+DATA_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "data")
+
+
+def data_file(name):
+    return os.path.join(DATA_DIR, name)
+
+
+class TestRead(unittest.TestCase):
+    def test_read_steps_and_metadata(self):
+        test = clt.read(data_file("search.rec"))
+        self.assertEqual(["echo hello", "false"], [step.input for step in test.steps])
+        self.assertEqual("hello\n", test.steps[0].output)
+        self.assertIsNone(test.steps[0].exit_code)
+        self.assertEqual(1, test.steps[1].exit_code)
+
+        metadata = test.metadata
+        self.assertEqual(["smoke", "search"], metadata.tags)
+        self.assertEqual("search-team", metadata.owner)
+        self.assertEqual(datetime.timedelta(seconds=30), metadata.timeout)
+        self.assertEqual("search-team", metadata.values["owner"])
+
+    def test_parse_and_print_keep_content(self):
+        with open(data_file("search.rec"), encoding="utf-8") as file:
+            content = file.read()
+        self.assertEqual(content, str(clt.parse(content)))
+
+    def test_yaml_round_trip(self):
+        test = clt.read(data_file("search.rec"))
+        converted = clt.from_yaml(test.to_yaml())
+        self.assertEqual(str(test), str(converted))
+        with self.assertRaises(ValueError):
+            clt.from_yaml("steps: [")
+
+    def test_find_steps(self):
+        test = clt.read(data_file("search.rec"))
+        self.assertEqual([0], test.find_steps(input="^echo"))
+        self.assertEqual([1], test.find_steps(output="exit"))
+        with self.assertRaises(ValueError):
+            test.find_steps(input="(")
+
+    def test_escape_output(self):
+        output = "––– input –––\nplain"
+        escaped = clt.escape_output(output)
+        self.assertNotEqual(output, escaped)
+        self.assertEqual(output, clt.unescape_output(escaped))
+
+
+class TestWrite(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.mkdtemp()
+        self.path = os.path.join(self.dir, "test.rec")
+        shutil.copy(data_file("search.rec"), self.path)
+
+    def tearDown(self):
+        shutil.rmtree(self.dir)
+
+    def test_write_steps(self):
+        test = clt.read(self.path)
+        test.steps = test.steps + [clt.Step("echo bye", "bye")]
+        test.write(self.path)
+        self.assertEqual(["echo hello", "false", "echo bye"], [step.input for step in clt.read(self.path).steps])
+
+    def test_write_conflicts_with_changes_of_others(self):
+        version = clt.file_version(self.path)
+        test = clt.read(self.path)
+        clt.insert_steps(self.path, 0, [clt.Step("echo first", "first")])
+        with self.assertRaises(ValueError):
+            test.write(self.path, version=version)
+
+    def test_dry_run_writes_nothing(self):
+        with open(self.path, encoding="utf-8") as file:
+            content = file.read()
+        preview = clt.insert_steps(self.path, 1, [clt.Step("echo middle", "middle")], dry_run=True)
+        self.assertIn("+echo middle", preview.diff)
+        preview = clt.remove_steps(self.path, [clt.Step("false", "––– exit: 1 –––")], dry_run=True)
+        self.assertIn("-false", preview.diff)
+        with open(self.path, encoding="utf-8") as file:
+            self.assertEqual(content, file.read())
+
+
+class TestCheck(unittest.TestCase):
+    def test_validate(self):
+        self.assertEqual([], clt.validate(data_file("search.rec")))
+        with self.assertRaises(ValueError):
+            clt.validate(data_file("invalid.rec"))
+
+    def test_compare(self):
+        result = clt.compare(data_file("search.rec"), data_file("search.rep"))
+        self.assertFalse(result.has_diff)
+        self.assertTrue(result)
+
+        result = clt.compare(data_file("search.rec"), data_file("failed.rep"))
+        self.assertTrue(result.has_diff)
+        self.assertIn("- hello\n+ bye", result.diff)
+        with self.assertRaises(OSError):
+            clt.compare(data_file("search.rec"), data_file("missing.rep"))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -14,9 +14,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod screen;
+
+use clt_core::replay::{get_base64_command, get_steps, Step};
+use clt_core::shell::{
+	clean_output, filter_prompt, filter_stdout_buf, get_bash_rcfile, get_error_section, get_pattern_string, get_runtime_env,
+	get_stderr_file, get_terminal_env, is_prompting, take_stderr, EXIT_CODE_REGEX, INTERRUPT_GRACE, INTERRUPT_KEYS, SHELL_CMD, SHELL_PROMPT,
+};
 use regex::Regex;
-use tokio::fs::{OpenOptions, File};
-use tokio::io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader, BufWriter};
+use std::sync::{Arc, Mutex};
+use screen::{ScreenRecorder, SCREEN_COLS, SCREEN_ROWS};
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::oneshot;
 use tokio::time::Instant;
@@ -60,13 +68,28 @@ struct Opt {
 		help = "Delay between commands in ms",
 		default_value = "0"
 	)]
-	delay: u64
-}
+	delay: u64,
+
+	#[structopt(
+		short = "S",
+		long = "screens",
+		help = "Directory to save the terminal screen after each replayed step to"
+	)]
+	screens_dir: Option<std::path::PathBuf>,
 
-const OUTPUT_HEADER: &str = "You can use regex in the output sections.\nMore info here: https://github.com/manticoresoftware/clt#refine\n";
-const SHELL_CMD: &str = "/usr/bin/env";
-const SHELL_PROMPT: &str = "clt> ";
-const INIT_CMD: &[u8] = b"export PS1='clt> ';export LANG='en_US.UTF-8' PATH='/bin:/usr/bin:/usr/local/bin:/sbin:/usr/local/sbin' COLUMNS=10000;enable -n exit enable;exec 2>&1;";
+	#[structopt(
+		long = "svg",
+		help = "Also render the saved terminal screens as SVG images"
+	)]
+	svg: bool,
+
+	#[structopt(
+		short = "T",
+		long = "terminal",
+		help = "Terminal size as COLSxROWS, e.g. 120x40, to run commands in"
+	)]
+	terminal: Option<String>,
+}
 
 #[derive(Debug)]
 enum Event {
@@ -74,29 +97,90 @@ enum Event {
 	Stdout(std::io::Result<Vec<u8>>),
 	Write(std::io::Result<Vec<u8>>),
 	Error(anyhow::Error),
-	Replay(String, oneshot::Sender<()>),
+	Replay(String, bool, bool, Option<std::time::Duration>, oneshot::Sender<Option<i32>>),
+	Resize(u16, u16, oneshot::Sender<()>),
 	Quit,
 }
 
 #[tokio::main]
 async fn async_main(opt: Opt) -> anyhow::Result<()> {
-	let Opt { input_file, output_file, mut prompts, delay } = opt;
+	let Opt { input_file, output_file, mut prompts, delay, screens_dir, svg, terminal } = opt;
+	let terminal = terminal.map(|size| parser::parse_terminal_size(&size)).transpose()?;
 	prompts.push(SHELL_PROMPT.to_string());
 	let mut stdout = tokio::io::stdout();
 
+	// The test is compiled before the shell starts because it may declare the environment of the shell
+	let compiled = match input_file {
+		Some(input_file) => {
+			let input_file = input_file.into_string().unwrap();
+			// Statements written with ASCII hyphens are converted in the test itself to not warn about them again,
+			// and all statements are written in the style of the project, e.g. the classic ones left by refine
+			if std::env::var("CLT_FIX_STATEMENTS").is_ok_and(|v| !v.is_empty() && v != "0") {
+				let content = std::fs::read_to_string(&input_file)?;
+				let (fixed, deprecations) = parser::fix_statements(&content, &input_file)?;
+				let fixed = parser::apply_statement_style(&fixed, parser::get_statement_style());
+				if fixed != content {
+					std::fs::write(&input_file, fixed)?;
+				}
+				for deprecation in deprecations {
+					eprintln!("Fixed {}", deprecation);
+				}
+			}
+			let strict = std::env::var("CLT_STRICT").is_ok_and(|v| !v.is_empty() && v != "0");
+			let compiled = parser::compile_checked(&input_file, strict)?;
+			if std::path::Path::new(parser::POLICY_FILE).exists() {
+				parser::check_policy(&compiled.content, parser::POLICY_FILE)?;
+			}
+			// Config templates are rendered before the shell starts, so the daemons the test starts read them
+			render_templates(&input_file, &compiled.content)?;
+			Some(compiled)
+		},
+		None => None,
+	};
+	// Values of the secrets are written to the .rep file and the screens as their tokens, so they do not leave the machine
+	let secrets = match &compiled {
+		Some(compiled) => parser::get_secrets(&compiled.source)?,
+		None => Vec::new(),
+	};
+	let (input_content, test_hash) = compiled.map(|compiled| (compiled.content, compiled.hash)).unzip();
+	let env = match &input_content {
+		Some(input_content) => parser::parse_env(input_content)?,
+		None => Vec::new(),
+	};
+
 	let mut pty = pty_process::Pty::new()?;
 	let pts = pty.pts()?;
+	if let Some((cols, rows)) = terminal {
+		pty.resize(pty_process::Size::new(rows, cols))?;
+	}
+	// Stderr is recorded apart from the output only for the tests that expect it in error sections
+	let stderr_file = match &input_content {
+		Some(input_content) if input_content.lines().any(|line| line == parser::ERROR_STATEMENT) => Some(get_stderr_file().await?),
+		_ => None,
+	};
+	let rcfile = get_bash_rcfile(&env, stderr_file.as_deref()).await?;
 	let mut process = pty_process::Command::new(SHELL_CMD);
 	process.arg("-i")
 		.arg(format!("PS1={}", SHELL_PROMPT))
+		.args(get_runtime_env())
+		.args(get_terminal_env(terminal))
 		.arg("bash")
 		.arg("--noprofile")
 		.arg("--rcfile")
-		.arg(get_bash_rcfile().await.unwrap())
+		.arg(&rcfile)
 		// .stdout(std::process::Stdio::piped())
 	;
 
-	let is_replay = input_file.is_some();
+	let is_replay = input_content.is_some();
+	let screen = match screens_dir {
+		Some(dir) if is_replay => {
+			let (cols, rows) = terminal.unwrap_or((SCREEN_COLS, SCREEN_ROWS));
+			Some(ScreenRecorder::new(dir, svg, rows, cols, secrets.clone())?)
+		},
+		_ => None,
+	};
+	let screen = Arc::new(Mutex::new(screen));
+	let exit_code: Arc<Mutex<Option<i32>>> = Arc::new(Mutex::new(None));
 	let mut child = process.spawn(&pts)?;
 
 	let mut input = textmode::blocking::Input::new()?;
@@ -104,30 +188,17 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 
 	let (event_w, mut event_r) = tokio::sync::mpsc::unbounded_channel();
 	let (input_w, mut input_r) = tokio::sync::mpsc::unbounded_channel();
+	let (resize_w, mut resize_r) = tokio::sync::mpsc::unbounded_channel::<(u16, u16)>();
 
 	// We use this buffer to gather all inputs we type
 	let mut output_fh = tokio::fs::File::create(output_file.clone()).await?;
 
 	// If we have input file passed, we replay, otherwise – record
 	// Replay the input_file and save results in output_file
-	if let Some(input_file) = input_file {
-		let input_file = input_file.into_string().unwrap();
-		let input_content = parser::compile(&input_file)?;
-
-		// Split compiled file into lines to process it next
-		let lines: Vec<&str> = input_content.split('\n').collect();
-
-		let mut commands = Vec::new();
+	if let Some(input_content) = input_content {
 		// We need to send empty command to block thread till we get forked and get clt> prompt
-		commands.push(String::from(""));
-
-		let mut last_line = "";
-		for line in lines {
-			if line.starts_with(parser::COMMAND_SEPARATOR) {
-				commands.push(last_line.to_string())
-			}
-			last_line = line;
-		}
+		let mut steps = vec![Step::Command { command: String::from(""), condition: None, timeout: None, base64: false }];
+		steps.extend(get_steps(&input_content)?);
 
 		// Trap the signals and exit process in case we receive it for replay only
 		{
@@ -152,11 +223,31 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 		{
 			let event_w = event_w.clone();
 			tokio::spawn(async move {
-				for command in commands {
-					let (tx, rx) = oneshot::channel();
-					event_w.send(Event::Replay(command.trim().to_string(), tx)).unwrap();
-					// Block until the command has finished executing.
-					rx.await.unwrap();
+				for step in steps {
+					match step {
+						Step::Command { command, condition, timeout, base64 } => {
+							// The condition runs as a hidden command, and the step is skipped when it succeeds
+							if let Some(condition) = condition {
+								let (tx, rx) = oneshot::channel();
+								event_w.send(Event::Replay(format!("({}) > /dev/null 2>&1", condition), true, false, None, tx)).unwrap();
+								if rx.await.unwrap() == Some(0) {
+									let skipped = format!("\n{}\n{}\n{}\n{}\n", parser::COMMAND_PREFIX, command.trim(), parser::COMMAND_SEPARATOR, parser::SKIPPED_LINE);
+									event_w.send(Event::Write(Ok(skipped.into_bytes()))).unwrap();
+									continue;
+								}
+							}
+
+							let (tx, rx) = oneshot::channel();
+							event_w.send(Event::Replay(command.trim().to_string(), false, base64, timeout, tx)).unwrap();
+							// Block until the command has finished executing.
+							rx.await.unwrap();
+						},
+						Step::Resize(cols, rows) => {
+							let (tx, rx) = oneshot::channel();
+							event_w.send(Event::Resize(cols, rows, tx)).unwrap();
+							rx.await.unwrap();
+						},
+					}
 
 					// Sleep for delay before process next command
 					if delay > 0 {
@@ -188,6 +279,9 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 
 	{
 		let event_w = event_w.clone();
+		let screen = screen.clone();
+		let exit_code = exit_code.clone();
+		let exit_code_re = regex::bytes::Regex::new(EXIT_CODE_REGEX)?;
 		tokio::task::spawn(async move {
 			loop {
 				let mut buf = [0_u8; 4096];
@@ -196,6 +290,17 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 						match res {
 							Ok(n) => {
 								let bytes = buf[..n].to_vec();
+								// The screen is fed before the output is sent, so it is complete once the prompt is detected
+								if let Some(screen) = screen.lock().unwrap().as_mut() {
+									screen.process(&bytes);
+								}
+								let bytes = match exit_code_re.captures(&bytes) {
+									Some(caps) => {
+										*exit_code.lock().unwrap() = std::str::from_utf8(&caps[1]).ok().and_then(|code| code.parse().ok());
+										exit_code_re.replace_all(&bytes, &b""[..]).to_vec()
+									},
+									None => bytes,
+								};
 								// println!("[{}]", String::from_utf8_lossy(&bytes));
 								// We need this write only for non replay action
 								let filtered = filter_stdout_buf(bytes);
@@ -228,6 +333,14 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 								.unwrap();
 						}
 					}
+					res = resize_r.recv() => {
+						// Resizing the pty sends SIGWINCH to the running program
+						if let Some((cols, rows)) = res {
+							if let Err(e) = pty.resize(pty_process::Size::new(rows, cols)) {
+								event_w.send(Event::Error(anyhow::anyhow!(e))).unwrap();
+							}
+						}
+					}
 					_ = child.wait() => {
 						event_w.send(Event::Quit).unwrap();
 						break;
@@ -242,6 +355,7 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 	let mut is_typing = false;
 	let mut command_output_last_line = String::new();
 	let mut total_duration: u128 = 0;
+	let mut step: usize = 0;
 	loop {
 		let var_name = match event_r.recv().await.unwrap() {
 			Event::Key(key) => {
@@ -336,30 +450,56 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 			Event::Error(e) => {
 				return Err(e);
 			}
-			Event::Replay(command, tx) => {
+			Event::Replay(command, hidden, base64, timeout, tx) => {
 				let start = Instant::now();
+				// The command is recorded as written, while the one typed into the shell encodes its output
+				let sent = if base64 { get_base64_command(&command) } else { command.clone() };
 				let mut command_output: String = String::new();
 				command_output.push_str(&command_output_last_line);
 				let mut result: Vec<u8> = Vec::new();
-				if !command.is_empty() {
+				exit_code.lock().unwrap().take();
+				if !command.is_empty() && !hidden {
 					let mut bytes: Vec<u8>;
-					bytes = command.as_bytes().to_vec();
+					bytes = sent.as_bytes().to_vec();
 					bytes.push(13u8); // Add enter keystroke
 
 					let input_cmd = format!("\n{}\n{}\n{}\n", parser::COMMAND_PREFIX, command, parser::COMMAND_SEPARATOR);
 					result.extend_from_slice(input_cmd.as_bytes());				// Send the command to the pty
 					input_w.send(bytes).unwrap();
+				} else if hidden {
+					let mut bytes = command.as_bytes().to_vec();
+					bytes.push(13u8);
+					input_w.send(bytes).unwrap();
 				}
 
 				// Wait for the shell prompt to appear in the output, indicating that
 				// the command has finished executing. You may need to adjust the
 				// prompt detection logic depending on the shell being used.
+				let mut deadline = timeout.map(|timeout| start + timeout);
+				let mut interrupt_keys = INTERRUPT_KEYS.iter();
+				let mut timed_out = false;
 				loop {
-					if let Event::Stdout(Ok(bytes)) = event_r.recv().await.unwrap() {
+					let event = match deadline {
+						Some(at) => match tokio::time::timeout_at(at, event_r.recv()).await {
+							Ok(event) => event,
+							Err(_) => {
+								// Interrupt the command with ^C as the user would, and quit it with ^\ if it does not exit
+								let Some(key) = interrupt_keys.next() else {
+									anyhow::bail!("the command does not exit after it was interrupted on timeout: {}", command);
+								};
+								timed_out = true;
+								input_w.send(vec![*key]).unwrap();
+								deadline = Some(Instant::now() + INTERRUPT_GRACE);
+								continue;
+							},
+						},
+						None => event_r.recv().await,
+					};
+					if let Event::Stdout(Ok(bytes)) = event.unwrap() {
 						let output = format!("{}", String::from_utf8_lossy(&bytes));
 						command_output.push_str(&output);
 
-						let suffix = regex::escape(&command);
+						let suffix = regex::escape(&sent);
 						let pattern_str = get_pattern_string(suffix, &prompts);
 						let re = Regex::new(&pattern_str).unwrap();
 						let is_done = if re.is_match(&command_output) && is_prompting(&command_output, &prompts) {
@@ -375,13 +515,29 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 								command_output_last_line = String::from(command_output_lines.last().unwrap_or(""));
 							}
 							let mut filtered_output = filter_prompt(command_output.as_str(), &prompts);
-							if filtered_output.trim() == command.as_str() || filtered_output.trim().starts_with(format!("{}{}", command.as_str(), "\n").as_str()) {
-								let start: usize = filtered_output.find(command.as_str()).unwrap_or(0) + command.len();
+							if filtered_output.trim() == sent.as_str() || filtered_output.trim().starts_with(format!("{}{}", sent.as_str(), "\n").as_str()) {
+								let start: usize = filtered_output.find(sent.as_str()).unwrap_or(0) + sent.len();
 								filtered_output = substring(&filtered_output, start, filtered_output.len() - start).to_string();
 							}
 
+							// Stderr of each command is taken even when it is not saved, so it does not go to the next one
+							let stderr = match &stderr_file {
+								Some(stderr_file) => take_stderr(stderr_file).await?,
+								None => String::new(),
+							};
+
+							// Output of hidden commands is not saved, only their exit code is returned
+							if hidden {
+								tx.send(exit_code.lock().unwrap().take()).unwrap();
+								break;
+							}
+
 							if !command.is_empty() {
-								result.extend_from_slice(filtered_output.as_bytes());
+								result.extend_from_slice(parser::escape_output(&filtered_output).as_bytes());
+								if timed_out {
+									result.extend_from_slice(format!("\n{}\n", parser::TIMED_OUT_LINE).as_bytes());
+								}
+								result.extend_from_slice(get_error_section(&stderr).as_bytes());
 								// Add duration line
 								let duration = parser::Duration {
 									duration: start.elapsed().as_millis(),
@@ -390,22 +546,43 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
 								total_duration += duration.duration;
 								let duration_line = parser::get_duration_line(duration);
 								result.extend_from_slice(duration_line.as_bytes());
+								if let Some(code) = exit_code.lock().unwrap().take() {
+									result.extend_from_slice(format!("\n{}", parser::get_exit_line(code)).as_bytes());
+								}
+
+								step += 1;
+								if let Some(screen) = screen.lock().unwrap().as_ref() {
+									screen.save(step)?;
+								}
 							}
 
 							let content = filter_stdout_buf(result);
 							event_w.send(Event::Write(Ok(content))).unwrap();
 
 							// Signal that the command has finished executing.
-							tx.send(()).unwrap();
+							tx.send(None).unwrap();
 							break;
 						}
 					}
 				}
 			}
+			Event::Resize(cols, rows, tx) => {
+				if let Some(screen) = screen.lock().unwrap().as_mut() {
+					screen.resize(rows, cols);
+				}
+				resize_w.send((cols, rows)).unwrap();
+				tx.send(()).unwrap();
+			}
 			Event::Quit => {
 				// Do a file clean up to remove spaces and make consistent output
+				// The terminal size is saved to the front matter of the recorded test to replay it with the same size
 				let file_path = output_file.clone().into_string().unwrap();
-				cleanup_file(file_path, total_duration).await.unwrap();
+				let terminal = if is_replay { None } else { terminal };
+				cleanup_file(file_path, total_duration, terminal, test_hash.as_deref(), &secrets).await.unwrap();
+				let _ = tokio::fs::remove_file(&rcfile).await;
+				if let Some(stderr_file) = &stderr_file {
+					let _ = tokio::fs::remove_file(stderr_file).await;
+				}
 
 				println!("");
 				break
@@ -429,133 +606,42 @@ fn main(opt: Opt) {
 	};
 }
 
-fn filter_stdout_buf(buf: Vec<u8>) -> Vec<u8> {
-	// Create new bytes vector and filter from buf zero bytes
-	// and also replace \n to \r int it due to we need return caret in terminal
-	let mut prev_byte = &0;
-	let mut bytes: Vec<u8> = Vec::new();
-	for byte in buf.iter() {
-		if *byte == b'\0' || *byte == 7u8 {
-			continue;
-		}
-
-		if *prev_byte != b'\r' && *byte == b'\n' {
-			bytes.push(b'\r');
-		}
-
-		bytes.push(*byte);
-		prev_byte = byte;
+/// Render the config templates declared in the front matter of the test to their paths in the container
+fn render_templates(rec_file_path: &str, content: &str) -> anyhow::Result<()> {
+	let templates = parser::parse_metadata(content)?.templates;
+	if templates.is_empty() {
+		return Ok(());
 	}
-	let bytes = clean_escape_sequences(bytes);
-	bytes
-}
-
-fn filter_prompt(prompt: &str, prompts: &[String]) -> String {
-	let pattern_str = get_pattern_string(String::from(".*"), prompts);
-	let re = regex::Regex::new(&pattern_str).unwrap();
-	re.replace_all(prompt, "").to_string()
-}
 
-fn clean_escape_sequences(input: Vec<u8>) -> Vec<u8> {
-	let mut result = Vec::with_capacity(input.len());
-	let mut inside_escape = false;
-	let mut bytes = input.into_iter().peekable();
-
-	while let Some(byte) = bytes.next() {
-		if byte == 0x1B && bytes.peek() == Some(&b'[') {
-			inside_escape = true;
-			bytes.next(); // Skip the '[' byte
-		} else if inside_escape {
-			if byte.is_ascii_alphabetic() {
-				inside_escape = false;
-			}
-		} else {
-			result.push(byte);
+	let vars = parser::get_template_variables(rec_file_path, content)?;
+	let input_dir = std::path::Path::new(rec_file_path).parent().unwrap_or_else(|| std::path::Path::new(""));
+	for (template, destination) in templates {
+		let template_path = input_dir.join(template);
+		let rendered = std::fs::read_to_string(&template_path)
+			.map_err(anyhow::Error::from)
+			.and_then(|template| parser::render_template(&template, &vars))
+			.map_err(|e| anyhow::anyhow!("failed to render {}: {}", template_path.display(), e))?;
+		if let Some(dir) = std::path::Path::new(&destination).parent() {
+			std::fs::create_dir_all(dir)?;
 		}
+		std::fs::write(&destination, rendered).map_err(|e| anyhow::anyhow!("failed to write {}: {}", destination, e))?;
 	}
 
-	result
-}
-
-fn is_prompting(output: &str, prompts: &[String]) -> bool {
-	let pattern_str: String = get_pattern_string(String::from(""), prompts);
-	let re = regex::Regex::new(&pattern_str).unwrap();
-	let last_line = output.lines().last().unwrap_or("");
-	re.is_match(last_line)
-}
-
-fn get_pattern_string(suffix: String, prompts: &[String]) -> String {
-	prompts.iter()
-		.map(|prompt| format!(r"(?m)^{}{}\r?$", regex::escape(prompt), suffix))
-		.collect::<Vec<_>>()
-		.join("|")
+	Ok(())
 }
 
 /// This function cleans up all empty lines and removes the last line containing "exit" to make the consistent output
-async fn cleanup_file(file_path: String, total_duration: u128) -> Result<(), Box<dyn std::error::Error>> {
-	let file = File::open(&file_path).await?;
+/// The values of the secrets in the inputs and outputs are replaced with their tokens
+async fn cleanup_file(file_path: String, total_duration: u128, terminal: Option<(u16, u16)>, test_hash: Option<&str>, secrets: &[(String, String)]) -> anyhow::Result<()> {
+	let content = tokio::fs::read_to_string(&file_path).await?;
 	let temp_output_file: String = format!("{}.tmp", &file_path);
-	let temp_file = OpenOptions::new()
-		.write(true)
-		.create(true)
-		.open(&temp_output_file)
-		.await?;
-	let reader = BufReader::new(file);
-	let mut writer = BufWriter::new(temp_file);
-
-	let mut lines = reader.lines();
-
-	let mut non_empty_lines = Vec::new();
-	non_empty_lines.push(String::from(OUTPUT_HEADER));
-	non_empty_lines.push(format!("Time taken for test: {}ms\n", total_duration));
-	while let Some(line) = lines.next_line().await? {
-		if !line.trim().is_empty() {
-			if parser::is_duration_line(&line) {
-				let mut duration = parser::parse_duration_line(&line)?;
-				duration.percentage = (duration.duration as f32 / total_duration as f32) * 100.0;
-				non_empty_lines.push(format!("{}\n", parser::get_duration_line(duration)));
-			} else {
-				non_empty_lines.push(format!("{}\n", line.trim()));
-			}
-		}
-	}
-
-	if let Some(last_line) = non_empty_lines.last() {
-		if last_line.trim().to_lowercase().contains("exit") {
-			non_empty_lines.pop();
-		}
-	}
-
-	for line in non_empty_lines {
-		writer.write_all(line.as_bytes()).await?;
-	}
-
-	writer.flush().await?;
-
-	tokio::fs::remove_file(&file_path).await?;
+	let content = parser::hide_secrets(&clean_output(&content, total_duration, terminal, test_hash)?, secrets)?;
+	tokio::fs::write(&temp_output_file, content).await?;
 	tokio::fs::rename(temp_output_file, file_path).await?;
 
 	Ok(())
 }
 
-async fn get_bash_rcfile() -> Result<String, Box<dyn std::error::Error>> {
-	let file_name = ".rec-bashrc";
-	let temp_dir = std::env::temp_dir();
-	let file_path = temp_dir.join(file_name);
-
-	let file = OpenOptions::new()
-		.write(true)
-		.create(true)
-		.truncate(true)
-		.open(&file_path)
-		.await?;
-	let mut writer = BufWriter::new(file);
-	writer.write_all(INIT_CMD).await?;
-	writer.flush().await?;
-
-	Ok(file_path.to_string_lossy().to_string())
-}
-
 fn substring(s: &str, start: usize, len: usize) -> &str {
 	let end = start + len;
 
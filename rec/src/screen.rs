@@ -0,0 +1,177 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use vt100::Color;
+
+/// Size of the emulated terminal when the test does not set its own
+pub const SCREEN_ROWS: u16 = 24;
+pub const SCREEN_COLS: u16 = 80;
+
+const CHAR_WIDTH: f32 = 8.4;
+const LINE_HEIGHT: f32 = 17.0;
+const FONT_SIZE: u32 = 14;
+const DEFAULT_FG: &str = "#d4d4d4";
+const DEFAULT_BG: &str = "#1e1e1e";
+const PALETTE: [&str; 16] = [
+	"#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5",
+	"#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff",
+];
+
+/// Emulates the terminal the replayed commands write to and saves its screen after each step,
+/// so failures of curses-based tools can be seen the way the user would see them
+pub struct ScreenRecorder {
+	parser: vt100::Parser,
+	dir: PathBuf,
+	svg: bool,
+	/// Values of the secrets of the test paired with their tokens to keep them out of the saved screens
+	secrets: Vec<(String, String)>,
+}
+
+impl ScreenRecorder {
+	pub fn new(dir: PathBuf, svg: bool, rows: u16, cols: u16, secrets: Vec<(String, String)>) -> std::io::Result<Self> {
+		std::fs::create_dir_all(&dir)?;
+		Ok(Self {
+			parser: vt100::Parser::new(rows, cols, 0),
+			dir,
+			svg,
+			secrets,
+		})
+	}
+
+	/// Feed the raw output of the terminal including escape sequences
+	pub fn process(&mut self, bytes: &[u8]) {
+		self.parser.process(bytes);
+	}
+
+	pub fn resize(&mut self, rows: u16, cols: u16) {
+		self.parser.set_size(rows, cols);
+	}
+
+	/// Save the current screen as step-N.txt and step-N.svg when SVG is enabled,
+	/// secrets are written as their tokens in the text and masked with * in the image
+	pub fn save(&self, step: usize) -> std::io::Result<()> {
+		let screen = self.parser.screen();
+		let contents = parser::hide_secrets(&screen.contents(), &self.secrets).map_err(std::io::Error::other)?;
+		std::fs::write(self.dir.join(format!("step-{}.txt", step)), format!("{}\n", contents))?;
+		if self.svg {
+			std::fs::write(self.dir.join(format!("step-{}.svg", step)), render_svg(screen, &self.secrets))?;
+		}
+		Ok(())
+	}
+}
+
+/// Columns of the row covered by the values of the secrets
+fn get_secret_columns(screen: &vt100::Screen, row: u16, secrets: &[(String, String)]) -> Vec<bool> {
+	let (_, cols) = screen.size();
+	let mut masked = vec![false; cols as usize];
+	if secrets.is_empty() {
+		return masked;
+	}
+
+	// Text of the row with the column each byte of it comes from
+	let mut text = String::new();
+	let mut columns = Vec::new();
+	for col in 0..cols {
+		let Some(cell) = screen.cell(row, col) else {
+			continue;
+		};
+		let contents = if cell.has_contents() { cell.contents() } else { " ".to_string() };
+		columns.extend(std::iter::repeat_n(col as usize, contents.len()));
+		text.push_str(&contents);
+	}
+
+	for (value, _) in secrets {
+		for (start, _) in text.match_indices(value.as_str()) {
+			for &col in &columns[start..start + value.len()] {
+				masked[col] = true;
+			}
+		}
+	}
+	masked
+}
+
+/// Render the screen as SVG with colors, bold text and inverse video, the values of the secrets are masked
+fn render_svg(screen: &vt100::Screen, secrets: &[(String, String)]) -> String {
+	let (rows, cols) = screen.size();
+	let (width, height) = (cols as f32 * CHAR_WIDTH, rows as f32 * LINE_HEIGHT);
+	let mut svg = format!(
+		"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"{}\">\n\
+		<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+		width, height, FONT_SIZE, DEFAULT_BG
+	);
+
+	for row in 0..rows {
+		let y = row as f32 * LINE_HEIGHT;
+		let mut text = String::new();
+		let masked = get_secret_columns(screen, row, secrets);
+		for col in 0..cols {
+			let Some(cell) = screen.cell(row, col) else {
+				continue;
+			};
+			if cell.is_wide_continuation() {
+				continue;
+			}
+
+			let (mut fg, mut bg) = (get_color(cell.fgcolor(), DEFAULT_FG), get_color(cell.bgcolor(), DEFAULT_BG));
+			if cell.inverse() {
+				std::mem::swap(&mut fg, &mut bg);
+			}
+			let x = col as f32 * CHAR_WIDTH;
+			if bg != DEFAULT_BG {
+				let cells = if cell.is_wide() { 2.0 } else { 1.0 };
+				let _ = writeln!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>", x, y, CHAR_WIDTH * cells, LINE_HEIGHT, bg);
+			}
+
+			let contents = if masked[col as usize] { "*".to_string() } else { cell.contents() };
+			if contents.trim().is_empty() {
+				continue;
+			}
+			let weight = if cell.bold() { " font-weight=\"bold\"" } else { "" };
+			let _ = write!(text, "<tspan x=\"{}\" fill=\"{}\"{}>{}</tspan>", x, fg, weight, escape_xml(&contents));
+		}
+
+		if !text.is_empty() {
+			let _ = writeln!(svg, "<text y=\"{}\" xml:space=\"preserve\">{}</text>", y + LINE_HEIGHT * 0.8, text);
+		}
+	}
+
+	svg.push_str("</svg>\n");
+	svg
+}
+
+/// Convert the color of the cell into the hex form of the xterm 256 color palette
+fn get_color(color: Color, default: &str) -> String {
+	match color {
+		Color::Default => default.to_string(),
+		Color::Idx(idx) if idx < 16 => PALETTE[idx as usize].to_string(),
+		Color::Idx(idx) if idx < 232 => {
+			let level = |value: u8| if value == 0 { 0 } else { value * 40 + 55 };
+			let idx = idx - 16;
+			format!("#{:02x}{:02x}{:02x}", level(idx / 36), level(idx / 6 % 6), level(idx % 6))
+		},
+		Color::Idx(idx) => {
+			let gray = (idx - 232) * 10 + 8;
+			format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+		},
+		Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+	}
+}
+
+fn escape_xml(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,137 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// Role of the client, each one can do everything the previous ones can
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+	/// Read jobs and their results, validate and lint tests
+	#[default]
+	Read,
+	/// Submit jobs
+	Run,
+	/// Cancel queued jobs
+	Admin,
+}
+
+impl std::str::FromStr for Role {
+	type Err = anyhow::Error;
+
+	fn from_str(role: &str) -> Result<Self> {
+		match role {
+			"read" => Ok(Role::Read),
+			"run" => Ok(Role::Run),
+			"admin" => Ok(Role::Admin),
+			_ => anyhow::bail!("unknown role \"{}\", use read, run or admin", role),
+		}
+	}
+}
+
+/// Client that sent the request, identified by the hash of its token,
+/// so the token itself is never stored with the jobs
+#[derive(Debug, Clone, Default)]
+pub struct Client {
+	pub id: String,
+	pub role: Role,
+	/// Maximum number of jobs of the client running at the same time
+	pub max_jobs: Option<usize>,
+}
+
+/// Tokens of the clients with their roles loaded from the file with "role token [max-jobs]" lines
+/// Without the file all clients may do everything
+#[derive(Default)]
+pub struct Tokens(Option<HashMap<String, Client>>);
+
+impl Tokens {
+	pub fn load(file_name: Option<&str>) -> Result<Self> {
+		let Some(file_name) = file_name else {
+			return Ok(Self::default());
+		};
+
+		let content = std::fs::read_to_string(file_name).with_context(|| format!("Failed to read tokens from {}", file_name))?;
+		let mut tokens = HashMap::new();
+		for (i, line) in content.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let (role, token, max_jobs) = match fields[..] {
+				[role, token] => (role, token, None),
+				[role, token, max_jobs] => (role, token, Some(max_jobs)),
+				_ => anyhow::bail!("{}:{}: expected \"role token [max-jobs]\"", file_name, i + 1),
+			};
+			let client = Client {
+				id: get_client_id(token),
+				role: role.parse().with_context(|| format!("{}:{}", file_name, i + 1))?,
+				max_jobs: max_jobs.map(str::parse).transpose()
+					.with_context(|| format!("{}:{}: invalid maximum number of jobs", file_name, i + 1))?,
+			};
+			tokens.insert(token.to_string(), client);
+		}
+		Ok(Self(Some(tokens)))
+	}
+
+	/// Maximum numbers of running jobs of the clients that have them
+	pub fn get_limits(&self) -> HashMap<String, usize> {
+		self.0.iter().flatten()
+			.filter_map(|(_, client)| Some((client.id.clone(), client.max_jobs?)))
+			.collect()
+	}
+
+	/// Find the client with the token and check that it may act in the role
+	pub fn authorize(&self, token: Option<&str>, role: Role) -> Result<Client, (StatusCode, &'static str)> {
+		let Some(tokens) = &self.0 else {
+			return Ok(Client { role: Role::Admin, ..Client::default() });
+		};
+
+		match token.and_then(|token| tokens.get(token)) {
+			Some(client) if client.role >= role => Ok(client.clone()),
+			Some(_) => Err((StatusCode::FORBIDDEN, "the token does not allow this action")),
+			None => Err((StatusCode::UNAUTHORIZED, "pass a valid token in the Authorization: Bearer header")),
+		}
+	}
+}
+
+/// Middleware that lets the request through when its token has the role
+/// and passes the client to the handlers as the extension of the request
+pub async fn authorize(State((tokens, role)): State<(Arc<Tokens>, Role)>, mut request: Request, next: Next) -> Response {
+	let token = request.headers().get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+	match tokens.authorize(token, role) {
+		Ok(client) => {
+			request.extensions_mut().insert(client);
+			next.run(request).await
+		},
+		Err((status, error)) => (status, Json(json!({ "error": error }))).into_response(),
+	}
+}
+
+fn get_client_id(token: &str) -> String {
+	format!("{:x}", Sha256::digest(token.as_bytes()))[..12].to_string()
+}
@@ -0,0 +1,365 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use tokio::sync::Notify;
+use crate::scheduler::Scheduler;
+
+const SCHEMA: &str = "
+	CREATE TABLE IF NOT EXISTS jobs (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		key TEXT NOT NULL,
+		test TEXT NOT NULL,
+		image TEXT NOT NULL,
+		tags TEXT,
+		status TEXT NOT NULL,
+		exit_code INTEGER,
+		output TEXT NOT NULL DEFAULT '',
+		created INTEGER NOT NULL,
+		started INTEGER,
+		finished INTEGER,
+		client TEXT NOT NULL DEFAULT ''
+	);
+	CREATE INDEX IF NOT EXISTS jobs_key ON jobs (key, status);
+";
+const JOB_COLUMNS: &str = "id, key, test, image, tags, status, exit_code, output, created, started, finished, client";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+	Queued,
+	Running,
+	Passed,
+	Failed,
+	/// The job was running when the service stopped
+	Interrupted,
+	Cancelled,
+}
+
+impl JobStatus {
+	fn as_str(&self) -> &'static str {
+		match self {
+			JobStatus::Queued => "queued",
+			JobStatus::Running => "running",
+			JobStatus::Passed => "passed",
+			JobStatus::Failed => "failed",
+			JobStatus::Interrupted => "interrupted",
+			JobStatus::Cancelled => "cancelled",
+		}
+	}
+
+	fn parse(status: &str) -> Self {
+		match status {
+			"queued" => JobStatus::Queued,
+			"running" => JobStatus::Running,
+			"passed" => JobStatus::Passed,
+			"failed" => JobStatus::Failed,
+			"cancelled" => JobStatus::Cancelled,
+			_ => JobStatus::Interrupted,
+		}
+	}
+}
+
+/// Test to run with ./clt test submitted by the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRequest {
+	/// Path to the .rec file or the directory of tests relative to the directory the service runs in
+	pub test: String,
+	/// Docker image to run the test in or build:path/to/Dockerfile
+	pub image: String,
+	/// Filter of tags like smoke,!slow when the test is the directory
+	#[serde(default)]
+	pub tags: Option<String>,
+}
+
+/// Tests are taken from the directory of the service only, so clients cannot reach other files of the host
+pub fn check_test_path(test: &str) -> Result<()> {
+	let path = Path::new(test);
+	if test.is_empty() || path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+		anyhow::bail!("the test should be a relative path inside the directory of the service, got \"{}\"", test);
+	}
+	if !path.exists() {
+		anyhow::bail!("the test does not exist: {}", test);
+	}
+	Ok(())
+}
+
+impl JobRequest {
+	pub fn validate(&self) -> Result<()> {
+		check_test_path(&self.test)?;
+		if self.image.is_empty() || self.image.starts_with('-') {
+			anyhow::bail!("invalid image \"{}\"", self.image);
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+	pub id: u64,
+	/// Hash of the test files, the image and the tags to find the same job submitted again
+	pub key: String,
+	/// Id of the token the job was submitted with, empty when the service has no tokens
+	pub client: String,
+	#[serde(flatten)]
+	pub request: JobRequest,
+	pub status: JobStatus,
+	pub exit_code: Option<i32>,
+	/// Output of ./clt test with stderr following stdout
+	pub output: String,
+	/// Unix timestamps of the job stages in seconds
+	pub created: u64,
+	pub started: Option<u64>,
+	pub finished: Option<u64>,
+}
+
+/// Jobs submitted to the service, the queued ones are run by a fixed number of workers,
+/// clients take turns and each of them runs no more jobs at the same time than its limit
+/// Jobs are stored in SQLite, so the queued ones are resumed after the restart of the service
+/// and the ones that were running are reported as interrupted
+#[derive(Clone)]
+pub struct Queue {
+	db: Arc<Mutex<Connection>>,
+	scheduler: Arc<Mutex<Scheduler>>,
+	notify: Arc<Notify>,
+}
+
+impl Queue {
+	/// Open the database and start the workers that run the jobs with the clt script at the path,
+	/// the limits are the maximum numbers of running jobs of the clients
+	pub fn start(clt_path: String, workers: usize, db_path: &str, limits: HashMap<String, usize>) -> Result<Self> {
+		let db = Connection::open(db_path).with_context(|| format!("Failed to open the database {}", db_path))?;
+		db.execute_batch(SCHEMA)?;
+		// Databases created before jobs were assigned to clients lack the column
+		let has_client: bool = db.query_row("SELECT COUNT(*) > 0 FROM pragma_table_info('jobs') WHERE name = 'client'", [], |row| row.get(0))?;
+		if !has_client {
+			db.execute_batch("ALTER TABLE jobs ADD COLUMN client TEXT NOT NULL DEFAULT ''")?;
+		}
+		db.execute(
+			"UPDATE jobs SET status = ?1, finished = ?2 WHERE status = ?3",
+			params![JobStatus::Interrupted.as_str(), get_timestamp(), JobStatus::Running.as_str()],
+		)?;
+		let mut scheduler = Scheduler::new(limits);
+		let queued: Vec<(String, u64)> = db.prepare("SELECT client, id FROM jobs WHERE status = ?1 ORDER BY id")?
+			.query_map([JobStatus::Queued.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?
+			.collect::<rusqlite::Result<_>>()?;
+		for (client, id) in queued {
+			scheduler.push(&client, id);
+		}
+
+		let queue = Self { db: Arc::new(Mutex::new(db)), scheduler: Arc::new(Mutex::new(scheduler)), notify: Arc::default() };
+		for _ in 0..workers.max(1) {
+			let (queue, clt_path) = (queue.clone(), clt_path.clone());
+			tokio::spawn(async move {
+				loop {
+					// Subscribe before checking the queue to not miss the job submitted in between
+					let notified = queue.notify.notified();
+					tokio::pin!(notified);
+					notified.as_mut().enable();
+					let next = queue.scheduler.lock().unwrap().pop();
+					let Some((client, id)) = next else {
+						notified.await;
+						continue;
+					};
+
+					if let Err(e) = queue.run(id, &clt_path).await {
+						eprintln!("Failed to run job {}: {:#}", id, e);
+					}
+					queue.scheduler.lock().unwrap().finish(&client);
+					// The client may have jobs waiting for its limit
+					queue.notify.notify_waiters();
+				}
+			});
+		}
+		Ok(queue)
+	}
+
+	/// Queue the job unless the same one is already queued or running,
+	/// returns the job and whether it is new to retry the submission safely
+	pub fn submit(&self, request: JobRequest, client: &str) -> Result<(Job, bool)> {
+		request.validate()?;
+		let key = get_job_key(&request)?;
+		let db = self.db.lock().unwrap();
+		let existing = db.query_row(
+			&format!("SELECT {} FROM jobs WHERE key = ?1 AND status IN (?2, ?3) ORDER BY id LIMIT 1", JOB_COLUMNS),
+			params![key, JobStatus::Queued.as_str(), JobStatus::Running.as_str()],
+			read_job,
+		).optional()?;
+		if let Some(job) = existing {
+			return Ok((job, false));
+		}
+
+		db.execute(
+			"INSERT INTO jobs (key, test, image, tags, status, created, client) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+			params![key, request.test, request.image, request.tags, JobStatus::Queued.as_str(), get_timestamp(), client],
+		)?;
+		let id = db.last_insert_rowid() as u64;
+		let job = get_job(&db, id)?.context("the job is not saved")?;
+		self.scheduler.lock().unwrap().push(client, id);
+		self.notify.notify_waiters();
+		Ok((job, true))
+	}
+
+	pub fn get(&self, id: u64) -> Result<Option<Job>> {
+		get_job(&self.db.lock().unwrap(), id)
+	}
+
+	pub fn list(&self) -> Result<Vec<Job>> {
+		let db = self.db.lock().unwrap();
+		let jobs = db.prepare(&format!("SELECT {} FROM jobs ORDER BY id", JOB_COLUMNS))?
+			.query_map([], read_job)?
+			.collect::<rusqlite::Result<_>>()?;
+		Ok(jobs)
+	}
+
+	/// Cancel the queued job, returns None when the job does not exist or has already started
+	pub fn cancel(&self, id: u64) -> Result<Option<Job>> {
+		let db = self.db.lock().unwrap();
+		let updated = db.execute(
+			"UPDATE jobs SET status = ?1, finished = ?2 WHERE id = ?3 AND status = ?4",
+			params![JobStatus::Cancelled.as_str(), get_timestamp(), id, JobStatus::Queued.as_str()],
+		)?;
+		if updated == 0 {
+			return Ok(None);
+		}
+		let job = get_job(&db, id)?;
+		if let Some(job) = &job {
+			self.scheduler.lock().unwrap().remove(&job.client, id);
+		}
+		Ok(job)
+	}
+
+	/// Delete the jobs that finished more than the number of seconds ago with their output,
+	/// returns the number of jobs deleted
+	pub fn remove_finished(&self, max_age: u64) -> Result<usize> {
+		let removed = self.db.lock().unwrap().execute(
+			"DELETE FROM jobs WHERE status NOT IN (?1, ?2) AND finished < ?3",
+			params![JobStatus::Queued.as_str(), JobStatus::Running.as_str(), get_timestamp().saturating_sub(max_age)],
+		)?;
+		Ok(removed)
+	}
+
+	async fn run(&self, id: u64, clt_path: &str) -> Result<()> {
+		let Some(request) = self.start_job(id)? else {
+			return Ok(());
+		};
+
+		let mut command = Command::new(clt_path);
+		command.args(["test", "-t", &request.test]);
+		if let Some(tags) = &request.tags {
+			command.arg(format!("--tags={}", tags));
+		}
+		command.arg(&request.image).stdin(Stdio::null());
+
+		let (exit_code, output) = match command.output().await {
+			Ok(output) => {
+				let text = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+				(output.status.code(), text)
+			},
+			Err(e) => (None, format!("Failed to run {}: {}", clt_path, e)),
+		};
+		let status = if exit_code == Some(0) { JobStatus::Passed } else { JobStatus::Failed };
+		self.db.lock().unwrap().execute(
+			"UPDATE jobs SET status = ?1, exit_code = ?2, output = ?3, finished = ?4 WHERE id = ?5",
+			params![status.as_str(), exit_code, output, get_timestamp(), id],
+		)?;
+		Ok(())
+	}
+
+	/// Mark the queued job as running and return what to run
+	fn start_job(&self, id: u64) -> Result<Option<JobRequest>> {
+		let db = self.db.lock().unwrap();
+		let updated = db.execute(
+			"UPDATE jobs SET status = ?1, started = ?2 WHERE id = ?3 AND status = ?4",
+			params![JobStatus::Running.as_str(), get_timestamp(), id, JobStatus::Queued.as_str()],
+		)?;
+		if updated == 0 {
+			return Ok(None);
+		}
+		Ok(get_job(&db, id)?.map(|job| job.request))
+	}
+}
+
+fn get_job(db: &Connection, id: u64) -> Result<Option<Job>> {
+	let job = db.query_row(&format!("SELECT {} FROM jobs WHERE id = ?1", JOB_COLUMNS), [id], read_job).optional()?;
+	Ok(job)
+}
+
+fn read_job(row: &Row) -> rusqlite::Result<Job> {
+	Ok(Job {
+		id: row.get(0)?,
+		key: row.get(1)?,
+		client: row.get(11)?,
+		request: JobRequest { test: row.get(2)?, image: row.get(3)?, tags: row.get(4)? },
+		status: JobStatus::parse(&row.get::<_, String>(5)?),
+		exit_code: row.get(6)?,
+		output: row.get(7)?,
+		created: row.get(8)?,
+		started: row.get(9)?,
+		finished: row.get(10)?,
+	})
+}
+
+/// Hash the content of the test files and the files they depend on with the image and the tags,
+/// so the same job is recognized until any of the files changes
+pub fn get_job_key(request: &JobRequest) -> Result<String> {
+	let mut hasher = Sha256::new();
+	let mut files = Vec::new();
+	collect_files(Path::new(&request.test), &mut files)?;
+	// Dependencies of the test that cannot be read are skipped, it is still run to report the error
+	let depends: Vec<_> = files.iter()
+		.filter(|file| file.extension().is_some_and(|ext| ext == "rec"))
+		.flat_map(|test| parser::get_dependencies(&test.to_string_lossy()).unwrap_or_default())
+		.collect();
+	files.extend(depends);
+	files.sort();
+	files.dedup();
+	// Declared dependencies that do not exist count by their path, so the key changes when they appear
+	for file in files {
+		hasher.update(file.to_string_lossy().as_bytes());
+		hasher.update([0]);
+		hasher.update(std::fs::read(&file).unwrap_or_default());
+		hasher.update([0]);
+	}
+	hasher.update(request.image.as_bytes());
+	hasher.update([0]);
+	hasher.update(request.tags.as_deref().unwrap_or_default().as_bytes());
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn collect_files(path: &Path, files: &mut Vec<std::path::PathBuf>) -> Result<()> {
+	if !path.is_dir() {
+		files.push(path.to_path_buf());
+		return Ok(());
+	}
+	for entry in std::fs::read_dir(path)? {
+		collect_files(&entry?.path(), files)?;
+	}
+	Ok(())
+}
+
+fn get_timestamp() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,209 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::middleware::from_fn_with_state;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+use serve::auth::{authorize, Client, Role, Tokens};
+use serve::jobs::{check_test_path, JobRequest, Queue};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+	name = "serve",
+	about = "Accept jobs to run CLT tests and check them over HTTP"
+)]
+struct Opt {
+	#[structopt(
+		short = "l",
+		long = "listen",
+		default_value = "127.0.0.1:8080",
+		help = "Address to listen on"
+	)]
+	listen: String,
+
+	#[structopt(
+		short = "w",
+		long = "workers",
+		default_value = "1",
+		help = "Number of jobs to run at the same time"
+	)]
+	workers: usize,
+
+	#[structopt(
+		long = "db",
+		default_value = ".clt-jobs.db",
+		help = "SQLite database to keep the jobs in across restarts"
+	)]
+	db: String,
+
+	#[structopt(
+		long = "tokens",
+		help = "File with \"role token [max-jobs]\" lines to require tokens with the read, run or admin role from clients"
+	)]
+	tokens: Option<String>,
+
+	#[structopt(
+		long = "keep-days",
+		help = "Delete finished jobs with their output after the number of days (default: keep all)"
+	)]
+	keep_days: Option<u64>,
+}
+
+/// How often finished jobs are checked against the retention
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+type Response = (StatusCode, Json<Value>);
+
+#[derive(Deserialize)]
+struct CheckRequest {
+	test: String,
+	#[serde(default)]
+	strict: bool,
+}
+
+/// Result of /validate with all fields set, the ones that do not apply are null or empty,
+/// so clients diffing the responses see the same shape whether the test is valid or not
+#[derive(Debug, Default, Serialize)]
+struct ValidationResult {
+	valid: bool,
+	error: Option<String>,
+	/// Position of the error in the test when it is known, both counted from 1
+	line: Option<usize>,
+	column: Option<usize>,
+	deprecations: Vec<String>,
+}
+
+fn get_error(status: StatusCode, e: impl std::fmt::Display) -> Response {
+	(status, Json(json!({ "error": e.to_string() })))
+}
+
+async fn submit_job(State(queue): State<Queue>, Extension(client): Extension<Client>, Json(request): Json<JobRequest>) -> Response {
+	// The same job submitted again while it is queued or running is returned as is
+	match queue.submit(request, &client.id) {
+		Ok((job, true)) => (StatusCode::ACCEPTED, Json(json!(job))),
+		Ok((job, false)) => (StatusCode::OK, Json(json!(job))),
+		Err(e) => get_error(StatusCode::BAD_REQUEST, e),
+	}
+}
+
+async fn get_job(State(queue): State<Queue>, Path(id): Path<u64>) -> Response {
+	match queue.get(id) {
+		Ok(Some(job)) => (StatusCode::OK, Json(json!(job))),
+		Ok(None) => get_error(StatusCode::NOT_FOUND, format!("job {} does not exist", id)),
+		Err(e) => get_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+	}
+}
+
+/// Cancel the job that has not started yet
+async fn cancel_job(State(queue): State<Queue>, Path(id): Path<u64>) -> Response {
+	match queue.cancel(id) {
+		Ok(Some(job)) => (StatusCode::OK, Json(json!(job))),
+		Ok(None) => get_error(StatusCode::CONFLICT, format!("job {} does not exist or is not queued", id)),
+		Err(e) => get_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+	}
+}
+
+async fn list_jobs(State(queue): State<Queue>) -> Response {
+	match queue.list() {
+		Ok(jobs) => (StatusCode::OK, Json(json!(jobs))),
+		Err(e) => get_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+	}
+}
+
+/// Compile the test and return the deprecations found or the reason it is invalid
+async fn validate_test(Json(request): Json<CheckRequest>) -> Response {
+	if let Err(e) = check_test_path(&request.test) {
+		return get_error(StatusCode::BAD_REQUEST, e);
+	}
+
+	let result = match parser::compile_keeping_secrets(&request.test, request.strict) {
+		Ok(compiled) => ValidationResult {
+			valid: true,
+			deprecations: compiled.deprecations.iter().map(ToString::to_string).collect(),
+			..Default::default()
+		},
+		Err(e) => {
+			let parse_error = e.downcast_ref::<parser::ParseError>();
+			ValidationResult {
+				valid: false,
+				error: Some(e.to_string()),
+				line: parse_error.map(|parse_error| parse_error.line),
+				column: parse_error.map(|parse_error| parse_error.column),
+				deprecations: Vec::new(),
+			}
+		},
+	};
+	(StatusCode::OK, Json(json!(result)))
+}
+
+async fn lint_test(Json(request): Json<CheckRequest>) -> Response {
+	if let Err(e) = check_test_path(&request.test) {
+		return get_error(StatusCode::BAD_REQUEST, e);
+	}
+
+	(StatusCode::OK, Json(json!({ "issues": parser::lint_test(&request.test) })))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+	let opt = Opt::from_args();
+	// The clt script exports the directory of the project when it starts the service
+	let clt_path = std::env::var("PROJECT_DIR").map_or("./clt".to_string(), |dir| format!("{}/clt", dir));
+
+	let tokens = Arc::new(Tokens::load(opt.tokens.as_deref())?);
+	let queue = Queue::start(clt_path, opt.workers, &opt.db, tokens.get_limits())?;
+	if let Some(days) = opt.keep_days {
+		let queue = queue.clone();
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+			loop {
+				interval.tick().await;
+				match queue.remove_finished(days * 86400) {
+					Ok(0) => {},
+					Ok(removed) => println!("Removed {} jobs finished more than {} days ago", removed, days),
+					Err(e) => eprintln!("Failed to remove old jobs: {:#}", e),
+				}
+			}
+		});
+	}
+
+	let read = Router::new()
+		.route("/jobs", get(list_jobs))
+		.route("/jobs/{id}", get(get_job))
+		.route("/validate", post(validate_test))
+		.route("/lint", post(lint_test))
+		.route_layer(from_fn_with_state((tokens.clone(), Role::Read), authorize));
+	let run = Router::new()
+		.route("/jobs", post(submit_job))
+		.route_layer(from_fn_with_state((tokens.clone(), Role::Run), authorize));
+	let admin = Router::new()
+		.route("/jobs/{id}", delete(cancel_job))
+		.route_layer(from_fn_with_state((tokens, Role::Admin), authorize));
+	let app = read.merge(run).merge(admin).with_state(queue);
+
+	let listener = tokio::net::TcpListener::bind(&opt.listen).await?;
+	println!("Listening on http://{}", listener.local_addr()?);
+	axum::serve(listener, app).await?;
+	Ok(())
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2023-present, Manticore Software LTD (https://manticoresearch.com)
+// All rights reserved
+//
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Picks the next job to run so that clients take turns, the client that was served last waits
+/// until the others with queued jobs are served, and clients never run more jobs than their limit
+#[derive(Default)]
+pub struct Scheduler {
+	/// Queued jobs of each client in the order of submission
+	queued: HashMap<String, VecDeque<u64>>,
+	/// Clients with queued jobs in the order they are served
+	clients: VecDeque<String>,
+	running: HashMap<String, usize>,
+	/// Maximum number of running jobs of the client, unlimited when it is not set
+	limits: HashMap<String, usize>,
+}
+
+impl Scheduler {
+	pub fn new(limits: HashMap<String, usize>) -> Self {
+		Self { limits, ..Self::default() }
+	}
+
+	pub fn push(&mut self, client: &str, id: u64) {
+		let jobs = self.queued.entry(client.to_string()).or_default();
+		if jobs.is_empty() {
+			self.clients.push_back(client.to_string());
+		}
+		jobs.push_back(id);
+	}
+
+	/// Take the next job of the first client in turn that is below its limit
+	pub fn pop(&mut self) -> Option<(String, u64)> {
+		let position = self.clients.iter().position(|client| {
+			let running = self.running.get(client).copied().unwrap_or_default();
+			self.limits.get(client).is_none_or(|limit| running < *limit)
+		})?;
+		let client = self.clients.remove(position)?;
+		let jobs = self.queued.get_mut(&client)?;
+		let id = jobs.pop_front()?;
+		if jobs.is_empty() {
+			self.queued.remove(&client);
+		} else {
+			self.clients.push_back(client.clone());
+		}
+		*self.running.entry(client.clone()).or_default() += 1;
+		Some((client, id))
+	}
+
+	pub fn finish(&mut self, client: &str) {
+		if let Some(running) = self.running.get_mut(client) {
+			*running = running.saturating_sub(1);
+		}
+	}
+
+	/// Remove the cancelled job from the queue
+	pub fn remove(&mut self, client: &str, id: u64) {
+		let Some(jobs) = self.queued.get_mut(client) else {
+			return;
+		};
+		jobs.retain(|job_id| *job_id != id);
+		if jobs.is_empty() {
+			self.queued.remove(client);
+			self.clients.retain(|queued_client| queued_client != client);
+		}
+	}
+}
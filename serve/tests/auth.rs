@@ -0,0 +1,39 @@
+use axum::http::StatusCode;
+use serve::auth::{Role, Tokens};
+
+#[test]
+fn test_tokens_authorize_roles() {
+  let tokens = Tokens::load(Some("tests/data/tokens")).unwrap();
+  let reader = tokens.authorize(Some("reader-token"), Role::Read).unwrap();
+  assert_eq!(Role::Read, reader.role);
+  assert_eq!(12, reader.id.len());
+  assert_eq!(StatusCode::FORBIDDEN, tokens.authorize(Some("reader-token"), Role::Run).unwrap_err().0);
+
+  // Each role can do everything the previous ones can
+  assert!(tokens.authorize(Some("bot-token"), Role::Read).is_ok());
+  assert!(tokens.authorize(Some("bot-token"), Role::Run).is_ok());
+  assert_eq!(StatusCode::FORBIDDEN, tokens.authorize(Some("bot-token"), Role::Admin).unwrap_err().0);
+  assert!(tokens.authorize(Some("admin-token"), Role::Admin).is_ok());
+
+  assert_eq!(StatusCode::UNAUTHORIZED, tokens.authorize(None, Role::Read).unwrap_err().0);
+  assert_eq!(StatusCode::UNAUTHORIZED, tokens.authorize(Some("unknown-token"), Role::Read).unwrap_err().0);
+}
+
+#[test]
+fn test_tokens_load() {
+  let tokens = Tokens::load(Some("tests/data/tokens")).unwrap();
+  let bot = tokens.authorize(Some("bot-token"), Role::Run).unwrap();
+  assert_eq!(Some(2), bot.max_jobs);
+  assert_eq!(tokens.get_limits(), [(bot.id, 2)].into());
+
+  assert!(Tokens::load(Some("tests/data/missing-tokens")).is_err());
+  assert!(Tokens::load(Some("tests/data/job.rec")).is_err());
+}
+
+#[test]
+fn test_tokens_without_file_allow_everything() {
+  let tokens = Tokens::load(None).unwrap();
+  let client = tokens.authorize(None, Role::Admin).unwrap();
+  assert_eq!(Role::Admin, client.role);
+  assert!(tokens.get_limits().is_empty());
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use serve::jobs::{get_job_key, JobRequest, JobStatus, Queue};
+
+fn get_request(test: &str, image: &str, tags: Option<&str>) -> JobRequest {
+  JobRequest { test: test.to_string(), image: image.to_string(), tags: tags.map(str::to_string) }
+}
+
+#[test]
+fn test_job_key_follows_files_image_and_tags() {
+  let dir = std::env::temp_dir().join(format!("clt-job-key-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let test = dir.join("test.rec").to_string_lossy().to_string();
+  std::fs::write(&test, "---\ndepends: config.conf\n---\n––– input –––\nls\n––– output –––\n").unwrap();
+
+  let key = get_job_key(&get_request(&test, "ubuntu", None)).unwrap();
+  assert_eq!(key, get_job_key(&get_request(&test, "ubuntu", None)).unwrap());
+  assert_ne!(key, get_job_key(&get_request(&test, "debian", None)).unwrap());
+  assert_ne!(key, get_job_key(&get_request(&test, "ubuntu", Some("smoke"))).unwrap());
+
+  // The declared file that does not exist yet changes the key when it appears
+  std::fs::write(dir.join("config.conf"), "port = 9306\n").unwrap();
+  let with_config = get_job_key(&get_request(&test, "ubuntu", None)).unwrap();
+  assert_ne!(key, with_config);
+  std::fs::write(dir.join("config.conf"), "port = 9307\n").unwrap();
+  assert_ne!(with_config, get_job_key(&get_request(&test, "ubuntu", None)).unwrap());
+
+  // The directory of tests is hashed with all files in it
+  let dir_key = get_job_key(&get_request(&dir.to_string_lossy(), "ubuntu", None)).unwrap();
+  std::fs::write(dir.join("other.rec"), "––– input –––\nls\n––– output –––\n").unwrap();
+  let other_key = get_job_key(&get_request(&dir.to_string_lossy(), "ubuntu", None));
+  std::fs::remove_dir_all(&dir).unwrap();
+  assert_ne!(dir_key, other_key.unwrap());
+}
+
+#[tokio::test]
+async fn test_queue_keeps_jobs_in_database() {
+  let db = std::env::temp_dir().join(format!("clt-jobs-{}.db", std::process::id())).to_string_lossy().to_string();
+  let _ = std::fs::remove_file(&db);
+  // The client may not run any jobs, so they stay queued while the queue is checked
+  let limits = HashMap::from([("client".to_string(), 0)]);
+
+  let queue = Queue::start("clt".to_string(), 1, &db, limits.clone()).unwrap();
+  let (job, is_new) = queue.submit(get_request("tests/data/job.rec", "ubuntu", Some("smoke")), "client").unwrap();
+  assert!(is_new);
+  assert_eq!(JobStatus::Queued, job.status);
+  assert_eq!("client", job.client);
+
+  // The same job submitted again while it is queued is returned as is
+  let (again, is_new) = queue.submit(get_request("tests/data/job.rec", "ubuntu", Some("smoke")), "client").unwrap();
+  assert!(!is_new);
+  assert_eq!(job.id, again.id);
+  let (other, _) = queue.submit(get_request("tests/data/job.rec", "debian", None), "client").unwrap();
+  assert_ne!(job.id, other.id);
+  drop(queue);
+
+  // The queued jobs are read back after the restart as they were saved
+  let queue = Queue::start("clt".to_string(), 1, &db, limits).unwrap();
+  let jobs = queue.list().unwrap();
+  assert_eq!(vec![job.id, other.id], jobs.iter().map(|job| job.id).collect::<Vec<_>>());
+  let saved = queue.get(job.id).unwrap().unwrap();
+  assert_eq!(job.key, saved.key);
+  assert_eq!("tests/data/job.rec", saved.request.test);
+  assert_eq!("ubuntu", saved.request.image);
+  assert_eq!(Some("smoke".to_string()), saved.request.tags);
+  assert_eq!(JobStatus::Queued, saved.status);
+  assert_eq!(job.created, saved.created);
+
+  let cancelled = queue.cancel(other.id).unwrap().unwrap();
+  assert_eq!(JobStatus::Cancelled, cancelled.status);
+  assert!(queue.cancel(other.id).unwrap().is_none());
+  assert!(queue.get(other.id + 1).unwrap().is_none());
+  std::fs::remove_file(&db).unwrap();
+}
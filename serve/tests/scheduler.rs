@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use serve::scheduler::Scheduler;
+
+#[test]
+fn test_scheduler_takes_turns_between_clients() {
+  let mut scheduler = Scheduler::new(HashMap::new());
+  for id in 1..=3 {
+    scheduler.push("bot", id);
+  }
+  scheduler.push("user", 4);
+  scheduler.push("other", 5);
+  scheduler.push("user", 6);
+
+  let order: Vec<(String, u64)> = std::iter::from_fn(|| scheduler.pop()).collect();
+  let order: Vec<(&str, u64)> = order.iter().map(|(client, id)| (client.as_str(), *id)).collect();
+  assert_eq!(order, [("bot", 1), ("user", 4), ("other", 5), ("bot", 2), ("user", 6), ("bot", 3)]);
+}
+
+#[test]
+fn test_scheduler_releases_limit_on_finish() {
+  let mut scheduler = Scheduler::new(HashMap::from([("bot".to_string(), 1)]));
+  scheduler.push("bot", 1);
+  scheduler.push("bot", 2);
+  scheduler.push("user", 3);
+
+  assert_eq!(Some(("bot".to_string(), 1)), scheduler.pop());
+  // The bot runs as many jobs as it may, so the others go first
+  assert_eq!(Some(("user".to_string(), 3)), scheduler.pop());
+  assert_eq!(None, scheduler.pop());
+
+  scheduler.finish("bot");
+  assert_eq!(Some(("bot".to_string(), 2)), scheduler.pop());
+  scheduler.finish("bot");
+  scheduler.finish("bot");
+  assert_eq!(None, scheduler.pop());
+}
+
+#[test]
+fn test_scheduler_removes_cancelled_jobs() {
+  let mut scheduler = Scheduler::new(HashMap::new());
+  scheduler.push("bot", 1);
+  scheduler.push("bot", 2);
+  scheduler.push("user", 3);
+  scheduler.remove("bot", 1);
+  scheduler.remove("user", 3);
+  scheduler.remove("missing", 4);
+
+  assert_eq!(Some(("bot".to_string(), 2)), scheduler.pop());
+  assert_eq!(None, scheduler.pop());
+
+  // The client without queued jobs is queued again at the end
+  scheduler.push("user", 5);
+  scheduler.push("bot", 6);
+  assert_eq!(Some(("user".to_string(), 5)), scheduler.pop());
+  assert_eq!(Some(("bot".to_string(), 6)), scheduler.pop());
+}
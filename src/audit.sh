@@ -0,0 +1,43 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+target=$1
+if [ -z "$target" ]; then
+	>&2 echo 'Usage: clt audit test-file|directory' && exit 1
+fi
+
+if [ -d "$target" ]; then
+	mapfile -t test_files < <(find "$target" -type f -name '*.rec' | sort)
+elif [ -f "$target" ]; then
+	test_files=("$target")
+else
+	>&2 echo "The test does not exist: $target" && exit 1
+fi
+
+# Audit against the same patterns the tests are compared with in the container
+image=$(tools_image)
+exit_code=0
+for test_file in "${test_files[@]}"; do
+	echo "Audit of $test_file"
+	cmp_exec "$image" --audit "$test_file" || exit_code=1
+done
+
+exit $exit_code
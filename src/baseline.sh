@@ -0,0 +1,133 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+
+BASELINES_DIR=.baselines
+
+usage() {
+	>&2 echo "Usage: clt baseline save|restore|diff|list [name] [directory]" && exit 1
+}
+
+# Validate the name of the baseline and print the path to it
+get_baseline_dir() {
+	name=$1
+	if [ -z "$name" ]; then
+		usage
+	fi
+
+	if ! [[ "$name" =~ ^[A-Za-z0-9_.-]+$ ]]; then
+		>&2 echo "Baseline name may contain only letters, digits, dots, dashes and underscores: $name" && exit 1
+	fi
+
+	echo "$BASELINES_DIR/$name"
+}
+
+# Snapshot expected outputs (.rec) and results (.rep) of the tests in the directory
+save() {
+	baseline_dir=$(get_baseline_dir "$1")
+	test_dir=${2:-tests}
+	if [ ! -d "$test_dir" ]; then
+		>&2 echo "Directory with tests does not exist: $test_dir" && exit 1
+	fi
+
+	rm -rf "$baseline_dir"
+	mkdir -p "$baseline_dir"
+	count=0
+	while read -r file; do
+		mkdir -p "$baseline_dir/$(dirname "$file")"
+		cp "$file" "$baseline_dir/$file"
+		count=$((count + 1))
+	done < <(find "$test_dir" -type f \( -name '*.rec' -o -name '*.recb' -o -name '*.rep' \) | sort)
+
+	echo "Saved $count files from $test_dir to the baseline: $baseline_dir"
+}
+
+# Show how the current expected outputs differ from the baseline
+diff_baseline() {
+	baseline_dir=$(get_baseline_dir "$1")
+	if [ ! -d "$baseline_dir" ]; then
+		>&2 echo "Baseline does not exist: $baseline_dir" && exit 1
+	fi
+
+	has_diff=0
+	while read -r file; do
+		file=${file#"$baseline_dir"/}
+		if [ ! -f "$file" ]; then
+			echo "Removed: $file"
+			has_diff=1
+		elif ! diff -u --label "baseline/$file" --label "$file" "$baseline_dir/$file" "$file"; then
+			has_diff=1
+		fi
+	done < <(find "$baseline_dir" -type f \( -name '*.rec' -o -name '*.recb' \) | sort)
+
+	if [ $has_diff -eq 1 ]; then
+		exit 1
+	fi
+}
+
+# Roll back expected outputs and results to the baseline
+restore() {
+	baseline_dir=$(get_baseline_dir "$1")
+	if [ ! -d "$baseline_dir" ]; then
+		>&2 echo "Baseline does not exist: $baseline_dir" && exit 1
+	fi
+
+	while read -r file; do
+		target=${file#"$baseline_dir"/}
+		if [ -f "$target" ] && cmp -s "$file" "$target"; then
+			continue
+		fi
+
+		mkdir -p "$(dirname "$target")"
+		cp "$file" "$target"
+		echo "Restored: $target"
+	done < <(find "$baseline_dir" -type f | sort)
+}
+
+list() {
+	if [ ! -d "$BASELINES_DIR" ]; then
+		return 0
+	fi
+
+	find "$BASELINES_DIR" -mindepth 1 -maxdepth 1 -type d -printf '%f\n' | sort
+}
+
+subcmd=$1
+shift || true
+
+case "$subcmd" in
+	save)
+		save "$@"
+		;;
+
+	restore)
+		restore "$@"
+		;;
+
+	diff)
+		diff_baseline "$@"
+		;;
+
+	list)
+		list
+		;;
+
+	*)
+		usage
+		;;
+esac
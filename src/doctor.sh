@@ -0,0 +1,125 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/rec.sh"
+
+# Small image that has bash inside, it's required to run the tests
+docker_image=${1:-debian:stable-slim}
+failed=0
+
+check_ok() {
+	echo "[OK]   $1"
+}
+
+check_fail() {
+	echo "[FAIL] $1"
+	echo "       Fix: $2"
+	failed=1
+}
+
+echo "Checking the environment to run CLT tests"
+
+# Docker is required for everything we do
+if ! command -v docker > /dev/null 2>&1; then
+	check_fail "Docker is not installed" "install Docker: https://docs.docker.com/engine/install/"
+	exit 1
+fi
+check_ok "Docker is installed: $(docker --version)"
+
+if ! docker_error=$(docker info 2>&1 > /dev/null); then
+	if [[ "$docker_error" == *"permission denied"* ]]; then
+		check_fail "No permissions to access the Docker daemon" \
+			"add your user to the docker group with 'sudo usermod -aG docker \$USER' and log in again"
+	else
+		check_fail "Docker daemon is not available" "start the Docker daemon, e.g. 'sudo systemctl start docker'"
+	fi
+	exit 1
+fi
+check_ok "Docker daemon is available"
+
+# Prebuilt binaries are selected by the architecture of the host
+for binary in rec cmp; do
+	if [ ! -x "$bin_path/$binary" ]; then
+		check_fail "Binary $binary is missing for architecture $ARCH" \
+			"build it with ./bin/cross-build or use a supported architecture: x86_64, aarch64"
+	else
+		check_ok "Binary $binary is available for architecture $ARCH"
+	fi
+done
+
+# Validate format of the patterns we merge into the container
+for patterns_file in "$PROJECT_DIR/.patterns" .patterns; do
+	if [ ! -f "$patterns_file" ]; then
+		continue
+	fi
+
+	line_number=0
+	invalid=0
+	while IFS= read -r line || [ -n "$line" ]; do
+		line_number=$((line_number + 1))
+		if [ -z "${line// }" ]; then
+			continue
+		fi
+
+		if ! [[ "$line" =~ ^[A-Z][A-Z_0-9]*[[:space:]]+[^[:space:]] ]]; then
+			check_fail "Invalid pattern in $patterns_file:$line_number: $line" \
+				"use the 'NAME REGEX' format where NAME contains only capital letters, digits and underscores"
+			invalid=1
+		fi
+	done < "$patterns_file"
+
+	if [ $invalid -eq 0 ]; then
+		check_ok "Patterns are valid in $patterns_file"
+	fi
+done
+
+if ! docker pull "$docker_image" > /dev/null 2>&1; then
+	check_fail "Failed to pull Docker image $docker_image" \
+		"check your network connection and registry access or pass another image: clt doctor image"
+	exit 1
+fi
+check_ok "Docker image $docker_image is available"
+
+# Run the full cycle of replay and compare on the trivial test
+temp_dir=$(mktemp -d)
+trap 'rm -rf "$temp_dir"' EXIT
+mkdir -p "$temp_dir/tests"
+cat > "$temp_dir/tests/doctor.rec" <<EOF
+––– input –––
+echo clt-doctor
+––– output –––
+clt-doctor
+EOF
+
+if (cd "$temp_dir" && test "$docker_image" "tests/doctor.rec") > "$temp_dir/doctor.log" 2>&1; then
+	check_ok "Replay and compare cycle works in $docker_image"
+else
+	check_fail "Replay and compare cycle failed in $docker_image" \
+		"make sure the image has bash installed and check the output below"
+	cat "$temp_dir/doctor.log"
+	if [ -f "$temp_dir/tests/doctor.cmp" ]; then
+		cat "$temp_dir/tests/doctor.cmp"
+	fi
+fi
+
+if [ $failed -eq 1 ]; then
+	echo "Some checks failed, please fix the issues above"
+	exit 1
+fi
+
+echo "Everything looks good, you are ready to run CLT tests"
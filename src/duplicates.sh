@@ -0,0 +1,141 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+
+test_dir=tests
+threshold=90
+
+# Parse input arguments for this command
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --threshold=*)
+      threshold="${key#*=}"
+      shift
+      ;;
+    --threshold)
+      threshold="$2"
+      shift
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      test_dir="$key"
+      shift
+      ;;
+  esac
+done
+
+if [ ! -d "$test_dir" ]; then
+	>&2 echo "Directory with tests does not exist: $test_dir" && exit 1
+fi
+
+if ! [[ "$threshold" =~ ^[0-9]+$ ]] || [ "$threshold" -gt 100 ]; then
+	>&2 echo "Threshold should be a number from 0 to 100: $threshold" && exit 1
+fi
+
+mapfile -t test_files < <(find "$test_dir" -type f -name '*.rec' | sort)
+if [ ${#test_files[@]} -lt 2 ]; then
+	echo "Nothing to compare, found ${#test_files[@]} tests in $test_dir"
+	exit 0
+fi
+
+# Each test is reduced to the sequence of its commands, block references count as one command
+# Then every pair of tests is scored by the longest common subsequence of the commands
+awk -v threshold="$threshold" '
+	FNR == 1 {
+		files[++file_count] = FILENAME
+		mode = ""
+	}
+
+	/^––– block: .* –––$/ {
+		commands[file_count, ++command_count[file_count]] = $0
+		mode = ""
+		next
+	}
+
+	/^––– input –––$/ {
+		mode = "input"
+		command = ""
+		next
+	}
+
+	/^––– output –––$/ {
+		if (mode == "input") {
+			commands[file_count, ++command_count[file_count]] = command
+		}
+		mode = "output"
+		next
+	}
+
+	mode == "input" {
+		command = (command == "" ? $0 : command "\n" $0)
+	}
+
+	END {
+		found = 0
+		for (a = 1; a <= file_count; a++) {
+			for (b = a + 1; b <= file_count; b++) {
+				n = command_count[a] + 0
+				m = command_count[b] + 0
+				if (n == 0 || m == 0) {
+					continue
+				}
+
+				for (i = 0; i <= n; i++) {
+					for (j = 0; j <= m; j++) {
+						if (i == 0 || j == 0) {
+							lcs[i, j] = 0
+						} else if (commands[a, i] == commands[b, j]) {
+							lcs[i, j] = lcs[i - 1, j - 1] + 1
+						} else {
+							lcs[i, j] = (lcs[i - 1, j] > lcs[i, j - 1] ? lcs[i - 1, j] : lcs[i, j - 1])
+						}
+					}
+				}
+
+				similarity = int(200 * lcs[n, m] / (n + m))
+				if (similarity < threshold) {
+					continue
+				}
+
+				prefix = 0
+				while (prefix < n && prefix < m && commands[a, prefix + 1] == commands[b, prefix + 1]) {
+					prefix++
+				}
+
+				found++
+				printf "%d%% %s %s\n", similarity, files[a], files[b]
+				if (similarity == 100) {
+					print "  Both tests run the same commands, consider removing one of them"
+				} else if (prefix > 1) {
+					printf "  The first %d commands are the same, consider extracting them into a block (.recb)\n", prefix
+				} else {
+					printf "  %d of the commands are shared, consider consolidating these tests\n", lcs[n, m]
+				}
+			}
+		}
+
+		if (found == 0) {
+			printf "No duplicate tests found with similarity of %d%% or more\n", threshold
+		}
+	}
+' "${test_files[@]}"
@@ -0,0 +1,168 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+
+max_age=30
+max_size=
+dry_run=
+dirs=()
+
+usage() {
+	>&2 echo "Usage: clt gc [--max-age=days] [--max-size=size] [--dry-run] [directory...]" && exit 1
+}
+
+# Remove the file or directory and count the space freed
+remove() {
+	if [ -n "$dry_run" ]; then
+		echo "Would remove $2"
+	else
+		rm -rf "$2"
+		echo "Removed $2"
+	fi
+	removed_count=$((removed_count + 1))
+	removed_size=$((removed_size + $1))
+}
+
+# Bundles are tarballs saved by clt test --bundle with the manifest in the root
+is_bundle() {
+	tar -tf "$1" 2>/dev/null | grep -qx '\./manifest'
+}
+
+# Print "mtime size path" of the results of runs in the directories, oldest first
+# Snapshots of the baselines are kept whatever their age
+list_artifacts() {
+	find "$@" -name .baselines -prune -o \
+		-type d -name '*.screens' -prune -print -o \
+		-type f \( -name '*.rep' -o -name '*.cmp' -o -name '*.tar' \) -print |
+	while IFS= read -r path; do
+		if [[ "$path" == *.tar ]] && ! is_bundle "$path"; then
+			continue
+		fi
+		printf '%s\t%s\t%s\n' "$(stat -c %Y "$path")" "$(du -sb "$path" | cut -f1)" "$path"
+	done | sort -n
+}
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --max-age=*)
+      max_age="${key#*=}"
+      shift
+      ;;
+    --max-age)
+      max_age="$2"
+      shift
+      shift
+      ;;
+    --max-size=*)
+      max_size="${key#*=}"
+      shift
+      ;;
+    --max-size)
+      max_size="$2"
+      shift
+      shift
+      ;;
+    --dry-run)
+      dry_run=1
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      dirs+=("$key")
+      shift
+      ;;
+  esac
+done
+
+if [ ${#dirs[@]} -eq 0 ]; then
+	dirs=(tests)
+fi
+
+for dir in "${dirs[@]}"; do
+	if [ ! -d "$dir" ]; then
+		>&2 echo "Directory does not exist: $dir" && exit 1
+	fi
+done
+
+if ! [[ "$max_age" =~ ^[0-9]+$ ]]; then
+	>&2 echo "Maximum age should be a number of days: $max_age" && usage
+fi
+
+if [ -n "$max_size" ]; then
+	max_size_bytes=$(numfmt --from=iec "$max_size" 2>/dev/null) || {
+		>&2 echo "Maximum size should be a number of bytes with an optional K, M or G suffix: $max_size" && usage
+	}
+fi
+
+cutoff=$(( $(date +%s) - max_age * 86400 ))
+removed_count=0
+removed_size=0
+kept_size=0
+kept=()
+
+# Results older than the maximum age go first, then the oldest ones while the rest exceed the size
+while IFS=$'\t' read -r mtime size path; do
+	if [ "$mtime" -lt "$cutoff" ]; then
+		remove "$size" "$path"
+	else
+		kept+=("$size"$'\t'"$path")
+		kept_size=$((kept_size + size))
+	fi
+done < <(list_artifacts "${dirs[@]}")
+
+if [ -n "$max_size" ]; then
+	for entry in "${kept[@]}"; do
+		if [ "$kept_size" -le "$max_size_bytes" ]; then
+			break
+		fi
+		size=${entry%%$'\t'*}
+		remove "$size" "${entry#*$'\t'}"
+		kept_size=$((kept_size - size))
+	done
+fi
+
+# History of the runs saved with clt test --results has the time of the run in the first column
+while IFS= read -r history_file; do
+	if grep -qvE '^[0-9]+ (passed|failed) ' "$history_file"; then
+		continue
+	fi
+
+	pruned=$(awk -v cutoff="$cutoff" '$1 < cutoff' "$history_file" | wc -l)
+	if [ "$pruned" -eq 0 ]; then
+		continue
+	fi
+
+	if [ -n "$dry_run" ]; then
+		echo "Would prune $pruned records from $history_file"
+	else
+		awk -v cutoff="$cutoff" '$1 >= cutoff' "$history_file" > "$history_file.tmp"
+		mv "$history_file.tmp" "$history_file"
+		echo "Pruned $pruned records from $history_file"
+	fi
+done < <(find "${dirs[@]}" -name .baselines -prune -o -type f -name results -print)
+
+freed=$(numfmt --to=iec "$removed_size")
+if [ -n "$dry_run" ]; then
+	echo "Would remove $removed_count files and directories, $freed in total"
+else
+	echo "Removed $removed_count files and directories, $freed in total"
+fi
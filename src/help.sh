@@ -23,33 +23,160 @@ CLT: Command Line Testing Utility
 Usage: clt COMMAND [OPTIONS]
 
 Commands:
+init     Create the files of a new project: clt init [--sample] [directory]
 record   Record an interactive session and store the inputs and outputs in a .rec file
 test     Replay a recorded session and test for differences
 refine   Replay a recorded session, compare the outputs, and edit differences
+accept   Same as refine, use accept --commit to commit the accepted outputs: clt accept --commit -t test-file image
+baseline Save, diff, restore or list named snapshots of expected outputs and results
+report   Compare results of two runs: clt report compare directory-a directory-b
+repro    Replay the failed test from the bundle saved with clt test --bundle: clt repro bundle.tar
+audit    Check a generated test before adding it to the suite: clt audit test-file|directory
+secret   Generate a key or encrypt a value to store in tests: clt secret keygen|encrypt
+tags     List tags used by the tests in the directory: clt tags list [directory]
+duplicates Find duplicate and near-duplicate tests in the directory
+owners   Show owners of the tests from their front matter or CODEOWNERS: clt owners [path...]
+stats    Export metrics of the suite for dashboards: clt stats export [--format=json|prometheus] [directory]
+gc       Remove old results of runs to keep the disk usage bounded: clt gc [--max-age=days] [directory...]
+migrate  Upgrade tests written for older versions to the current format: clt migrate [--dry-run] [path...]
+undo     Revert the last edit of the test made by clt migrate or the tools editing it: clt undo test-file
+merge-tool Merge versions of a test step by step as the git merge driver: clt merge-tool --install
+serve    Accept jobs to run tests and check them over HTTP: clt serve [--listen=address] [--workers=N]
+lsp      Run the language server for .rec files that editors talk to over stdin and stdout
+self-update Install the latest release of CLT with its binaries: clt self-update [--check] [--version=tag]
+doctor   Check the environment and show how to fix the issues found
 help     Show this help message
 
+Init options:
+  --sample
+    Also create the sample test tests/sample.rec
+  [directory]
+    Directory of the project (default: current directory)
+
 Record options:
   -t, --test-file=path-to-file
     Path to the .rec file to store inputs and outputs (optional)
   -n, --no-refine
     Do not run refine, just record inputs in the .rec file (optional)
   [docker image]
-    Docker image to run commands in
+    Docker image to run commands in or build:path/to/Dockerfile to build it
 
 Test options:
   -t, --test-file=path-to-file
-    Path to the .rec file containing inputs and outputs
+    Path to the .rec file containing inputs and outputs or directory to run all tests from
   -d, --debug, --diff
     Show diff produced by cmp tool to stdout
+  --show-matches
+    Show the diff with the text consumed by each pattern of the matched lines marked
 	-D, --delay=timeout-in-ms
 	  Delay between commands in ms (default: 5)
+  -j, --jobs=number
+    Number of tests to run in parallel when running the directory (default: 1)
+  -r, --results=directory
+    Save results of the run to the directory to compare them later with clt report compare
+  --seed=number
+    Seed exposed to tests as %{SEED} and CLT_SEED to reproduce the run (default: random)
+  --disk-report
+    Report tests that write the most to the container and tests that leave files behind
+  --bundle=directory
+    Save a tarball to reproduce each failed test with clt repro to the directory
+  --screens[=text|svg]
+    Save the terminal screen after each step of failed tests to the .screens directory next to the test
+  --fixture=path-to-file
+    Run all tests in one container with daemons started and reset by the commands from the file
+  --snapshot=path-to-file
+    Run the setup test once and start all tests from the state of the container after it
+  --tags=list
+    Run only tests with any of the tags and without tags prefixed with !, e.g. smoke,!slow
+  --changed=ref
+    Run only tests changed since the git ref or the ones whose blocks, expected output files, tables or files in depends changed
+  --vars=path-to-file
+    File with NAME=value lines to substitute into inputs as %{NAME} (default: .clt/variables)
+  --webhook=url
+    Post the failed tests with their owners and failed steps as JSON to the URL, CLT_WEBHOOK_URL works too
   [docker image]
-    Docker image to run commands in
+    Docker image to run commands in or build:path/to/Dockerfile to build it
 
-Refine options:
+Refine and accept options:
   -t, --test-file=path-to-file
     Path to the .rec file containing inputs and outputs
+  --commit
+    Commit the refined test and the patterns edited meanwhile with the message listing the steps that changed
+  [docker image]
+    Docker image to run commands in or build:path/to/Dockerfile to build it
+
+Baseline commands:
+  save name [directory]
+    Snapshot .rec, .recb and .rep files from the directory (default: tests) as the named baseline
+  diff name
+    Show how the current expected outputs differ from the baseline
+  restore name
+    Roll back the files to the baseline
+  list
+    List saved baselines
+
+Repro options:
+  --failed-steps
+    Replay only the steps that failed in the bundle
+  bundle.tar
+    Path to the bundle saved with clt test --bundle
+
+Duplicates options:
+  --threshold=percent
+    Minimal similarity of the command sequences to report (default: 90)
+  [directory]
+    Directory to look for .rec files in (default: tests)
+
+Stats export options:
+  --format=json|prometheus
+    Format of the metrics: JSON or the Prometheus text format (default: json)
+  --results=directory
+    Directory with the runs saved with clt test --results to count failures and flakiness of the tests (optional)
+  [directory]
+    Directory with the tests and their .rep files (default: tests)
+
+Gc options:
+  --max-age=days
+    Remove .rep, .cmp, .screens, bundles and records of the run history older than the days (default: 30)
+  --max-size=size
+    Remove the oldest results while the rest take more than the size, e.g. 500M or 2G (optional)
+  --dry-run
+    Show what would be removed without removing it
+  [directory...]
+    Directories with tests, results saved with --results and bundles (default: tests)
+
+Migrate options:
+  --dry-run
+    Show the changes as a diff without writing them, fail when there are any
+  [path...]
+    Tests, blocks or directories with them to migrate (default: tests)
+
+Merge-tool options:
+  --install
+    Register the merge driver in the git config and assign it to .rec and .recb files in .gitattributes
+  base-file ours-file theirs-file [path]
+    Versions of the test git passes to the driver, the merged test is written to ours-file
+
+Serve options:
+  -l, --listen=address
+    Address to listen on (default: 127.0.0.1:8080)
+  -w, --workers=number
+    Number of jobs to run at the same time (default: 1)
+  --db=path
+    SQLite database to keep the jobs in across restarts (default: .clt-jobs.db)
+  --tokens=path
+    File with "role token [max-jobs]" lines to require tokens with the read, run or admin role from clients
+  --keep-days=number
+    Delete finished jobs with their output after the number of days (default: keep all)
+
+Self-update options:
+  --check
+    Only show whether a newer release is available
+  --version=tag
+    Install the release with the tag instead of the latest one, e.g. to roll back
+
+Doctor options:
   [docker image]
-    Docker image to run commands in
+    Docker image to run the trivial test in (default: debian:stable-slim)
 
 EOF
@@ -0,0 +1,114 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+
+project_dir=.
+sample=
+
+# Create the file with the content from stdin unless it exists, so init can be rerun safely
+create_file() {
+	file="$project_dir/$1"
+	if [ -e "$file" ]; then
+		echo "Skipped $1, it exists"
+		cat > /dev/null
+		return 0
+	fi
+
+	mkdir -p "$(dirname "$file")"
+	cat > "$file"
+	echo "Created $1"
+}
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --sample)
+      sample=1
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      project_dir="$key"
+      shift
+      ;;
+  esac
+done
+
+mkdir -p "$project_dir"
+
+create_file .patterns <<'PATTERNS'
+# Patterns of the project extend the ones shipped with CLT, one per line as NAME REGEX
+# Use them in expected outputs as %{NAME}, e.g.
+# BUILDID [a-f0-9]{N}
+PATTERNS
+
+create_file .ignore-lines <<'IGNORE'
+# Regexes of noisy lines removed from both expected and actual outputs before comparing, e.g.
+# ^ERROR: ld\.so: object .* from LD_PRELOAD cannot be preloaded
+IGNORE
+
+create_file .templates/.gitkeep < /dev/null
+
+create_file .clt/config <<'CONFIG'
+# Directories to look for blocks in when they are not next to the test, separated by colons
+# block-path: ../shared-blocks
+# Accept statements written as === input === for editors that mangle en dashes
+# statement-style: ascii
+CONFIG
+
+create_file .clt/variables <<'VARIABLES'
+# Values substituted into inputs as %{NAME}, one per line as NAME=value
+# HOST=127.0.0.1
+VARIABLES
+
+create_file tests/.gitkeep < /dev/null
+
+if [ -n "$sample" ]; then
+	create_file tests/sample.rec <<'SAMPLE'
+---
+tags: smoke
+---
+––– input –––
+echo 'Hello from CLT'
+––– output –––
+Hello from CLT
+––– input –––
+date +%Y
+––– output –––
+%{YEAR}
+SAMPLE
+fi
+
+# Results of runs should not be committed along with the tests
+gitignore="$project_dir/.gitignore"
+added=0
+for entry in '*.rep' '*.cmp' '*.screens/' '.clt-jobs.db' '.clt/history/'; do
+	if [ -f "$gitignore" ] && grep -qxF "$entry" "$gitignore"; then
+		continue
+	fi
+	echo "$entry" >> "$gitignore"
+	added=$((added + 1))
+done
+if [ $added -gt 0 ]; then
+	echo "Added $added entries to .gitignore"
+fi
+
+echo "The project is ready, record the first test with: clt record image"
@@ -0,0 +1,70 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+usage() {
+	>&2 echo "Usage: clt merge-tool --install | clt merge-tool base-file ours-file theirs-file [path]" && exit 1
+}
+
+# Register the driver in the git config of the repository and assign it to the tests in .gitattributes
+if [ "$1" == "--install" ]; then
+	if ! git rev-parse --is-inside-work-tree > /dev/null 2>&1; then
+		>&2 echo "The merge tool can only be installed in a git repository" && exit 1
+	fi
+
+	git config merge.clt.name "CLT merge of tests by steps"
+	git config merge.clt.driver "'$PROJECT_DIR/clt' merge-tool %O %A %B %P"
+	attributes="$(git rev-parse --show-toplevel)/.gitattributes"
+	for entry in '*.rec merge=clt' '*.recb merge=clt'; do
+		if [ -f "$attributes" ] && grep -qxF "$entry" "$attributes"; then
+			continue
+		fi
+		echo "$entry" >> "$attributes"
+	done
+	echo "Installed the merge driver for .rec and .recb files, commit .gitattributes to use it in other clones"
+	exit 0
+fi
+
+if [ $# -lt 3 ] || [ $# -gt 4 ] || [[ "$1" == -* ]]; then
+	usage
+fi
+
+# Git passes the versions as %O %A %B and expects the result in the file of ours
+base_file=$1
+ours_file=$2
+theirs_file=$3
+path=${4:-$2}
+
+merged_file=$(mktemp)
+trap 'rm -f "$merged_file"' EXIT
+exit_code=0
+cmp_exec "$(tools_image)" --merge "$base_file" "$ours_file" "$theirs_file" > "$merged_file" || exit_code=$?
+
+# Files that can not be split into steps are merged line by line as git does by default
+if [ $exit_code -gt 1 ]; then
+	>&2 echo "Merging $path line by line"
+	exit_code=0
+	git merge-file -L ours -L base -L theirs "$ours_file" "$base_file" "$theirs_file" || exit_code=$?
+	exit $exit_code
+fi
+
+cat "$merged_file" > "$ours_file"
+if [ $exit_code -ne 0 ]; then
+	>&2 echo "Resolve the conflicts in $path and remove the steps marking them"
+fi
+exit $exit_code
@@ -0,0 +1,94 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+dry_run=
+paths=()
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --dry-run)
+      dry_run=1
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      paths+=("$key")
+      shift
+      ;;
+  esac
+done
+
+if [ ${#paths[@]} -eq 0 ]; then
+	paths=(tests)
+fi
+
+test_files=()
+for path in "${paths[@]}"; do
+	if [ -d "$path" ]; then
+		mapfile -t -O "${#test_files[@]}" test_files < <(find "$path" -type f \( -name '*.rec' -o -name '*.recb' \) | sort)
+	elif [ -f "$path" ]; then
+		test_files+=("$path")
+	else
+		>&2 echo "The test does not exist: $path" && exit 1
+	fi
+done
+
+migrated=$(mktemp)
+trap 'rm -f "$migrated"' EXIT
+
+image=$(tools_image)
+exit_code=0
+changed_count=0
+for test_file in "${test_files[@]}"; do
+	if ! cmp_exec "$image" --migrate "$test_file" > "$migrated"; then
+		exit_code=1
+		continue
+	fi
+
+	if diff -q "$test_file" "$migrated" > /dev/null; then
+		continue
+	fi
+
+	changed_count=$((changed_count + 1))
+	if [ -n "$dry_run" ]; then
+		diff -u --label "$test_file" --label "$test_file (migrated)" "$test_file" "$migrated" || true
+	else
+		cmp_exec "$image" --save-history "$test_file"
+		# Write into the file to keep its permissions
+		cat "$migrated" > "$test_file"
+		echo "Migrated $test_file"
+	fi
+done
+
+if [ -n "$dry_run" ]; then
+	echo "$changed_count of ${#test_files[@]} files would be migrated"
+	# The dry run fails when there is something to migrate to catch legacy tests in CI
+	if [ $changed_count -gt 0 ]; then
+		exit_code=1
+	fi
+else
+	echo "Migrated $changed_count of ${#test_files[@]} files"
+fi
+
+exit $exit_code
@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/meta.sh"
+source "$PROJECT_DIR/lib/owners.sh"
+
+if [ $# -eq 0 ]; then
+	set -- tests
+fi
+
+# Print each test with its owners, tests nobody owns are marked to assign them
+while IFS= read -r test_file; do
+	owners=$(owners_get "$test_file" | paste -sd' ' -)
+	echo "$test_file ${owners:-(no owner)}"
+done < <(find "$@" -type f -name '*.rec' | sort)
@@ -22,11 +22,17 @@ source "$PROJECT_DIR/lib/argument.sh"
 docker_image=$(argument_parse_docker_image "$@")
 set -- "${@:1:$(($#-1))}"
 
+commit=
+
 # Parse input arguments for this command
 while [[ $# -gt 0 ]]; do
   key="$1"
 
   case $key in
+    --commit)
+      commit=1
+      shift
+      ;;
     -t=*|--test-file=*)
       record_file="${key#*=}"
       shift
@@ -42,4 +48,45 @@ while [[ $# -gt 0 ]]; do
   esac
 done
 
-refine "$docker_image" "$record_file"
\ No newline at end of file
+if [ -n "$commit" ] && ! git rev-parse --is-inside-work-tree > /dev/null 2>&1; then
+	>&2 echo "The --commit flag needs the test to be in a git repository" && exit 1
+fi
+
+# Patterns edited while refining the test are committed with it
+patterns_hash=$(git hash-object .patterns 2> /dev/null || true)
+
+refine "$docker_image" "$record_file"
+
+if [ -n "$commit" ]; then
+	commit_files=("$record_file")
+	patterns_note=
+	if [ -f .patterns ] && [ "$(git hash-object .patterns)" != "$patterns_hash" ]; then
+		commit_files+=(.patterns)
+		patterns_note=$'\n\nThe patterns in .patterns were updated to match the outputs.'
+	fi
+
+	if [ -z "$(git status --porcelain -- "${commit_files[@]}")" ]; then
+		echo "The expected outputs of $record_file are the same, nothing to commit"
+		exit 0
+	fi
+
+	# Steps are compared with the last committed version, untracked tests are compared with an empty one
+	# The file is kept next to the test, so it is in the project that clt-cmp sees in the container
+	committed_file=$(mktemp "$(dirname "$record_file")/.$(basename "$record_file").XXXXXX")
+	trap 'rm -f "$committed_file"' EXIT
+	tracked_file=$(git ls-files --full-name -- "$record_file")
+	if [ -z "$tracked_file" ] || ! git show "HEAD:$tracked_file" > "$committed_file" 2>/dev/null; then
+		: > "$committed_file"
+	fi
+	changes=$(cmp_exec "$docker_image" --diff-structure "$committed_file" "$record_file")
+
+	# Only the refined test and the patterns are committed, other staged changes stay staged
+	git add -- "${commit_files[@]}"
+	git commit -q -F - -- "${commit_files[@]}" <<MESSAGE
+Refine expected outputs of $record_file
+
+The outputs were accepted from the replay in $docker_image:
+$changes$patterns_note
+MESSAGE
+	echo "Committed the refined ${commit_files[*]}"
+fi
@@ -0,0 +1,145 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/meta.sh"
+source "$PROJECT_DIR/lib/owners.sh"
+
+# Print the status of each test in the results directory saved with clt test --results
+# The test is flaky when the directory holds several runs of it with different outcomes
+get_statuses() {
+	awk '
+		{
+			file = $0
+			sub(/^[^ ]+ [^ ]+ /, "", file)
+			seen[file] = seen[file] " " $2
+		}
+		END {
+			for (file in seen) {
+				if (seen[file] ~ /passed/ && seen[file] ~ /failed/) {
+					status = "flaky"
+				} else if (seen[file] ~ /failed/) {
+					status = "failed"
+				} else {
+					status = "passed"
+				}
+				print status " " file
+			}
+		}
+	' "$1/results" | sort -k2
+}
+
+# Print the steps that differ from the saved output of cmp
+print_failed_steps() {
+	cmp_file=$1
+	if [ ! -f "$cmp_file" ]; then
+		return 0
+	fi
+
+	awk '
+		{
+			colored = ($0 ~ /\033\[3[12]m/)
+			if (colored) {
+				has_colors = 1
+			}
+			gsub(/\033\[[0-9;]*m/, "")
+		}
+		/^––– input –––$/ {
+			getline command
+			gsub(/\033\[[0-9;]*m/, "", command)
+			next
+		}
+		(has_colors ? colored : 1) && /^[-+] / {
+			if (command != printed) {
+				print "    $ " command
+				printed = command
+			}
+			print "      " $0
+		}
+	' "$cmp_file"
+}
+
+compare_results() {
+	dir_a=$1
+	dir_b=$2
+	if [ -z "$dir_a" ] || [ -z "$dir_b" ]; then
+		>&2 echo 'Usage: clt report compare directory-a directory-b' && exit 1
+	fi
+
+	for dir in "$dir_a" "$dir_b"; do
+		if [ ! -f "$dir/results" ]; then
+			>&2 echo "There are no results in the directory, save them with clt test --results: $dir" && exit 1
+		fi
+	done
+
+	report=$(awk '
+		FNR == 1 { run++ }
+		{
+			status = $1
+			file = substr($0, length(status) + 2)
+			if (run == 1) {
+				a[file] = status
+			} else {
+				b[file] = status
+			}
+		}
+		END {
+			for (file in b) {
+				if (b[file] == "failed" && a[file] != "failed") {
+					print "failing " file
+				} else if (b[file] == "passed" && (a[file] == "failed" || a[file] == "flaky")) {
+					print "passing " file
+				} else if (b[file] == "flaky" && a[file] != "flaky") {
+					print "flaky " file
+				}
+			}
+		}
+	' <(get_statuses "$dir_a") <(get_statuses "$dir_b") | sort -k2)
+
+	newly_failing=0
+	for category in failing passing flaky; do
+		mapfile -t files < <(echo "$report" | grep "^$category " | cut -d' ' -f2- || true)
+		echo "Newly $category: ${#files[@]}"
+		for file in "${files[@]}"; do
+			if [ "$category" = failing ]; then
+				owners=$(owners_get "$file" | paste -sd' ' -)
+				echo "  $file${owners:+ ($owners)}"
+				print_failed_steps "$dir_b/${file%.*}.cmp"
+				newly_failing=1
+			else
+				echo "  $file"
+			fi
+		done
+	done
+
+	if [ $newly_failing -eq 1 ]; then
+		exit 1
+	fi
+}
+
+subcmd=$1
+shift || true
+
+case "$subcmd" in
+	compare)
+		compare_results "$@"
+		;;
+
+	*)
+		>&2 echo "Unknown report command '$subcmd', use: clt report compare directory-a directory-b" && exit 1
+		;;
+esac
@@ -0,0 +1,146 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/rec.sh"
+
+usage() {
+	>&2 echo "Usage: clt repro bundle.tar [--failed-steps]" && exit 1
+}
+
+# Print the value of the key from the manifest of the bundle
+manifest_get() {
+	sed -n "s/^$1:[[:space:]]*//p" manifest | head -n 1
+}
+
+# Print numbers of the steps that differ in the saved output of cmp
+get_failed_steps() {
+	awk '
+		{
+			colored = ($0 ~ /\033\[3[12]m/)
+			if (colored) {
+				has_colors = 1
+			}
+			gsub(/\033\[[0-9;]*m/, "")
+		}
+		/^––– input –––$/ {
+			step++
+			next
+		}
+		(has_colors ? colored : 1) && /^[-+] / && !(step in failed) {
+			failed[step] = 1
+			print step
+		}
+	' "$1"
+}
+
+# Print the test with the preamble and only the listed steps
+filter_steps() {
+	awk -v steps=" $2 " '
+		/^––– input –––$/ {
+			step++
+		}
+		step == 0 || index(steps, " " step " ") {
+			print
+		}
+	' "$1"
+}
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --failed-steps)
+      failed_steps=1
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      bundle_file="$1"
+      shift
+      ;;
+  esac
+done
+
+if [ -z "$bundle_file" ]; then
+	usage
+fi
+
+if [ ! -f "$bundle_file" ]; then
+	>&2 echo "The bundle does not exist: $bundle_file" && exit 1
+fi
+
+work_dir=$(mktemp -d)
+tar -xf "$bundle_file" -C "$work_dir"
+cd "$work_dir"
+if [ ! -f manifest ] || [ ! -f tests/test.rec ]; then
+	>&2 echo "The file is not a bundle saved with clt test --bundle: $bundle_file" && exit 1
+fi
+
+# Keep results of the failed run aside, the replay overwrites them
+mkdir -p original
+for ext in rep cmp; do
+	if [ -f "tests/test.$ext" ]; then
+		mv "tests/test.$ext" "original/test.$ext"
+	fi
+done
+
+# Run in the exact image of the failed run when its digest can be pulled
+image=$(manifest_get image)
+digest=$(manifest_get digest)
+if [[ "$digest" == *@sha256:* ]] && docker pull -q "$digest" > /dev/null; then
+	image=$digest
+elif [ -n "$digest" ] && docker image inspect "$digest" > /dev/null 2>&1; then
+	image=$digest
+else
+	>&2 echo "Failed to get the image by digest $digest, using $image instead"
+fi
+
+record_file=tests/test.rec
+if [ -n "$failed_steps" ]; then
+	if [ ! -f original/test.cmp ]; then
+		>&2 echo "The bundle has no output of cmp to find the failed steps in" && exit 1
+	fi
+
+	steps=$(get_failed_steps original/test.cmp | paste -sd' ' -)
+	if [ -z "$steps" ]; then
+		>&2 echo "There are no failed steps in the output of cmp of the bundle" && exit 1
+	fi
+
+	echo "Running only the failed steps: $steps"
+	record_file=tests/failed.rec
+	filter_steps tests/test.rec "$steps" > "$record_file"
+fi
+
+CLT_SEED=$(manifest_get seed)
+CLT_TZ=$(manifest_get timezone)
+CLT_NORMALIZE=$(manifest_get normalize)
+CLT_STRICT=$(manifest_get strict)
+export CLT_SEED CLT_TZ CLT_NORMALIZE CLT_STRICT
+
+echo "Reproducing $(manifest_get test) from the run at $(manifest_get created)"
+exit_code=0
+test "$image" "$record_file" 1 "$(manifest_get delay)" || exit_code=$?
+if [ $exit_code -eq 0 ]; then
+	echo "The failure is not reproduced, the test passes"
+else
+	echo "The failure is reproduced"
+fi
+echo "Files of the reproduction are kept in: $work_dir"
+exit $exit_code
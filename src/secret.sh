@@ -0,0 +1,42 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+subcmd=$1
+
+case "$subcmd" in
+	keygen)
+		head -c 32 /dev/urandom | base64
+		;;
+
+	encrypt)
+		if [ -z "$CLT_SECRET_KEY" ]; then
+			>&2 echo 'Set CLT_SECRET_KEY to encrypt the secret, generate it with: clt secret keygen' && exit 1
+		fi
+
+		if [ -t 0 ]; then
+			>&2 echo 'Enter the value to encrypt:'
+		fi
+		cmp_exec "$(tools_image)" --encrypt
+		;;
+
+	*)
+		>&2 echo "Unknown secret command '$subcmd', use: clt secret keygen|encrypt" && exit 1
+		;;
+esac
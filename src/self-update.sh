@@ -0,0 +1,132 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+releases_url=${CLT_RELEASES_URL:-https://github.com/manticoresoftware/clt/releases}
+api_url=${CLT_RELEASES_API_URL:-https://api.github.com/repos/manticoresoftware/clt/releases}
+arch=${ARCH/arm64/aarch64}
+archive="clt-$arch.tar.gz"
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --check)
+      check_only=1
+      shift
+      ;;
+    --version=*)
+      version="${key#*=}"
+      shift
+      ;;
+    --version)
+      version="$2"
+      shift
+      shift
+      ;;
+    *)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+  esac
+done
+
+current=$(cat "$PROJECT_DIR/VERSION" 2>/dev/null || echo unknown)
+if [ -z "$version" ]; then
+	version=$(curl -fsSL "$api_url/latest" | sed -n 's/^ *"tag_name": *"\([^"]*\)".*/\1/p' | head -n 1)
+	if [ -z "$version" ]; then
+		>&2 echo "Failed to get the latest release from $api_url/latest" && exit 1
+	fi
+fi
+
+# The tag goes into the download URLs, so anything but a plain tag like v1.2.3 is refused
+if ! [[ "$version" =~ ^[A-Za-z0-9][A-Za-z0-9._-]*$ ]] || [[ "$version" == *..* ]]; then
+	>&2 echo "Invalid release tag: $version" && exit 1
+fi
+
+if [ "$version" = "$current" ]; then
+	echo "CLT $current is up to date"
+	exit 0
+fi
+
+if [ -n "$check_only" ]; then
+	echo "CLT $version is available, the installed version is $current, run clt self-update to install it"
+	exit 0
+fi
+
+# Files of the clone are managed by git, replacing them would break it
+if [ -d "$PROJECT_DIR/.git" ]; then
+	>&2 echo "CLT is installed as a clone of the repository, update it with: git -C $PROJECT_DIR pull" && exit 1
+fi
+
+if [ ! -w "$(dirname "$PROJECT_DIR")" ]; then
+	>&2 echo "No permission to replace the installation in $(dirname "$PROJECT_DIR")" && exit 1
+fi
+
+download_dir=$(mktemp -d)
+trap 'rm -rf "$download_dir"' EXIT
+echo "Downloading CLT $version for $arch"
+for file in "$archive" SHA256SUMS; do
+	if ! curl -fsSL -o "$download_dir/$file" "$releases_url/download/$version/$file"; then
+		>&2 echo "Failed to download $file of the release $version" && exit 1
+	fi
+done
+
+# The checksums must be signed with the release key shipped in release-key.gpg of the installed version,
+# gpgv accepts only the keys of that keyring and not any key imported by the user,
+# set CLT_SKIP_SIGNATURE=1 to install a release that cannot be checked
+keyring="$PROJECT_DIR/release-key.gpg"
+if [ -n "$CLT_SKIP_SIGNATURE" ] && [ "$CLT_SKIP_SIGNATURE" != 0 ]; then
+	>&2 echo "WARNING: CLT_SKIP_SIGNATURE is set, the signature of the release $version is NOT checked,"
+	>&2 echo "WARNING: anyone who can change the release or its download can run code on this machine"
+else
+	if ! command -v gpgv > /dev/null 2>&1; then
+		>&2 echo "gpgv is not installed to check the signature of the release $version, install gnupg or set CLT_SKIP_SIGNATURE=1" && exit 1
+	fi
+	if [ ! -f "$keyring" ]; then
+		>&2 echo "The release key $keyring is missing, reinstall CLT from the archive of the release or set CLT_SKIP_SIGNATURE=1" && exit 1
+	fi
+	if ! curl -fsSL -o "$download_dir/SHA256SUMS.asc" "$releases_url/download/$version/SHA256SUMS.asc"; then
+		>&2 echo "Failed to download SHA256SUMS.asc of the release $version, set CLT_SKIP_SIGNATURE=1 to install it unsigned" && exit 1
+	fi
+	if ! gpgv --keyring "$keyring" "$download_dir/SHA256SUMS.asc" "$download_dir/SHA256SUMS" 2>/dev/null; then
+		>&2 echo "The checksums of the release $version are not signed with the release key in $keyring" && exit 1
+	fi
+fi
+
+if ! (cd "$download_dir" && grep "  $archive\$" SHA256SUMS | sha256sum -c --status -); then
+	>&2 echo "The checksum of $archive does not match the one in SHA256SUMS of the release $version" && exit 1
+fi
+
+# Unpack next to the installation and swap the directories, so a failure leaves the old version in place
+new_dir="$PROJECT_DIR.new.$$"
+old_dir="$PROJECT_DIR.old.$$"
+mkdir "$new_dir"
+if ! tar -xzf "$download_dir/$archive" -C "$new_dir" || [ ! -x "$new_dir/clt" ] || [ ! -x "$new_dir/bin/$arch/rec" ]; then
+	rm -rf "$new_dir"
+	>&2 echo "The archive of the release $version is not a CLT installation" && exit 1
+fi
+
+mv "$PROJECT_DIR" "$old_dir"
+if ! mv "$new_dir" "$PROJECT_DIR"; then
+	mv "$old_dir" "$PROJECT_DIR"
+	rm -rf "$new_dir"
+	>&2 echo "Failed to replace the installation in $PROJECT_DIR" && exit 1
+fi
+rm -rf "$old_dir"
+echo "Updated CLT from $current to $version"
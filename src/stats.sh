@@ -0,0 +1,214 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+
+format=json
+results_dir=
+test_dir=tests
+
+# Print tab-separated records the metrics are aggregated from:
+# the tests, the time their last replay took, the outcomes of the saved runs and the patterns used
+collect_records() {
+	while IFS= read -r test_file; do
+		printf 'test\t%s\n' "$test_file"
+		rep_file="${test_file%.*}.rep"
+		if [ -f "$rep_file" ]; then
+			awk -v file="$test_file" '
+				/^––– duration: [0-9.]+ms / {
+					duration = $3
+					sub(/ms$/, "", duration)
+					total += duration
+				}
+				END {
+					printf "duration\t%s\t%d\n", file, total
+				}
+			' "$rep_file"
+		fi
+	done < <(find "$test_dir" -name .baselines -prune -o -type f -name '*.rec' -print | sort)
+
+	if [ -n "$results_dir" ]; then
+		sort -n -k1,1 -s "$results_dir/results" | awk '
+			{
+				file = $0
+				sub(/^[^ ]+ [^ ]+ /, "", file)
+				printf "run\t%s\t%s\n", file, $2
+			}
+		'
+	fi
+
+	find "$test_dir" -name .baselines -prune -o -type f \( -name '*.rec' -o -name '*.recb' \) -print0 |
+		xargs -0 -r grep -ohE '%\{[A-Z][A-Z0-9_]*|#!/' |
+		awk '{ print "pattern\t" ($0 == "#!/" ? "inline" : substr($0, 3)) }'
+}
+
+# Aggregate the records into the metrics and print them in the format
+print_metrics() {
+	awk -F'\t' -v format="$format" '
+		function escape(value) {
+			gsub(/\\/, "\\\\", value)
+			gsub(/"/, "\\\"", value)
+			return value
+		}
+		function metric(name, help, type) {
+			print "# HELP clt_" name " " help
+			print "# TYPE clt_" name " " type
+		}
+		$1 == "test" {
+			tests[$2] = 1
+			test_count++
+		}
+		$1 == "duration" {
+			duration[$2] = $3
+			duration_total += $3
+			duration_count++
+		}
+		# The flakiness is the share of the runs with the outcome different from the previous run
+		$1 == "run" {
+			tests[$2] = 1
+			runs[$2]++
+			if ($3 == "failed") {
+				failures[$2]++
+			}
+			if (($2 in last) && last[$2] != $3) {
+				flips[$2]++
+			}
+			last[$2] = $3
+		}
+		$1 == "pattern" {
+			patterns[$2]++
+		}
+		END {
+			average = duration_count ? duration_total / duration_count : 0
+			n = 0
+			for (file in tests) {
+				files[++n] = file
+				flakiness[file] = runs[file] > 1 ? flips[file] / (runs[file] - 1) : 0
+				if (flakiness[file] > 0) {
+					flaky_count++
+				}
+			}
+			# Sort the files to keep the output stable between runs
+			for (i = 2; i <= n; i++) {
+				for (j = i; j > 1 && files[j] < files[j - 1]; j--) {
+					file = files[j]; files[j] = files[j - 1]; files[j - 1] = file
+				}
+			}
+
+			if (format == "prometheus") {
+				metric("tests", "Number of tests in the suite", "gauge")
+				printf "clt_tests %d\n", test_count
+				metric("flaky_tests", "Number of tests with different outcomes in the saved runs", "gauge")
+				printf "clt_flaky_tests %d\n", flaky_count
+				metric("average_duration_ms", "Average time the last replay of the tests took", "gauge")
+				printf "clt_average_duration_ms %.2f\n", average
+				metric("test_duration_ms", "Time the last replay of the test took", "gauge")
+				for (i = 1; i <= n; i++) {
+					if (files[i] in duration) {
+						printf "clt_test_duration_ms{test=\"%s\"} %d\n", escape(files[i]), duration[files[i]]
+					}
+				}
+				metric("test_runs", "Number of the saved runs of the test", "gauge")
+				for (i = 1; i <= n; i++) {
+					printf "clt_test_runs{test=\"%s\"} %d\n", escape(files[i]), runs[files[i]]
+				}
+				metric("test_failures", "Number of the saved runs the test failed in", "gauge")
+				for (i = 1; i <= n; i++) {
+					printf "clt_test_failures{test=\"%s\"} %d\n", escape(files[i]), failures[files[i]]
+				}
+				metric("test_flakiness", "Share of the saved runs of the test with the outcome different from the previous run", "gauge")
+				for (i = 1; i <= n; i++) {
+					printf "clt_test_flakiness{test=\"%s\"} %.4f\n", escape(files[i]), flakiness[files[i]]
+				}
+				metric("pattern_usage", "Number of times the pattern is used in the tests and blocks", "gauge")
+				for (name in patterns) {
+					printf "clt_pattern_usage{pattern=\"%s\"} %d\n", name, patterns[name]
+				}
+				exit
+			}
+
+			printf "{\n  \"tests\": %d,\n  \"flaky_tests\": %d,\n  \"average_duration_ms\": %.2f,\n", test_count, flaky_count, average
+			printf "  \"per_test\": ["
+			for (i = 1; i <= n; i++) {
+				file = files[i]
+				printf "%s\n    {\"test\": \"%s\", ", (i > 1 ? "," : ""), escape(file)
+				printf "\"duration_ms\": %s, ", (file in duration) ? duration[file] : "null"
+				printf "\"runs\": %d, \"failures\": %d, \"flakiness\": %.4f}", runs[file], failures[file], flakiness[file]
+			}
+			printf "%s],\n  \"patterns\": {", (n ? "\n  " : "")
+			sep = ""
+			for (name in patterns) {
+				printf "%s\n    \"%s\": %d", sep, name, patterns[name]
+				sep = ","
+			}
+			printf "%s}\n}\n", (sep ? "\n  " : "")
+		}
+	'
+}
+
+subcmd=$1
+shift || true
+
+if [ "$subcmd" != "export" ]; then
+	>&2 echo "Unknown stats command '$subcmd', use: clt stats export [--format=json|prometheus] [--results=directory] [directory]" && exit 1
+fi
+
+while [[ $# -gt 0 ]]; do
+  key="$1"
+
+  case $key in
+    --format=*)
+      format="${key#*=}"
+      shift
+      ;;
+    --format)
+      format="$2"
+      shift
+      shift
+      ;;
+    --results=*)
+      results_dir="${key#*=}"
+      shift
+      ;;
+    --results)
+      results_dir="$2"
+      shift
+      shift
+      ;;
+    -*)
+      >&2 echo "Unsupported flag: $key" && exit 1
+      ;;
+    *)
+      test_dir="$key"
+      shift
+      ;;
+  esac
+done
+
+if [ "$format" != json ] && [ "$format" != prometheus ]; then
+	>&2 echo "Format of the statistics should be json or prometheus: $format" && exit 1
+fi
+
+if [ ! -d "$test_dir" ]; then
+	>&2 echo "Directory with tests does not exist: $test_dir" && exit 1
+fi
+
+if [ -n "$results_dir" ] && [ ! -f "$results_dir/results" ]; then
+	>&2 echo "There are no results in the directory, save them with clt test --results: $results_dir" && exit 1
+fi
+
+collect_records | print_metrics
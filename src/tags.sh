@@ -0,0 +1,53 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/meta.sh"
+
+subcmd=$1
+test_dir=${2:-tests}
+
+if [ "$subcmd" != "list" ]; then
+	>&2 echo "Unknown tags command '$subcmd', use: clt tags list [directory]" && exit 1
+fi
+
+if [ ! -d "$test_dir" ]; then
+	>&2 echo "Directory with tests does not exist: $test_dir" && exit 1
+fi
+
+# Count how many tests use each tag
+total=0
+untagged=0
+declare -A tag_count
+while read -r test_file; do
+	total=$((total + 1))
+	mapfile -t tags < <(meta_tags "$test_file")
+	if [ ${#tags[@]} -eq 0 ]; then
+		untagged=$((untagged + 1))
+		continue
+	fi
+
+	for tag in "${tags[@]}"; do
+		tag_count[$tag]=$(( ${tag_count[$tag]:-0} + 1 ))
+	done
+done < <(find "$test_dir" -type f -name '*.rec' | sort)
+
+for tag in "${!tag_count[@]}"; do
+	echo "${tag_count[$tag]} $tag"
+done | sort -k1,1nr -k2
+
+echo "Tests: $total, untagged: $untagged"
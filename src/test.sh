@@ -18,6 +18,8 @@
 set -e
 source "$PROJECT_DIR/lib/rec.sh"
 source "$PROJECT_DIR/lib/argument.sh"
+source "$PROJECT_DIR/lib/meta.sh"
+source "$PROJECT_DIR/lib/owners.sh"
 
 docker_image=$(argument_parse_docker_image "$@")
 set -- "${@:1:$(($#-1))}"
@@ -39,6 +41,11 @@ while [[ $# -gt 0 ]]; do
     -d|--debug|--diff)
       show_diff=1
       shift
+      ;;
+    --show-matches)
+      show_diff=1
+      export CLT_SHOW_MATCHES=1
+      shift
       ;;
 		-D=*|--delay=*)
 			delay="${key#*=}"
@@ -49,11 +56,362 @@ while [[ $# -gt 0 ]]; do
 			shift
 			shift
 			;;
+    -j=*|--jobs=*)
+      jobs="${key#*=}"
+      shift
+      ;;
+    -j|--jobs)
+      jobs="$2"
+      shift
+      shift
+      ;;
+    -r=*|--results=*)
+      report_dir="${key#*=}"
+      shift
+      ;;
+    -r|--results)
+      report_dir="$2"
+      shift
+      shift
+      ;;
+    --seed=*)
+      seed="${key#*=}"
+      shift
+      ;;
+    --seed)
+      seed="$2"
+      shift
+      shift
+      ;;
+    --disk-report)
+      disk_report=1
+      shift
+      ;;
+    --bundle=*)
+      bundle_dir="${key#*=}"
+      shift
+      ;;
+    --bundle)
+      bundle_dir="$2"
+      shift
+      shift
+      ;;
+    --screens=*)
+      screens="${key#*=}"
+      shift
+      ;;
+    --screens)
+      screens=text
+      shift
+      ;;
+    --fixture=*)
+      fixture_file="${key#*=}"
+      shift
+      ;;
+    --fixture)
+      fixture_file="$2"
+      shift
+      shift
+      ;;
+    --snapshot=*)
+      snapshot_file="${key#*=}"
+      shift
+      ;;
+    --snapshot)
+      snapshot_file="$2"
+      shift
+      shift
+      ;;
+    --tags=*)
+      tags="${key#*=}"
+      shift
+      ;;
+    --tags)
+      tags="$2"
+      shift
+      shift
+      ;;
+    --vars=*)
+      vars_file="${key#*=}"
+      shift
+      ;;
+    --vars)
+      vars_file="$2"
+      shift
+      shift
+      ;;
+    --changed=*)
+      changed_ref="${key#*=}"
+      shift
+      ;;
+    --changed)
+      changed_ref="$2"
+      shift
+      shift
+      ;;
+    --webhook=*)
+      webhook_url="${key#*=}"
+      shift
+      ;;
+    --webhook)
+      webhook_url="$2"
+      shift
+      shift
+      ;;
     *)
       >&2 echo "Unsupported flag: $key" && exit 1
       ;;
   esac
 done
 
-test "$docker_image" "$record_file" "$show_diff" "$delay"
+# We can run a single test or all tests from the directory
+if [ -d "$record_file" ]; then
+	mapfile -t record_files < <(find "$record_file" -type f -name '*.rec' | sort)
+else
+	record_files=("$record_file")
+fi
+
+# Tests run after the tests they require, which are added to the run when they are not in it
+# The plan is needed only when some test declares the requires key in its front matter
+if [ ${#record_files[@]} -gt 0 ] && grep -qs '^requires:' "${record_files[@]}"; then
+	plan=$(cmp_exec "$docker_image" --plan "${record_files[@]}") || exit 1
+	mapfile -t record_files <<< "$plan"
+fi
+
+jobs=${jobs:-1}
+if ! [[ "$jobs" =~ ^[1-9][0-9]*$ ]]; then
+	>&2 echo "Number of jobs should be a positive number: $jobs" && exit 1
+fi
+
+if [ -n "$fixture_file" ] && [ ! -f "$fixture_file" ]; then
+	>&2 echo "The fixture file does not exist: $fixture_file" && exit 1
+fi
+
+if [ -n "$fixture_file" ] && [ "$jobs" -gt 1 ]; then
+	>&2 echo "Tests share the fixture container, so they cannot run in parallel" && exit 1
+fi
+
+if [ -n "$screens" ] && [ "$screens" != text ] && [ "$screens" != svg ]; then
+	>&2 echo "Format of terminal screens should be text or svg: $screens" && exit 1
+fi
+CLT_SCREENS=$screens
+export CLT_SCREENS
+
+CLT_BUNDLE_DIR=$bundle_dir
+export CLT_BUNDLE_DIR
+
+if [ -n "$vars_file" ]; then
+	if [ ! -f "$vars_file" ]; then
+		>&2 echo "The file with values of variables does not exist: $vars_file" && exit 1
+	fi
+	CLT_VARS_FILE=$(realpath "$vars_file")
+	export CLT_VARS_FILE
+fi
+
+# Resolve the files changed since the ref, including uncommitted and untracked ones, to run only the tests they affect
+if [ -n "$changed_ref" ]; then
+	if ! changed=$(git diff --name-only --relative "$changed_ref" -- && git ls-files --others --exclude-standard); then
+		>&2 echo "Failed to get the files changed since $changed_ref" && exit 1
+	fi
+
+	declare -A changed_files
+	while read -r path; do
+		if [ -n "$path" ]; then
+			changed_files[$(realpath -m "$path")]=1
+		fi
+	done <<< "$changed"
+fi
+
+# Check that the test or any file it depends on, like its blocks or the configs declared in depends, has changed
+# The test that cmp fails to read is considered changed to show the error when it runs
+test_is_changed() {
+	file=$1
+	depends=$(cmp_exec "$docker_image" --depends "$file" 2> /dev/null) || return 0
+	while read -r path; do
+		if [ -n "$path" ] && [ -n "${changed_files[$(realpath -m "$path")]}" ]; then
+			return 0
+		fi
+	done <<< "$file"$'\n'"$depends"
+	return 1
+}
+
+if [ "$jobs" -gt 1 ] && ! command -v flock > /dev/null 2>&1; then
+	>&2 echo "Running tests in parallel requires flock from util-linux" && exit 1
+fi
+
+# All tests of the run share the same seed, so the run can be reproduced with --seed
+CLT_SEED=${seed:-$RANDOM}
+if ! [[ "$CLT_SEED" =~ ^[0-9]+$ ]]; then
+	>&2 echo "Seed should be a number: $CLT_SEED" && exit 1
+fi
+export CLT_SEED
+echo "Using seed $CLT_SEED, pass --seed $CLT_SEED to reproduce this run"
+
+# Build the image once before running tests in parallel when it is referenced as build:path/to/Dockerfile
+docker_image=$(resolve_image "$docker_image")
+
+results_dir=$(mktemp -d)
+cleanup() {
+	rm -rf "$results_dir"
+	if [ -n "$CLT_FIXTURE_CONTAINER" ]; then
+		docker rm -f "$CLT_FIXTURE_CONTAINER" > /dev/null 2>&1
+	fi
+
+	if [ -n "$snapshot_image" ]; then
+		docker rmi -f "$snapshot_image" > /dev/null 2>&1
+	fi
+}
+trap cleanup EXIT
+
+if [ -n "$disk_report" ]; then
+	CLT_DISK_USAGE_FILE="$results_dir/disk-usage"
+	export CLT_DISK_USAGE_FILE
+	touch "$CLT_DISK_USAGE_FILE"
+fi
+
+# Start all tests from the state of the container after the setup instead of running it in each test
+if [ -n "$snapshot_file" ]; then
+	snapshot_image="clt-snapshot:$$"
+	snapshot "$docker_image" "$snapshot_file" "$snapshot_image"
+	docker_image=$snapshot_image
+fi
+
+# Start declared daemons once in the fixture container and only reset them between tests
+if [ -n "$fixture_file" ]; then
+	CLT_FIXTURE_CONTAINER="clt-fixture-$$"
+	export CLT_FIXTURE_CONTAINER
+	fixture_reset=$(fixture_get "$fixture_file" reset)
+	fixture_start "$docker_image" "$(dirname "${record_files[0]}" | cut -d/ -f1)" \
+		"$CLT_FIXTURE_CONTAINER" "$(fixture_get "$fixture_file" start)"
+fi
+
+# Run the test holding the locks it needs when we run tests in parallel
+# Every test takes a shared lock of the suite, and tests marked as parallel-safe: false take it exclusively,
+# so they run alone. Named locks from the locks: list are exclusive and taken in sorted order to avoid deadlocks
+run_test() {
+	file=$1
+	(
+		if [ "$jobs" -gt 1 ]; then
+			exec {suite_fd}>"$results_dir/.suite.lock"
+			if [ "$(meta_get "$file" parallel-safe)" = "false" ]; then
+				flock -x "$suite_fd"
+			else
+				flock -s "$suite_fd"
+			fi
+
+			while read -r lock; do
+				exec {lock_fd}>"$results_dir/${lock//\//_}.lock"
+				flock -x "$lock_fd"
+			done < <(meta_get_list "$file" locks | sort -u)
+		fi
+
+		if [ -n "$fixture_reset" ]; then
+			docker exec "$CLT_FIXTURE_CONTAINER" /bin/bash -c "$fixture_reset" > /dev/null || {
+				>&2 echo "Failed to reset the fixture before the test: $file"
+				exit 1
+			}
+		fi
+
+		test "$docker_image" "$file" "$show_diff" "$delay"
+	)
+}
+
+test_count=0
+for file in "${record_files[@]}"; do
+	if [ -n "$snapshot_file" ] && [ "$file" -ef "$snapshot_file" ]; then
+		continue
+	fi
+
+	if [ -n "$tags" ] && ! meta_match_tags "$file" "$tags"; then
+		echo "Skipping the test that does not match tags $tags: $file"
+		continue
+	fi
+
+	if [ -n "$changed_ref" ] && ! test_is_changed "$file"; then
+		echo "Skipping the test not affected by the changes since $changed_ref: $file"
+		continue
+	fi
+
+	test_count=$((test_count + 1))
+	echo "$file" > "$results_dir/$test_count.file"
+	if [ "$jobs" -eq 1 ]; then
+		run_test "$file" || echo $? > "$results_dir/$test_count.code"
+		continue
+	fi
+
+	while [ "$(jobs -rp | wc -l)" -ge "$jobs" ]; do
+		wait -n || true
+	done
+
+	# The required tests come earlier in the plan, so the test that requires others waits for all started before it
+	if [ -n "$(meta_get "$file" requires)" ]; then
+		wait || true
+	fi
+
+	# Keep the output of each test together to not mix it with others running at the same time
+	{
+		run_test "$file" > "$results_dir/$test_count.log" 2>&1 || echo $? > "$results_dir/$test_count.code"
+		cat "$results_dir/$test_count.log"
+	} &
+done
+wait
+
+exit_code=0
+failed_files=()
+for ((i = 1; i <= test_count; i++)); do
+	file=$(cat "$results_dir/$i.file")
+	status=passed
+	if [ -f "$results_dir/$i.code" ]; then
+		exit_code=$(cat "$results_dir/$i.code")
+		failed_files+=("$file")
+		status=failed
+	fi
+
+	# Keep results of the run to compare them later with clt report compare
+	if [ -n "$report_dir" ]; then
+		mkdir -p "$report_dir/$(dirname "$file")"
+		for ext in rep cmp; do
+			if [ -f "${file%.*}.$ext" ]; then
+				cp "${file%.*}.$ext" "$report_dir/${file%.*}.$ext"
+			fi
+		done
+		if [ -d "${file%.*}.screens" ]; then
+			rm -rf "$report_dir/${file%.*}.screens"
+			cp -r "${file%.*}.screens" "$report_dir/${file%.*}.screens"
+		fi
+		echo "$(date +%s) $status $file" >> "$report_dir/results"
+	fi
+done
+
+# Show the tests that consume the most disk space in the container and leave files behind
+if [ -n "$disk_report" ]; then
+	echo "Top disk consuming tests:"
+	sort -k1,1nr "$CLT_DISK_USAGE_FILE" | head -n 10 | while read -r layer_size _ file; do
+		echo "  $(numfmt --to=iec "$layer_size") $file"
+	done
+
+	echo "Tests leaving files in the directory with tests:"
+	sort -k2,2nr "$CLT_DISK_USAGE_FILE" | awk '$2 > 0' | while read -r _ leftover_size file; do
+		echo "  $(numfmt --to=iec "$leftover_size") $file"
+	done
+
+	if [ -n "$report_dir" ]; then
+		cp "$CLT_DISK_USAGE_FILE" "$report_dir/disk-usage"
+	fi
+fi
+
+if [ ${#record_files[@]} -gt 1 ] && [ ${#failed_files[@]} -gt 0 ]; then
+	echo "Failed tests:"
+	for file in "${failed_files[@]}"; do
+		owners=$(owners_get "$file" | paste -sd' ' -)
+		echo "$file${owners:+ ($owners)}"
+	done
+fi
+
+# A failed notification should not change the outcome of the run
+webhook_url=${webhook_url:-$CLT_WEBHOOK_URL}
+if [ -n "$webhook_url" ] && [ ${#failed_files[@]} -gt 0 ]; then
+	owners_notify "$webhook_url" "$test_count" "${failed_files[@]}" || >&2 echo "Failed to send the notification to the webhook"
+fi
 
+exit "$exit_code"
@@ -0,0 +1,27 @@
+#!/usr/bin/env bash
+# Copyright (c) 2023-present, Manticore Software LTD (https:#manticoresearch.com)
+# All rights reserved
+#
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#    http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -e
+source "$PROJECT_DIR/lib/container.sh"
+
+if [ $# -ne 1 ] || [[ "$1" == -* ]]; then
+	>&2 echo "Usage: clt undo test-file" && exit 1
+fi
+
+# Each call goes one edit further back, the snapshots are kept in .clt/history
+cmp_exec "$(tools_image)" --revert "$1"
+echo "Reverted the last edit of $1"